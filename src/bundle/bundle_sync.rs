@@ -1,6 +1,7 @@
+use crate::git::git_repo::read_repo_refs;
 use crate::node::node_id::NodeId;
 use crate::repo::repo::Repo;
-use crate::storage::repo_model;
+use crate::storage::{fetch_request, repo_model};
 use anyhow::Result;
 use std::sync::Arc;
 use std::time::Duration;
@@ -11,9 +12,22 @@ use tracing::{debug, info, warn};
 use super::BundleService;
 
 const SYNC_INTERVAL: Duration = Duration::from_secs(30);
+const FETCH_POLL_INTERVAL: Duration = Duration::from_secs(2);
 
 /// 后台任务：定时检查和同步 external repos 的 bundle
 pub async fn start_bundle_sync_task(bundle_service: Arc<Mutex<BundleService>>) {
+    // 处理 CLI（`repo fetch`）写入的拉取请求
+    let fetch_service = Arc::clone(&bundle_service);
+    tokio::spawn(async move {
+        let mut tick = interval(FETCH_POLL_INTERVAL);
+        loop {
+            tick.tick().await;
+            if let Err(e) = process_fetch_requests(&fetch_service).await {
+                warn!("Failed to process fetch requests: {}", e);
+            }
+        }
+    });
+
     tokio::spawn(async move {
         let mut tick = interval(SYNC_INTERVAL);
 
@@ -74,14 +88,144 @@ async fn request_bundle_from_owner(
     // 解析所有者的 NodeId
     let owner_node_id = NodeId::from_string(owner_node_id_str)?;
 
+    // 已有本地克隆时携带其 refs 指向的提交，让对方只发送缺失的部分
+    let have = local_clone_commits(repo);
+
     // 通过 BundleService 的 request_bundle 发送 Request 消息
     let service = bundle_service.lock().await;
     service
-        .request_bundle(&owner_node_id, &repo.repo_id)
+        .request_bundle_since(&owner_node_id, &repo.repo_id, have)
         .await?;
 
     Ok(())
 }
 
+/// 发送所有待处理的拉取请求，发送成功后删除
+async fn process_fetch_requests(bundle_service: &Arc<Mutex<BundleService>>) -> Result<()> {
+    for request in fetch_request::list_fetch_requests().await? {
+        let have = match repo_model::load_repo_from_db(&request.repo_id).await? {
+            Some(repo) => local_clone_commits(&repo),
+            None => Vec::new(),
+        };
+        // 无法解析的节点 ID 永远发不出去：记录并删除该请求，继续处理其余请求
+        let peer = match NodeId::from_string(&request.peer_id) {
+            Ok(peer) => peer,
+            Err(e) => {
+                warn!(
+                    "Dropping fetch request for repo {}: invalid peer id {}: {}",
+                    request.repo_id, request.peer_id, e
+                );
+                if let Err(e) = fetch_request::delete_fetch_request(&request.repo_id).await {
+                    warn!(
+                        "Failed to delete fetch request for repo {}: {}",
+                        request.repo_id, e
+                    );
+                }
+                continue;
+            }
+        };
+
+        let service = bundle_service.lock().await;
+        match service
+            .request_bundle_since(&peer, &request.repo_id, have)
+            .await
+        {
+            Ok(()) => {
+                info!(
+                    "Fetch request for repo {} sent to {}",
+                    request.repo_id, request.peer_id
+                );
+                fetch_request::delete_fetch_request(&request.repo_id).await?;
+            }
+            Err(e) => {
+                debug!(
+                    "Fetch request for repo {} to {} not sent yet: {}",
+                    request.repo_id, request.peer_id, e
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 读取本地克隆中各 ref 指向的提交
+///
+/// 使用克隆的实际 refs 而不是 refs 表：收到 RepoAnnouncement 时 refs 表
+/// 已被更新为远端的最新值，并不代表本地已经拥有这些提交。
+fn local_clone_commits(repo: &Repo) -> Vec<String> {
+    if repo.path.as_os_str().is_empty() || !repo.path.exists() {
+        return Vec::new();
+    }
+
+    match read_repo_refs(&repo.path.to_string_lossy()) {
+        Ok(refs) => {
+            let mut commits: Vec<String> = refs.into_values().collect();
+            commits.sort();
+            commits.dedup();
+            commits
+        }
+        Err(e) => {
+            debug!(
+                "Failed to read refs of local clone {}: {}",
+                repo.path.display(),
+                e
+            );
+            Vec::new()
+        }
+    }
+}
+
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use super::*;
+    use crate::identity::keypair::KeyPair;
+    use crate::transport::quic::ConnectionManager;
+
+    #[tokio::test]
+    async fn test_invalid_fetch_request_does_not_block_others() -> Result<()> {
+        let _ = rustls::crypto::ring::default_provider().install_default();
+        let dir = std::env::temp_dir().join(format!(
+            "megaengine-fetch-requests-{}",
+            uuid::Uuid::new_v4()
+        ));
+        std::fs::create_dir_all(&dir)?;
+        let path = |f: &str| dir.join(f).to_string_lossy().to_string();
+        crate::transport::cert::ensure_certificates(
+            &path("cert.pem"),
+            &path("key.pem"),
+            &path("ca-cert.pem"),
+        )?;
+        let config = crate::transport::config::QuicConfig::new(
+            "127.0.0.1:0".parse()?,
+            path("cert.pem"),
+            path("key.pem"),
+            path("ca-cert.pem"),
+        );
+        let conn_mgr = Arc::new(Mutex::new(ConnectionManager::run_server(config).await?));
+        let service = Arc::new(Mutex::new(BundleService::new(
+            conn_mgr,
+            dir.join("bundles"),
+        )));
+
+        crate::storage::with_test_db(async {
+            let offline = NodeId::from_keypair(&KeyPair::generate()?);
+            fetch_request::save_fetch_request("did:repo:fetch-invalid", "not-a-node-id").await?;
+            fetch_request::save_fetch_request("did:repo:fetch-offline", offline.as_str()).await?;
+
+            process_fetch_requests(&service).await?;
+
+            // 无效的请求被删除，发送失败的请求留待下次重试
+            let remaining: Vec<String> = fetch_request::list_fetch_requests()
+                .await?
+                .into_iter()
+                .map(|r| r.repo_id)
+                .collect();
+            assert_eq!(remaining, vec!["did:repo:fetch-offline".to_string()]);
+            Ok::<_, anyhow::Error>(())
+        })
+        .await?;
+
+        let _ = std::fs::remove_dir_all(&dir);
+        Ok(())
+    }
+}
@@ -4,4 +4,4 @@ pub mod transfer;
 
 pub use bundle_sync::start_bundle_sync_task;
 pub use service::BundleService;
-pub use transfer::BundleTransferManager;
+pub use transfer::{BundleProgress, BundleTransferManager, TransferDirection};
@@ -1,10 +1,12 @@
-use crate::bundle::transfer::BundleMessageType;
-use crate::bundle::transfer::BundleTransferManager;
+use crate::bundle::transfer::{BundleProgress, BundleTransferManager};
+use crate::error::Result as MegaResult;
+use crate::identity::keypair::KeyPair;
 use crate::node::node_id::NodeId;
 use crate::transport::quic::ConnectionManager;
 use anyhow::Result;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::mpsc;
 use tokio::sync::Mutex;
 
@@ -34,19 +36,52 @@ impl BundleService {
         }
     }
 
+    /// 设置发送 bundle 时是否使用 zstd 压缩
+    pub fn with_compression(self, enabled: bool) -> Self {
+        self.bundle_manager.set_compression(enabled);
+        self
+    }
+
+    /// 设置接收中传输的空闲超时，超时后中止传输并删除临时文件
+    pub fn with_idle_timeout(self, timeout: Duration) -> Self {
+        self.bundle_manager.set_idle_timeout(timeout);
+        self
+    }
+
+    /// 设置本节点密钥，用于解密发给本节点的加密传输
+    pub fn with_keypair(self, keypair: KeyPair) -> Self {
+        self.bundle_manager.set_keypair(keypair);
+        self
+    }
+
+    /// 设置是否对所有发出的 bundle 做端到端加密，有白名单的仓库总是加密
+    pub fn with_encryption(self, enabled: bool) -> Self {
+        self.bundle_manager.set_encryption(enabled);
+        self
+    }
+
+    /// 注册传输进度订阅者
+    ///
+    /// 每个事件携带 repo_id 和对端 NodeId，一个订阅者即可跟踪多个并发传输
+    pub fn with_progress(self, tx: mpsc::Sender<BundleProgress>) -> Self {
+        self.bundle_manager.set_progress(tx);
+        self
+    }
+
     /// 启动 Bundle 服务：注册 data_sender 并处理接收的 bundle 消息
     pub async fn start(self: Arc<Self>) -> Result<()> {
         // 注册数据传输接收器
         let (data_tx, mut data_rx) = mpsc::channel::<(NodeId, Vec<u8>)>(256);
 
-        {
+        let mgr = {
             let mgr = self.connection_manager.lock().await;
             mgr.register_data_sender(data_tx).await;
-        }
+            mgr.clone()
+        };
 
-        // Bundle 数据处理任务
+        // Bundle 数据处理任务：由 ConnectionManager 跟踪，shutdown 时会等待剩余消息处理完毕
         let s = Arc::clone(&self);
-        tokio::spawn(async move {
+        mgr.spawn_task(async move {
             while let Some((from, data)) = data_rx.recv().await {
                 if let Err(e) = s.bundle_manager.handle_bundle_message(from, data).await {
                     tracing::warn!("Failed to handle bundle message: {}", e);
@@ -54,6 +89,9 @@ impl BundleService {
             }
         });
 
+        // 传输看门狗：发送方中途消失时清理写了一半的临时文件
+        mgr.spawn_task(Arc::clone(&self.bundle_manager).run_stall_watchdog(mgr.shutdown_token()));
+
         Ok(())
     }
 
@@ -63,7 +101,7 @@ impl BundleService {
         target_node_id: NodeId,
         repo_id: String,
         bundle_path: &str,
-    ) -> Result<()> {
+    ) -> MegaResult<()> {
         self.bundle_manager
             .send_bundle(target_node_id, repo_id, bundle_path)
             .await
@@ -75,18 +113,20 @@ impl BundleService {
     }
 
     /// 向指定节点请求 bundle（发送 Request 消息）
-    pub async fn request_bundle(&self, target_node_id: &NodeId, repo_id: &str) -> Result<()> {
-        // 构造 Request 消息
-        let start_msg = BundleMessageType::Request {
-            repo_id: repo_id.to_string(),
-        };
-
-        let payload = serde_json::to_vec(&start_msg)?;
+    pub async fn request_bundle(&self, target_node_id: &NodeId, repo_id: &str) -> MegaResult<()> {
+        self.request_bundle_since(target_node_id, repo_id, Vec::new())
+            .await
+    }
 
-        let mgr = self.connection_manager.lock().await;
-        let peers = mgr.list_peers().await;
-        tracing::info!("Sent bundle peers: {:?}", peers);
-        mgr.send_data_message(target_node_id.clone(), payload)
+    /// 向指定节点请求 bundle，携带本地已有的提交以便对方只发送增量
+    pub async fn request_bundle_since(
+        &self,
+        target_node_id: &NodeId,
+        repo_id: &str,
+        have: Vec<String>,
+    ) -> MegaResult<()> {
+        self.bundle_manager
+            .send_request(target_node_id, repo_id, have)
             .await?;
 
         tracing::info!(
@@ -1,48 +1,284 @@
+use crate::error::{MegaError, Result as MegaResult};
+use crate::git::pack::BundleKind;
+use crate::identity::keypair::KeyPair;
 use crate::node::node_id::NodeId;
 use crate::storage::repo_model;
-use crate::transport::quic::ConnectionManager;
+use crate::transport::quic::{ConnectionManager, DataStream};
 use crate::util::get_node_id_last_part;
 use crate::util::get_repo_id_last_part;
 use anyhow::Context;
 use anyhow::Result;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
 use std::io::SeekFrom;
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::{Duration, Instant};
 use tokio::fs;
 use tokio::io::{AsyncSeekExt, AsyncWriteExt};
-use tokio::sync::Mutex;
+use tokio::sync::{mpsc, Mutex};
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, info, warn};
+use uuid::Uuid;
 
 const TRANSFER_CHUNK_SIZE: usize = 64 * 1024; // 64KB per chunk
+const ZSTD_LEVEL: i32 = 3;
+/// 接收中的传输在此时间内没有收到任何数据块即视为中断
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+/// 发出的 Request 在此时间内没有开始回应即不再等待
+const PENDING_REQUEST_TTL: Duration = Duration::from_secs(600);
 
 /// Bundle 消息类型（用于多帧传输）
+///
+/// 每条消息携带 transfer_id：Start/Chunk/Done 用它区分同时进行的多个传输，
+/// NotFound/NotAuthorized 回显 Request 的 transfer_id。旧节点不发送该字段，反序列化为 nil，
+/// 接收时改用由对端和仓库派生的 ID（见 `legacy_transfer_id`）。
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub enum BundleMessageType {
     Request {
+        #[serde(default)]
+        transfer_id: Uuid,
         repo_id: String,
+        /// 请求方已有的提交（各 ref 的 commit），非空时提供方尽量只发送增量 bundle
+        #[serde(default)]
+        have: Vec<String>,
     },
     /// 开始传输：包含文件元数据
     Start {
+        #[serde(default)]
+        transfer_id: Uuid,
         repo_id: String,
         file_name: String,
         total_size: u64,
+        /// 是否为增量（thin）bundle，需要解包到已有仓库中
+        #[serde(default)]
+        delta: bool,
+        /// 数据块是否经过 zstd 压缩（每个块独立压缩）
+        #[serde(default)]
+        compressed: bool,
+        /// 数据块是否为接收方端到端加密（先压缩后加密，每个块独立加密）
+        #[serde(default)]
+        encrypted: bool,
+        /// 加密传输的接收方，只有它能解密数据块
+        #[serde(default)]
+        recipient: Option<NodeId>,
     },
     /// 数据块：包含分块数据
     Chunk {
+        #[serde(default)]
+        transfer_id: Uuid,
         repo_id: String,
         chunk_idx: u32,
         data: Vec<u8>,
     },
     /// 传输完成
     Done {
+        #[serde(default)]
+        transfer_id: Uuid,
         repo_id: String,
     },
+    /// 对 Request 的回复：提供方本地没有该仓库
+    NotFound {
+        #[serde(default)]
+        transfer_id: Uuid,
+        repo_id: String,
+    },
+    /// 对 Request 的回复：请求方不在该仓库的拉取白名单中
+    NotAuthorized {
+        #[serde(default)]
+        transfer_id: Uuid,
+        repo_id: String,
+    },
+    /// 对 Request 的回复：请求方已拥有全部提交，不发送 bundle
+    UpToDate {
+        #[serde(default)]
+        transfer_id: Uuid,
+        repo_id: String,
+    },
+}
+
+/// 接收传输时使用的 ID：旧节点发来的 nil ID 以 (对端, 仓库) 派生，
+/// 同一对端的多个旧版传输、以及不同对端的旧版传输互不冲突
+fn legacy_transfer_id(from: &NodeId, repo_id: &str, transfer_id: Uuid) -> Uuid {
+    if !transfer_id.is_nil() {
+        return transfer_id;
+    }
+    let digest = Sha256::new()
+        .chain_update(from.as_str())
+        .chain_update([0u8])
+        .chain_update(repo_id)
+        .finalize();
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&digest[..16]);
+    Uuid::from_bytes(bytes)
+}
+
+/// Bundle 传输方向
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TransferDirection {
+    Sending,
+    Receiving,
+}
+
+/// Bundle 传输进度事件
+#[derive(Debug, Clone)]
+pub struct BundleProgress {
+    pub repo_id: String,
+    /// 对端节点（发送时为接收方，接收时为发送方）
+    pub peer: NodeId,
+    pub direction: TransferDirection,
+    pub bytes_transferred: u64,
+    pub total_size: u64,
+}
+
+impl BundleProgress {
+    /// 传输完成比例（0.0 ~ 1.0）
+    pub fn fraction(&self) -> f64 {
+        if self.total_size == 0 {
+            return 1.0;
+        }
+        (self.bytes_transferred as f64 / self.total_size as f64).min(1.0)
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.bytes_transferred >= self.total_size
+    }
 }
 
 /// Bundle 文件传输管理器
 pub struct BundleTransferManager {
     connection_manager: Arc<Mutex<ConnectionManager>>,
     storage_dir: PathBuf,
+    /// 发送时是否使用 zstd 压缩数据块；管理器共享之后仍可修改
+    compress: AtomicBool,
+    /// 正在接收的 bundle 传输（transfer_id -> 打开的临时文件及元数据）
+    incoming: Mutex<HashMap<Uuid, IncomingTransfer>>,
+    /// 传输进度订阅者
+    progress_tx: RwLock<Option<mpsc::Sender<BundleProgress>>>,
+    /// 接收中的传输允许的最长空闲时间（毫秒）
+    idle_timeout_ms: AtomicU64,
+    /// 本节点密钥，用于解密发给本节点的加密传输
+    keypair: RwLock<Option<KeyPair>>,
+    /// 是否加密所有发出的 bundle（受限仓库总是加密）
+    encrypt: AtomicBool,
+    /// 已发出、尚未得到回应的 Request（transfer_id -> 请求）；增量 bundle 只接受这些请求的回应
+    requests: Mutex<HashMap<Uuid, PendingRequest>>,
+}
+
+/// 已发出的 bundle 请求
+struct PendingRequest {
+    repo_id: String,
+    peer: NodeId,
+    sent_at: Instant,
+}
+
+impl PendingRequest {
+    fn answered_by(&self, from: &NodeId, repo_id: &str) -> bool {
+        &self.peer == from && self.repo_id == repo_id
+    }
+}
+
+/// 接收中的 bundle 传输选项（来自 Start 消息）
+#[derive(Debug, Clone, Copy)]
+struct TransferOptions {
+    delta: bool,
+    compressed: bool,
+    encrypted: bool,
+    total_size: u64,
+}
+
+/// 接收中的 bundle 传输
+struct IncomingTransfer {
+    repo_id: String,
+    from: NodeId,
+    options: TransferOptions,
+    received: u64,
+    /// 最近一次收到 Start/Chunk 的时间
+    last_activity: Instant,
+    file: fs::File,
+    /// 接收过程中写入的临时文件
+    part_path: PathBuf,
+    /// Done 之后的最终路径
+    final_path: PathBuf,
+}
+
+/// 数据消息的投递路径：直连目标，或经由一个已直连的中继节点
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum DataRoute {
+    Direct,
+    Relay(NodeId),
+}
+
+impl DataRoute {
+    /// 目标已直连时直接发送，否则选择一个已直连、且最近报告与目标直连的中继节点
+    pub(crate) async fn resolve(mgr: &ConnectionManager, target: &NodeId) -> MegaResult<Self> {
+        if mgr.list_peers().await.contains(target) {
+            return Ok(DataRoute::Direct);
+        }
+        match mgr.relays_connected_to(target).await.into_iter().next() {
+            Some(relay) => {
+                debug!("Routing data for {} via relay {}", target, relay);
+                Ok(DataRoute::Relay(relay))
+            }
+            None => Err(MegaError::NotFound(format!(
+                "Connection to node[{}], or a connected relay that reports it",
+                target
+            ))),
+        }
+    }
+
+    pub(crate) async fn send(
+        &self,
+        mgr: &ConnectionManager,
+        target: &NodeId,
+        payload: Vec<u8>,
+    ) -> MegaResult<()> {
+        match self {
+            DataRoute::Direct => mgr.send_data_message(target.clone(), payload).await,
+            DataRoute::Relay(relay) => {
+                mgr.send_relayed_data_message(relay.clone(), target.clone(), payload)
+                    .await
+            }
+        }
+    }
+}
+
+/// 一次 bundle 传输的发送端：直连时 Start/Chunk/Done 按顺序写在一条专用数据流上，
+/// 经中继时仍逐条发送（中继按完整消息转发）
+enum BundleSink {
+    Stream(DataStream),
+    Messages(DataRoute),
+}
+
+impl BundleSink {
+    async fn open(route: DataRoute, mgr: &ConnectionManager, target: &NodeId) -> MegaResult<Self> {
+        match route {
+            DataRoute::Direct => Ok(BundleSink::Stream(
+                mgr.open_data_stream(target.clone()).await?,
+            )),
+            route => Ok(BundleSink::Messages(route)),
+        }
+    }
+
+    async fn send(
+        &mut self,
+        mgr: &ConnectionManager,
+        target: &NodeId,
+        payload: Vec<u8>,
+    ) -> MegaResult<()> {
+        match self {
+            BundleSink::Stream(stream) => stream.send(&payload).await,
+            BundleSink::Messages(route) => route.send(mgr, target, payload).await,
+        }
+    }
+
+    fn finish(self) -> MegaResult<()> {
+        match self {
+            BundleSink::Stream(stream) => stream.finish(),
+            BundleSink::Messages(_) => Ok(()),
+        }
+    }
 }
 
 impl BundleTransferManager {
@@ -51,6 +287,105 @@ impl BundleTransferManager {
         Self {
             connection_manager,
             storage_dir,
+            compress: AtomicBool::new(false),
+            incoming: Mutex::new(HashMap::new()),
+            progress_tx: RwLock::new(None),
+            idle_timeout_ms: AtomicU64::new(DEFAULT_IDLE_TIMEOUT.as_millis() as u64),
+            keypair: RwLock::new(None),
+            encrypt: AtomicBool::new(false),
+            requests: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 设置接收中传输的空闲超时（默认 30 秒）
+    pub fn with_idle_timeout(self, timeout: Duration) -> Self {
+        self.set_idle_timeout(timeout);
+        self
+    }
+
+    pub(crate) fn set_idle_timeout(&self, timeout: Duration) {
+        self.idle_timeout_ms
+            .store(timeout.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    fn idle_timeout(&self) -> Duration {
+        Duration::from_millis(self.idle_timeout_ms.load(Ordering::Relaxed))
+    }
+
+    /// 设置发送时是否使用 zstd 压缩数据块（默认关闭，兼容旧节点）
+    pub fn with_compression(self, enabled: bool) -> Self {
+        self.set_compression(enabled);
+        self
+    }
+
+    /// 修改只影响之后开始的传输
+    pub(crate) fn set_compression(&self, enabled: bool) {
+        self.compress.store(enabled, Ordering::Relaxed);
+    }
+
+    /// 设置本节点密钥，接收加密传输时用于解密
+    pub fn with_keypair(self, keypair: KeyPair) -> Self {
+        self.set_keypair(keypair);
+        self
+    }
+
+    pub(crate) fn set_keypair(&self, keypair: KeyPair) {
+        *self.keypair.write().unwrap_or_else(PoisonError::into_inner) = Some(keypair);
+    }
+
+    fn keypair(&self) -> Option<KeyPair> {
+        self.keypair
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// 设置是否对所有发出的 bundle 做端到端加密（默认只加密有白名单的仓库）
+    ///
+    /// 加密后中继节点只能看到密文，接收方必须支持加密传输
+    pub fn with_encryption(self, enabled: bool) -> Self {
+        self.set_encryption(enabled);
+        self
+    }
+
+    pub(crate) fn set_encryption(&self, enabled: bool) {
+        self.encrypt.store(enabled, Ordering::Relaxed);
+    }
+
+    /// 注册传输进度订阅者，发送和接收 bundle 时都会上报进度
+    pub fn with_progress(self, tx: mpsc::Sender<BundleProgress>) -> Self {
+        self.set_progress(tx);
+        self
+    }
+
+    pub(crate) fn set_progress(&self, tx: mpsc::Sender<BundleProgress>) {
+        *self
+            .progress_tx
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = Some(tx);
+    }
+
+    /// 上报进度；订阅者处理不过来时丢弃事件，不阻塞传输
+    fn report_progress(
+        &self,
+        repo_id: &str,
+        peer: &NodeId,
+        direction: TransferDirection,
+        bytes_transferred: u64,
+        total_size: u64,
+    ) {
+        let progress_tx = self
+            .progress_tx
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some(tx) = progress_tx.as_ref() {
+            let _ = tx.try_send(BundleProgress {
+                repo_id: repo_id.to_string(),
+                peer: peer.clone(),
+                direction,
+                bytes_transferred,
+                total_size,
+            });
         }
     }
 
@@ -70,10 +405,44 @@ impl BundleTransferManager {
         target_node_id: NodeId,
         repo_id: String,
         bundle_path: &str,
-    ) -> Result<()> {
+    ) -> MegaResult<()> {
+        let encrypt = self.encrypt.load(Ordering::Relaxed);
+        self.send_bundle_file(
+            target_node_id,
+            Uuid::new_v4(),
+            repo_id,
+            bundle_path,
+            false,
+            encrypt,
+        )
+        .await
+    }
+
+    /// 发送 bundle 文件，delta 标记是否为增量 bundle，encrypt 时数据块只有目标节点能解密
+    ///
+    /// 回应 Request 时 transfer_id 沿用请求的 ID，接收方据此确认是自己请求的传输
+    async fn send_bundle_file(
+        &self,
+        target_node_id: NodeId,
+        transfer_id: Uuid,
+        repo_id: String,
+        bundle_path: &str,
+        delta: bool,
+        encrypt: bool,
+    ) -> MegaResult<()> {
         // 读取 bundle 文件
         let path = Path::new(bundle_path);
-        let bundle_data = fs::read(path).await.context("Failed to read bundle file")?;
+        let bundle_data = match fs::read(path).await {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(MegaError::NotFound(format!("Bundle file {}", bundle_path)));
+            }
+            Err(e) => {
+                return Err(anyhow::Error::from(e)
+                    .context("Failed to read bundle file")
+                    .into())
+            }
+        };
 
         let file_name = path
             .file_name()
@@ -82,38 +451,63 @@ impl BundleTransferManager {
             .to_string();
 
         let total_size = bundle_data.len() as u64;
+        let recipient = if encrypt {
+            Some(
+                target_node_id
+                    .to_keypair()
+                    .context("Cannot encrypt bundle: invalid recipient NodeId")?,
+            )
+        } else {
+            None
+        };
 
         info!(
-            "Sending bundle {} ({} bytes) to node {}",
-            file_name, total_size, target_node_id
+            "Sending bundle {} ({} bytes) to node {} (transfer {})",
+            file_name, total_size, target_node_id, transfer_id
         );
 
         let mgr = self.connection_manager.lock().await;
+        let route = DataRoute::resolve(&mgr, &target_node_id).await?;
+        let mut sink = BundleSink::open(route, &mgr, &target_node_id).await?;
 
-        // 1. 发送 START 消息
+        // 1. 发送 START 消息；整个传输使用同一个压缩设置
+        let compress = self.compress.load(Ordering::Relaxed);
         let start_msg = BundleMessageType::Start {
+            transfer_id,
             repo_id: repo_id.clone(),
             file_name: file_name.clone(),
             total_size,
+            delta,
+            compressed: compress,
+            encrypted: encrypt,
+            recipient: encrypt.then(|| target_node_id.clone()),
         };
         let start_payload = serde_json::to_vec(&start_msg).context("Failed to serialize START")?;
-        mgr.send_data_message(target_node_id.clone(), start_payload)
-            .await
-            .context("Failed to send START message")?;
+        sink.send(&mgr, &target_node_id, start_payload).await?;
 
         // 2. 分块发送数据
+        let mut bytes_sent: u64 = 0;
         for (chunk_idx, chunk) in bundle_data.chunks(TRANSFER_CHUNK_SIZE).enumerate() {
+            let mut data = if compress {
+                zstd::encode_all(chunk, ZSTD_LEVEL).context("Failed to compress chunk")?
+            } else {
+                chunk.to_vec()
+            };
+            if let Some(recipient) = &recipient {
+                data = recipient
+                    .encrypt_to_node(&recipient.verifying_key, &data)
+                    .context("Failed to encrypt chunk")?;
+            }
             let chunk_msg = BundleMessageType::Chunk {
+                transfer_id,
                 repo_id: repo_id.clone(),
                 chunk_idx: chunk_idx as u32,
-                data: chunk.to_vec(),
+                data,
             };
             let chunk_payload =
                 serde_json::to_vec(&chunk_msg).context("Failed to serialize CHUNK")?;
 
-            mgr.send_data_message(target_node_id.clone(), chunk_payload)
-                .await
-                .context("Failed to send CHUNK message")?;
+            sink.send(&mgr, &target_node_id, chunk_payload).await?;
 
             debug!(
                 "Sent chunk {} ({} bytes) for repo {}",
@@ -121,16 +515,25 @@ impl BundleTransferManager {
                 chunk.len(),
                 repo_id
             );
+
+            bytes_sent += chunk.len() as u64;
+            self.report_progress(
+                &repo_id,
+                &target_node_id,
+                TransferDirection::Sending,
+                bytes_sent,
+                total_size,
+            );
         }
 
         // 3. 发送 DONE 消息
         let done_msg = BundleMessageType::Done {
+            transfer_id,
             repo_id: repo_id.clone(),
         };
         let done_payload = serde_json::to_vec(&done_msg).context("Failed to serialize DONE")?;
-        mgr.send_data_message(target_node_id.clone(), done_payload)
-            .await
-            .context("Failed to send DONE message")?;
+        sink.send(&mgr, &target_node_id, done_payload).await?;
+        sink.finish()?;
 
         info!(
             "Bundle {} sent successfully to node {} ({} chunks)",
@@ -150,29 +553,187 @@ impl BundleTransferManager {
         let msg: BundleMessageType =
             serde_json::from_slice(&data).context("Failed to deserialize bundle message")?;
         match msg {
-            BundleMessageType::Request { repo_id } => {
-                self.handle_bundle_request(&from, &repo_id).await
+            BundleMessageType::Request {
+                transfer_id,
+                repo_id,
+                have,
+            } => {
+                self.handle_bundle_request(&from, transfer_id, &repo_id, &have)
+                    .await
             }
             BundleMessageType::Start {
+                transfer_id,
                 repo_id,
                 file_name,
                 total_size,
+                delta,
+                compressed,
+                encrypted,
+                recipient,
             } => {
-                self.handle_bundle_start(&from, &repo_id, &file_name, total_size)
+                if encrypted && !self.can_decrypt(recipient.as_ref()) {
+                    warn!(
+                        "Ignoring encrypted bundle transfer {} of repo {} from {}: not addressed to this node",
+                        transfer_id, repo_id, from
+                    );
+                    return Ok(());
+                }
+                let options = TransferOptions {
+                    delta,
+                    compressed,
+                    encrypted,
+                    total_size,
+                };
+                // 增量 bundle 会移动本地克隆的 ref，只接受本节点请求过的传输
+                if delta && !self.is_requested(&from, transfer_id, &repo_id).await {
+                    warn!(
+                        "Ignoring unsolicited delta bundle transfer {} of repo {} from {}",
+                        transfer_id, repo_id, from
+                    );
+                    return Ok(());
+                }
+                let transfer_id = legacy_transfer_id(&from, &repo_id, transfer_id);
+                self.handle_bundle_start(&from, transfer_id, &repo_id, &file_name, options)
                     .await
             }
             BundleMessageType::Chunk {
+                transfer_id,
                 repo_id,
                 chunk_idx,
                 data,
             } => {
-                self.handle_bundle_chunk(&from, &repo_id, chunk_idx, data)
+                let transfer_id = legacy_transfer_id(&from, &repo_id, transfer_id);
+                self.handle_bundle_chunk(&from, transfer_id, chunk_idx, data)
                     .await
             }
-            BundleMessageType::Done { repo_id } => self.handle_bundle_done(&from, &repo_id).await,
+            BundleMessageType::Done {
+                transfer_id,
+                repo_id,
+            } => {
+                let transfer_id = legacy_transfer_id(&from, &repo_id, transfer_id);
+                self.handle_bundle_done(&from, transfer_id).await
+            }
+            BundleMessageType::NotFound {
+                transfer_id,
+                repo_id,
+            } => {
+                self.take_request(&from, transfer_id, &repo_id).await;
+                warn!(
+                    "Node {} does not have repo {} (request {})",
+                    from, repo_id, transfer_id
+                );
+                Ok(())
+            }
+            BundleMessageType::NotAuthorized {
+                transfer_id,
+                repo_id,
+            } => {
+                self.take_request(&from, transfer_id, &repo_id).await;
+                warn!(
+                    "Node {} refused to share repo {} with us (request {})",
+                    from, repo_id, transfer_id
+                );
+                Ok(())
+            }
+            BundleMessageType::UpToDate {
+                transfer_id,
+                repo_id,
+            } => {
+                self.take_request(&from, transfer_id, &repo_id).await;
+                info!(
+                    "Repo {} is already up to date with node {} (request {})",
+                    repo_id, from, transfer_id
+                );
+                Ok(())
+            }
+        }
+    }
+
+    /// 加密传输的接收方是否为本节点（需要本节点密钥才能解密）
+    fn can_decrypt(&self, recipient: Option<&NodeId>) -> bool {
+        match (self.keypair(), recipient) {
+            (Some(kp), Some(recipient)) => NodeId::from_keypair(&kp) == *recipient,
+            _ => false,
         }
     }
 
+    /// 向指定节点发送 Request，记录下来以便确认之后收到的回应
+    pub(crate) async fn send_request(
+        &self,
+        target: &NodeId,
+        repo_id: &str,
+        have: Vec<String>,
+    ) -> MegaResult<()> {
+        let transfer_id = Uuid::new_v4();
+        let request = BundleMessageType::Request {
+            transfer_id,
+            repo_id: repo_id.to_string(),
+            have,
+        };
+        let payload = serde_json::to_vec(&request).context("Failed to serialize REQUEST")?;
+
+        self.requests.lock().await.insert(
+            transfer_id,
+            PendingRequest {
+                repo_id: repo_id.to_string(),
+                peer: target.clone(),
+                sent_at: Instant::now(),
+            },
+        );
+        let mgr = self.connection_manager.lock().await;
+        let sent = match DataRoute::resolve(&mgr, target).await {
+            Ok(route) => route.send(&mgr, target, payload).await,
+            Err(e) => Err(e),
+        };
+        if sent.is_err() {
+            self.requests.lock().await.remove(&transfer_id);
+        }
+        sent
+    }
+
+    /// 该传输是否回应本节点发给 `from` 的 Request
+    async fn is_requested(&self, from: &NodeId, transfer_id: Uuid, repo_id: &str) -> bool {
+        self.requests
+            .lock()
+            .await
+            .get(&transfer_id)
+            .is_some_and(|request| request.answered_by(from, repo_id))
+    }
+
+    /// 取出 `from` 回应的 Request，不匹配时返回 None 且保留记录
+    async fn take_request(
+        &self,
+        from: &NodeId,
+        transfer_id: Uuid,
+        repo_id: &str,
+    ) -> Option<PendingRequest> {
+        let mut requests = self.requests.lock().await;
+        if !requests
+            .get(&transfer_id)
+            .is_some_and(|request| request.answered_by(from, repo_id))
+        {
+            return None;
+        }
+        requests.remove(&transfer_id)
+    }
+
+    /// 丢弃超过 `PENDING_REQUEST_TTL` 仍未开始回应的 Request
+    async fn expire_requests(&self) {
+        let incoming = self.incoming.lock().await;
+        let mut requests = self.requests.lock().await;
+        requests.retain(|id, request| {
+            let pending =
+                incoming.contains_key(id) || request.sent_at.elapsed() <= PENDING_REQUEST_TTL;
+            if !pending {
+                debug!(
+                    "Request {} for repo {} to {} got no answer, forgetting it",
+                    id, request.repo_id, request.peer
+                );
+            }
+            pending
+        });
+    }
+
     /// 将 NodeId 编码为合法的目录名（替换非法字符）
     fn encode_node_id(node_id: &NodeId) -> String {
         let id_str = node_id.to_string();
@@ -180,7 +741,15 @@ impl BundleTransferManager {
     }
 
     /// 处理 Request 消息：检查本地 repo 是否存在，如果存在则生成 bundle 并发送
-    async fn handle_bundle_request(&self, from: &NodeId, repo_id: &str) -> Result<()> {
+    ///
+    /// 请求方提供了已有提交时生成增量 bundle，历史分叉时回退为完整 bundle
+    async fn handle_bundle_request(
+        &self,
+        from: &NodeId,
+        request_id: Uuid,
+        repo_id: &str,
+        have: &[String],
+    ) -> Result<()> {
         info!("Received bundle request from {} for repo {}", from, repo_id);
 
         // 检查本地是否有该 repo
@@ -192,13 +761,33 @@ impl BundleTransferManager {
                         "Cannot send bundle for external repo {} to {}",
                         repo_id, from
                     );
-                    return Ok(());
+                    return self.reply_not_found(from, request_id, repo_id).await;
                 }
 
+                if !crate::storage::repo_access::is_allowed(repo_id, from.as_str()).await? {
+                    warn!(
+                        "Refusing bundle request for repo {} from {}: not in the allow-list",
+                        repo_id, from
+                    );
+                    return self.reply_not_authorized(from, request_id, repo_id).await;
+                }
+
+                // 有白名单的仓库视为私有，总是加密给请求方，中继节点只能看到密文
+                let encrypt = self.encrypt.load(Ordering::Relaxed)
+                    || !crate::storage::repo_access::allowed_peers(repo_id)
+                        .await?
+                        .is_empty();
+
                 let repo_path = repo.path.to_string_lossy().to_string();
-                let bundle_dir = self.storage_dir.clone();
-                let bundle_file_name = format!("{}.bundle", get_repo_id_last_part(repo_id));
-                let bundle_path = bundle_dir.join(&bundle_file_name);
+                // 每个请求打包到自己的文件，并发请求同一仓库时互不覆盖，发送后删除
+                let transfer_id = if request_id.is_nil() {
+                    Uuid::new_v4()
+                } else {
+                    request_id
+                };
+                let bundle_file_name =
+                    format!("{}.{}.bundle", get_repo_id_last_part(repo_id), transfer_id);
+                let bundle_path = self.storage_dir.join(&bundle_file_name);
 
                 info!(
                     "Found local repo {} at {}, generating bundle for request from {}",
@@ -208,26 +797,50 @@ impl BundleTransferManager {
                 // 生成 bundle 文件（同步操作，需要在线程中运行）
                 let repo_path_clone = repo_path.clone();
                 let bundle_path_clone = bundle_path.clone();
+                let have = have.to_vec();
 
-                tokio::task::spawn_blocking(move || {
-                    crate::git::pack::pack_repo_bundle(
+                let packed = tokio::task::spawn_blocking(move || {
+                    crate::git::pack::pack_repo_delta_bundle(
                         &repo_path_clone,
                         bundle_path_clone.to_str().unwrap_or(""),
+                        &have,
                     )
                 })
                 .await
-                .context("Failed to spawn bundle packing task")??;
+                .context("Failed to spawn bundle packing task")?;
+                let kind = match packed {
+                    Ok(kind) => kind,
+                    Err(e) => {
+                        let _ = fs::remove_file(&bundle_path).await;
+                        return Err(e);
+                    }
+                };
 
-                info!("Bundle generated successfully for repo {}", repo_id);
+                // 请求方已拥有全部提交：只回复 UpToDate，不发送完整历史
+                if kind == BundleKind::UpToDate {
+                    info!("{} is already up to date with repo {}", from, repo_id);
+                    return self.reply_up_to_date(from, request_id, repo_id).await;
+                }
+                let delta = kind == BundleKind::Delta;
+                info!(
+                    "{} bundle generated successfully for repo {}",
+                    if delta { "Delta" } else { "Full" },
+                    repo_id
+                );
 
                 // 发送 bundle 给请求者
-                self.send_bundle(
-                    from.clone(),
-                    repo_id.to_string(),
-                    bundle_path.to_str().unwrap_or(""),
-                )
-                .await
-                .context("Failed to send bundle in response to request")?;
+                let sent = self
+                    .send_bundle_file(
+                        from.clone(),
+                        transfer_id,
+                        repo_id.to_string(),
+                        bundle_path.to_str().unwrap_or(""),
+                        delta,
+                        encrypt,
+                    )
+                    .await;
+                let _ = fs::remove_file(&bundle_path).await;
+                sent.context("Failed to send bundle in response to request")?;
 
                 info!("Bundle for repo {} sent successfully to {}", repo_id, from);
 
@@ -238,7 +851,7 @@ impl BundleTransferManager {
                     "Received bundle request for non-existent repo {} from {}",
                     repo_id, from
                 );
-                Ok(())
+                self.reply_not_found(from, request_id, repo_id).await
             }
             Err(e) => {
                 warn!(
@@ -250,31 +863,118 @@ impl BundleTransferManager {
         }
     }
 
-    /// 处理 START 消息
+    /// 告知请求方本地没有该仓库
+    async fn reply_not_found(
+        &self,
+        target: &NodeId,
+        request_id: Uuid,
+        repo_id: &str,
+    ) -> Result<()> {
+        let reply = BundleMessageType::NotFound {
+            transfer_id: request_id,
+            repo_id: repo_id.to_string(),
+        };
+        self.send_reply(target, &reply, "NOT_FOUND").await
+    }
+
+    /// 告知请求方无权拉取该仓库
+    async fn reply_not_authorized(
+        &self,
+        target: &NodeId,
+        request_id: Uuid,
+        repo_id: &str,
+    ) -> Result<()> {
+        let reply = BundleMessageType::NotAuthorized {
+            transfer_id: request_id,
+            repo_id: repo_id.to_string(),
+        };
+        self.send_reply(target, &reply, "NOT_AUTHORIZED").await
+    }
+
+    /// 告知请求方已是最新，无需传输
+    async fn reply_up_to_date(
+        &self,
+        target: &NodeId,
+        request_id: Uuid,
+        repo_id: &str,
+    ) -> Result<()> {
+        let reply = BundleMessageType::UpToDate {
+            transfer_id: request_id,
+            repo_id: repo_id.to_string(),
+        };
+        self.send_reply(target, &reply, "UP_TO_DATE").await
+    }
+
+    async fn send_reply(
+        &self,
+        target: &NodeId,
+        reply: &BundleMessageType,
+        kind: &str,
+    ) -> Result<()> {
+        let payload =
+            serde_json::to_vec(reply).with_context(|| format!("Failed to serialize {}", kind))?;
+        let mgr = self.connection_manager.lock().await;
+        DataRoute::resolve(&mgr, target)
+            .await?
+            .send(&mgr, target, payload)
+            .await
+            .with_context(|| format!("Failed to send {} message", kind))
+    }
+
+    /// 处理 START 消息：为该传输创建独立的临时文件
     async fn handle_bundle_start(
         &self,
         from: &NodeId,
+        transfer_id: Uuid,
         repo_id: &str,
         file_name: &str,
-        total_size: u64,
+        options: TransferOptions,
     ) -> Result<()> {
-        let encoded_id = Self::encode_node_id(from);
-        let dir = self.storage_dir.join(&encoded_id);
+        let final_path = self.get_bundle_path(from, &get_repo_id_last_part(repo_id));
+        let dir = final_path
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| self.storage_dir.clone());
         fs::create_dir_all(&dir)
             .await
             .context("Failed to create bundle storage directory")?;
 
-        // 确保文件从头开始：如果存在则清空，如果不存在则创建
-        let encoded_repo_id = get_repo_id_last_part(repo_id);
-        let file_path = dir.join(format!("{}.bundle", encoded_repo_id));
-
-        let _ = fs::File::create(&file_path)
+        // 每个传输写入自己的 .part 文件，并发传输同一仓库时互不干扰
+        let part_path = dir.join(format!(
+            "{}.{}.part",
+            get_repo_id_last_part(repo_id),
+            transfer_id
+        ));
+        let file = fs::File::create(&part_path)
             .await
-            .context("Failed to create/truncate bundle file")?;
+            .context("Failed to create bundle part file")?;
+
+        let previous = self.incoming.lock().await.insert(
+            transfer_id,
+            IncomingTransfer {
+                repo_id: repo_id.to_string(),
+                from: from.clone(),
+                options,
+                received: 0,
+                last_activity: Instant::now(),
+                file,
+                part_path,
+                final_path,
+            },
+        );
+        if let Some(previous) = previous {
+            let _ = fs::remove_file(&previous.part_path).await;
+        }
 
         info!(
-            "Bundle transfer START from {}: repo={}, file={}, size={} bytes",
-            from, repo_id, file_name, total_size
+            "Bundle transfer START from {}: transfer={}, repo={}, file={}, size={} bytes, delta={}, compressed={}",
+            from,
+            transfer_id,
+            repo_id,
+            file_name,
+            options.total_size,
+            options.delta,
+            options.compressed
         );
 
         Ok(())
@@ -284,82 +984,322 @@ impl BundleTransferManager {
     async fn handle_bundle_chunk(
         &self,
         from: &NodeId,
-        repo_id: &str,
+        transfer_id: Uuid,
         chunk_idx: u32,
         data: Vec<u8>,
     ) -> Result<()> {
-        let encoded_id = Self::encode_node_id(from);
-        let dir = self.storage_dir.join(&encoded_id);
-        let encoded_repo_id = get_repo_id_last_part(repo_id);
-        let file_path = dir.join(format!("{}.bundle", encoded_repo_id));
-
-        // 如果文件不存在（可能是 Start 消息丢失），先创建
-        if !file_path.exists() {
-            if let Some(parent) = file_path.parent() {
-                fs::create_dir_all(parent).await?;
+        let mut incoming = self.incoming.lock().await;
+        let transfer = match incoming.get_mut(&transfer_id) {
+            Some(t) if &t.from == from => t,
+            _ => {
+                debug!(
+                    "Ignoring chunk {} of unknown transfer {} from {}",
+                    chunk_idx, transfer_id, from
+                );
+                return Ok(());
             }
-        }
-
-        // 使用 Write 模式打开，不追加，而是使用 Seek
-        let mut file = fs::OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(false)
-            .open(&file_path)
-            .await
-            .context("Failed to open bundle file")?;
+        };
 
+        let data = if transfer.options.encrypted {
+            // Start 时已确认本节点有密钥且是接收方
+            let kp = self
+                .keypair()
+                .context("No keypair to decrypt bundle chunk")?;
+            kp.decrypt_message(&data)
+                .context("Failed to decrypt chunk")?
+        } else {
+            data
+        };
+        // 解压输出不超过一个数据块，防止小数据块解压出巨大的内容耗尽内存
+        let data = if transfer.options.compressed {
+            zstd::bulk::decompress(&data, TRANSFER_CHUNK_SIZE)
+        } else {
+            Ok(data)
+        };
         let offset = (chunk_idx as u64) * (TRANSFER_CHUNK_SIZE as u64);
-        file.seek(SeekFrom::Start(offset))
+        let data = match data {
+            Ok(data)
+                if data.len() <= TRANSFER_CHUNK_SIZE
+                    && offset + data.len() as u64 <= transfer.options.total_size =>
+            {
+                data
+            }
+            invalid => {
+                let reason = match invalid {
+                    Err(e) => format!("failed to decompress: {}", e),
+                    Ok(data) => format!(
+                        "{} bytes at offset {} exceed the chunk size or the {} byte bundle",
+                        data.len(),
+                        offset,
+                        transfer.options.total_size
+                    ),
+                };
+                let transfer = incoming
+                    .remove(&transfer_id)
+                    .expect("transfer checked above");
+                drop(transfer.file);
+                let _ = fs::remove_file(&transfer.part_path).await;
+                return Err(anyhow::anyhow!(
+                    "Aborted bundle transfer {} of repo {} from {}: chunk {} {}",
+                    transfer_id,
+                    transfer.repo_id,
+                    from,
+                    chunk_idx,
+                    reason
+                ));
+            }
+        };
+
+        transfer
+            .file
+            .seek(SeekFrom::Start(offset))
             .await
             .context("Failed to seek to chunk position")?;
-
-        file.write_all(&data)
+        transfer
+            .file
+            .write_all(&data)
             .await
             .context("Failed to write chunk data")?;
+        // tokio 的 File 在 drop 时不保证写入完成，需要显式 flush
+        transfer
+            .file
+            .flush()
+            .await
+            .context("Failed to flush chunk data")?;
 
-        info!(
-            "Received chunk {} (offset {}) ({} bytes) for repo {} from {}",
+        transfer.last_activity = Instant::now();
+        transfer.received =
+            (transfer.received + data.len() as u64).min(transfer.options.total_size);
+        self.report_progress(
+            &transfer.repo_id,
+            from,
+            TransferDirection::Receiving,
+            transfer.received,
+            transfer.options.total_size,
+        );
+
+        debug!(
+            "Received chunk {} (offset {}) ({} bytes) for transfer {} from {}",
             chunk_idx,
             offset,
             data.len(),
-            repo_id,
+            transfer_id,
             from
         );
 
         Ok(())
     }
 
-    /// 处理 DONE 消息
-    async fn handle_bundle_done(&self, from: &NodeId, repo_id: &str) -> Result<()> {
-        let encoded_id = Self::encode_node_id(from);
-        let dir = self.storage_dir.join(&encoded_id);
-        let encoded_repo_id = get_repo_id_last_part(repo_id);
-        let file_path = dir.join(format!("{}.bundle", encoded_repo_id));
+    /// 处理 DONE 消息：将临时文件落到最终路径
+    ///
+    /// 同一仓库的其它未完成传输会被取消，先完成的来源胜出
+    async fn handle_bundle_done(&self, from: &NodeId, transfer_id: Uuid) -> Result<()> {
+        let transfer = {
+            let mut incoming = self.incoming.lock().await;
+            match incoming.get(&transfer_id) {
+                Some(t) if &t.from == from => {}
+                _ => {
+                    warn!(
+                        "Bundle transfer DONE received for unknown transfer {} from {}",
+                        transfer_id, from
+                    );
+                    return Ok(());
+                }
+            }
+            let transfer = incoming
+                .remove(&transfer_id)
+                .expect("transfer checked above");
 
-        if file_path.exists() {
-            let metadata = fs::metadata(&file_path)
-                .await
-                .context("Failed to get bundle file metadata")?;
-            // 标记 bundle 已接收
-            let bundle_path = file_path.to_string_lossy().to_string();
-            repo_model::update_repo_bundle(repo_id, &bundle_path).await?;
-            info!(
-                "Bundle transfer completed from {}: repo={}, file_size={} bytes",
-                from,
-                repo_id,
-                metadata.len()
-            );
-        } else {
-            warn!(
-                "Bundle transfer DONE message received but file not found for repo {} from {}",
-                repo_id, from
+            let superseded: Vec<Uuid> = incoming
+                .iter()
+                .filter(|(_, t)| t.repo_id == transfer.repo_id)
+                .map(|(id, _)| *id)
+                .collect();
+            for id in superseded {
+                if let Some(other) = incoming.remove(&id) {
+                    info!(
+                        "Cancelling transfer {} of repo {} from {}: superseded by {}",
+                        id, other.repo_id, other.from, transfer_id
+                    );
+                    let _ = fs::remove_file(&other.part_path).await;
+                }
+            }
+            transfer
+        };
+
+        let IncomingTransfer {
+            repo_id,
+            options,
+            file,
+            part_path,
+            final_path,
+            ..
+        } = transfer;
+        drop(file);
+        let requested = self.take_request(from, transfer_id, &repo_id).await;
+
+        // 增量 bundle 不能单独使用：单独存放并应用到本地克隆，再由更新后的克隆重新打包完整 bundle，
+        // 使 bundle 记录的 ref 与克隆一致；应用失败时改为请求完整 bundle
+        if options.delta {
+            // 只把本节点请求过的增量应用到从其他节点拉取的仓库，本节点自己的仓库不接受外来的 ref
+            let external = matches!(
+                repo_model::load_repo_from_db(&repo_id).await,
+                Ok(Some(repo)) if repo.is_external
             );
+            if requested.is_none() || !external {
+                warn!(
+                    "Dropping delta bundle transfer {} of repo {} from {}: not a requested update of an external repo",
+                    transfer_id, repo_id, from
+                );
+                let _ = fs::remove_file(&part_path).await;
+                return Ok(());
+            }
+            let delta_path = part_path.with_extension("delta.bundle");
+            fs::rename(&part_path, &delta_path)
+                .await
+                .context("Failed to finalize delta bundle file")?;
+            let applied = self
+                .apply_delta_bundle(&repo_id, &delta_path, &final_path)
+                .await;
+            let _ = fs::remove_file(&delta_path).await;
+            match applied {
+                Ok(updates) => info!(
+                    "Delta bundle applied from {}: transfer={}, repo={}, {} refs updated",
+                    from,
+                    transfer_id,
+                    repo_id,
+                    updates.len()
+                ),
+                Err(e) => {
+                    warn!(
+                        "Failed to apply delta bundle for repo {} from {}: {}, requesting full bundle",
+                        repo_id, from, e
+                    );
+                    self.request_full_bundle(from, &repo_id).await?;
+                }
+            }
+            return Ok(());
         }
 
+        fs::rename(&part_path, &final_path)
+            .await
+            .context("Failed to finalize bundle file")?;
+        let bundle_path = final_path.to_string_lossy().to_string();
+
+        // 标记 bundle 已接收
+        repo_model::update_repo_bundle(&repo_id, &bundle_path).await?;
+        info!(
+            "Bundle transfer completed from {}: transfer={}, repo={}, file_size={} bytes",
+            from, transfer_id, repo_id, options.total_size
+        );
+
         Ok(())
     }
 
+    /// 中止空闲超时的传输并删除其临时文件，返回中止的数量
+    pub async fn abort_stalled_transfers(&self) -> usize {
+        let idle_timeout = self.idle_timeout();
+        let mut incoming = self.incoming.lock().await;
+        let stalled: Vec<Uuid> = incoming
+            .iter()
+            .filter(|(_, t)| t.last_activity.elapsed() > idle_timeout)
+            .map(|(id, _)| *id)
+            .collect();
+
+        for id in &stalled {
+            if let Some(transfer) = incoming.remove(id) {
+                warn!(
+                    "Aborting stalled bundle transfer {} of repo {} from {}: no data for {:?} ({}/{} bytes received)",
+                    id,
+                    transfer.repo_id,
+                    transfer.from,
+                    idle_timeout,
+                    transfer.received,
+                    transfer.options.total_size
+                );
+                drop(transfer.file);
+                let _ = fs::remove_file(&transfer.part_path).await;
+            }
+        }
+        stalled.len()
+    }
+
+    /// 定期检查并中止空闲超时的传输，直到 shutdown 被触发
+    ///
+    /// 检查间隔为空闲超时的一半，每轮重新读取，运行中修改超时也会生效
+    pub async fn run_stall_watchdog(self: Arc<Self>, shutdown: CancellationToken) {
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => break,
+                _ = tokio::time::sleep(self.idle_timeout().div_f32(2.0)) => {}
+            }
+            self.abort_stalled_transfers().await;
+            self.expire_requests().await;
+        }
+    }
+
+    /// 将增量 bundle 应用到仓库的本地克隆并移动其 ref，返回 ref 的更新
+    ///
+    /// 有 ref 更新时由克隆重新打包完整 bundle 写到 `full_bundle_path` 并更新仓库记录
+    async fn apply_delta_bundle(
+        &self,
+        repo_id: &str,
+        bundle_path: &Path,
+        full_bundle_path: &Path,
+    ) -> Result<Vec<(String, String, String)>> {
+        let repo = repo_model::load_repo_from_db(repo_id)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("repo {} not found", repo_id))?;
+        if repo.path.as_os_str().is_empty() {
+            return Err(anyhow::anyhow!("repo {} has no local clone", repo_id));
+        }
+
+        let repo_path = repo.path.to_string_lossy().to_string();
+        let bundle_path = bundle_path.to_string_lossy().to_string();
+        let full_path = full_bundle_path.to_path_buf();
+        let (updates, refreshed) = tokio::task::spawn_blocking(move || {
+            let updates = crate::git::git_repo::unbundle_into(&repo_path, &bundle_path)?;
+            if updates.is_empty() {
+                return Ok::<_, anyhow::Error>((updates, Ok(false)));
+            }
+            // 先写临时文件再替换，打包失败时原来的完整 bundle 仍然可用
+            let tmp_path = full_path.with_extension("bundle.tmp");
+            let refreshed =
+                crate::git::pack::pack_repo_bundle(&repo_path, &tmp_path.to_string_lossy())
+                    .and_then(|()| {
+                        std::fs::rename(&tmp_path, &full_path)
+                            .context("Failed to replace full bundle")
+                    })
+                    .map(|()| true);
+            if refreshed.is_err() {
+                let _ = std::fs::remove_file(&tmp_path);
+            }
+            Ok((updates, refreshed))
+        })
+        .await
+        .context("Failed to spawn bundle unbundle task")??;
+
+        // 增量已经应用，重新打包失败只影响同步状态的显示，不再请求完整 bundle
+        match refreshed {
+            Ok(true) => {
+                repo_model::update_repo_bundle(repo_id, &full_bundle_path.to_string_lossy()).await?
+            }
+            Ok(false) => {}
+            Err(e) => warn!(
+                "Failed to refresh the full bundle of repo {} after a delta: {}",
+                repo_id, e
+            ),
+        }
+        Ok(updates)
+    }
+
+    /// 向提供方请求完整 bundle（不携带已有提交）
+    async fn request_full_bundle(&self, target: &NodeId, repo_id: &str) -> Result<()> {
+        self.send_request(target, repo_id, Vec::new())
+            .await
+            .context("Failed to send REQUEST message")
+    }
+
     /// 获取从指定节点接收的 bundle 文件路径
     pub fn get_bundle_path(&self, from: &NodeId, repo_id: &str) -> PathBuf {
         let encoded_id = Self::encode_node_id(from);
@@ -375,10 +1315,17 @@ mod tests {
 
     #[test]
     fn test_bundle_message_serialization() {
+        let id = Uuid::new_v4();
+        let node = NodeId::from_keypair(&KeyPair::generate().unwrap());
         let msg = BundleMessageType::Start {
+            transfer_id: id,
             repo_id: "repo123".to_string(),
             file_name: "repo.bundle".to_string(),
             total_size: 1024,
+            delta: true,
+            compressed: true,
+            encrypted: true,
+            recipient: Some(node.clone()),
         };
 
         let serialized = serde_json::to_vec(&msg).unwrap();
@@ -386,15 +1333,801 @@ mod tests {
 
         match deserialized {
             BundleMessageType::Start {
+                transfer_id,
                 repo_id,
                 file_name,
                 total_size,
+                delta,
+                compressed,
+                encrypted,
+                recipient,
             } => {
+                assert_eq!(transfer_id, id);
                 assert_eq!(repo_id, "repo123");
                 assert_eq!(file_name, "repo.bundle");
                 assert_eq!(total_size, 1024);
+                assert!(delta);
+                assert!(compressed);
+                assert!(encrypted);
+                assert_eq!(recipient, Some(node));
             }
             _ => panic!("Wrong message type"),
         }
     }
+
+    #[test]
+    fn test_not_found_serialization() {
+        let id = Uuid::new_v4();
+        let payload = serde_json::to_vec(&BundleMessageType::NotFound {
+            transfer_id: id,
+            repo_id: "repo123".to_string(),
+        })
+        .unwrap();
+        match serde_json::from_slice(&payload).unwrap() {
+            BundleMessageType::NotFound {
+                transfer_id,
+                repo_id,
+            } => {
+                assert_eq!(transfer_id, id);
+                assert_eq!(repo_id, "repo123");
+            }
+            other => panic!("unexpected message: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_legacy_request_defaults_to_full_bundle() {
+        let msg: BundleMessageType =
+            serde_json::from_str(r#"{"Request":{"repo_id":"repo123"}}"#).unwrap();
+        match msg {
+            BundleMessageType::Request {
+                transfer_id,
+                repo_id,
+                have,
+            } => {
+                assert!(transfer_id.is_nil());
+                assert_eq!(repo_id, "repo123");
+                assert!(have.is_empty());
+            }
+            _ => panic!("Wrong message type"),
+        }
+    }
+
+    async fn test_manager(name: &str) -> (BundleTransferManager, PathBuf) {
+        let _ = rustls::crypto::ring::default_provider().install_default();
+        let dir = std::env::temp_dir().join(format!("megaengine-{}-{}", name, Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = |f: &str| dir.join(f).to_string_lossy().to_string();
+        crate::transport::cert::ensure_certificates(
+            &path("cert.pem"),
+            &path("key.pem"),
+            &path("ca-cert.pem"),
+        )
+        .unwrap();
+        let config = crate::transport::config::QuicConfig::new(
+            "127.0.0.1:0".parse().unwrap(),
+            path("cert.pem"),
+            path("key.pem"),
+            path("ca-cert.pem"),
+        );
+        let conn_mgr = ConnectionManager::run_server(config).await.unwrap();
+        let manager =
+            BundleTransferManager::new(Arc::new(Mutex::new(conn_mgr)), dir.join("bundles"));
+        (manager, dir)
+    }
+
+    #[tokio::test]
+    async fn test_stalled_transfer_is_aborted() {
+        let (manager, dir) = test_manager("stalled-transfer").await;
+        let manager = Arc::new(manager.with_idle_timeout(Duration::from_millis(200)));
+        let shutdown = CancellationToken::new();
+        let watchdog = tokio::spawn(Arc::clone(&manager).run_stall_watchdog(shutdown.clone()));
+
+        let peer = NodeId::from_keypair(&crate::identity::keypair::KeyPair::generate().unwrap());
+        let transfer_id = Uuid::new_v4();
+        let start = BundleMessageType::Start {
+            transfer_id,
+            repo_id: "did:repo:stalled-transfer-test".to_string(),
+            file_name: "repo.bundle".to_string(),
+            total_size: (TRANSFER_CHUNK_SIZE * 2) as u64,
+            delta: false,
+            compressed: false,
+            encrypted: false,
+            recipient: None,
+        };
+        manager
+            .handle_bundle_message(peer.clone(), encode(start))
+            .await
+            .unwrap();
+        let chunk = BundleMessageType::Chunk {
+            transfer_id,
+            repo_id: "did:repo:stalled-transfer-test".to_string(),
+            chunk_idx: 0,
+            data: vec![0u8; TRANSFER_CHUNK_SIZE],
+        };
+        manager
+            .handle_bundle_message(peer.clone(), encode(chunk))
+            .await
+            .unwrap();
+
+        let part_path = manager.incoming.lock().await[&transfer_id]
+            .part_path
+            .clone();
+        assert!(part_path.exists());
+
+        // 发送方不再发送任何数据，看门狗应在超时后清理
+        tokio::time::sleep(Duration::from_millis(600)).await;
+        assert!(manager.incoming.lock().await.is_empty());
+        assert!(!part_path.exists());
+
+        shutdown.cancel();
+        watchdog.await.unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_chunks_abort_transfer() {
+        let (manager, dir) = test_manager("oversized-chunks").await;
+        let peer = NodeId::from_keypair(&KeyPair::generate().unwrap());
+        let repo_id = "did:repo:oversized-chunk-test";
+        let start = |transfer_id, compressed| BundleMessageType::Start {
+            transfer_id,
+            repo_id: repo_id.to_string(),
+            file_name: "repo.bundle".to_string(),
+            total_size: 100,
+            delta: false,
+            compressed,
+            encrypted: false,
+            recipient: None,
+        };
+        let chunk = |transfer_id, chunk_idx, data| BundleMessageType::Chunk {
+            transfer_id,
+            repo_id: repo_id.to_string(),
+            chunk_idx,
+            data,
+        };
+
+        // 很小的压缩块解压后远大于一个数据块
+        let bomb = zstd::encode_all(&vec![0u8; 16 * TRANSFER_CHUNK_SIZE][..], ZSTD_LEVEL).unwrap();
+        assert!(bomb.len() < TRANSFER_CHUNK_SIZE);
+        let compressed = Uuid::new_v4();
+        // 数据块写到声明的大小之外
+        let beyond = Uuid::new_v4();
+        for (transfer_id, is_compressed, msg) in [
+            (compressed, true, chunk(compressed, 0, bomb)),
+            (beyond, false, chunk(beyond, 1, vec![1u8; 10])),
+        ] {
+            manager
+                .handle_bundle_message(peer.clone(), encode(start(transfer_id, is_compressed)))
+                .await
+                .unwrap();
+            assert!(manager
+                .handle_bundle_message(peer.clone(), encode(msg))
+                .await
+                .is_err());
+            assert!(!manager.incoming.lock().await.contains_key(&transfer_id));
+        }
+
+        let bundle_dir = manager
+            .get_bundle_path(&peer, &get_repo_id_last_part(repo_id))
+            .parent()
+            .unwrap()
+            .to_path_buf();
+        assert_eq!(std::fs::read_dir(&bundle_dir).unwrap().count(), 0);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    /// 两个已连接的节点：(提供方, 请求方, 请求方收到的数据消息)
+    async fn connected_pair() -> (
+        (ConnectionManager, NodeId),
+        (ConnectionManager, NodeId),
+        mpsc::Receiver<(NodeId, Vec<u8>)>,
+    ) {
+        use crate::transport::config::QuicConfig;
+
+        let _ = rustls::crypto::ring::default_provider().install_default();
+        let start = |kp: &KeyPair| {
+            let config = QuicConfig::new(
+                "127.0.0.1:0".parse().unwrap(),
+                String::new(),
+                String::new(),
+                String::new(),
+            )
+            .with_identity(kp.clone())
+            .with_peer_verification(true);
+            ConnectionManager::run_server(config)
+        };
+        let kp_owner = KeyPair::generate().unwrap();
+        let kp_peer = KeyPair::generate().unwrap();
+        let owner_id = NodeId::from_keypair(&kp_owner);
+        let peer_id = NodeId::from_keypair(&kp_peer);
+        let owner_mgr = start(&kp_owner).await.unwrap();
+        let peer_mgr = start(&kp_peer).await.unwrap();
+        let (tx, rx) = mpsc::channel(64);
+        peer_mgr.register_data_sender(tx).await;
+        owner_mgr
+            .connect(owner_id.clone(), peer_id.clone(), peer_mgr.local_addrs())
+            .await
+            .unwrap();
+        ((owner_mgr, owner_id), (peer_mgr, peer_id), rx)
+    }
+
+    #[tokio::test]
+    async fn test_request_outside_allow_list_is_refused() {
+        use crate::repo::repo::{P2PDescription, Repo};
+
+        let ((owner_mgr, owner_id), (peer_mgr, peer_id), mut rx) = connected_pair().await;
+
+        let dir = std::env::temp_dir().join(format!("megaengine-access-{}", Uuid::new_v4()));
+        let manager =
+            BundleTransferManager::new(Arc::new(Mutex::new(owner_mgr.clone())), dir.clone());
+        let repo_id = "did:repo:access-refused-test";
+
+        crate::storage::with_test_db(async {
+            let desc = P2PDescription {
+                creator: owner_id.to_string(),
+                name: "private".to_string(),
+                description: String::new(),
+                language: String::new(),
+                latest_commit_at: 0,
+                size: 0,
+                tags: Vec::new(),
+                commit_count: 0,
+                contributors: 0,
+            };
+            let repo = Repo::new(repo_id.to_string(), desc, dir.join("private"));
+            repo_model::save_repo_to_db(&repo).await.unwrap();
+            crate::storage::repo_access::allow_peer(repo_id, "did:key:someone-else")
+                .await
+                .unwrap();
+
+            let transfer_id = Uuid::new_v4();
+            let request = BundleMessageType::Request {
+                transfer_id,
+                repo_id: repo_id.to_string(),
+                have: Vec::new(),
+            };
+            manager
+                .handle_bundle_message(peer_id.clone(), encode(request))
+                .await
+                .unwrap();
+
+            let (from, data) = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(from, owner_id);
+            match serde_json::from_slice(&data).unwrap() {
+                BundleMessageType::NotAuthorized {
+                    transfer_id: id,
+                    repo_id: replied_repo,
+                } => {
+                    assert_eq!(id, transfer_id);
+                    assert_eq!(replied_repo, repo_id);
+                }
+                other => panic!("unexpected reply: {:?}", other),
+            }
+        })
+        .await;
+
+        owner_mgr.shutdown().await;
+        peer_mgr.shutdown().await;
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    /// 在 `dir/name` 下创建只有一个提交的仓库，返回仓库路径
+    fn init_test_repo(dir: &Path, name: &str) -> PathBuf {
+        let path = dir.join(name);
+        std::fs::create_dir_all(&path).unwrap();
+        for args in [
+            &["init", "-q", "-b", "main"][..],
+            &["config", "user.email", "test@example.com"],
+            &["config", "user.name", "Test User"],
+            &["commit", "-q", "--allow-empty", "-m", "first"],
+        ] {
+            let status = std::process::Command::new("git")
+                .current_dir(&path)
+                .args(args)
+                .status()
+                .unwrap();
+            assert!(status.success(), "git {:?} failed", args);
+        }
+        path
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_requests_use_separate_bundle_files() {
+        use crate::repo::repo::{P2PDescription, Repo};
+
+        let ((owner_mgr, owner_id), (peer_mgr, peer_id), mut rx) = connected_pair().await;
+        let dir = std::env::temp_dir().join(format!("megaengine-serve-{}", Uuid::new_v4()));
+        let storage_dir = dir.join("bundles");
+        let manager = BundleTransferManager::new(
+            Arc::new(Mutex::new(owner_mgr.clone())),
+            storage_dir.clone(),
+        );
+        let repo_id = "did:repo:concurrent-requests-test";
+        let repo_path = init_test_repo(&dir, "src");
+
+        crate::storage::with_test_db(async {
+            let desc = P2PDescription {
+                creator: owner_id.to_string(),
+                name: "served".to_string(),
+                description: String::new(),
+                language: String::new(),
+                latest_commit_at: 0,
+                size: 0,
+                tags: Vec::new(),
+                commit_count: 0,
+                contributors: 0,
+            };
+            repo_model::save_repo_to_db(&Repo::new(repo_id.to_string(), desc, repo_path))
+                .await
+                .unwrap();
+
+            let ids = [Uuid::new_v4(), Uuid::new_v4()];
+            let request = |transfer_id| {
+                encode(BundleMessageType::Request {
+                    transfer_id,
+                    repo_id: repo_id.to_string(),
+                    have: Vec::new(),
+                })
+            };
+            let (first, second) = tokio::join!(
+                manager.handle_bundle_message(peer_id.clone(), request(ids[0])),
+                manager.handle_bundle_message(peer_id.clone(), request(ids[1])),
+            );
+            first.unwrap();
+            second.unwrap();
+
+            // 每个回应沿用对应 Request 的 transfer_id
+            let mut done = Vec::new();
+            while done.len() < 2 {
+                let (_, data) = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+                    .await
+                    .unwrap()
+                    .unwrap();
+                if let BundleMessageType::Done { transfer_id, .. } =
+                    serde_json::from_slice(&data).unwrap()
+                {
+                    done.push(transfer_id);
+                }
+            }
+            done.sort();
+            let mut ids = ids.to_vec();
+            ids.sort();
+            assert_eq!(done, ids);
+        })
+        .await;
+
+        // 发送完成后不留下打包的文件
+        let leftovers = std::fs::read_dir(&storage_dir).unwrap().count();
+        assert_eq!(leftovers, 0);
+
+        owner_mgr.shutdown().await;
+        peer_mgr.shutdown().await;
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_transfer_only_decrypted_by_recipient() {
+        crate::storage::with_test_db(async {
+            let kp = KeyPair::generate().unwrap();
+            let me = NodeId::from_keypair(&kp);
+            let (manager, dir) = test_manager("encrypted-transfer").await;
+            let manager = manager.with_keypair(kp.clone());
+            let peer = NodeId::from_keypair(&KeyPair::generate().unwrap());
+            let repo_id = "did:repo:encrypted-transfer-test";
+            let plain = vec![7u8; TRANSFER_CHUNK_SIZE + 10];
+
+            // 发送方的处理：先压缩后加密，每个块独立加密
+            let recipient = me.to_keypair().unwrap();
+            let encrypt = |chunk: &[u8]| {
+                let compressed = zstd::encode_all(chunk, ZSTD_LEVEL).unwrap();
+                recipient
+                    .encrypt_to_node(&recipient.verifying_key, &compressed)
+                    .unwrap()
+            };
+
+            // 加密给其他节点的传输被忽略
+            let stranger = BundleMessageType::Start {
+                transfer_id: Uuid::new_v4(),
+                repo_id: repo_id.to_string(),
+                file_name: "repo.bundle".to_string(),
+                total_size: plain.len() as u64,
+                delta: false,
+                compressed: true,
+                encrypted: true,
+                recipient: Some(peer.clone()),
+            };
+            manager
+                .handle_bundle_message(peer.clone(), encode(stranger))
+                .await
+                .unwrap();
+            assert!(manager.incoming.lock().await.is_empty());
+
+            let transfer_id = Uuid::new_v4();
+            let start = BundleMessageType::Start {
+                transfer_id,
+                repo_id: repo_id.to_string(),
+                file_name: "repo.bundle".to_string(),
+                total_size: plain.len() as u64,
+                delta: false,
+                compressed: true,
+                encrypted: true,
+                recipient: Some(me.clone()),
+            };
+            manager
+                .handle_bundle_message(peer.clone(), encode(start))
+                .await
+                .unwrap();
+            for (idx, chunk) in plain.chunks(TRANSFER_CHUNK_SIZE).enumerate() {
+                let msg = BundleMessageType::Chunk {
+                    transfer_id,
+                    repo_id: repo_id.to_string(),
+                    chunk_idx: idx as u32,
+                    data: encrypt(chunk),
+                };
+                manager
+                    .handle_bundle_message(peer.clone(), encode(msg))
+                    .await
+                    .unwrap();
+            }
+            let done = BundleMessageType::Done {
+                transfer_id,
+                repo_id: repo_id.to_string(),
+            };
+            manager
+                .handle_bundle_message(peer.clone(), encode(done))
+                .await
+                .unwrap();
+
+            let final_path = manager.get_bundle_path(&peer, &get_repo_id_last_part(repo_id));
+            assert_eq!(std::fs::read(&final_path).unwrap(), plain);
+
+            let _ = std::fs::remove_dir_all(&dir);
+        })
+        .await;
+    }
+
+    fn encode(msg: BundleMessageType) -> Vec<u8> {
+        serde_json::to_vec(&msg).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_options_apply_to_shared_manager() {
+        let (manager, dir) = test_manager("shared-options").await;
+        let manager = Arc::new(manager);
+        let _shared = Arc::clone(&manager);
+
+        manager.set_compression(true);
+        assert!(manager.compress.load(Ordering::Relaxed));
+
+        let (tx, mut rx) = mpsc::channel(1);
+        manager.set_progress(tx);
+        let peer = NodeId::from_keypair(&KeyPair::generate().unwrap());
+        manager.report_progress("did:repo:shared", &peer, TransferDirection::Sending, 1, 2);
+        assert_eq!(rx.try_recv().unwrap().bytes_transferred, 1);
+
+        manager.set_idle_timeout(Duration::from_millis(1500));
+        assert_eq!(manager.idle_timeout(), Duration::from_millis(1500));
+
+        let kp = KeyPair::generate().unwrap();
+        manager.set_keypair(kp.clone());
+        assert!(manager.can_decrypt(Some(&NodeId::from_keypair(&kp))));
+        manager.set_encryption(true);
+        assert!(manager.encrypt.load(Ordering::Relaxed));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    /// 以一个数据块发送增量 bundle 的 Start/Chunk/Done
+    async fn deliver_delta(
+        manager: &BundleTransferManager,
+        from: &NodeId,
+        transfer_id: Uuid,
+        repo_id: &str,
+        data: &[u8],
+    ) {
+        let messages = [
+            BundleMessageType::Start {
+                transfer_id,
+                repo_id: repo_id.to_string(),
+                file_name: "repo.bundle".to_string(),
+                total_size: data.len() as u64,
+                delta: true,
+                compressed: false,
+                encrypted: false,
+                recipient: None,
+            },
+            BundleMessageType::Chunk {
+                transfer_id,
+                repo_id: repo_id.to_string(),
+                chunk_idx: 0,
+                data: data.to_vec(),
+            },
+            BundleMessageType::Done {
+                transfer_id,
+                repo_id: repo_id.to_string(),
+            },
+        ];
+        for msg in messages {
+            manager
+                .handle_bundle_message(from.clone(), encode(msg))
+                .await
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_delta_bundle_moves_refs_and_refreshes_full_bundle() {
+        use crate::repo::repo::{P2PDescription, Repo};
+        use std::process::Command;
+
+        let git = |cwd: &Path, args: &[&str]| {
+            let output = Command::new("git")
+                .current_dir(cwd)
+                .args(args)
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "git {:?} failed", args);
+            String::from_utf8_lossy(&output.stdout).trim().to_string()
+        };
+
+        let (manager, dir) = test_manager("delta-transfer").await;
+        let peer = NodeId::from_keypair(&KeyPair::generate().unwrap());
+        let repo_id = format!("did:repo:delta-transfer-{}", Uuid::new_v4());
+        let src = dir.join("src");
+        let dst = dir.join("dst");
+        std::fs::create_dir_all(&src).unwrap();
+        git(&src, &["init", "-q", "-b", "main"]);
+        git(&src, &["config", "user.email", "test@example.com"]);
+        git(&src, &["config", "user.name", "Test User"]);
+        std::fs::write(src.join("a.txt"), "a\n").unwrap();
+        git(&src, &["add", "."]);
+        git(&src, &["commit", "-q", "-m", "first"]);
+        let full = dir.join("full.bundle");
+        crate::git::pack::pack_repo_bundle(src.to_str().unwrap(), full.to_str().unwrap()).unwrap();
+        git(
+            &dir,
+            &["clone", "-q", src.to_str().unwrap(), dst.to_str().unwrap()],
+        );
+
+        std::fs::write(src.join("b.txt"), "b\n").unwrap();
+        git(&src, &["add", "."]);
+        git(&src, &["commit", "-q", "-m", "second"]);
+        let new_head = git(&src, &["rev-parse", "HEAD"]);
+        let delta = dir.join("delta.bundle");
+        let have = vec![git(&dst, &["rev-parse", "HEAD"])];
+        assert_eq!(
+            crate::git::pack::pack_repo_delta_bundle(
+                src.to_str().unwrap(),
+                delta.to_str().unwrap(),
+                &have
+            )
+            .unwrap(),
+            BundleKind::Delta
+        );
+        let delta_data = std::fs::read(&delta).unwrap();
+
+        crate::storage::with_test_db(async {
+            let desc = P2PDescription {
+                creator: peer.to_string(),
+                name: "delta".to_string(),
+                description: String::new(),
+                language: String::new(),
+                latest_commit_at: 0,
+                size: 0,
+                tags: Vec::new(),
+                commit_count: 0,
+                contributors: 0,
+            };
+            let mut repo = Repo::new(repo_id.clone(), desc, dst.clone());
+            repo_model::save_repo_to_db(&repo).await.unwrap();
+            // 之前收到的完整 bundle
+            let stored = manager.get_bundle_path(&peer, &get_repo_id_last_part(&repo_id));
+            std::fs::create_dir_all(stored.parent().unwrap()).unwrap();
+            std::fs::copy(&full, &stored).unwrap();
+            repo_model::update_repo_bundle(&repo_id, stored.to_str().unwrap())
+                .await
+                .unwrap();
+
+            let old_head = git(&dst, &["rev-parse", "HEAD"]);
+            let request = |to: &NodeId| PendingRequest {
+                repo_id: repo_id.clone(),
+                peer: to.clone(),
+                sent_at: Instant::now(),
+            };
+
+            // 本节点自己的仓库不接受增量
+            let transfer_id = Uuid::new_v4();
+            manager
+                .requests
+                .lock()
+                .await
+                .insert(transfer_id, request(&peer));
+            deliver_delta(&manager, &peer, transfer_id, &repo_id, &delta_data).await;
+            assert_eq!(git(&dst, &["rev-parse", "HEAD"]), old_head);
+
+            repo.is_external = true;
+            repo.bundle = stored.clone();
+            repo_model::save_repo_to_db(&repo).await.unwrap();
+
+            // 未请求过的增量、以及并非被请求节点发来的增量都被丢弃
+            deliver_delta(&manager, &peer, Uuid::new_v4(), &repo_id, &delta_data).await;
+            assert_eq!(git(&dst, &["rev-parse", "HEAD"]), old_head);
+            let other = NodeId::from_keypair(&KeyPair::generate().unwrap());
+            let transfer_id = Uuid::new_v4();
+            manager
+                .requests
+                .lock()
+                .await
+                .insert(transfer_id, request(&peer));
+            deliver_delta(&manager, &other, transfer_id, &repo_id, &delta_data).await;
+            assert_eq!(git(&dst, &["rev-parse", "HEAD"]), old_head);
+
+            deliver_delta(&manager, &peer, transfer_id, &repo_id, &delta_data).await;
+            assert!(manager.requests.lock().await.is_empty());
+            assert_eq!(git(&dst, &["rev-parse", "HEAD"]), new_head);
+            // 完整 bundle 由更新后的克隆重新打包，与克隆的 ref 一致
+            let bundle_refs =
+                crate::git::pack::extract_bundle_refs(stored.to_str().unwrap()).unwrap();
+            assert_eq!(bundle_refs["refs/heads/main"], new_head);
+            let pending = crate::git::git_repo::pending_ref_updates(
+                dst.to_str().unwrap(),
+                &bundle_refs,
+                crate::git::git_repo::RefFilter::default(),
+            )
+            .unwrap();
+            assert!(
+                pending.is_empty(),
+                "unexpected pending updates: {:?}",
+                pending
+            );
+            let repo = repo_model::load_repo_from_db(&repo_id)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(repo.bundle, stored);
+            let leftovers = std::fs::read_dir(stored.parent().unwrap()).unwrap().count();
+            assert_eq!(leftovers, 1);
+        })
+        .await;
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_interleaved_transfers_of_same_repo() {
+        crate::storage::with_test_db(async {
+            let (manager, dir) = test_manager("interleaved-transfers").await;
+            let peer =
+                NodeId::from_keypair(&crate::identity::keypair::KeyPair::generate().unwrap());
+            let repo_id = "did:repo:interleaved-transfer-test";
+            let first = Uuid::new_v4();
+            let second = Uuid::new_v4();
+            let first_data = vec![1u8; TRANSFER_CHUNK_SIZE + 10];
+            let second_data = vec![2u8; TRANSFER_CHUNK_SIZE + 10];
+
+            for id in [first, second] {
+                let start = BundleMessageType::Start {
+                    transfer_id: id,
+                    repo_id: repo_id.to_string(),
+                    file_name: "repo.bundle".to_string(),
+                    total_size: first_data.len() as u64,
+                    delta: false,
+                    compressed: false,
+                    encrypted: false,
+                    recipient: None,
+                };
+                manager
+                    .handle_bundle_message(peer.clone(), encode(start))
+                    .await
+                    .unwrap();
+            }
+
+            // 两个传输的数据块交错到达
+            for (idx, range) in [
+                (0u32, 0..TRANSFER_CHUNK_SIZE),
+                (1, TRANSFER_CHUNK_SIZE..first_data.len()),
+            ] {
+                for (id, data) in [(first, &first_data), (second, &second_data)] {
+                    let chunk = BundleMessageType::Chunk {
+                        transfer_id: id,
+                        repo_id: repo_id.to_string(),
+                        chunk_idx: idx,
+                        data: data[range.clone()].to_vec(),
+                    };
+                    manager
+                        .handle_bundle_message(peer.clone(), encode(chunk))
+                        .await
+                        .unwrap();
+                }
+            }
+
+            let done = BundleMessageType::Done {
+                transfer_id: second,
+                repo_id: repo_id.to_string(),
+            };
+            manager
+                .handle_bundle_message(peer.clone(), encode(done))
+                .await
+                .unwrap();
+
+            // 先完成的传输胜出，另一个传输被取消并清理临时文件
+            let final_path = manager.get_bundle_path(&peer, &get_repo_id_last_part(repo_id));
+            assert_eq!(std::fs::read(&final_path).unwrap(), second_data);
+            assert!(manager.incoming.lock().await.is_empty());
+            let leftovers = std::fs::read_dir(final_path.parent().unwrap())
+                .unwrap()
+                .filter(|e| e.as_ref().unwrap().path().extension() == Some("part".as_ref()))
+                .count();
+            assert_eq!(leftovers, 0);
+
+            let _ = std::fs::remove_dir_all(&dir);
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_legacy_transfers_from_one_peer_do_not_collide() {
+        crate::storage::with_test_db(async {
+            let (manager, dir) = test_manager("legacy-transfers").await;
+            let peer = NodeId::from_keypair(&KeyPair::generate().unwrap());
+            let repos = [
+                ("did:repo:legacy-transfer-a", vec![1u8; 100]),
+                ("did:repo:legacy-transfer-b", vec![2u8; 100]),
+            ];
+
+            // 旧节点不发送 transfer_id，两个传输交错到达
+            for (repo_id, data) in &repos {
+                let start = BundleMessageType::Start {
+                    transfer_id: Uuid::nil(),
+                    repo_id: repo_id.to_string(),
+                    file_name: "repo.bundle".to_string(),
+                    total_size: data.len() as u64,
+                    delta: false,
+                    compressed: false,
+                    encrypted: false,
+                    recipient: None,
+                };
+                manager
+                    .handle_bundle_message(peer.clone(), encode(start))
+                    .await
+                    .unwrap();
+            }
+            for (repo_id, data) in &repos {
+                let chunk = BundleMessageType::Chunk {
+                    transfer_id: Uuid::nil(),
+                    repo_id: repo_id.to_string(),
+                    chunk_idx: 0,
+                    data: data.clone(),
+                };
+                manager
+                    .handle_bundle_message(peer.clone(), encode(chunk))
+                    .await
+                    .unwrap();
+            }
+            assert_eq!(manager.incoming.lock().await.len(), 2);
+            for (repo_id, _) in &repos {
+                let done = BundleMessageType::Done {
+                    transfer_id: Uuid::nil(),
+                    repo_id: repo_id.to_string(),
+                };
+                manager
+                    .handle_bundle_message(peer.clone(), encode(done))
+                    .await
+                    .unwrap();
+            }
+
+            for (repo_id, data) in &repos {
+                let path = manager.get_bundle_path(&peer, &get_repo_id_last_part(repo_id));
+                assert_eq!(&std::fs::read(&path).unwrap(), data);
+            }
+            assert!(manager.incoming.lock().await.is_empty());
+
+            let _ = std::fs::remove_dir_all(&dir);
+        })
+        .await;
+    }
 }
@@ -0,0 +1,73 @@
+use anyhow::{anyhow, Result};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use rand_core::{OsRng, RngCore};
+
+/// 频道共享密钥长度（字节）
+pub const CHANNEL_KEY_LEN: usize = 32;
+
+/// 解析十六进制编码的频道密钥
+pub fn parse_channel_key(hex_key: &str) -> Result<[u8; CHANNEL_KEY_LEN]> {
+    let bytes =
+        hex::decode(hex_key.trim()).map_err(|e| anyhow!("Invalid channel key hex: {}", e))?;
+    bytes.try_into().map_err(|b: Vec<u8>| {
+        anyhow!(
+            "Invalid channel key length: expected {} bytes, got {}",
+            CHANNEL_KEY_LEN,
+            b.len()
+        )
+    })
+}
+
+/// 使用频道共享密钥加密，输出格式: Nonce (12) + Ciphertext
+pub fn encrypt_with_channel_key(key: &[u8; CHANNEL_KEY_LEN], message: &[u8]) -> Result<Vec<u8>> {
+    let cipher = ChaCha20Poly1305::new(&Key::from(*key));
+
+    let mut nonce_bytes = [0u8; 12];
+    OsRng.fill_bytes(&mut nonce_bytes);
+    let nonce = Nonce::from(nonce_bytes);
+    let ciphertext = cipher
+        .encrypt(&nonce, message)
+        .map_err(|e| anyhow!("Encryption failed: {}", e))?;
+
+    let mut result = Vec::with_capacity(12 + ciphertext.len());
+    result.extend_from_slice(&nonce_bytes);
+    result.extend_from_slice(&ciphertext);
+    Ok(result)
+}
+
+/// 使用频道共享密钥解密 `encrypt_with_channel_key` 的输出
+pub fn decrypt_with_channel_key(key: &[u8; CHANNEL_KEY_LEN], payload: &[u8]) -> Result<Vec<u8>> {
+    if payload.len() < 12 {
+        return Err(anyhow!("Message too short"));
+    }
+
+    let cipher = ChaCha20Poly1305::new(&Key::from(*key));
+    let nonce_bytes: [u8; 12] = payload[0..12].try_into()?;
+    let nonce = Nonce::from(nonce_bytes);
+    cipher
+        .decrypt(&nonce, &payload[12..])
+        .map_err(|e| anyhow!("Decryption failed: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channel_encrypt_decrypt() -> Result<()> {
+        let key = parse_channel_key(&hex::encode([7u8; CHANNEL_KEY_LEN]))?;
+        let payload = encrypt_with_channel_key(&key, b"hello channel")?;
+        assert_eq!(decrypt_with_channel_key(&key, &payload)?, b"hello channel");
+
+        // 错误的密钥无法解密
+        let other = [8u8; CHANNEL_KEY_LEN];
+        assert!(decrypt_with_channel_key(&other, &payload).is_err());
+
+        assert!(parse_channel_key("abcd").is_err());
+        assert!(parse_channel_key("not-hex").is_err());
+        Ok(())
+    }
+}
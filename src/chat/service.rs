@@ -1,5 +1,6 @@
 use crate::gossip::message::{
-    ChatAckMessage, EncryptedChatMessage, Envelope, GossipMessage, SignedMessage,
+    ChatAckMessage, ChatReadMessage, EncryptedChatMessage, Envelope, GossipMessage,
+    GroupChatMessage, SignedMessage,
 };
 use crate::node::node::Node;
 use crate::node::node_id::NodeId;
@@ -22,6 +23,9 @@ pub async fn start_chat_sender_task(
         if let Err(e) = process_pending_messages(manager.clone(), my_node.clone()).await {
             tracing::error!("Failed to process pending messages: {}", e);
         }
+        if let Err(e) = process_pending_read_receipts(manager.clone(), my_node.clone()).await {
+            tracing::error!("Failed to process pending read receipts: {}", e);
+        }
         tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
     }
 }
@@ -30,16 +34,43 @@ async fn process_pending_messages(
     manager: Arc<Mutex<ConnectionManager>>,
     my_node: Node,
 ) -> Result<()> {
-    // 1. Find all messages with status 'Sending'
+    // 1. Find all messages with status 'Sending' whose retry time has come
     let db = crate::storage::get_db_conn().await?;
     let pending_msgs = crate::storage::chat_message::Entity::find()
         .filter(crate::storage::chat_message::Column::Status.eq(MessageStatus::Sending))
+        .filter(crate::storage::chat_message::Column::NextRetryAt.lte(timestamp_now()))
         .all(&db)
         .await?;
 
     for msg in pending_msgs {
         tracing::info!("Processing pending message: {}", msg.id);
 
+        if let Some(channel_id) = msg.channel_id.clone() {
+            match try_send_group_msg(
+                manager.clone(),
+                my_node.clone(),
+                channel_id,
+                msg.content.clone(),
+                msg.id.clone(),
+            )
+            .await
+            {
+                Ok(_) => {
+                    crate::storage::chat_message::update_message_status(
+                        &msg.id,
+                        MessageStatus::Sent,
+                    )
+                    .await?;
+                    tracing::info!("Group message {} sent successfully", msg.id);
+                }
+                Err(e) => {
+                    tracing::error!("Failed to send group message {}: {}", msg.id, e);
+                    crate::storage::chat_message::record_send_failure(&msg.id).await?;
+                }
+            }
+            continue;
+        }
+
         let receiver_node_id = match NodeId::from_string(&msg.to) {
             Ok(id) => id,
             Err(_) => {
@@ -66,14 +97,89 @@ async fn process_pending_messages(
             }
             Err(e) => {
                 tracing::error!("Failed to send message {}: {}", msg.id, e);
-                // We can keep it as 'Sending' to retry later, or make a 'Failed' logic
-                // For now, retry indefinitely
+                // Back off exponentially; give up after MAX_SEND_ATTEMPTS
+                if let Some(MessageStatus::Failed) =
+                    crate::storage::chat_message::record_send_failure(&msg.id).await?
+                {
+                    tracing::warn!(
+                        "Message {} failed after {} attempts, marking failed",
+                        msg.id,
+                        crate::storage::chat_message::MAX_SEND_ATTEMPTS
+                    );
+                }
             }
         }
     }
     Ok(())
 }
 
+async fn process_pending_read_receipts(
+    manager: Arc<Mutex<ConnectionManager>>,
+    my_node: Node,
+) -> Result<()> {
+    // Messages addressed to me that the user has read but whose receipt is not sent yet
+    let db = crate::storage::get_db_conn().await?;
+    let pending = crate::storage::chat_message::Entity::find()
+        .filter(crate::storage::chat_message::Column::Status.eq(MessageStatus::Read))
+        .filter(crate::storage::chat_message::Column::ReceiptPending.eq(true))
+        .filter(crate::storage::chat_message::Column::To.eq(my_node.node_id().to_string()))
+        .all(&db)
+        .await?;
+
+    for msg in pending {
+        let target_id = match NodeId::from_string(&msg.from) {
+            Ok(id) => id,
+            Err(_) => {
+                tracing::error!(
+                    "Invalid sender node id: {}, dropping read receipt",
+                    msg.from
+                );
+                crate::storage::chat_message::clear_receipt_pending(&msg.id).await?;
+                continue;
+            }
+        };
+
+        let read_msg = ChatReadMessage {
+            reader_id: my_node.node_id().clone(),
+            target_id,
+            msg_id: msg.id.clone(),
+            timestamp: timestamp_now(),
+        };
+
+        let mut signed_read = SignedMessage {
+            msg_id: Some(Uuid::new_v4()),
+            node_id: my_node.node_id().clone(),
+            message: GossipMessage::ChatRead(read_msg),
+            timestamp: timestamp_now(),
+            signature: "".to_string(),
+        };
+        let self_hash = signed_read.self_hash();
+        let sign = my_node.sign_message(self_hash.as_slice())?;
+        signed_read.signature = hex::encode(sign);
+
+        let envelope = Envelope {
+            payload: signed_read,
+            ttl: TTL,
+        };
+        let data = serde_json::to_vec(&envelope)?;
+
+        let mgr = manager.lock().await;
+        let peers = mgr.list_peers().await;
+        if peers.is_empty() {
+            // No peers yet, keep the receipt pending and try again later
+            return Ok(());
+        }
+        for peer in peers {
+            let _ = mgr.send_gossip_message(peer.clone(), data.clone()).await;
+        }
+        drop(mgr);
+
+        crate::storage::chat_message::clear_receipt_pending(&msg.id).await?;
+        tracing::info!("Read receipt sent for msg {}", msg.id);
+    }
+    Ok(())
+}
+
 async fn try_send_pending_msg(
     manager: Arc<Mutex<ConnectionManager>>,
     my_node: Node,
@@ -103,6 +209,7 @@ async fn try_send_pending_msg(
 
     // 4. Sign & Broadcast/Send
     let mut signed_msg = SignedMessage {
+        msg_id: Some(Uuid::new_v4()),
         node_id: my_node.node_id().clone(),
         message,
         timestamp: timestamp_now(),
@@ -188,6 +295,66 @@ async fn try_send_pending_msg(
     Ok(())
 }
 
+async fn try_send_group_msg(
+    manager: Arc<Mutex<ConnectionManager>>,
+    my_node: Node,
+    channel_id: String,
+    content: String,
+    msg_id: String,
+) -> Result<()> {
+    // 1. Get Channel Key
+    let key_hex = crate::storage::channel::get_channel_key(&channel_id)
+        .await?
+        .ok_or_else(|| anyhow!("Not joined to channel {}", channel_id))?;
+    let key = crate::chat::channel::parse_channel_key(&key_hex)?;
+
+    // 2. Encrypt & Construct Message
+    let ciphertext = crate::chat::channel::encrypt_with_channel_key(&key, content.as_bytes())?;
+    let message = GossipMessage::GroupChat(GroupChatMessage {
+        channel_id,
+        sender_id: my_node.node_id().clone(),
+        msg_id,
+        ciphertext,
+    });
+
+    // 3. Sign & Broadcast
+    let mut signed_msg = SignedMessage {
+        msg_id: Some(Uuid::new_v4()),
+        node_id: my_node.node_id().clone(),
+        message,
+        timestamp: timestamp_now(),
+        signature: "".to_string(),
+    };
+    let self_hash = signed_msg.self_hash();
+    let sign = my_node.sign_message(self_hash.as_slice())?;
+    signed_msg.signature = hex::encode(sign);
+
+    let envelope = Envelope {
+        payload: signed_msg,
+        ttl: TTL,
+    };
+    let data = serde_json::to_vec(&envelope)?;
+
+    let mgr = manager.lock().await;
+    let peers = mgr.list_peers().await;
+    if peers.is_empty() {
+        return Err(anyhow!("No peers connected to send message"));
+    }
+
+    let mut at_least_one_success = false;
+    for peer in peers {
+        match mgr.send_gossip_message(peer.clone(), data.clone()).await {
+            Ok(()) => at_least_one_success = true,
+            Err(e) => tracing::warn!("Failed to send group message to peer {}: {}", peer, e),
+        }
+    }
+    if !at_least_one_success {
+        return Err(anyhow!("Failed to send group message to any peer"));
+    }
+
+    Ok(())
+}
+
 pub async fn send_chat_message(
     _manager: Arc<Mutex<ConnectionManager>>,
     my_node: Node,
@@ -267,6 +434,7 @@ pub async fn process_incoming_chat(
     let gossip_msg = GossipMessage::ChatAck(ack_msg);
 
     let mut signed_ack = SignedMessage {
+        msg_id: Some(Uuid::new_v4()),
         node_id: my_node.node_id().clone(),
         message: gossip_msg,
         timestamp: timestamp_now(),
@@ -307,8 +475,159 @@ pub async fn process_ack(
 
     tracing::info!("Received ACK for msg {}", ack.msg_id);
 
+    // A late delivery ACK must not downgrade a message that has already been read
+    let db = crate::storage::get_db_conn().await?;
+    if let Some(m) = crate::storage::chat_message::Entity::find_by_id(ack.msg_id.clone())
+        .one(&db)
+        .await?
+    {
+        if m.status == MessageStatus::Read {
+            return Ok(());
+        }
+    }
+
     crate::storage::chat_message::update_message_status(&ack.msg_id, MessageStatus::Delivered)
         .await?;
 
     Ok(())
 }
+
+pub async fn process_read(read: ChatReadMessage, signer: &NodeId, my_node: Node) -> Result<()> {
+    // 1. Check if it's for me
+    if read.target_id != *my_node.node_id() {
+        tracing::info!(
+            "Read receipt not for me (target: {}), skip local forwarding and let gossip handle it",
+            read.target_id
+        );
+        return Ok(());
+    }
+
+    // 2. The receipt must come from the recipient of the original message
+    if *signer != read.reader_id {
+        tracing::warn!(
+            "Ignoring read receipt for msg {}: signed by {}, not reader {}",
+            read.msg_id,
+            signer.short(),
+            read.reader_id.short()
+        );
+        return Ok(());
+    }
+    let db = crate::storage::get_db_conn().await?;
+    let Some(message) = crate::storage::chat_message::Entity::find_by_id(read.msg_id.clone())
+        .one(&db)
+        .await?
+    else {
+        tracing::debug!("Read receipt for unknown msg {}", read.msg_id);
+        return Ok(());
+    };
+    if message.to != read.reader_id.as_str() {
+        tracing::warn!(
+            "Ignoring read receipt for msg {} from {}: message was sent to {}",
+            read.msg_id,
+            read.reader_id.short(),
+            message.to
+        );
+        return Ok(());
+    }
+
+    tracing::info!("Msg {} read by {}", read.msg_id, read.reader_id);
+
+    crate::storage::chat_message::update_message_status(&read.msg_id, MessageStatus::Read).await?;
+
+    Ok(())
+}
+
+pub async fn process_group_chat(msg: GroupChatMessage, my_node: Node) -> Result<()> {
+    // 1. Only handle channels we have joined; gossip keeps flooding the rest
+    let Some(key_hex) = crate::storage::channel::get_channel_key(&msg.channel_id).await? else {
+        tracing::debug!(
+            "Not joined to channel {}, skip local handling",
+            msg.channel_id
+        );
+        return Ok(());
+    };
+    if msg.sender_id == *my_node.node_id() {
+        return Ok(());
+    }
+
+    // 2. Decrypt
+    let key = crate::chat::channel::parse_channel_key(&key_hex)?;
+    let plaintext_bytes = crate::chat::channel::decrypt_with_channel_key(&key, &msg.ciphertext)?;
+    let content = String::from_utf8(plaintext_bytes)?;
+
+    tracing::info!(
+        "Received group chat in {} from {}: {}",
+        msg.channel_id,
+        msg.sender_id.0,
+        content
+    );
+
+    // 3. Store
+    let db = crate::storage::get_db_conn().await?;
+    if (crate::storage::chat_message::Entity::find_by_id(msg.msg_id.clone())
+        .one(&db)
+        .await?)
+        .is_none()
+    {
+        crate::storage::chat_message::save_channel_message(
+            msg.msg_id.clone(),
+            msg.sender_id.to_string(),
+            msg.channel_id.clone(),
+            content,
+            timestamp_now(),
+            MessageStatus::Delivered,
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::keypair::KeyPair;
+    use crate::node::node::NodeType;
+
+    #[tokio::test]
+    async fn test_read_receipt_requires_recipient_signature() -> Result<()> {
+        crate::storage::with_test_db(async {
+            let me = Node::from_keypair(&KeyPair::generate()?, "me", Vec::new(), NodeType::Normal);
+            let recipient = NodeId::from_keypair(&KeyPair::generate()?);
+            let other = NodeId::from_keypair(&KeyPair::generate()?);
+            let msg_id = Uuid::new_v4().to_string();
+            crate::storage::chat_message::save_message(
+                msg_id.clone(),
+                me.node_id().to_string(),
+                recipient.to_string(),
+                "hello".to_string(),
+                timestamp_now(),
+                MessageStatus::Delivered,
+            )
+            .await?;
+            let receipt = |reader_id: &NodeId| ChatReadMessage {
+                reader_id: reader_id.clone(),
+                target_id: me.node_id().clone(),
+                msg_id: msg_id.clone(),
+                timestamp: timestamp_now(),
+            };
+            let status = || async {
+                let db = crate::storage::get_db_conn().await?;
+                let model = crate::storage::chat_message::Entity::find_by_id(msg_id.clone())
+                    .one(&db)
+                    .await?;
+                anyhow::Ok(model.map(|m| m.status))
+            };
+
+            // 由他人签名、或读者不是原消息接收方的回执都被忽略
+            process_read(receipt(&recipient), &other, me.clone()).await?;
+            process_read(receipt(&other), &other, me.clone()).await?;
+            assert_eq!(status().await?, Some(MessageStatus::Delivered));
+
+            process_read(receipt(&recipient), &recipient, me.clone()).await?;
+            assert_eq!(status().await?, Some(MessageStatus::Read));
+            Ok(())
+        })
+        .await
+    }
+}
@@ -1,8 +1,10 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use megaengine::identity::keystore::{self, EncryptedKeyPair};
+use megaengine::node::node_id::NodeId;
 use megaengine::storage;
 
-pub async fn handle_auth() -> Result<()> {
-    let kp_path = storage::keypair_path();
+pub async fn handle_auth(encrypt: bool, profile: Option<&str>) -> Result<()> {
+    let kp_path = storage::keypair_path(profile);
     if kp_path.exists() {
         tracing::info!(
             "Keypair already exists at {:?}; skipping generation",
@@ -11,8 +13,81 @@ pub async fn handle_auth() -> Result<()> {
     } else {
         tracing::info!("Generating new keypair...");
         let kp = megaengine::identity::keypair::KeyPair::generate()?;
-        storage::save_keypair(&kp)?;
-        tracing::info!("Keypair saved to {:?}", storage::keypair_path());
+        if encrypt {
+            let passphrase = read_new_passphrase()?;
+            storage::save_keypair_encrypted(&kp, &passphrase, profile)?;
+        } else {
+            storage::save_keypair(&kp, profile)?;
+        }
+        tracing::info!("Keypair saved to {:?}", kp_path);
     }
     Ok(())
 }
+
+pub async fn handle_auth_list() -> Result<()> {
+    let profiles = storage::list_profiles()?;
+    if profiles.is_empty() {
+        println!("No identity profiles found. Run `auth init` first.");
+    } else {
+        for profile in profiles {
+            println!("{}", profile);
+        }
+    }
+    Ok(())
+}
+
+/// 读取新口令：优先使用环境变量，否则提示输入两次并确认一致
+fn read_new_passphrase() -> Result<String> {
+    if let Ok(passphrase) = std::env::var(storage::PASSPHRASE_ENV) {
+        return Ok(passphrase);
+    }
+
+    let passphrase = rpassword::prompt_password("New passphrase: ")?;
+    if passphrase.is_empty() {
+        return Err(anyhow!("Passphrase must not be empty"));
+    }
+    let confirm = rpassword::prompt_password("Confirm passphrase: ")?;
+    if passphrase != confirm {
+        return Err(anyhow!("Passphrases do not match"));
+    }
+    Ok(passphrase)
+}
+
+/// 导出身份：明文密钥导出为 base58 私钥，加密密钥原样导出信封
+pub async fn handle_auth_export(out: String, profile: Option<&str>) -> Result<()> {
+    let content = match storage::load_sealed_keypair(profile)? {
+        Some(sealed) => serde_json::to_string_pretty(&sealed)?,
+        None => keystore::export_signing_key(&storage::load_keypair(profile)?)?,
+    };
+    std::fs::write(&out, content)?;
+    println!("Identity exported to {}", out);
+    println!("Keep this file secret: it contains your node signing key.");
+    Ok(())
+}
+
+/// 导入身份：校验后安装，已有密钥时需要 --force 才会覆盖
+pub async fn handle_auth_import(input: String, force: bool, profile: Option<&str>) -> Result<()> {
+    let kp_path = storage::keypair_path(profile);
+    if kp_path.exists() && !force {
+        return Err(anyhow!(
+            "Keypair already exists at {:?}; use --force to overwrite",
+            kp_path
+        ));
+    }
+
+    let content = std::fs::read_to_string(&input)?;
+    let kp = if let Ok(sealed) = serde_json::from_str::<EncryptedKeyPair>(&content) {
+        // 先用口令解密校验，再原样安装加密信封
+        let kp = sealed.open(&storage::read_passphrase()?)?;
+        storage::save_sealed_keypair(&sealed, profile)?;
+        kp
+    } else {
+        let kp = keystore::import_signing_key(&content)?;
+        storage::save_keypair(&kp, profile)?;
+        kp
+    };
+
+    println!("Identity imported to {:?}", kp_path);
+    println!("Node ID: {}", NodeId::from_keypair(&kp));
+    Ok(())
+}
@@ -1,32 +1,107 @@
-use anyhow::Result;
+use anyhow::{anyhow, Context, Result};
 use clap::Subcommand;
 use megaengine::node::node_id::NodeId;
 use megaengine::storage::chat_message::{Entity as ChatMessage, MessageStatus};
 use megaengine::util::timestamp_now;
-use sea_orm::{EntityTrait, QueryOrder};
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, QueryOrder};
+use std::path::PathBuf;
 use uuid::Uuid;
 
 #[derive(Clone, Debug, Subcommand)]
 pub enum ChatCommand {
-    /// Send a message to a node
+    /// Send a message to a node or a joined channel
     Send {
-        /// Target Node ID (did:key:...)
+        /// Target Node ID (did:key:...) or alias of a known node
+        #[arg(long, required_unless_present = "channel", conflicts_with = "channel")]
+        to: Option<String>,
+        /// Target channel ID (must be joined first)
         #[arg(long)]
-        to: String,
+        channel: Option<String>,
         /// Message content
         #[arg(long)]
         msg: String,
     },
     /// List messages
-    List,
+    List {
+        /// Only show messages of this channel
+        #[arg(long)]
+        channel: Option<String>,
+    },
+    /// Join a group channel with a shared key distributed out of band
+    Join {
+        /// Channel ID
+        channel_id: String,
+        /// Shared channel key (32 bytes, hex)
+        #[arg(long)]
+        key: String,
+    },
+    /// List known peers (alias -> Node ID)
+    Peers,
+    /// Mark a received message as read and send a read receipt
+    Read {
+        /// Message ID
+        msg_id: String,
+    },
+    /// Reset a failed message so it is sent again
+    Retry {
+        /// Message ID
+        msg_id: String,
+    },
+    /// Export message history to a JSON file
+    Export {
+        /// Output file
+        #[arg(long)]
+        out: PathBuf,
+        /// Only export the 1:1 conversation with this Node ID or alias
+        #[arg(long)]
+        with: Option<String>,
+    },
+    /// Import messages from a file written by `chat export`, skipping existing IDs
+    Import {
+        /// File written by `chat export`
+        file: PathBuf,
+    },
 }
 
-pub async fn run_chat_command(cmd: ChatCommand) -> Result<()> {
+pub async fn run_chat_command(cmd: ChatCommand, profile: Option<&str>) -> Result<()> {
     match cmd {
-        ChatCommand::Send { to, msg } => {
+        ChatCommand::Send {
+            channel: Some(channel_id),
+            msg,
+            ..
+        } => {
+            let keypair = megaengine::storage::load_keypair(profile)?;
+            let my_node_id = NodeId::from_keypair(&keypair);
+            if megaengine::storage::channel::get_channel_key(&channel_id)
+                .await?
+                .is_none()
+            {
+                return Err(anyhow!(
+                    "Not joined to channel '{}', run `chat join` first",
+                    channel_id
+                ));
+            }
+
+            let msg_id = Uuid::new_v4().to_string();
+            megaengine::storage::chat_message::save_channel_message(
+                msg_id.clone(),
+                my_node_id.to_string(),
+                channel_id.clone(),
+                msg,
+                timestamp_now(),
+                MessageStatus::Sending,
+            )
+            .await?;
+
+            println!("Message queued (ID: {}) to channel {}.", msg_id, channel_id);
+            println!("It will be delivered automatically when the node service is active.");
+        }
+        ChatCommand::Send { to, msg, .. } => {
             // Load identity
-            let keypair = megaengine::storage::load_keypair()?;
+            let keypair = megaengine::storage::load_keypair(profile)?;
             let my_node_id = NodeId::from_keypair(&keypair);
+            let to = to.ok_or_else(|| anyhow!("Either --to or --channel is required"))?;
+            let to = resolve_recipient(&to).await?;
 
             let msg_id = Uuid::new_v4().to_string();
 
@@ -36,36 +111,119 @@ pub async fn run_chat_command(cmd: ChatCommand) -> Result<()> {
             megaengine::storage::chat_message::save_message(
                 msg_id.clone(),
                 my_node_id.to_string(),
-                to.clone(),
+                to.to_string(),
                 msg,
                 timestamp_now(),
                 MessageStatus::Sending,
             )
             .await?;
 
-            println!("Message queued (ID: {}).", msg_id);
+            println!("Message queued (ID: {}) to {}.", msg_id, to);
             println!("It will be delivered automatically when the node service is active.");
         }
-        ChatCommand::List => {
+        ChatCommand::List { channel } => {
             let db = megaengine::storage::get_db_conn().await?;
-            let messages = ChatMessage::find()
-                .order_by_desc(megaengine::storage::chat_message::Column::CreatedAt)
+            let mut query = ChatMessage::find();
+            if let Some(channel_id) = channel {
+                query = query
+                    .filter(megaengine::storage::chat_message::Column::ChannelId.eq(channel_id));
+            }
+            let messages = query
+                .order_by_desc(megaengine::storage::chat_message::Column::CreatedAtMs)
                 .all(&db)
                 .await?;
 
             println!("--- Chat History ---");
             for m in messages {
-                let time = if let Some(dt) = chrono::DateTime::from_timestamp(m.created_at, 0) {
-                    dt.format("%Y-%m-%d %H:%M:%S").to_string()
-                } else {
-                    m.created_at.to_string()
-                };
+                let time = megaengine::util::format_timestamp(m.created_at, "%Y-%m-%d %H:%M:%S");
                 println!(
-                    "[{}] From: {} To: {} : {} ({:?})",
-                    time, m.from, m.to, m.content, m.status
+                    "[{}] From: {} To: {} : {} {}",
+                    time,
+                    m.from,
+                    m.to,
+                    m.content,
+                    m.status.icon()
                 );
             }
         }
+        ChatCommand::Join { channel_id, key } => {
+            megaengine::chat::channel::parse_channel_key(&key)?;
+            megaengine::storage::channel::save_channel(&channel_id, key.trim()).await?;
+            println!("Joined channel {}.", channel_id);
+        }
+        ChatCommand::Peers => {
+            let nodes = megaengine::storage::node_model::list_nodes().await?;
+            println!("--- Known Peers ---");
+            for node in nodes {
+                println!("{:<20} {}", node.alias, node.node_id);
+            }
+        }
+        ChatCommand::Read { msg_id } => {
+            let keypair = megaengine::storage::load_keypair(profile)?;
+            let my_node_id = NodeId::from_keypair(&keypair);
+            if megaengine::storage::chat_message::mark_read(&msg_id, my_node_id.as_str()).await? {
+                println!("Message {} marked as read.", msg_id);
+                println!("The read receipt will be sent when the node service is active.");
+            } else {
+                eprintln!("Message {} not found or not addressed to you.", msg_id);
+            }
+        }
+        ChatCommand::Retry { msg_id } => {
+            if megaengine::storage::chat_message::reset_for_retry(&msg_id).await? {
+                println!("Message {} queued for retry.", msg_id);
+            } else {
+                eprintln!("Message {} not found or not in Failed state.", msg_id);
+            }
+        }
+        ChatCommand::Export { out, with } => {
+            let peer = match with {
+                Some(with) => Some(resolve_recipient(&with).await?),
+                None => None,
+            };
+            let messages =
+                megaengine::storage::chat_message::list_messages(peer.as_ref().map(|p| p.as_str()))
+                    .await?;
+            std::fs::write(&out, serde_json::to_vec_pretty(&messages)?)
+                .with_context(|| format!("Failed to write {}", out.display()))?;
+            println!("Exported {} messages to {}.", messages.len(), out.display());
+        }
+        ChatCommand::Import { file } => {
+            let content = std::fs::read(&file)
+                .with_context(|| format!("Failed to read {}", file.display()))?;
+            let messages: Vec<megaengine::storage::chat_message::Model> =
+                serde_json::from_slice(&content)
+                    .with_context(|| format!("{} is not a chat export", file.display()))?;
+            let total = messages.len();
+            let imported = megaengine::storage::chat_message::import_messages(messages).await?;
+            println!(
+                "Imported {} messages ({} already present).",
+                imported,
+                total - imported
+            );
+        }
     }
     Ok(())
 }
+
+/// 将 `--to` 解析为 NodeId：优先按 did:key 解析，否则按 nodes 表中的别名查找
+pub(crate) async fn resolve_recipient(to: &str) -> Result<NodeId> {
+    if let Ok(node_id) = NodeId::from_string(to) {
+        return Ok(node_id);
+    }
+
+    let mut matches = megaengine::storage::node_model::find_node_ids_by_alias(to).await?;
+    match matches.len() {
+        0 => Err(anyhow!("Unknown recipient: no node with alias '{}'", to)),
+        1 => Ok(matches.remove(0)),
+        _ => Err(anyhow!(
+            "Ambiguous alias '{}' matches {} nodes, use a Node ID instead: {}",
+            to,
+            matches.len(),
+            matches
+                .iter()
+                .map(|id| id.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
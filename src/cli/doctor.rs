@@ -0,0 +1,408 @@
+use anyhow::Result;
+use megaengine::{
+    config::Config,
+    identity::keypair::KeyPair,
+    node::{node_addr::NodeAddr, node_id::NodeId},
+    storage,
+    transport::{
+        cert::{certificate_days_remaining, CERT_RENEW_BEFORE_DAYS},
+        quic::ConnectionManager,
+    },
+};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use super::node::{node_quic_config, parse_socket_addrs};
+
+/// 连接引导节点的超时时间
+const BOOTSTRAP_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+/// 诊断清单中的一项
+struct Check {
+    name: String,
+    status: Status,
+    detail: String,
+    /// 未通过时的修复建议
+    hint: Option<String>,
+}
+
+impl Check {
+    fn pass(name: impl Into<String>, detail: impl Into<String>) -> Self {
+        Self::new(name, Status::Pass, detail, None)
+    }
+
+    fn warn(name: impl Into<String>, detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self::new(name, Status::Warn, detail, Some(hint.into()))
+    }
+
+    fn fail(name: impl Into<String>, detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self::new(name, Status::Fail, detail, Some(hint.into()))
+    }
+
+    fn new(
+        name: impl Into<String>,
+        status: Status,
+        detail: impl Into<String>,
+        hint: Option<String>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            status,
+            detail: detail.into(),
+            hint,
+        }
+    }
+
+    fn print(&self) {
+        let mark = match self.status {
+            Status::Pass => "✅",
+            Status::Warn => "⚠️ ",
+            Status::Fail => "❌",
+        };
+        println!("{} {}: {}", mark, self.name, self.detail);
+        if let Some(hint) = &self.hint {
+            println!("   → {}", hint);
+        }
+    }
+}
+
+/// 检查密钥、证书、数据库、监听地址和引导节点，打印带修复建议的清单
+///
+/// 有检查失败时返回错误，便于脚本根据退出码判断
+pub async fn handle_node_doctor(
+    root_path: &str,
+    bootstrap_node: Option<String>,
+    profile: Option<&str>,
+) -> Result<()> {
+    println!("Checking node setup in {}\n", root_path);
+    let root = Path::new(root_path);
+    let mut checks = Vec::new();
+
+    let (check, config) = check_config(root);
+    check.print();
+    checks.push(check);
+
+    let (check, keypair) = check_keypair(profile);
+    check.print();
+    checks.push(check);
+
+    let cert_dir = config.cert_dir(root).to_string_lossy().to_string();
+    for check in [
+        check_certificates(&config, &cert_dir),
+        check_database().await,
+    ] {
+        check.print();
+        checks.push(check);
+    }
+
+    for check in check_listen_addrs(&config) {
+        check.print();
+        checks.push(check);
+    }
+
+    let bootstrap = match bootstrap_node {
+        Some(addr) => vec![addr],
+        None => config.node.bootstrap.clone(),
+    };
+    for addr in bootstrap {
+        let check = check_bootstrap(&config, &cert_dir, keypair.as_ref(), &addr).await;
+        check.print();
+        checks.push(check);
+    }
+
+    let failed = checks.iter().filter(|c| c.status == Status::Fail).count();
+    let warned = checks.iter().filter(|c| c.status == Status::Warn).count();
+    println!();
+    if failed > 0 {
+        anyhow::bail!("{} check(s) failed, {} warning(s)", failed, warned);
+    }
+    if warned > 0 {
+        println!("No problems found, {} warning(s)", warned);
+    } else {
+        println!("All checks passed");
+    }
+    Ok(())
+}
+
+fn check_config(root: &Path) -> (Check, Config) {
+    match Config::load(root) {
+        Ok(config) => (
+            Check::pass("Config", "megaengine.toml is valid or absent"),
+            config,
+        ),
+        Err(e) => (
+            Check::fail(
+                "Config",
+                format!("{:#}", e),
+                "Fix megaengine.toml in the root directory; the checks below use the defaults",
+            ),
+            Config::default(),
+        ),
+    }
+}
+
+fn check_keypair(profile: Option<&str>) -> (Check, Option<KeyPair>) {
+    let path = storage::keypair_path(profile);
+    let init_cmd = match profile {
+        Some(name) => format!("megaengine --profile {} auth init", name),
+        None => "megaengine auth init".to_string(),
+    };
+    if !path.exists() {
+        let check = Check::fail(
+            "Keypair",
+            format!("no keypair at {}", path.display()),
+            format!("Run `{}` to generate one", init_cmd),
+        );
+        return (check, None);
+    }
+
+    match storage::load_keypair(profile) {
+        Ok(kp) => {
+            let node_id = NodeId::from_keypair(&kp);
+            (Check::pass("Keypair", node_id.0), Some(kp))
+        }
+        Err(e) => {
+            let hint = if matches!(storage::load_sealed_keypair(profile), Ok(Some(_))) {
+                "The keypair is encrypted; check the passphrase or $MEGAENGINE_PASSPHRASE"
+                    .to_string()
+            } else {
+                format!(
+                    "The file is unreadable; restore it with `auth import` or create a new identity with `{}`",
+                    init_cmd
+                )
+            };
+            let check = Check::fail(
+                "Keypair",
+                format!("failed to load {}: {:#}", path.display(), e),
+                hint,
+            );
+            (check, None)
+        }
+    }
+}
+
+fn check_certificates(config: &Config, cert_dir: &str) -> Check {
+    let cert_path = format!("{}/cert.pem", cert_dir);
+    let key_path = format!("{}/key.pem", cert_dir);
+    let ca_path = format!("{}/ca-cert.pem", cert_dir);
+    let renew = "Run `megaengine node cert renew`";
+
+    if !Path::new(&cert_path).exists() && !Path::new(&ca_path).exists() {
+        return Check::pass(
+            "Certificates",
+            format!(
+                "not created yet in {}; `node start` generates them",
+                cert_dir
+            ),
+        );
+    }
+    if !Path::new(&key_path).exists() {
+        return Check::fail(
+            "Certificates",
+            format!("{} is missing", key_path),
+            "Run `megaengine node cert renew --force` to issue a new certificate and key",
+        );
+    }
+
+    let server_days = match certificate_days_remaining(&cert_path) {
+        Ok(days) => days,
+        Err(e) => {
+            return Check::fail(
+                "Certificates",
+                format!("cannot read {}: {:#}", cert_path, e),
+                "Run `megaengine node cert renew --force` to issue a new certificate",
+            )
+        }
+    };
+    let ca_days = match certificate_days_remaining(&ca_path) {
+        Ok(days) => days,
+        Err(e) => {
+            return Check::fail(
+                "Certificates",
+                format!("cannot read {}: {:#}", ca_path, e),
+                renew,
+            )
+        }
+    };
+
+    let days = server_days.min(ca_days);
+    if days < 0 {
+        return Check::fail("Certificates", "certificate has expired", renew);
+    }
+    if days < CERT_RENEW_BEFORE_DAYS {
+        return Check::warn(
+            "Certificates",
+            format!("certificate expires in {} days", days),
+            format!("{} (or restart the node, which renews it)", renew),
+        );
+    }
+
+    let mode = if config.node.identity_tls {
+        "identity certificate in use, file certificate kept for --legacy-tls"
+    } else {
+        "file certificate in use"
+    };
+    Check::pass(
+        "Certificates",
+        format!("valid for {} days ({})", days, mode),
+    )
+}
+
+async fn check_database() -> Check {
+    if let Ok(url) = std::env::var(storage::DB_URL_ENV) {
+        if !url.is_empty() {
+            return Check::pass(
+                "Database",
+                format!("${} is set, using {}", storage::DB_URL_ENV, url),
+            );
+        }
+    }
+    let path = storage::db_path();
+    if !path.exists() {
+        return Check::pass(
+            "Database",
+            format!(
+                "{} not created yet; it is created on first use",
+                path.display()
+            ),
+        );
+    }
+
+    let status = match storage::inspect_database(&path).await {
+        Ok(status) => status,
+        Err(e) => return Check::fail(
+            "Database",
+            format!("cannot open {}: {:#}", path.display(), e),
+            "Check the file permissions, or move the file aside to start with an empty database",
+        ),
+    };
+
+    let expected = storage::SCHEMA_VERSION;
+    if status.schema_version > expected {
+        return Check::fail(
+            "Database",
+            format!(
+                "schema version {} is newer than the supported version {}",
+                status.schema_version, expected
+            ),
+            "Upgrade megaengine to the version that last wrote this database",
+        );
+    }
+    if status.schema_version < expected {
+        return Check::warn(
+            "Database",
+            format!("schema version {} of {}", status.schema_version, expected),
+            "Pending migrations are applied on the next `node start` or `repo` command",
+        );
+    }
+    if !status.missing_tables.is_empty() {
+        return Check::fail(
+            "Database",
+            format!("missing tables: {}", status.missing_tables.join(", ")),
+            "The database looks damaged; move it aside to start with an empty one",
+        );
+    }
+    Check::pass("Database", format!("schema version {}", expected))
+}
+
+fn check_listen_addrs(config: &Config) -> Vec<Check> {
+    let addrs = match parse_socket_addrs(&config.node.listen, "listen") {
+        Ok(addrs) => addrs,
+        Err(e) => {
+            return vec![Check::fail(
+                "Listen",
+                format!("{:#}", e),
+                "Use ip:port values in node.listen or --addr",
+            )]
+        }
+    };
+
+    // QUIC 使用 UDP，尝试绑定后立即释放
+    addrs
+        .into_iter()
+        .map(|addr| match std::net::UdpSocket::bind(addr) {
+            Ok(_) => Check::pass(format!("Listen {}", addr), "bindable"),
+            Err(e) => Check::fail(
+                format!("Listen {}", addr),
+                format!("cannot bind: {}", e),
+                "Another process (perhaps a running node) uses this port; stop it or choose another --addr",
+            ),
+        })
+        .collect()
+}
+
+async fn check_bootstrap(
+    config: &Config,
+    cert_dir: &str,
+    keypair: Option<&KeyPair>,
+    addr: &str,
+) -> Check {
+    let name = "Bootstrap";
+    let target = match NodeAddr::parse(addr) {
+        Ok(target) => target,
+        Err(e) => {
+            return Check::fail(
+                name,
+                format!("invalid address {}: {:#}", addr, e),
+                "Use the form peer_id@address[,address...] printed by `node id --addr`",
+            )
+        }
+    };
+    let Some(kp) = keypair else {
+        return Check::warn(
+            name,
+            format!("skipped {} (no keypair)", target),
+            "Fix the keypair first",
+        );
+    };
+
+    let bind = "0.0.0.0:0".parse().expect("valid bind address");
+    let mgr =
+        match ConnectionManager::run_server(node_quic_config(config, cert_dir, bind, kp)).await {
+            Ok(mgr) => mgr,
+            Err(e) => {
+                return Check::fail(
+                    name,
+                    format!("cannot start a QUIC client: {:#}", e),
+                    "Fix the certificate problems above",
+                )
+            }
+        };
+
+    let started = Instant::now();
+    let result = tokio::time::timeout(
+        BOOTSTRAP_TIMEOUT,
+        mgr.connect(
+            NodeId::from_keypair(kp),
+            target.peer_id.clone(),
+            target.addresses.clone(),
+        ),
+    )
+    .await;
+    mgr.shutdown().await;
+
+    let hint = "Check the address, that the bootstrap node is running, and that UDP traffic to it is not blocked";
+    match result {
+        Ok(Ok(())) => Check::pass(
+            name,
+            format!("reached {} in {} ms", target, started.elapsed().as_millis()),
+        ),
+        Ok(Err(e)) => Check::fail(name, format!("cannot reach {}: {}", target, e), hint),
+        Err(_) => Check::fail(
+            name,
+            format!(
+                "no answer from {} within {} s",
+                target,
+                BOOTSTRAP_TIMEOUT.as_secs()
+            ),
+            hint,
+        ),
+    }
+}
@@ -1,9 +1,10 @@
 pub mod auth;
 pub mod chat;
+pub mod doctor;
 pub mod node;
 pub mod repo;
 
-pub use auth::handle_auth;
+pub use auth::{handle_auth, handle_auth_export, handle_auth_import, handle_auth_list};
 pub use chat::run_chat_command as handle_chat;
 pub use node::handle_node;
 pub use repo::handle_repo;
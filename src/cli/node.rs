@@ -1,44 +1,158 @@
 use anyhow::Result;
 use megaengine::mcp::start_sse_server;
 use megaengine::{
-    bundle::BundleService, node::node_addr::NodeAddr, storage, transport::config::QuicConfig,
+    bundle::{BundleProgress, BundleService, TransferDirection},
+    config::Config,
+    gossip::ping_peer,
+    node::{
+        node::{Node, NodeType},
+        node_addr::NodeAddr,
+    },
+    storage::{self, node_model},
+    transport::{
+        cert::{certificate_days_remaining, renew_certificates, CERT_RENEW_BEFORE_DAYS},
+        config::QuicConfig,
+        quic::{ConnectionManager, NodeMetrics, RetryPolicy},
+    },
 };
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+const RECONNECT_INITIAL_BACKOFF: Duration = Duration::from_secs(5);
+const RECONNECT_MAX_BACKOFF: Duration = Duration::from_secs(300);
+const RECONNECT_MAX_ATTEMPTS: u32 = 8;
+const PROGRESS_BAR_WIDTH: usize = 20;
+const METRICS_REPORT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// 运行中节点写出的指标快照
+#[derive(Serialize, Deserialize)]
+struct MetricsSnapshot {
+    updated_at: i64,
+    #[serde(flatten)]
+    metrics: NodeMetrics,
+}
+
+/// 定期记录节点指标摘要，并写出快照供 `node stats` 读取
+async fn report_node_metrics(mgr: ConnectionManager) {
+    let shutdown = mgr.shutdown_token();
+    let mut tick = tokio::time::interval(METRICS_REPORT_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            _ = tick.tick() => {}
+        }
+        let metrics = mgr.metrics().await;
+        tracing::info!(
+            "Node metrics: connections={} sent={} ({} msgs) received={} (gossip={} data={})",
+            metrics.active_connections,
+            metrics.bytes_sent,
+            metrics.messages_sent,
+            metrics.bytes_received,
+            metrics.gossip_messages_received,
+            metrics.data_messages_received
+        );
+        let snapshot = MetricsSnapshot {
+            updated_at: megaengine::util::timestamp_now(),
+            metrics,
+        };
+        let written = serde_json::to_vec_pretty(&snapshot)
+            .map_err(anyhow::Error::from)
+            .and_then(|data| Ok(std::fs::write(storage::metrics_path(), data)?));
+        if let Err(e) = written {
+            tracing::warn!("Failed to write metrics snapshot: {}", e);
+        }
+    }
+    // 节点停止后删除快照，避免 `node stats` 展示过期数据
+    let _ = std::fs::remove_file(storage::metrics_path());
+}
+
+/// 在终端打印 bundle 传输进度，每个传输按 10% 的粒度刷新
+async fn print_bundle_progress(mut rx: mpsc::Receiver<BundleProgress>) {
+    let mut last_step: HashMap<(String, String, TransferDirection), u64> = HashMap::new();
+    while let Some(p) = rx.recv().await {
+        let key = (p.repo_id.clone(), p.peer.to_string(), p.direction);
+        let step = (p.fraction() * 10.0) as u64;
+        if last_step.get(&key) == Some(&step) && !p.is_complete() {
+            continue;
+        }
+        let filled = (p.fraction() * PROGRESS_BAR_WIDTH as f64) as usize;
+        let (verb, prep) = match p.direction {
+            TransferDirection::Sending => ("Sending", "to"),
+            TransferDirection::Receiving => ("Receiving", "from"),
+        };
+        println!(
+            "📦 {} {} {} {} [{}{}] {:>3}% ({}/{} bytes)",
+            verb,
+            p.repo_id,
+            prep,
+            p.peer,
+            "#".repeat(filled),
+            "-".repeat(PROGRESS_BAR_WIDTH - filled),
+            (p.fraction() * 100.0) as u64,
+            p.bytes_transferred,
+            p.total_size
+        );
+        if p.is_complete() {
+            last_step.remove(&key);
+        } else {
+            last_step.insert(key, step);
+        }
+    }
+}
 
 pub async fn handle_node_start(
     root_path: &str,
-    alias: String,
-    addr: String,
-    cert_path: String,
-    bootstrap_node: Option<String>,
+    config: &Config,
     enable_mcp: bool,
     mcp_sse_port: Option<u16>,
+    profile: Option<&str>,
 ) -> Result<()> {
     tracing::info!("Starting node...");
-    let cert_dir = format!("{}/{}", root_path, cert_path);
+    let node_config = &config.node;
+    let cert_dir = config
+        .cert_dir(std::path::Path::new(root_path))
+        .to_string_lossy()
+        .to_string();
     megaengine::transport::cert::ensure_certificates(
         &format!("{}/cert.pem", cert_dir),
         &format!("{}/key.pem", cert_dir),
         &format!("{}/ca-cert.pem", cert_dir),
     )?;
 
-    let kp = match storage::load_keypair() {
+    let kp = match storage::load_keypair(profile) {
         Ok(k) => k,
         Err(e) => {
             tracing::error!("failed to load keypair: {}", e);
-            tracing::info!("Run `auth init` first to generate keys");
+            tracing::info!("Run `auth init` first to generate keys, or `node doctor` to check the setup");
             return Ok(());
         }
     };
 
-    let addrs: Vec<std::net::SocketAddr> = vec![addr.parse()?];
+    let addrs = parse_socket_addrs(&node_config.listen, "listen")?;
+    if addrs.is_empty() {
+        anyhow::bail!("At least one listen address is required (--addr or node.listen)");
+    }
+    let announce_addrs = parse_socket_addrs(&node_config.announce, "announce")?;
 
     let mut node = megaengine::node::node::Node::from_keypair(
         &kp,
-        &alias,
-        addrs.clone(),
-        megaengine::node::node::NodeType::Normal,
+        &node_config.alias,
+        if announce_addrs.is_empty() {
+            addrs.clone()
+        } else {
+            announce_addrs.clone()
+        },
+        if node_config.relay {
+            megaengine::node::node::NodeType::Relay
+        } else if node_config.as_bootstrap {
+            megaengine::node::node::NodeType::Bootstrap
+        } else {
+            megaengine::node::node::NodeType::Normal
+        },
     );
     tracing::info!(
         "Node initialized: alias={} id={}",
@@ -46,45 +160,92 @@ pub async fn handle_node_start(
         node.node_id().0
     );
 
-    let quic_config = QuicConfig::new(
-        addr.parse()?,
-        format!("{}/cert.pem", cert_dir),
-        format!("{}/key.pem", cert_dir),
-        format!("{}/ca-cert.pem", cert_dir),
-    );
+    let trusted_relays = node_config
+        .trusted_relays
+        .iter()
+        .map(|id| {
+            megaengine::node::node_id::NodeId::from_string(id).map_err(|e| {
+                anyhow::anyhow!("Invalid node id {} in node.trusted_relays: {}", id, e)
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let quic_config = node_quic_config(config, &cert_dir, addrs[0], &kp)
+        .with_extra_bind_addrs(addrs[1..].to_vec())
+        .with_relay_forwarding(node_config.relay)
+        .with_trusted_relays(trusted_relays);
 
-    tracing::info!("Starting QUIC server on {}...", addr);
+    tracing::info!(
+        "Starting QUIC server on {}...",
+        node_config.listen.join(", ")
+    );
     node.start_quic_server(quic_config).await?;
 
+    let bound = match &node.connection_manager {
+        Some(conn_mgr) => conn_mgr.lock().await.local_addrs(),
+        None => addrs.clone(),
+    };
+    // 未指定 --announce-addr 时只公告实际绑定成功的地址
+    if announce_addrs.is_empty() {
+        node.info.addresses = bound.clone();
+    }
+
+    let mut gossip_service = None;
     if let Some(conn_mgr) = &node.connection_manager {
         // 启动 Gossip 服务
-        let gossip = Arc::new(megaengine::gossip::GossipService::new(
-            Arc::clone(conn_mgr),
-            node.clone(),
-            None,
-        ));
-        tokio::spawn(gossip.start());
+        let gossip = Arc::new(
+            megaengine::gossip::GossipService::new(Arc::clone(conn_mgr), node.clone(), None)
+                .with_peer_exchange_dial(!node_config.passive)
+                .with_relay_store(node_config.relay)
+                .with_max_connections(config.gossip.max_connections)
+                .with_forward_rate_limit(config.gossip.forward_rate_limit())
+                .with_ping_interval(Duration::from_secs(
+                    config.gossip.ping_interval_secs.max(1),
+                )),
+        );
+        tokio::spawn(Arc::clone(&gossip).start());
+        gossip_service = Some(gossip);
         tracing::info!("Gossip protocol started");
 
         // 启动 Bundle 传输服务
+        let idle_timeout = Duration::from_secs(config.bundle.idle_timeout_secs.max(1));
         let bundles_dir = PathBuf::from(format!("{}/bundles", root_path));
         let bundle_storage = bundles_dir.clone();
-        let bundle_service = Arc::new(BundleService::new(Arc::clone(conn_mgr), bundle_storage));
+        let (progress_tx, progress_rx) = mpsc::channel(256);
+        tokio::spawn(print_bundle_progress(progress_rx));
+        let bundle_service = Arc::new(
+            BundleService::new(Arc::clone(conn_mgr), bundle_storage)
+                .with_compression(config.bundle.compress)
+                .with_encryption(config.bundle.encrypt)
+                .with_keypair(kp.clone())
+                .with_idle_timeout(idle_timeout)
+                .with_progress(progress_tx),
+        );
         tokio::spawn(bundle_service.clone().start());
         tracing::info!("Bundle transfer service started");
 
         // 启动 Bundle 同步后台任务
-        let bundle_service_for_sync = Arc::new(tokio::sync::Mutex::new(BundleService::new(
-            Arc::clone(conn_mgr),
-            bundles_dir,
-        )));
+        let bundle_service_for_sync = Arc::new(tokio::sync::Mutex::new(
+            BundleService::new(Arc::clone(conn_mgr), bundles_dir)
+                .with_compression(config.bundle.compress)
+                .with_encryption(config.bundle.encrypt)
+                .with_keypair(kp.clone())
+                .with_idle_timeout(idle_timeout),
+        ));
         megaengine::bundle::start_bundle_sync_task(bundle_service_for_sync).await;
         tracing::info!("Bundle sync task started");
 
         // 启动 Repo 同步后台任务
-        megaengine::repo::start_repo_sync_task().await;
+        megaengine::repo::start_repo_sync_task(
+            Duration::from_secs(node_config.repo_check_interval_secs.max(1)),
+            config.repo.history_limit,
+        )
+        .await;
         tracing::info!("Repo sync task started");
 
+        // 定期输出节点指标
+        let metrics_mgr = conn_mgr.lock().await.clone();
+        metrics_mgr.spawn_task(report_node_metrics(metrics_mgr.clone()));
+
         // Start Chat Sender Task
         let chat_node = node.clone();
         let chat_mgr = Arc::clone(conn_mgr);
@@ -97,8 +258,18 @@ pub async fn handle_node_start(
     }
 
     // 连接到 bootstrap node
-    if let Some(bootstrap_addr_str) = bootstrap_node {
-        connect_to_bootstrap_node(&node, bootstrap_addr_str).await;
+    for bootstrap_addr_str in &node_config.bootstrap {
+        connect_to_bootstrap_node(&node, bootstrap_addr_str.clone()).await;
+    }
+
+    // 后台连接引导节点列表文件中的节点
+    connect_to_bootstrap_list(&node, root_path, node_config.bootstrap_file.clone()).await;
+
+    // 后台重连数据库中已知的节点
+    if !node_config.reconnect {
+        tracing::info!("Reconnecting to known peers disabled (--no-reconnect)");
+    } else {
+        reconnect_known_peers(&node).await;
     }
 
     println!(
@@ -106,9 +277,10 @@ pub async fn handle_node_start(
         node.node_id().0,
         node.alias()
     );
-    println!("Listening on: {}", addr);
+    let listening: Vec<String> = bound.iter().map(|a| a.to_string()).collect();
+    println!("Listening on: {}", listening.join(", "));
 
-    let node_addr = NodeAddr::new(node.node_id().clone(), addr.parse()?);
+    let node_addr = NodeAddr::with_addresses(node.node_id().clone(), node.addresses().to_vec())?;
     println!("Node address: {}", node_addr);
     println!("Press Ctrl+C to stop");
 
@@ -132,11 +304,61 @@ pub async fn handle_node_start(
         });
     }
 
-    loop {
-        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+    tokio::signal::ctrl_c().await?;
+    println!("Shutting down gracefully...");
+    // 关闭连接前通知邻居本节点主动离开
+    if let Some(gossip) = &gossip_service {
+        if let Err(e) = gossip.announce_leave().await {
+            tracing::warn!("Failed to announce leave: {}", e);
+        }
+    }
+    if let Some(conn_mgr) = &node.connection_manager {
+        // 克隆出 ConnectionManager，避免关闭期间持有锁导致 bundle 处理任务阻塞
+        let mgr = conn_mgr.lock().await.clone();
+        mgr.shutdown().await;
+    }
+    tracing::info!("Node stopped");
+
+    Ok(())
+}
+
+/// 按节点配置构造 QUIC 配置：证书目录中的文件证书，以及身份证书和对端校验选项
+pub(crate) fn node_quic_config(
+    config: &Config,
+    cert_dir: &str,
+    bind_addr: std::net::SocketAddr,
+    kp: &megaengine::identity::keypair::KeyPair,
+) -> QuicConfig {
+    let quic_config = QuicConfig::new(
+        bind_addr,
+        format!("{}/cert.pem", cert_dir),
+        format!("{}/key.pem", cert_dir),
+        format!("{}/ca-cert.pem", cert_dir),
+    )
+    .with_identity(kp.clone())
+    .with_peer_verification(config.node.identity_tls)
+    .with_legacy_peer_certs(config.node.accept_legacy_certs)
+    .with_idle_timeout(Duration::from_secs(
+        config.node.connection_idle_timeout_secs,
+    ))
+    .with_keep_alive_interval(Duration::from_secs(config.node.keep_alive_interval_secs));
+    if config.bundle.bulk_transfer {
+        quic_config.for_bulk_transfer()
+    } else {
+        quic_config
     }
 }
 
+pub(crate) fn parse_socket_addrs(values: &[String], kind: &str) -> Result<Vec<std::net::SocketAddr>> {
+    values
+        .iter()
+        .map(|a| {
+            a.parse::<std::net::SocketAddr>()
+                .map_err(|_| anyhow::anyhow!("Invalid {} address: {}", kind, a))
+        })
+        .collect()
+}
+
 async fn connect_to_bootstrap_node(
     node: &megaengine::node::node::Node,
     bootstrap_addr_str: String,
@@ -149,26 +371,24 @@ async fn connect_to_bootstrap_node(
 
         match NodeAddr::parse(&bootstrap_addr_str) {
             Ok(bootstrap_info) => {
-                match conn_mgr
-                    .lock()
-                    .await
-                    .connect(
+                // 克隆出 ConnectionManager，避免重试期间持有锁
+                let mgr = conn_mgr.lock().await.clone();
+                // 引导节点可能仍在启动，按退避策略重试几次
+                match mgr
+                    .connect_with_retry(
                         node.node_id().clone(),
                         bootstrap_info.peer_id.clone(),
-                        vec![bootstrap_info.address],
+                        bootstrap_info.addresses.clone(),
+                        RetryPolicy::default(),
                     )
                     .await
                 {
                     Ok(_) => {
                         tracing::info!(
-                            "Successfully connected to bootstrap node {} at {}",
-                            bootstrap_info.peer_id,
-                            bootstrap_info.address
-                        );
-                        println!(
-                            "Connected to bootstrap node: {} at {}",
-                            bootstrap_info.peer_id, bootstrap_info.address
+                            "Successfully connected to bootstrap node {}",
+                            bootstrap_info
                         );
+                        println!("Connected to bootstrap node: {}", bootstrap_info);
                     }
                     Err(e) => {
                         tracing::warn!("Failed to connect to bootstrap node: {}", e);
@@ -184,42 +404,481 @@ async fn connect_to_bootstrap_node(
     }
 }
 
-pub async fn handle_node_id() -> Result<()> {
-    let kp = match storage::load_keypair() {
+/// 读取引导节点列表文件，为其中每个节点启动后台连接任务
+///
+/// 未指定 --bootstrap-file 时读取 `<root>/bootstrap.txt`，文件不存在则跳过
+async fn connect_to_bootstrap_list(
+    node: &megaengine::node::node::Node,
+    root_path: &str,
+    bootstrap_file: Option<String>,
+) {
+    let explicit = bootstrap_file.is_some();
+    let path = bootstrap_file.unwrap_or_else(|| format!("{}/bootstrap.txt", root_path));
+    let content = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if !explicit && e.kind() == std::io::ErrorKind::NotFound => return,
+        Err(e) => {
+            tracing::warn!("Failed to read bootstrap list {}: {}", path, e);
+            eprintln!("Warning: Failed to read bootstrap list {}: {}", path, e);
+            return;
+        }
+    };
+    let entries = match NodeAddr::parse_list(&content) {
+        Ok(entries) => entries,
+        Err(e) => {
+            tracing::error!("Invalid bootstrap list {}: {}", path, e);
+            eprintln!("Error: invalid bootstrap list {}: {}", path, e);
+            return;
+        }
+    };
+
+    tracing::info!("Loaded {} bootstrap nodes from {}", entries.len(), path);
+    for entry in entries {
+        if &entry.peer_id == node.node_id() {
+            continue;
+        }
+        let node = node.clone();
+        tokio::spawn(async move {
+            connect_to_bootstrap_node(&node, entry.to_string()).await;
+        });
+    }
+}
+
+/// 为 nodes 表中的每个已知节点启动后台重连任务，失败时按指数退避重试
+async fn reconnect_known_peers(node: &megaengine::node::node::Node) {
+    let Some(conn_mgr) = &node.connection_manager else {
+        return;
+    };
+
+    let known_nodes = match node_model::list_nodes().await {
+        Ok(nodes) => nodes,
+        Err(e) => {
+            tracing::warn!("Failed to load known nodes for reconnect: {}", e);
+            return;
+        }
+    };
+
+    let self_id = node.node_id().clone();
+    let mut scheduled = 0;
+    for info in known_nodes {
+        if info.node_id == self_id || info.addresses.is_empty() {
+            continue;
+        }
+
+        let conn_mgr = Arc::clone(conn_mgr);
+        let self_id = self_id.clone();
+        scheduled += 1;
+        tokio::spawn(async move {
+            let mut backoff = RECONNECT_INITIAL_BACKOFF;
+            for attempt in 1..=RECONNECT_MAX_ATTEMPTS {
+                // 克隆出 ConnectionManager，避免在握手期间持有锁
+                let mgr = conn_mgr.lock().await.clone();
+                if mgr.list_peers().await.contains(&info.node_id) {
+                    return;
+                }
+
+                match mgr
+                    .connect(
+                        self_id.clone(),
+                        info.node_id.clone(),
+                        info.addresses.clone(),
+                    )
+                    .await
+                {
+                    Ok(_) => {
+                        tracing::info!(
+                            "Reconnected to known peer {} ({})",
+                            info.node_id,
+                            info.alias
+                        );
+                        return;
+                    }
+                    Err(e) => {
+                        tracing::debug!(
+                            "Reconnect attempt {}/{} to {} failed: {}; retrying in {:?}",
+                            attempt,
+                            RECONNECT_MAX_ATTEMPTS,
+                            info.node_id,
+                            e,
+                            backoff
+                        );
+                    }
+                }
+
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(RECONNECT_MAX_BACKOFF);
+            }
+            tracing::debug!("Giving up reconnecting to known peer {}", info.node_id);
+        });
+    }
+
+    tracing::info!("Scheduled reconnect to {} known peers", scheduled);
+}
+
+pub async fn handle_node_id(addr: Option<String>, qr: bool, profile: Option<&str>) -> Result<()> {
+    let kp = match storage::load_keypair(profile) {
         Ok(k) => k,
         Err(e) => {
             tracing::error!("failed to load keypair: {}", e);
-            tracing::info!("Run `auth init` first to generate keys");
+            tracing::info!("Run `auth init` first to generate keys, or `node doctor` to check the setup");
             return Ok(());
         }
     };
 
     let node_id = megaengine::node::node_id::NodeId::from_keypair(&kp);
-    println!("{}", node_id);
+    let Some(addr) = addr else {
+        println!("{}", node_id);
+        return print_qr(&node_id.to_string(), qr);
+    };
+
+    // 按 --bootstrap-node 的格式解析一遍，保证输出可以原样传回
+    let node_addr = NodeAddr::parse(&format!("{}@{}", node_id, addr))?;
+    if node_addr.addresses.iter().any(|a| a.ip().is_unspecified()) {
+        eprintln!("⚠️  Unspecified addresses like 0.0.0.0 cannot be dialed by other nodes");
+    }
+    println!("{}", node_addr);
+    print_qr(&node_addr.to_string(), qr)
+}
+
+#[cfg(feature = "qr")]
+fn print_qr(data: &str, qr: bool) -> Result<()> {
+    use qrcode::render::unicode::Dense1x2;
+
+    if !qr {
+        return Ok(());
+    }
+    let code = qrcode::QrCode::new(data.as_bytes())?;
+    // 终端通常是深色背景，反转颜色以便手机扫描
+    let image = code
+        .render::<Dense1x2>()
+        .dark_color(Dense1x2::Light)
+        .light_color(Dense1x2::Dark)
+        .build();
+    println!("{}", image);
     Ok(())
 }
 
-pub async fn handle_node(root_path: String, action: crate::NodeAction) -> Result<()> {
+#[cfg(not(feature = "qr"))]
+fn print_qr(_data: &str, qr: bool) -> Result<()> {
+    if qr {
+        return Err(anyhow::anyhow!(
+            "QR output is not available; rebuild with `--features qr`"
+        ));
+    }
+    Ok(())
+}
+
+/// `node ping` 等待连接建立或单个 Pong 的超时时间
+const PING_TIMEOUT: Duration = Duration::from_secs(5);
+/// `node ping` 相邻两次 Ping 的间隔
+const PING_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 连接对端后发送 gossip Ping，打印每次的往返时间及 min/avg/max 统计
+pub async fn handle_node_ping(
+    root_path: &str,
+    addr: String,
+    peer_id: String,
+    count: u32,
+    profile: Option<&str>,
+) -> Result<()> {
+    let kp = match storage::load_keypair(profile) {
+        Ok(k) => k,
+        Err(e) => {
+            tracing::error!("failed to load keypair: {}", e);
+            tracing::info!("Run `auth init` first to generate keys, or `node doctor` to check the setup");
+            return Ok(());
+        }
+    };
+    let addrs: Vec<String> = addr.split(',').map(|a| a.trim().to_string()).collect();
+    let addrs = parse_socket_addrs(&addrs, "peer")?;
+    let peer = crate::cli::chat::resolve_recipient(&peer_id).await?;
+
+    let root = std::path::Path::new(root_path);
+    let config = Config::load(root)?;
+    let cert_dir = config.cert_dir(root).to_string_lossy().to_string();
+    let bind = if addrs.first().is_some_and(|a| a.is_ipv6()) {
+        "[::]:0"
+    } else {
+        "0.0.0.0:0"
+    };
+    let mgr =
+        ConnectionManager::run_server(node_quic_config(&config, &cert_dir, bind.parse()?, &kp))
+            .await?;
+    let node = Node::from_keypair(&kp, &config.node.alias, Vec::new(), NodeType::Normal);
+    let (gossip_tx, mut gossip_rx) = mpsc::channel(64);
+    mgr.register_gossip_sender(gossip_tx).await;
+
+    println!("PING {} ({})", peer, addr);
+    let started = std::time::Instant::now();
+    let connected = tokio::time::timeout(
+        PING_TIMEOUT,
+        mgr.connect(node.node_id().clone(), peer.clone(), addrs),
+    )
+    .await;
+    match connected {
+        Ok(Ok(())) => println!("Connected in {} ms", started.elapsed().as_millis()),
+        Ok(Err(e)) => {
+            mgr.shutdown().await;
+            anyhow::bail!("Cannot reach {}: {}", peer, e);
+        }
+        Err(_) => {
+            mgr.shutdown().await;
+            anyhow::bail!(
+                "No answer from {} within {} s",
+                peer,
+                PING_TIMEOUT.as_secs()
+            );
+        }
+    }
+
+    let mut rtts = Vec::new();
+    for seq in 1..=count {
+        let probe = ping_peer(&mgr, &node, &mut gossip_rx, &peer);
+        match tokio::time::timeout(PING_TIMEOUT, probe).await {
+            Ok(Ok(rtt)) => {
+                println!(
+                    "Pong from {}: seq={} time={:.2} ms",
+                    peer.short(),
+                    seq,
+                    as_millis_f64(rtt)
+                );
+                rtts.push(rtt);
+            }
+            Ok(Err(e)) => println!("Ping seq={} failed: {}", seq, e),
+            Err(_) => println!("Request timeout for seq={}", seq),
+        }
+        if seq < count {
+            tokio::time::sleep(PING_INTERVAL).await;
+        }
+    }
+    mgr.shutdown().await;
+
+    println!("\n--- {} ping statistics ---", peer.short());
+    let received = rtts.len() as u32;
+    let loss = (count - received) * 100 / count;
+    println!("{} sent, {} received, {}% loss", count, received, loss);
+    match rtt_summary(&rtts) {
+        Some((min, avg, max)) => println!("rtt min/avg/max = {:.2}/{:.2}/{:.2} ms", min, avg, max),
+        // 旧版本节点不认识 Ping
+        None => println!(
+            "No pongs received; the peer may run an older version that does not answer pings"
+        ),
+    }
+    Ok(())
+}
+
+fn as_millis_f64(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+/// 往返时间的最小值、平均值和最大值（毫秒）
+fn rtt_summary(rtts: &[Duration]) -> Option<(f64, f64, f64)> {
+    let min = rtts.iter().min()?;
+    let max = rtts.iter().max()?;
+    let total: Duration = rtts.iter().sum();
+    let avg = total / rtts.len() as u32;
+    Some((as_millis_f64(*min), as_millis_f64(avg), as_millis_f64(*max)))
+}
+
+pub async fn handle_node_stats() -> Result<()> {
+    let data = match std::fs::read(storage::metrics_path()) {
+        Ok(data) => data,
+        Err(_) => {
+            println!(
+                "No metrics available. Is the node running? (metrics are reported every {}s)",
+                METRICS_REPORT_INTERVAL.as_secs()
+            );
+            return Ok(());
+        }
+    };
+    let snapshot: MetricsSnapshot = serde_json::from_slice(&data)?;
+    let m = snapshot.metrics;
+    let age = megaengine::util::timestamp_now() - snapshot.updated_at;
+    println!("📊 Node metrics (reported {}s ago)", age.max(0));
+    println!("   Connections:       {}", m.active_connections);
+    println!(
+        "   Bytes sent:        {} ({} messages)",
+        m.bytes_sent, m.messages_sent
+    );
+    println!("   Bytes received:    {}", m.bytes_received);
+    println!("   Gossip messages:   {}", m.gossip_messages_received);
+    println!("   Data messages:     {}", m.data_messages_received);
+    Ok(())
+}
+
+/// 将 last_seen 格式化为相对时间，例如 "3m ago"
+fn format_last_seen(last_seen: i64) -> String {
+    if last_seen <= 0 {
+        return "never (learned from peers)".to_string();
+    }
+    megaengine::util::format_relative_time(last_seen)
+}
+
+pub async fn handle_node_list(
+    node_type: Option<String>,
+    bootstrap: bool,
+    json: bool,
+) -> Result<()> {
+    let node_type = if bootstrap {
+        Some("bootstrap".to_string())
+    } else {
+        node_type
+    };
+    let mut nodes = node_model::list_nodes_with_last_seen().await?;
+    if let Some(wanted) = node_type {
+        nodes.retain(|(info, _)| format!("{:?}", info.node_type).eq_ignore_ascii_case(&wanted));
+    }
+    nodes.sort_by_key(|(_, last_seen)| std::cmp::Reverse(*last_seen));
+    let left = node_model::left_nodes().await?;
+    if json {
+        let mut values = Vec::with_capacity(nodes.len());
+        for (info, last_seen) in nodes {
+            let left_at = left.get(info.node_id.as_str()).copied().unwrap_or(0);
+            let mut value = serde_json::to_value(&info)?;
+            value["last_seen"] = last_seen.into();
+            value["left_at"] = left_at.into();
+            values.push(value);
+        }
+        println!("{}", serde_json::to_string_pretty(&values)?);
+        return Ok(());
+    }
+    if nodes.is_empty() {
+        println!("No nodes found.");
+        return Ok(());
+    }
+
+    println!("Found {} nodes:", nodes.len());
+    println!("{}", "─".repeat(60));
+    for (info, last_seen) in nodes {
+        println!(
+            "🖥️  Node: {}",
+            if info.alias.is_empty() {
+                "(no alias)"
+            } else {
+                &info.alias
+            }
+        );
+        println!("   ID:          {}", info.node_id);
+        println!("   Type:        {:?}", info.node_type);
+        println!("   Last seen:   {}", format_last_seen(last_seen));
+        if let Some(left_at) = left.get(info.node_id.as_str()) {
+            println!("   Status:      left {}", format_last_seen(*left_at));
+        }
+        // 可直接用作另一个节点的 --bootstrap-node 参数
+        if let Ok(node_addr) = NodeAddr::with_addresses(info.node_id.clone(), info.addresses) {
+            println!("   Address:     {}", node_addr);
+        }
+        println!("{}", "─".repeat(60));
+    }
+    Ok(())
+}
+
+/// 检查证书有效期，即将过期或指定 --force 时重新生成服务器证书
+fn handle_node_cert_renew(root_path: &str, force: bool) -> Result<()> {
+    let config = Config::load(std::path::Path::new(root_path))?;
+    let cert_dir = config.cert_dir(std::path::Path::new(root_path));
+    let cert_path = cert_dir.join("cert.pem").to_string_lossy().to_string();
+    let key_path = cert_dir.join("key.pem").to_string_lossy().to_string();
+    let ca_cert_path = cert_dir.join("ca-cert.pem").to_string_lossy().to_string();
+
+    if let Ok(days) = certificate_days_remaining(&cert_path) {
+        println!("Current certificate expires in {} days", days);
+    }
+    let renewed = renew_certificates(&cert_path, &key_path, &ca_cert_path, force)?;
+    let days = certificate_days_remaining(&cert_path)?;
+    if renewed {
+        println!(
+            "✅ Certificate renewed: {} (expires in {} days)",
+            cert_path, days
+        );
+        println!("   Restart the node to use the new certificate.");
+    } else {
+        println!(
+            "Certificate is valid for more than {} days; use --force to rotate it anyway",
+            CERT_RENEW_BEFORE_DAYS
+        );
+    }
+    Ok(())
+}
+
+pub async fn handle_node(
+    root_path: String,
+    action: crate::NodeAction,
+    profile: Option<&str>,
+) -> Result<()> {
     match action {
         crate::NodeAction::Start {
             alias,
             addr,
+            announce_addr,
             cert_path,
             bootstrap_node,
+            bootstrap_file,
+            as_bootstrap,
+            no_reconnect,
+            passive,
+            enable_relay_store,
+            legacy_tls,
+            strict_peer_certs,
+            compress_bundles,
+            repo_check_interval,
             mcp,
             mcp_sse_port,
         } => {
-            handle_node_start(
-                &root_path,
-                alias,
-                addr,
-                cert_path,
-                bootstrap_node,
-                mcp,
-                mcp_sse_port,
-            )
-            .await
-        }
-        crate::NodeAction::Id => handle_node_id().await,
+            // 命令行参数覆盖配置文件中的值
+            let mut config = Config::load(std::path::Path::new(&root_path))?;
+            let node_config = &mut config.node;
+            if let Some(alias) = alias {
+                node_config.alias = alias;
+            }
+            if !addr.is_empty() {
+                node_config.listen = addr;
+            }
+            if !announce_addr.is_empty() {
+                node_config.announce = announce_addr;
+            }
+            if let Some(cert_path) = cert_path {
+                node_config.cert_dir = cert_path;
+            }
+            if let Some(bootstrap_node) = bootstrap_node {
+                node_config.bootstrap = vec![bootstrap_node];
+            }
+            if bootstrap_file.is_some() {
+                node_config.bootstrap_file = bootstrap_file;
+            }
+            if let Some(interval) = repo_check_interval {
+                node_config.repo_check_interval_secs = interval;
+            }
+            node_config.as_bootstrap |= as_bootstrap;
+            node_config.relay |= enable_relay_store;
+            node_config.passive |= passive;
+            node_config.reconnect &= !no_reconnect;
+            node_config.identity_tls &= !legacy_tls;
+            node_config.accept_legacy_certs &= !strict_peer_certs;
+            config.bundle.compress |= compress_bundles;
+            if config.node.as_bootstrap && config.node.relay {
+                anyhow::bail!("A node cannot be both a bootstrap node and a relay");
+            }
+
+            handle_node_start(&root_path, &config, mcp, mcp_sse_port, profile).await
+        }
+        crate::NodeAction::Id { addr, qr } => handle_node_id(addr, qr, profile).await,
+        crate::NodeAction::Stats => handle_node_stats().await,
+        crate::NodeAction::Ping {
+            addr,
+            peer_id,
+            count,
+        } => handle_node_ping(&root_path, addr, peer_id, count, profile).await,
+        crate::NodeAction::List {
+            node_type,
+            bootstrap,
+            json,
+        } => handle_node_list(node_type, bootstrap, json).await,
+        crate::NodeAction::Doctor { bootstrap_node } => {
+            super::doctor::handle_node_doctor(&root_path, bootstrap_node, profile).await
+        }
+        crate::NodeAction::Cert {
+            action: crate::CertAction::Renew { force },
+        } => handle_node_cert_renew(&root_path, force),
     }
 }
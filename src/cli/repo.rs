@@ -1,19 +1,34 @@
 use anyhow::Result;
 use megaengine::{
-    git::pack::{pull_repo_from_bundle, restore_repo_from_bundle},
+    config::Config,
+    git::git_repo::RefFilter,
+    git::pack::{restore_repo_from_bundle_with_progress, verify_bundle},
+    gossip::SignedMessage,
     node::node_id::NodeId,
     repo::{self, repo::Repo, repo_id::RepoId},
     storage,
-    util::timestamp_now,
+    util::{
+        calculate_directory_size_async, format_bytes, format_relative_time, format_timestamp,
+        timestamp_now,
+    },
 };
 use std::path::PathBuf;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
 
-pub async fn handle_repo_add(path: String, description: String) -> Result<()> {
-    let kp = match storage::load_keypair() {
+pub async fn handle_repo_add(
+    path: String,
+    description: String,
+    tags: Vec<String>,
+    dry_run: bool,
+    force: bool,
+    profile: Option<&str>,
+) -> Result<()> {
+    let kp = match storage::load_keypair(profile) {
         Ok(k) => k,
         Err(e) => {
             tracing::error!("failed to load keypair: {}", e);
-            tracing::info!("Run `auth init` first to generate keys");
+            tracing::info!("Run `auth init` first to generate keys, or `node doctor` to check the setup");
             return Ok(());
         }
     };
@@ -37,17 +52,38 @@ pub async fn handle_repo_add(path: String, description: String) -> Result<()> {
     };
 
     let name = megaengine::git::git_repo::repo_name_space(&path);
-    let language = detect_language(&path);
+    // 扫描工作区是阻塞的文件系统操作，放到阻塞线程池中执行
+    let languages = {
+        let path = PathBuf::from(&path);
+        tokio::task::spawn_blocking(move || repo::language::detect_language(&path))
+            .await
+            .ok()
+    };
+    let language = languages
+        .as_ref()
+        .map(|stats| stats.primary.clone())
+        .unwrap_or_else(|| "Unknown".to_string());
 
     // Calculate size: prefer .git directory size (repository data) over working tree size
     let path_p = std::path::Path::new(&path);
     let git_dir = path_p.join(".git");
     let size = if git_dir.exists() {
-        calculate_directory_size(&git_dir)
+        calculate_directory_size_async(git_dir).await
     } else {
         0
     };
 
+    // 统计提交数和贡献者需要遍历提交历史，同样放到阻塞线程池中
+    let history = {
+        let path = path.clone();
+        let limit = history_limit();
+        tokio::task::spawn_blocking(move || megaengine::git::git_repo::history_stats(&path, limit))
+            .await
+            .ok()
+            .and_then(|r| r.ok())
+            .unwrap_or_default()
+    };
+
     // Try to get latest git commit time, fallback to now if failed (e.g. empty repo)
     let latest_commit_at = match megaengine::git::git_repo::get_latest_commit_time(&path) {
         Ok(t) => t,
@@ -61,10 +97,15 @@ pub async fn handle_repo_add(path: String, description: String) -> Result<()> {
         language: language.clone(),
         latest_commit_at,
         size,
+        tags: repo::repo::normalize_tags(tags),
+        commit_count: history.commits,
+        contributors: history.contributors,
     };
 
     let mut repo_obj =
         repo::repo::Repo::new(repo_id.to_string(), desc, PathBuf::from(path.clone()));
+    repo_obj.root_commit = hex::encode(&root_bytes);
+    repo_obj.verified = true;
 
     // Read and populate refs from the git repository
     match megaengine::git::git_repo::read_repo_refs(&path) {
@@ -77,167 +118,211 @@ pub async fn handle_repo_add(path: String, description: String) -> Result<()> {
         }
     }
 
+    if dry_run {
+        print_repo_add_preview(&repo_obj, languages.as_ref());
+        return Ok(());
+    }
+
     let mut manager = repo::repo_manager::RepoManager::new();
-    match manager.register_repo(repo_obj).await {
-        Ok(_) => {
-            tracing::info!("Repo {} added", repo_id);
-            println!("✅ Repository added successfully!");
-            println!("  ID:     {}", repo_id);
-            println!("  Name:   {}", name);
+    // 同一路径已登记为其他 RepoId（如换了密钥或改写了根提交）；--force 时由新记录取代
+    let stale_ids: Vec<String> = manager
+        .get_repo_ids_by_path(&repo_obj.path)
+        .await?
+        .into_iter()
+        .filter(|id| *id != repo_obj.repo_id)
+        .collect();
+    if let Some(other_id) = stale_ids.first() {
+        if !force {
+            anyhow::bail!(
+                "Path {} is already registered as repository {}; re-run with --force to replace it",
+                path,
+                other_id
+            );
         }
-        Err(e) => {
-            tracing::error!("Failed to add repo: {}", e);
-            eprintln!("❌ Failed to add repository: {}", e);
+    }
+    if let Some(existing) = manager.get_repo(&repo_obj.repo_id).await? {
+        // RepoId 由根提交和密钥决定，同一仓库的另一份副本会得到相同的 ID
+        if existing.path != repo_obj.path {
+            println!(
+                "⚠️  Repository {} is already registered at a different path: {}",
+                repo_id,
+                existing.path.display()
+            );
         }
+        if !force {
+            print_repo_info(&existing).await;
+            anyhow::bail!(
+                "Repository {} is already registered; re-run with --force to overwrite the existing record",
+                repo_id
+            );
+        }
+        tracing::info!("Overwriting existing repo {}", repo_id);
+    }
+
+    manager
+        .register_repo(repo_obj)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to add repository: {}", e))?;
+    for stale_id in &stale_ids {
+        manager.remove_repo(stale_id).await.map_err(|e| {
+            anyhow::anyhow!("Failed to remove replaced repository {}: {}", stale_id, e)
+        })?;
+        println!(
+            "⚠️  Replaced repository {} previously registered at {}",
+            stale_id, path
+        );
+    }
+    // 重新添加之前删除过的仓库时，清除本地墓碑
+    if let Err(e) = storage::repo_tombstone::delete_tombstone(&repo_id.to_string()).await {
+        tracing::warn!("Failed to clear tombstone for repo {}: {}", repo_id, e);
+    }
+    tracing::info!("Repo {} added", repo_id);
+    println!("✅ Repository added successfully!");
+    println!("  ID:     {}", repo_id);
+    println!("  Name:   {}", name);
+    if let Some(summary) = languages
+        .map(|stats| stats.summary(3))
+        .filter(|summary| !summary.is_empty())
+    {
+        println!("  Lang:   {}", summary);
     }
     Ok(())
 }
 
-fn detect_language(path: &str) -> String {
-    use std::collections::HashMap;
-    use std::fs;
-
-    let mut ext_counts: HashMap<String, usize> = HashMap::new();
-    let mut stack = vec![PathBuf::from(path)];
-    let mut files_scanned = 0;
-
-    while let Some(dir) = stack.pop() {
-        if files_scanned > 2000 {
-            break;
-        } // limit scanning
-
-        if let Ok(entries) = fs::read_dir(&dir) {
-            for entry in entries.flatten() {
-                let path = entry.path();
-                if path.is_dir() {
-                    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
-                    if name.starts_with('.')
-                        || name == "target"
-                        || name == "node_modules"
-                        || name == "dist"
-                        || name == "build"
-                    {
-                        continue;
-                    }
-                    if stack.len() < 50 {
-                        stack.push(path);
-                    }
-                } else if let Some(ext) = path.extension().and_then(|s| s.to_str()) {
-                    *ext_counts.entry(ext.to_lowercase()).or_insert(0) += 1;
-                    files_scanned += 1;
-                }
-            }
+pub async fn handle_repo_update(repo_id: String) -> Result<()> {
+    let mut repo = match storage::repo_model::load_repo_from_db(&repo_id).await? {
+        Some(repo) => repo,
+        None => {
+            eprintln!("❌ Error: Repository {} not found.", repo_id);
+            return Ok(());
         }
+    };
+    if repo.is_external {
+        eprintln!(
+            "❌ Error: Repository {} is external; only local repositories can be updated.",
+            repo_id
+        );
+        return Ok(());
     }
 
-    let mut lang_stats = HashMap::new();
-    for (ext, count) in ext_counts {
-        let lang = match ext.as_str() {
-            "rs" => "Rust",
-            "go" => "Go",
-            "py" => "Python",
-            "js" => "JavaScript",
-            "ts" | "tsx" => "TypeScript",
-            "java" => "Java",
-            "c" | "h" => "C",
-            "cpp" | "hpp" | "cc" | "cxx" => "C++",
-            "cs" => "C#",
-            "rb" => "Ruby",
-            "php" => "PHP",
-            "html" => "HTML",
-            "css" | "scss" | "less" => "CSS",
-            "swift" => "Swift",
-            "kt" | "kts" => "Kotlin",
-            "scala" => "Scala",
-            "lua" => "Lua",
-            "sh" | "bash" | "zsh" => "Shell",
-            "sql" => "SQL",
-            "md" => "Markdown",
-            "json" | "yaml" | "yml" | "toml" | "xml" => "Config/Data",
-            _ => continue,
-        };
-        *lang_stats.entry(lang).or_insert(0) += count;
+    let path = repo.path.to_string_lossy().to_string();
+    if path.is_empty() || !repo.path.exists() {
+        tracing::warn!("Path of repo {} no longer exists: {}", repo_id, path);
+        eprintln!("⚠️  Repository path {} no longer exists.", path);
+        return Ok(());
     }
-
-    // 找出数量最多的语言，排除配置类
-    lang_stats
-        .into_iter()
-        .filter(|(l, _)| *l != "Config/Data" && *l != "Markdown")
-        .max_by_key(|&(_, count)| count)
-        .map(|(lang, _)| lang.to_string())
-        .unwrap_or_else(|| "Unknown".to_string())
-}
-
-fn calculate_directory_size(path: &std::path::Path) -> u64 {
-    use std::fs;
-
-    const MAX_DEPTH: usize = 64;
-    const MAX_ENTRIES: u64 = 200_000;
-    const MAX_TOTAL_SIZE: u64 = 20 * 1024 * 1024 * 1024; // 20 GiB
-
-    fn walk(path: &std::path::Path, depth: usize, entries_seen: &mut u64, total: &mut u64) {
-        if depth > MAX_DEPTH || *entries_seen >= MAX_ENTRIES || *total >= MAX_TOTAL_SIZE {
-            return;
+    let refs = match megaengine::git::git_repo::read_repo_refs(&path) {
+        Ok(refs) => refs,
+        Err(e) => {
+            tracing::warn!("Failed to read refs of repo {}: {}", repo_id, e);
+            eprintln!("⚠️  {} is not a readable git repository: {}", path, e);
+            return Ok(());
         }
+    };
 
-        let Ok(entries) = fs::read_dir(path) else {
-            return;
-        };
-
-        for entry in entries.flatten() {
-            if *entries_seen >= MAX_ENTRIES || *total >= MAX_TOTAL_SIZE {
-                break;
-            }
-
-            *entries_seen += 1;
-            let p = entry.path();
-
-            // Never follow symlinks to avoid cycles and unbounded traversal.
-            let Ok(meta) = fs::symlink_metadata(&p) else {
-                continue;
-            };
+    println!("📦 Re-packing repository {}...", repo_id);
+    if let Err(e) = repo::repo_sync::repack_local_repo(&mut repo, refs, history_limit()).await {
+        tracing::error!("Failed to repack repo {}: {}", repo_id, e);
+        eprintln!("❌ Failed to update repository: {}", e);
+        return Ok(());
+    }
 
-            let file_type = meta.file_type();
-            if file_type.is_symlink() {
-                continue;
-            }
+    tracing::info!("Repo {} updated", repo_id);
+    println!("✅ Repository updated successfully!");
+    println!("  Refs:   {}", repo.refs.len());
+    println!("  Bundle: {}", repo.bundle.display());
+    println!("  Peers will see the update with the next announcement of the running node.");
+    Ok(())
+}
 
-            if file_type.is_file() {
-                *total = total.saturating_add(meta.len());
-            } else if file_type.is_dir() {
-                walk(&p, depth + 1, entries_seen, total);
-            }
+/// 配置文件中的提交历史遍历上限，配置无法读取时使用默认值
+fn history_limit() -> usize {
+    match Config::load(&storage::data_dir()) {
+        Ok(config) => config.repo.history_limit,
+        Err(e) => {
+            tracing::warn!(
+                "Failed to load config, using default history limit: {:#}",
+                e
+            );
+            megaengine::git::git_repo::DEFAULT_HISTORY_LIMIT
         }
     }
+}
 
-    let mut entries_seen = 0;
-    let mut total = 0;
-    walk(path, 0, &mut entries_seen, &mut total);
-    total
+fn format_commits(desc: &repo::repo::P2PDescription) -> String {
+    let plural = if desc.contributors == 1 { "" } else { "s" };
+    format!(
+        "{} ({} contributor{})",
+        desc.commit_count, desc.contributors, plural
+    )
 }
 
-fn format_bytes(bytes: u64) -> String {
-    const KB: u64 = 1024;
-    const MB: u64 = KB * 1024;
-    const GB: u64 = MB * 1024;
-
-    if bytes >= GB {
-        format!("{:.2} GB", bytes as f64 / GB as f64)
-    } else if bytes >= MB {
-        format!("{:.2} MB", bytes as f64 / MB as f64)
-    } else if bytes >= KB {
-        format!("{:.2} KB", bytes as f64 / KB as f64)
-    } else {
-        format!("{} B", bytes)
+/// `repo add --dry-run`：打印将要登记的元数据，不写数据库也不广播
+fn print_repo_add_preview(repo: &Repo, languages: Option<&repo::language::LanguageStats>) {
+    let desc = &repo.p2p_description;
+    println!("🔍 Dry run: nothing was written");
+    println!("   ID:          {}", repo.repo_id);
+    println!("   Name:        {}", desc.name);
+    println!("   Creator:     {}", desc.creator);
+    match languages
+        .map(|stats| stats.summary(3))
+        .filter(|summary| !summary.is_empty())
+    {
+        Some(summary) => println!("   Language:    {} ({})", desc.language, summary),
+        None => println!("   Language:    {}", desc.language),
     }
+    if !desc.tags.is_empty() {
+        println!("   Tags:        {}", desc.tags.join(", "));
+    }
+    println!(
+        "   Updated:     {} ({})",
+        format_timestamp(desc.latest_commit_at, "%Y-%m-%d %H:%M:%S"),
+        format_relative_time(desc.latest_commit_at)
+    );
+    println!("   Size:        {}", format_bytes(desc.size));
+    println!("   Commits:     {}", format_commits(desc));
+    println!("   Refs:        {}", repo.refs.len());
+    if !desc.description.is_empty() {
+        println!("   Description: {}", desc.description);
+    }
+    println!("   Path:        {}", repo.path.display());
 }
 
-pub async fn handle_repo_list() -> Result<()> {
-    match storage::repo_model::list_repos().await {
+pub async fn handle_repo_list(
+    language: Option<String>,
+    mine: bool,
+    tag: Option<String>,
+    limit: Option<u64>,
+    page: u64,
+    json: bool,
+    profile: Option<&str>,
+) -> Result<()> {
+    let creator = if mine {
+        let keypair = storage::load_keypair(profile)?;
+        Some(NodeId::from_keypair(&keypair).to_string())
+    } else {
+        None
+    };
+    let filter = storage::repo_model::RepoFilter {
+        creator,
+        language,
+        tag,
+        ..Default::default()
+    };
+    let limit = limit.unwrap_or(0);
+    let offset = page.saturating_sub(1) * limit;
+
+    match storage::repo_model::list_repos_paged(offset, limit, filter).await {
+        Ok(repos) if json => {
+            println!("{}", serde_json::to_string_pretty(&repos)?);
+        }
         Ok(repos) => {
             if repos.is_empty() {
                 println!("No repositories found.");
             } else {
+                if limit > 0 {
+                    println!("Page {} ({} per page):", page.max(1), limit);
+                }
                 println!("Found {} repositories:", repos.len());
                 println!("{}", "─".repeat(60));
                 for repo in repos {
@@ -253,17 +338,177 @@ pub async fn handle_repo_list() -> Result<()> {
     Ok(())
 }
 
+pub async fn handle_repo_tag(repo_id: String, add: Vec<String>, remove: Vec<String>) -> Result<()> {
+    match storage::repo_model::load_repo_from_db(&repo_id).await? {
+        None => {
+            eprintln!("❌ Error: Repository {} not found.", repo_id);
+            return Ok(());
+        }
+        Some(repo) if repo.is_external => {
+            eprintln!(
+                "❌ Error: Repository {} belongs to another node; only its creator can tag it.",
+                repo_id
+            );
+            return Ok(());
+        }
+        Some(_) => {}
+    }
+
+    if let Some(tags) = storage::repo_model::update_repo_tags(&repo_id, &add, &remove).await? {
+        println!("✅ Tags updated for {}", repo_id);
+        if tags.is_empty() {
+            println!("   Tags:        (none)");
+        } else {
+            println!("   Tags:        {}", tags.join(", "));
+        }
+        println!("   The new tags are included in the next repository announcement.");
+    }
+    Ok(())
+}
+
+/// `repo share`：维护仓库的拉取白名单，白名单为空时所有节点都可以拉取
+pub async fn handle_repo_share(
+    repo_id: String,
+    with: Vec<String>,
+    revoke: Vec<String>,
+) -> Result<()> {
+    match storage::repo_model::load_repo_from_db(&repo_id).await? {
+        None => {
+            eprintln!("❌ Error: Repository {} not found.", repo_id);
+            return Ok(());
+        }
+        Some(repo) if repo.is_external => {
+            eprintln!(
+                "❌ Error: Repository {} belongs to another node; only its creator can share it.",
+                repo_id
+            );
+            return Ok(());
+        }
+        Some(_) => {}
+    }
+
+    for node in &with {
+        let peer = crate::cli::chat::resolve_recipient(node).await?;
+        if storage::repo_access::allow_peer(&repo_id, peer.as_str()).await? {
+            println!("✅ {} may now pull {}", peer, repo_id);
+        } else {
+            println!("{} is already allowed to pull {}", peer, repo_id);
+        }
+    }
+    for node in &revoke {
+        let peer = crate::cli::chat::resolve_recipient(node).await?;
+        if storage::repo_access::revoke_peer(&repo_id, peer.as_str()).await? {
+            println!("✅ Revoked {} from {}", peer, repo_id);
+        } else {
+            println!("{} was not in the allow-list of {}", peer, repo_id);
+        }
+    }
+
+    let peers = storage::repo_access::allowed_peers(&repo_id).await?;
+    if peers.is_empty() {
+        println!("   Access:      open to all peers");
+    } else {
+        println!("   Access:      {} allowed peer(s)", peers.len());
+        for peer in peers {
+            println!("                {}", peer);
+        }
+    }
+    Ok(())
+}
+
+pub async fn handle_repo_stats(recent: usize, json: bool) -> Result<()> {
+    let stats = repo::stats::collect_repo_stats(recent).await?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+        return Ok(());
+    }
+
+    println!(
+        "Repositories: {} ({} local, {} external)",
+        stats.total, stats.local, stats.external
+    );
+    println!(
+        "Bundles:      {} on disk, {}",
+        stats.bundles,
+        format_bytes(stats.bundle_bytes)
+    );
+    if !stats.languages.is_empty() {
+        let languages: Vec<String> = stats
+            .languages
+            .iter()
+            .map(|l| format!("{} {}", l.language, l.repos))
+            .collect();
+        println!("Languages:    {}", languages.join(", "));
+    }
+    if !stats.recent.is_empty() {
+        println!("Recently updated:");
+        for repo in &stats.recent {
+            let updated = format_timestamp(repo.latest_commit_at, "%Y-%m-%d %H:%M");
+            let origin = if repo.is_external {
+                "external"
+            } else {
+                "local"
+            };
+            println!(
+                "  {}  📦 {} [{}] {}",
+                updated, repo.name, origin, repo.repo_id
+            );
+        }
+    }
+    Ok(())
+}
+
+pub async fn handle_repo_search(query: String) -> Result<()> {
+    let repos = storage::repo_model::search_repos(&query).await?;
+    if repos.is_empty() {
+        println!("No repositories match \"{}\".", query);
+        return Ok(());
+    }
+
+    println!("Found {} repositories matching \"{}\":", repos.len(), query);
+    for repo in repos {
+        let origin = if repo.is_external {
+            "external"
+        } else {
+            "local"
+        };
+        println!(
+            "📦 {} [{}] {}",
+            repo.p2p_description.name, origin, repo.repo_id
+        );
+        if !repo.p2p_description.description.is_empty() {
+            println!("   {}", repo.p2p_description.description);
+        }
+        if !repo.p2p_description.tags.is_empty() {
+            println!("   #{}", repo.p2p_description.tags.join(" #"));
+        }
+    }
+    Ok(())
+}
+
 async fn print_repo_info(repo: &Repo) {
     println!("📦 Repo: {}", repo.p2p_description.name);
     println!("   ID:          {}", repo.repo_id);
     println!("   Creator:     {}", repo.p2p_description.creator);
+    if repo.is_external {
+        let verified = if repo.verified {
+            "yes (id matches root commit and creator)"
+        } else {
+            "no (root commit not announced)"
+        };
+        println!("   Verified:    {}", verified);
+    }
     println!("   Language:    {}", repo.p2p_description.language);
+    if !repo.p2p_description.tags.is_empty() {
+        println!("   Tags:        {}", repo.p2p_description.tags.join(", "));
+    }
     if repo.p2p_description.latest_commit_at > 0 {
-        if let Some(dt) = chrono::DateTime::from_timestamp(repo.p2p_description.latest_commit_at, 0)
-        {
-            let local = dt.with_timezone(&chrono::Local);
-            println!("   Updated:     {}", local.format("%Y-%m-%d %H:%M:%S"));
-        }
+        let updated = repo.p2p_description.latest_commit_at;
+        println!(
+            "   Updated:     {} ({})",
+            format_timestamp(updated, "%Y-%m-%d %H:%M:%S"),
+            format_relative_time(updated)
+        );
     }
     if repo.p2p_description.size > 0 {
         println!(
@@ -271,6 +516,9 @@ async fn print_repo_info(repo: &Repo) {
             format_bytes(repo.p2p_description.size)
         );
     }
+    if repo.p2p_description.commit_count > 0 {
+        println!("   Commits:     {}", format_commits(&repo.p2p_description));
+    }
     if !repo.p2p_description.description.is_empty() {
         println!("   Description: {}", repo.p2p_description.description);
     }
@@ -292,17 +540,23 @@ async fn print_repo_info(repo: &Repo) {
                 let mut status_msg = "✅ Synced".to_string();
                 let mut updates = Vec::new();
 
+                // 只比较 refs/heads/* 和 refs/tags/*，远程跟踪分支和 HEAD 不参与比较
                 if !repo.path.as_os_str().is_empty() && repo.path.exists() {
-                    match megaengine::git::git_repo::read_repo_refs(
+                    match megaengine::git::git_repo::pending_ref_updates(
                         repo.path.to_str().unwrap_or(""),
+                        &local_refs,
+                        RefFilter::default(),
                     ) {
-                        Ok(current_refs) => {
-                            if current_refs != local_refs {
+                        Ok(pending) => {
+                            if !pending.is_empty() {
                                 status_msg = "⚠️  Out of Sync".to_string();
-                                for (ref_name, commit) in &current_refs {
-                                    if local_refs.get(ref_name) != Some(commit) {
-                                        updates.push(format!("{} -> {}", ref_name, &commit[0..7]));
+                            }
+                            for (ref_name, commit) in pending {
+                                match commit {
+                                    Some(commit) => {
+                                        updates.push(format!("{} -> {}", ref_name, &commit[0..7]))
                                     }
+                                    None => updates.push(format!("{} (deleted)", ref_name)),
                                 }
                             }
                         }
@@ -334,49 +588,33 @@ pub async fn handle_repo_pull(repo_id: String) -> Result<()> {
     println!("🔄 Pulling repository {}...", repo_id);
     match storage::repo_model::load_repo_from_db(&repo_id).await {
         Ok(Some(repo)) => {
-            // Check if repo has a local path
-            if repo.path.as_os_str().is_empty() {
-                tracing::error!("Repository {} has no local path", repo_id);
-                eprintln!(
-                    "❌ Error: Repository {} has no local path configured.",
-                    repo_id
-                );
-                return Ok(());
-            }
-            // Check if bundle exists
-            if repo.bundle.as_os_str().is_empty() {
-                tracing::error!("Repository {} has no bundle available", repo_id);
-                eprintln!("❌ Error: Repository {} has no bundle available.", repo_id);
-                return Ok(());
-            }
-
-            let path_str = match repo.path.as_os_str().to_str() {
-                Some(s) => s,
-                None => {
-                    eprintln!("❌ Error: Local path is not valid UTF-8.");
-                    return Ok(());
-                }
-            };
-
-            let bundle_str = match repo.bundle.as_os_str().to_str() {
-                Some(s) => s,
-                None => {
-                    eprintln!("❌ Error: Bundle path is not valid UTF-8.");
-                    return Ok(());
-                }
-            };
-
-            let result = pull_repo_from_bundle(path_str, bundle_str, "master");
+            let pulled = repo.clone();
+            let result = tokio::task::spawn_blocking(move || pulled.pull_from_bundle()).await?;
 
             match result {
-                Ok(()) => {
+                Ok(report) => {
                     tracing::info!("Repository {} fetched successfully from bundle", repo_id);
-                    println!("✅ Repository updated successfully!");
+                    if report.is_up_to_date() {
+                        println!("✅ Repository is already up to date.");
+                    } else {
+                        println!("✅ Repository updated successfully!");
+                    }
                     println!("   Name: {}", repo.p2p_description.name);
                     println!("   Path: {}", repo.path.display());
+                    for (name, old, new) in &report.advanced {
+                        println!(
+                            "   Updated: {} {}..{}",
+                            name,
+                            short_hash(old),
+                            short_hash(new)
+                        );
+                    }
+                    for (name, commit) in &report.created {
+                        println!("   New:     {} {}", name, short_hash(commit));
+                    }
                 }
                 Err(e) => {
-                    tracing::error!("Failed to spawn fetch task: {}", e);
+                    tracing::error!("Failed to pull repository {}: {}", repo_id, e);
                     eprintln!("❌ Failed to update repository: {}", e);
                 }
             }
@@ -393,6 +631,166 @@ pub async fn handle_repo_pull(repo_id: String) -> Result<()> {
     Ok(())
 }
 
+pub async fn handle_repo_fetch(repo_id: String, from: String) -> Result<()> {
+    if storage::repo_model::load_repo_from_db(&repo_id)
+        .await?
+        .is_none()
+    {
+        eprintln!(
+            "❌ Error: Repository {} is unknown. Wait for its announcement first.",
+            repo_id
+        );
+        return Ok(());
+    }
+
+    let peer = crate::cli::chat::resolve_recipient(&from).await?;
+    storage::fetch_request::save_fetch_request(&repo_id, peer.as_str()).await?;
+    println!("📨 Fetch request for {} queued to {}", repo_id, peer);
+    println!("   The running node will send it and store the received bundle.");
+    println!("   Use `repo clone` or `repo pull` once the bundle has arrived.");
+    Ok(())
+}
+
+/// `repo history`：不克隆仓库，直接从本地工作区或 bundle 列出最近的提交
+pub async fn handle_repo_history(repo_id: String, limit: usize) -> Result<()> {
+    let repo = match storage::repo_model::load_repo_from_db(&repo_id).await? {
+        Some(repo) => repo,
+        None => return Err(anyhow::anyhow!("Repository {} not found", repo_id)),
+    };
+
+    let name = repo.p2p_description.name.clone();
+    let commits = tokio::task::spawn_blocking(move || repo.recent_commits(limit))
+        .await?
+        .map_err(|e| anyhow::anyhow!("Cannot read history: {}", e))?;
+    if commits.is_empty() {
+        println!("No commits in {}", name);
+        return Ok(());
+    }
+
+    println!("📜 Last {} commit(s) of {}:", commits.len(), name);
+    for commit in &commits {
+        let date = format_timestamp(commit.time, "%Y-%m-%d %H:%M");
+        println!(
+            "   {}  {}  {:<20}  {}",
+            short_hash(&commit.id),
+            date,
+            commit.author,
+            commit.subject
+        );
+    }
+    Ok(())
+}
+
+pub async fn handle_repo_verify(repo_id: String) -> Result<()> {
+    let repo = match storage::repo_model::load_repo_from_db(&repo_id).await? {
+        Some(repo) => repo,
+        None => return Err(anyhow::anyhow!("Repository {} not found", repo_id)),
+    };
+    if repo.bundle.as_os_str().is_empty() {
+        return Err(anyhow::anyhow!("Repository {} has no bundle", repo_id));
+    }
+
+    println!("🔍 Verifying bundle {}...", repo.bundle.display());
+    let clone_path = repo.path.to_string_lossy().to_string();
+    let bundle_refs = verify_bundle(
+        &repo.bundle.to_string_lossy(),
+        (!clone_path.is_empty()).then_some(clone_path.as_str()),
+    )
+    .map_err(|e| anyhow::anyhow!("Bundle of {} is missing or corrupt: {}", repo_id, e))?;
+    let stored_refs = storage::ref_model::load_refs_for_repo(&repo_id).await?;
+
+    let mut names: Vec<&String> = bundle_refs.keys().chain(stored_refs.keys()).collect();
+    names.sort();
+    names.dedup();
+
+    let mut mismatches = 0;
+    for name in names {
+        match (stored_refs.get(name), bundle_refs.get(name)) {
+            (Some(stored), Some(bundled)) if stored == bundled => {}
+            (Some(stored), Some(bundled)) => {
+                println!(
+                    "   ≠ {}: stored {} but bundle has {}",
+                    name,
+                    short_hash(stored),
+                    short_hash(bundled)
+                );
+                mismatches += 1;
+            }
+            (Some(stored), None) => {
+                println!("   - {}: {} missing from bundle", name, short_hash(stored));
+                mismatches += 1;
+            }
+            (None, Some(bundled)) => {
+                println!("   + {}: {} not in stored refs", name, short_hash(bundled));
+                mismatches += 1;
+            }
+            (None, None) => {}
+        }
+    }
+
+    if mismatches > 0 {
+        return Err(anyhow::anyhow!(
+            "Bundle of {} does not match stored refs ({} mismatched)",
+            repo_id,
+            mismatches
+        ));
+    }
+    println!(
+        "✅ Bundle is intact and matches {} stored refs",
+        bundle_refs.len()
+    );
+    Ok(())
+}
+
+pub async fn handle_repo_export(repo_id: String, out: String, verify: bool) -> Result<()> {
+    let repo = match storage::repo_model::load_repo_from_db(&repo_id).await? {
+        Some(repo) => repo,
+        None => return Err(anyhow::anyhow!("Repository {} not found", repo_id)),
+    };
+    if repo.bundle.as_os_str().is_empty() {
+        return Err(anyhow::anyhow!("Repository {} has no bundle", repo_id));
+    }
+    if !repo.bundle.exists() {
+        return Err(anyhow::anyhow!(
+            "Bundle of {} not found at {}",
+            repo_id,
+            repo.bundle.display()
+        ));
+    }
+
+    let out_path = PathBuf::from(&out);
+    if out_path.exists() {
+        return Err(anyhow::anyhow!("{} already exists", out_path.display()));
+    }
+
+    if verify {
+        verify_bundle(&repo.bundle.to_string_lossy(), None)
+            .map_err(|e| anyhow::anyhow!("Bundle of {} is corrupt: {}", repo_id, e))?;
+    }
+
+    if let Some(parent) = out_path.parent() {
+        if !parent.as_os_str().is_empty() {
+            std::fs::create_dir_all(parent)?;
+        }
+    }
+    let size = std::fs::copy(&repo.bundle, &out_path).map_err(|e| {
+        anyhow::anyhow!(
+            "Failed to copy {} to {}: {}",
+            repo.bundle.display(),
+            out_path.display(),
+            e
+        )
+    })?;
+
+    println!(
+        "📦 Exported bundle of {} to {}",
+        repo_id,
+        out_path.display()
+    );
+    println!("   Size:        {}", format_bytes(size));
+    Ok(())
+}
+
 pub async fn handle_repo_clone(output: String, repo_id: String) -> Result<()> {
     println!("📥 Cloning repository {}...", repo_id);
     match storage::repo_model::load_repo_from_db(&repo_id).await {
@@ -418,7 +816,33 @@ pub async fn handle_repo_clone(output: String, repo_id: String) -> Result<()> {
                 output
             );
 
-            match restore_repo_from_bundle(&bundle_path, &output).await {
+            // 逐行打印 git clone 进度，Ctrl-C 时中止克隆并删除未完成的目录
+            let (progress_tx, mut progress_rx) = mpsc::channel::<String>(64);
+            let printer = tokio::spawn(async move {
+                while let Some(line) = progress_rx.recv().await {
+                    eprintln!("   {}", line);
+                }
+            });
+            let cancel = CancellationToken::new();
+            let ctrl_c = {
+                let cancel = cancel.clone();
+                tokio::spawn(async move {
+                    if tokio::signal::ctrl_c().await.is_ok() {
+                        cancel.cancel();
+                    }
+                })
+            };
+            let result = restore_repo_from_bundle_with_progress(
+                &bundle_path,
+                &output,
+                Some(progress_tx),
+                cancel,
+            )
+            .await;
+            ctrl_c.abort();
+            let _ = printer.await;
+
+            match result {
                 Ok(_) => {
                     tracing::info!("Repository {} cloned successfully to {}", repo_id, output);
                     println!("✅ Repository cloned successfully!");
@@ -477,11 +901,150 @@ pub async fn handle_repo_clone(output: String, repo_id: String) -> Result<()> {
     Ok(())
 }
 
-pub async fn handle_repo(action: crate::RepoAction) -> Result<()> {
+pub async fn handle_repo_remove(
+    repo_id: String,
+    keep_bundle: bool,
+    profile: Option<&str>,
+) -> Result<()> {
+    let mut manager = repo::repo_manager::RepoManager::new();
+    let repo = match manager.remove_repo(&repo_id).await {
+        Ok(Some(repo)) => repo,
+        Ok(None) => {
+            tracing::error!("Repository {} not found in database", repo_id);
+            eprintln!("❌ Error: Repository {} not found.", repo_id);
+            return Ok(());
+        }
+        Err(e) => {
+            tracing::error!("Failed to remove repository {}: {}", repo_id, e);
+            eprintln!("❌ Failed to remove repository: {}", e);
+            return Ok(());
+        }
+    };
+
+    if repo.is_external {
+        tracing::warn!("Removing external repository {}", repo_id);
+        println!(
+            "⚠️  Repository {} is an external repository announced by {}; it may be re-added by gossip.",
+            repo_id, repo.p2p_description.creator
+        );
+    }
+
+    if let Err(e) = storage::ref_model::delete_refs_for_repo(&repo_id).await {
+        tracing::warn!("Failed to delete refs for repo {}: {}", repo_id, e);
+    }
+
+    // 本地创建的仓库：记录创建者签名的删除墓碑，由运行中的节点广播给其他节点
+    if !repo.is_external {
+        queue_repo_deletion(&repo, profile).await;
+    }
+
+    if !keep_bundle && !repo.bundle.as_os_str().is_empty() && repo.bundle.exists() {
+        match std::fs::remove_file(&repo.bundle) {
+            Ok(_) => tracing::info!("Deleted bundle file {}", repo.bundle.display()),
+            Err(e) => {
+                tracing::warn!(
+                    "Failed to delete bundle file {}: {}",
+                    repo.bundle.display(),
+                    e
+                );
+            }
+        }
+    }
+
+    tracing::info!("Repo {} removed", repo_id);
+    println!("✅ Repository removed successfully!");
+    println!("  ID:     {}", repo_id);
+    println!("  Name:   {}", repo.p2p_description.name);
+    if keep_bundle && !repo.bundle.as_os_str().is_empty() {
+        println!("  Bundle: {} (kept)", repo.bundle.display());
+    }
+    Ok(())
+}
+
+async fn queue_repo_deletion(repo: &Repo, profile: Option<&str>) {
+    let kp = match storage::load_keypair(profile) {
+        Ok(k) => k,
+        Err(e) => {
+            tracing::warn!("Failed to load keypair, deletion not announced: {}", e);
+            return;
+        }
+    };
+
+    let node_id = NodeId::from_keypair(&kp);
+    if repo.p2p_description.creator != node_id.to_string() {
+        tracing::warn!(
+            "Repo {} was created by {}, deletion not announced",
+            repo.repo_id,
+            repo.p2p_description.creator
+        );
+        return;
+    }
+
+    let signed = match SignedMessage::new_repo_deletion_sign_message(repo.repo_id.clone(), &kp) {
+        Ok(s) => s,
+        Err(e) => {
+            tracing::warn!("Failed to sign deletion of repo {}: {}", repo.repo_id, e);
+            return;
+        }
+    };
+
+    match storage::repo_tombstone::save_tombstone(&repo.repo_id, node_id.as_str(), &signed).await {
+        Ok(_) => {
+            println!("Deletion will be announced to peers when the node service is active.");
+        }
+        Err(e) => {
+            tracing::warn!("Failed to save tombstone for repo {}: {}", repo.repo_id, e);
+        }
+    }
+}
+
+pub async fn handle_repo(action: crate::RepoAction, profile: Option<&str>) -> Result<()> {
     match action {
-        crate::RepoAction::Add { path, description } => handle_repo_add(path, description).await,
-        crate::RepoAction::List => handle_repo_list().await,
+        crate::RepoAction::Add {
+            path,
+            description,
+            tags,
+            dry_run,
+            force,
+        } => handle_repo_add(path, description, tags, dry_run, force, profile).await,
+        crate::RepoAction::List {
+            language,
+            mine,
+            tag,
+            limit,
+            page,
+            json,
+        } => handle_repo_list(language, mine, tag, limit, page, json, profile).await,
+        crate::RepoAction::Tag {
+            repo_id,
+            add,
+            remove,
+        } => handle_repo_tag(repo_id, add, remove).await,
+        crate::RepoAction::Share {
+            repo_id,
+            with,
+            revoke,
+        } => handle_repo_share(repo_id, with, revoke).await,
+        crate::RepoAction::Stats { recent, json } => handle_repo_stats(recent, json).await,
+        crate::RepoAction::Search { query } => handle_repo_search(query).await,
         crate::RepoAction::Pull { repo_id } => handle_repo_pull(repo_id).await,
+        crate::RepoAction::Fetch { repo_id, from } => handle_repo_fetch(repo_id, from).await,
+        crate::RepoAction::Update { repo_id } => handle_repo_update(repo_id).await,
+        crate::RepoAction::Verify { repo_id } => handle_repo_verify(repo_id).await,
+        crate::RepoAction::History { repo_id, limit } => handle_repo_history(repo_id, limit).await,
+        crate::RepoAction::Export {
+            repo_id,
+            out,
+            verify,
+        } => handle_repo_export(repo_id, out, verify).await,
         crate::RepoAction::Clone { output, repo_id } => handle_repo_clone(output, repo_id).await,
+        crate::RepoAction::Remove {
+            repo_id,
+            keep_bundle,
+        } => handle_repo_remove(repo_id, keep_bundle, profile).await,
     }
 }
+
+fn short_hash(hash: &str) -> &str {
+    &hash[..hash.len().min(7)]
+}
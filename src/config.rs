@@ -0,0 +1,295 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::gossip::ForwardRateLimit;
+
+/// 数据目录下的配置文件名
+pub const CONFIG_FILE_NAME: &str = "megaengine.toml";
+
+/// 解析数据目录：`--root` 参数优先，其次是 `MEGAENGINE_ROOT` 环境变量，最后是 `~/.megaengine`
+///
+/// 两者都支持以 `~/` 开头的路径
+pub fn resolve_data_dir(root_arg: Option<&str>) -> PathBuf {
+    if let Some(root) = root_arg {
+        return expand_tilde(root);
+    }
+    if let Ok(root) = std::env::var("MEGAENGINE_ROOT") {
+        return expand_tilde(&root);
+    }
+    match home_dir() {
+        Some(home) => home.join(".megaengine"),
+        // As a last resort fall back to cwd/.megaengine
+        None => std::env::current_dir()
+            .unwrap_or_else(|_| PathBuf::from("."))
+            .join(".megaengine"),
+    }
+}
+
+/// 将开头的 `~` 展开为用户主目录，找不到主目录时原样返回
+pub fn expand_tilde(path: &str) -> PathBuf {
+    let rest = if path == "~" {
+        Some("")
+    } else {
+        path.strip_prefix("~/")
+    };
+    match (rest, home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+fn home_dir() -> Option<PathBuf> {
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+}
+
+/// 节点配置，从数据目录下的 `megaengine.toml` 加载
+///
+/// 优先级：命令行参数 > 配置文件 > 默认值。文件中缺省的字段使用默认值
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub node: NodeConfig,
+    pub gossip: GossipConfig,
+    pub bundle: BundleConfig,
+    pub repo: RepoConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NodeConfig {
+    pub alias: String,
+    /// 监听地址
+    pub listen: Vec<String>,
+    /// 对外公告的地址，为空时公告实际绑定的地址
+    pub announce: Vec<String>,
+    /// 证书目录，相对路径基于数据目录
+    pub cert_dir: String,
+    /// 启动时连接的引导节点，格式为 peer_id@address[,address...]
+    pub bootstrap: Vec<String>,
+    /// 引导节点列表文件，默认使用数据目录下的 bootstrap.txt
+    pub bootstrap_file: Option<String>,
+    /// 作为长期在线的引导节点公告
+    pub as_bootstrap: bool,
+    /// 作为中继节点运行
+    pub relay: bool,
+    /// 信任的中继节点 NodeId，它们转交的数据总是接受
+    pub trusted_relays: Vec<String>,
+    /// 只记录 PeerExchange 学到的节点，不主动连接
+    pub passive: bool,
+    /// 启动时重连节点表中已知的节点
+    pub reconnect: bool,
+    /// 检查本地仓库新提交的间隔（秒）
+    pub repo_check_interval_secs: u64,
+    /// 使用由身份密钥派生的 TLS 证书，并校验对端证书与其 NodeId 一致；
+    /// 关闭时回退到证书目录中的文件证书
+    pub identity_tls: bool,
+    /// 开启 identity_tls 时仍接受旧版节点的文件证书
+    pub accept_legacy_certs: bool,
+    /// 连接空闲多久后关闭（秒）
+    pub connection_idle_timeout_secs: u64,
+    /// 连接保活包的发送间隔（秒），必须小于空闲超时，为 0 时不发送
+    pub keep_alive_interval_secs: u64,
+}
+
+impl Default for NodeConfig {
+    fn default() -> Self {
+        Self {
+            alias: "mega-node".to_string(),
+            listen: vec!["0.0.0.0:9000".to_string()],
+            announce: Vec::new(),
+            cert_dir: "cert".to_string(),
+            bootstrap: Vec::new(),
+            bootstrap_file: None,
+            as_bootstrap: false,
+            relay: false,
+            trusted_relays: Vec::new(),
+            passive: false,
+            reconnect: true,
+            repo_check_interval_secs: 60,
+            identity_tls: true,
+            accept_legacy_certs: true,
+            connection_idle_timeout_secs: crate::transport::config::DEFAULT_IDLE_TIMEOUT.as_secs(),
+            keep_alive_interval_secs: crate::transport::config::DEFAULT_KEEP_ALIVE_INTERVAL
+                .as_secs(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GossipConfig {
+    /// 通过 PeerExchange 主动连接时的最大连接数
+    pub max_connections: usize,
+    /// 每个邻居每秒最多转发的消息数
+    pub forward_messages_per_sec: u32,
+    /// 每个邻居每秒最多转发的字节数
+    pub forward_bytes_per_sec: u64,
+    /// 向直连邻居发送存活探测的间隔（秒）
+    pub ping_interval_secs: u64,
+}
+
+impl Default for GossipConfig {
+    fn default() -> Self {
+        let limit = ForwardRateLimit::default();
+        Self {
+            max_connections: crate::gossip::DEFAULT_MAX_CONNECTIONS,
+            forward_messages_per_sec: limit.messages_per_sec,
+            forward_bytes_per_sec: limit.bytes_per_sec,
+            ping_interval_secs: crate::gossip::DEFAULT_PING_INTERVAL.as_secs(),
+        }
+    }
+}
+
+impl GossipConfig {
+    pub fn forward_rate_limit(&self) -> ForwardRateLimit {
+        ForwardRateLimit {
+            messages_per_sec: self.forward_messages_per_sec,
+            bytes_per_sec: self.forward_bytes_per_sec,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BundleConfig {
+    /// 发送 bundle 时使用 zstd 压缩
+    pub compress: bool,
+    /// 接收中的传输在此时间内没有收到数据即视为中断（秒）
+    pub idle_timeout_secs: u64,
+    /// 对所有发出的 bundle 做端到端加密（有白名单的仓库总是加密）
+    pub encrypt: bool,
+    /// 放大 QUIC 流控窗口以提高大 bundle 的传输吞吐，代价是每个连接占用更多缓冲内存
+    pub bulk_transfer: bool,
+}
+
+impl Default for BundleConfig {
+    fn default() -> Self {
+        Self {
+            compress: false,
+            idle_timeout_secs: crate::bundle::transfer::DEFAULT_IDLE_TIMEOUT.as_secs(),
+            encrypt: false,
+            bulk_transfer: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RepoConfig {
+    /// 统计提交数和贡献者时最多遍历的提交数
+    pub history_limit: usize,
+}
+
+impl Default for RepoConfig {
+    fn default() -> Self {
+        Self {
+            history_limit: crate::git::git_repo::DEFAULT_HISTORY_LIMIT,
+        }
+    }
+}
+
+impl Config {
+    /// 加载数据目录下的配置文件，文件不存在时返回默认配置
+    pub fn load(data_dir: &Path) -> Result<Self> {
+        let path = data_dir.join(CONFIG_FILE_NAME);
+        match std::fs::read_to_string(&path) {
+            Ok(content) => Self::parse(&content)
+                .with_context(|| format!("Invalid config file {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        }
+    }
+
+    pub fn parse(content: &str) -> Result<Self> {
+        let config: Self = toml::from_str(content)?;
+        if config.node.as_bootstrap && config.node.relay {
+            anyhow::bail!("node.as_bootstrap and node.relay cannot both be enabled");
+        }
+        if config.node.connection_idle_timeout_secs == 0 {
+            anyhow::bail!("node.connection_idle_timeout_secs must be greater than 0");
+        }
+        if config.node.keep_alive_interval_secs >= config.node.connection_idle_timeout_secs {
+            anyhow::bail!(
+                "node.keep_alive_interval_secs must be shorter than node.connection_idle_timeout_secs"
+            );
+        }
+        Ok(config)
+    }
+
+    /// 证书目录的完整路径
+    pub fn cert_dir(&self, data_dir: &Path) -> PathBuf {
+        data_dir.join(expand_tilde(&self.node.cert_dir))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_partial_config_uses_defaults() {
+        let config = Config::parse(
+            r#"
+[node]
+alias = "office"
+listen = ["0.0.0.0:9100", "[::]:9100"]
+bootstrap = ["did:key:z6Mk@127.0.0.1:9000"]
+
+[bundle]
+compress = true
+
+[repo]
+history_limit = 500
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.node.alias, "office");
+        assert_eq!(config.node.listen.len(), 2);
+        assert_eq!(config.node.cert_dir, "cert");
+        assert!(config.node.reconnect);
+        assert!(config.bundle.compress);
+        assert_eq!(config.bundle.idle_timeout_secs, 30);
+        assert!(!config.bundle.encrypt);
+        assert!(!config.bundle.bulk_transfer);
+        assert_eq!(config.gossip, GossipConfig::default());
+        assert_eq!(config.repo.history_limit, 500);
+
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+        assert!(Config::parse("[node]\nalais = \"typo\"\n").is_err());
+        assert!(Config::parse("[node]\nas_bootstrap = true\nrelay = true\n").is_err());
+        assert!(Config::parse("[node]\nkeep_alive_interval_secs = 300\n").is_err());
+        assert!(Config::parse("[node]\nconnection_idle_timeout_secs = 0\n").is_err());
+        let config = Config::parse(
+            "[node]\nconnection_idle_timeout_secs = 60\nkeep_alive_interval_secs = 0\n",
+        )
+        .unwrap();
+        assert_eq!(config.node.connection_idle_timeout_secs, 60);
+        assert_eq!(config.node.keep_alive_interval_secs, 0);
+    }
+
+    #[test]
+    fn test_expand_tilde_and_cert_dir() {
+        let home = home_dir().unwrap();
+        assert_eq!(expand_tilde("~/.megaengine"), home.join(".megaengine"));
+        assert_eq!(expand_tilde("~"), home);
+        assert_eq!(expand_tilde("/srv/mega"), PathBuf::from("/srv/mega"));
+        assert_eq!(expand_tilde("a/~/b"), PathBuf::from("a/~/b"));
+        // 只展开开头的 ~，路径中其他位置的 ~ 保持不变
+        assert_eq!(expand_tilde("~/a~b/~"), home.join("a~b/~"));
+        // 不支持 ~user 形式，原样返回
+        assert_eq!(expand_tilde("~alice/x"), PathBuf::from("~alice/x"));
+        assert_eq!(
+            resolve_data_dir(Some("/srv/mega")),
+            PathBuf::from("/srv/mega")
+        );
+
+        let data_dir = Path::new("/srv/mega");
+        let mut config = Config::default();
+        assert_eq!(config.cert_dir(data_dir), data_dir.join("cert"));
+        config.node.cert_dir = "/etc/mega/cert".to_string();
+        assert_eq!(config.cert_dir(data_dir), PathBuf::from("/etc/mega/cert"));
+    }
+}
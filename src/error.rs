@@ -0,0 +1,81 @@
+use thiserror::Error;
+
+/// 库边界使用的结构化错误，调用方可以按错误类型匹配而不必解析错误字符串
+///
+/// 库内部仍然使用 anyhow，未归类的错误统一落到 `Other`
+#[derive(Debug, Error)]
+pub enum MegaError {
+    #[error("{0} not found")]
+    NotFound(String),
+    #[error("{0} already exists")]
+    AlreadyExists(String),
+    #[error("transport error: {0}")]
+    Transport(String),
+    #[error("database error: {0:#}")]
+    Db(anyhow::Error),
+    #[error("git error: {0:#}")]
+    Git(anyhow::Error),
+    /// 本地仓库缺少增量 bundle 的前置提交，调用方可以改为请求完整 bundle
+    #[error("missing prerequisite commits: {0}")]
+    MissingPrerequisite(String),
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+pub type Result<T, E = MegaError> = std::result::Result<T, E>;
+
+impl MegaError {
+    /// 错误类型的稳定名称，供 MCP 等上层协议返回给调用方
+    pub fn kind(&self) -> &'static str {
+        match self {
+            MegaError::NotFound(_) => "not_found",
+            MegaError::AlreadyExists(_) => "already_exists",
+            MegaError::Transport(_) => "transport",
+            MegaError::Db(_) => "db",
+            MegaError::Git(_) => "git",
+            MegaError::MissingPrerequisite(_) => "missing_prerequisite",
+            MegaError::Other(_) => "other",
+        }
+    }
+}
+
+macro_rules! transport_error_from {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl From<$ty> for MegaError {
+                fn from(e: $ty) -> Self {
+                    MegaError::Transport(e.to_string())
+                }
+            }
+        )*
+    };
+}
+
+transport_error_from!(
+    quinn::ConnectError,
+    quinn::ConnectionError,
+    quinn::WriteError,
+    quinn::ClosedStream,
+    quinn::ReadToEndError,
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_kind_and_anyhow_roundtrip() {
+        let err = MegaError::NotFound("repository did:repo:x".to_string());
+        assert_eq!(err.kind(), "not_found");
+        assert_eq!(err.to_string(), "repository did:repo:x not found");
+
+        // 经过 anyhow 传递后仍可还原出具体类型
+        let wrapped: anyhow::Error = err.into();
+        let recovered = wrapped.downcast_ref::<MegaError>().unwrap();
+        assert!(matches!(recovered, MegaError::NotFound(_)));
+
+        let other: MegaError = anyhow::anyhow!("boom").into();
+        assert_eq!(other.kind(), "other");
+        assert_eq!(other.to_string(), "boom");
+    }
+}
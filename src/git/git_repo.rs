@@ -1,5 +1,8 @@
 use anyhow::Result;
-use git2::{BranchType, Repository, Sort};
+use git2::{BranchType, Oid, Repository, Sort};
+use std::collections::HashMap;
+
+use crate::error::MegaError;
 
 pub fn repo_root_commit_bytes(path: &str) -> Result<Vec<u8>> {
     let repo =
@@ -35,12 +38,69 @@ pub fn repo_name_space(path: &str) -> String {
     "".to_string()
 }
 
+/// Which ref namespaces to read or compare; `refs/heads/*` is always included
+///
+/// `HEAD` and other pseudo-refs never match, so a bundle listing them compares
+/// equal to a repository that only has the branches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RefFilter {
+    /// Include `refs/tags/*`
+    pub tags: bool,
+    /// Include remote-tracking branches (`refs/remotes/*`)
+    pub remotes: bool,
+}
+
+impl Default for RefFilter {
+    /// Branches and tags, the namespaces bundles are packed with
+    fn default() -> Self {
+        Self {
+            tags: true,
+            remotes: false,
+        }
+    }
+}
+
+impl RefFilter {
+    /// Only local branches (`refs/heads/*`)
+    pub fn heads_only() -> Self {
+        Self {
+            tags: false,
+            remotes: false,
+        }
+    }
+
+    pub fn with_tags(mut self, tags: bool) -> Self {
+        self.tags = tags;
+        self
+    }
+
+    pub fn with_remotes(mut self, remotes: bool) -> Self {
+        self.remotes = remotes;
+        self
+    }
+
+    /// Whether a full ref name falls into one of the selected namespaces
+    pub fn matches(&self, ref_name: &str) -> bool {
+        ref_name.starts_with("refs/heads/")
+            || (self.tags && ref_name.starts_with("refs/tags/"))
+            || (self.remotes && ref_name.starts_with("refs/remotes/"))
+    }
+}
+
 /// Read all refs (branches and tags) from a git repository
-pub fn read_repo_refs(path: &str) -> Result<std::collections::HashMap<String, String>> {
+///
+/// Remote-tracking branches are left out; use [`read_repo_refs_filtered`] to choose
+/// the namespaces explicitly.
+pub fn read_repo_refs(path: &str) -> Result<HashMap<String, String>> {
+    read_repo_refs_filtered(path, RefFilter::default())
+}
+
+/// Read the refs of a git repository that fall into the namespaces selected by `filter`
+pub fn read_repo_refs_filtered(path: &str, filter: RefFilter) -> Result<HashMap<String, String>> {
     let repo =
         Repository::open(path).map_err(|e| anyhow::anyhow!("failed to open git repo: {}", e))?;
 
-    let mut refs = std::collections::HashMap::new();
+    let mut refs = HashMap::new();
 
     // Read local branches (refs/heads/*)
     let branches = repo
@@ -59,14 +119,32 @@ pub fn read_repo_refs(path: &str) -> Result<std::collections::HashMap<String, St
     }
 
     // Read tags (refs/tags/*)
-    let tag_names = repo
-        .tag_names(None)
-        .map_err(|e| anyhow::anyhow!("failed to read tags: {}", e))?;
-
-    for tag_name in tag_names.iter().flatten() {
-        if let Ok(reference) = repo.find_reference(&format!("refs/tags/{}", tag_name)) {
-            if let Some(oid) = reference.target() {
-                refs.insert(format!("refs/tags/{}", tag_name), oid.to_string());
+    if filter.tags {
+        let tag_names = repo
+            .tag_names(None)
+            .map_err(|e| anyhow::anyhow!("failed to read tags: {}", e))?;
+
+        for tag_name in tag_names.iter().flatten() {
+            if let Ok(reference) = repo.find_reference(&format!("refs/tags/{}", tag_name)) {
+                if let Some(oid) = reference.target() {
+                    refs.insert(format!("refs/tags/{}", tag_name), oid.to_string());
+                }
+            }
+        }
+    }
+
+    // Read remote-tracking branches (refs/remotes/*), skipping symbolic ones like origin/HEAD
+    if filter.remotes {
+        let remotes = repo
+            .branches(Some(BranchType::Remote))
+            .map_err(|e| anyhow::anyhow!("failed to read remote branches: {}", e))?;
+
+        for branch_result in remotes {
+            let (branch, _branch_type) =
+                branch_result.map_err(|e| anyhow::anyhow!("failed to read branch: {}", e))?;
+            let reference = branch.get();
+            if let (Some(name), Some(oid)) = (reference.name(), reference.target()) {
+                refs.insert(name.to_string(), oid.to_string());
             }
         }
     }
@@ -74,6 +152,150 @@ pub fn read_repo_refs(path: &str) -> Result<std::collections::HashMap<String, St
     Ok(refs)
 }
 
+/// Compare a working repository against the refs recorded in its bundle
+///
+/// Both sides are restricted to the same `filter` before comparing, so a namespace
+/// present on only one side (remote-tracking branches, `HEAD`) never shows up as a
+/// difference. Returns the differing refs sorted by name, with the repository's
+/// current commit or `None` when the ref no longer exists in the repository.
+pub fn pending_ref_updates(
+    repo_path: &str,
+    bundle_refs: &HashMap<String, String>,
+    filter: RefFilter,
+) -> Result<Vec<(String, Option<String>)>> {
+    let current = read_repo_refs_filtered(repo_path, filter)?;
+
+    let mut updates: Vec<(String, Option<String>)> = current
+        .iter()
+        .filter(|(name, commit)| bundle_refs.get(*name) != Some(*commit))
+        .map(|(name, commit)| (name.clone(), Some(commit.clone())))
+        .collect();
+    updates.extend(
+        bundle_refs
+            .keys()
+            .filter(|name| filter.matches(name) && !current.contains_key(*name))
+            .map(|name| (name.clone(), None)),
+    );
+    updates.sort();
+
+    Ok(updates)
+}
+
+/// Apply a bundle to an existing repository in place, without a fresh clone
+///
+/// Fetches every ref of the bundle (fast-forwarding the checked-out branch) and returns
+/// the ref updates as `(ref_name, old_commit, new_commit)`; refs that did not exist
+/// before have an all-zero `old_commit`. Fails with `MegaError::MissingPrerequisite`
+/// when the repository lacks the commits a thin bundle is based on, so callers can
+/// fall back to a full transfer.
+///
+/// # Example
+/// ```ignore
+/// let updates = unbundle_into("/path/to/repo", "/tmp/repo.bundle")?;
+/// ```
+pub fn unbundle_into(
+    repo_path: &str,
+    bundle_path: &str,
+) -> crate::error::Result<Vec<(String, String, String)>> {
+    let report = crate::git::pack::pull_repo_from_bundle(repo_path, bundle_path)
+        .map_err(|e| e.downcast::<MegaError>().unwrap_or_else(MegaError::Git))?;
+
+    let mut updates = report.advanced;
+    updates.extend(
+        report
+            .created
+            .into_iter()
+            .map(|(name, commit)| (name, git2::Oid::zero().to_string(), commit)),
+    );
+    updates.sort();
+    Ok(updates)
+}
+
+/// Detect the default branch of a repository (short name, e.g. `main`)
+///
+/// Uses the branch `HEAD` points to; when `HEAD` is detached or unborn, falls back
+/// to `main`, then `master`, then the only local branch, and fails if that is ambiguous.
+pub fn default_branch(path: &str) -> Result<String> {
+    let repo =
+        Repository::open(path).map_err(|e| anyhow::anyhow!("failed to open git repo: {}", e))?;
+
+    let mut branches = Vec::new();
+    for branch_result in repo
+        .branches(Some(BranchType::Local))
+        .map_err(|e| anyhow::anyhow!("failed to read branches: {}", e))?
+    {
+        let (branch, _) =
+            branch_result.map_err(|e| anyhow::anyhow!("failed to read branch: {}", e))?;
+        if let Ok(Some(name)) = branch.name() {
+            branches.push(name.to_string());
+        }
+    }
+
+    // HEAD 为符号引用时直接取其目标分支（分离 HEAD 时 symbolic_target 为空）
+    let head = repo
+        .find_reference("HEAD")
+        .ok()
+        .and_then(|head| head.symbolic_target().map(|t| t.to_string()))
+        .and_then(|target| target.strip_prefix("refs/heads/").map(|b| b.to_string()));
+
+    pick_default_branch(head, branches)
+        .map_err(|e| anyhow::anyhow!("cannot detect default branch of {}: {}", path, e))
+}
+
+/// Detect the default branch recorded in a bundle (short name, e.g. `main`)
+///
+/// Uses the branch whose commit matches the bundle's `HEAD` when it has one,
+/// with the same fallbacks as [`default_branch`]. Returns `None` when the bundle
+/// carries no branches at all (e.g. only `HEAD`).
+pub fn bundle_default_branch(bundle_path: &str) -> Result<Option<String>> {
+    let refs = crate::git::pack::extract_bundle_refs(bundle_path)?;
+    let branches: Vec<String> = refs
+        .keys()
+        .filter_map(|name| name.strip_prefix("refs/heads/"))
+        .map(|name| name.to_string())
+        .collect();
+    if branches.is_empty() {
+        return Ok(None);
+    }
+
+    let head = refs.get("HEAD").and_then(|head_commit| {
+        let matching: Vec<&String> = branches
+            .iter()
+            .filter(|b| refs.get(&format!("refs/heads/{}", b)) == Some(head_commit))
+            .collect();
+        match matching.as_slice() {
+            [only] => Some(only.to_string()),
+            _ => None,
+        }
+    });
+
+    pick_default_branch(head, branches)
+        .map(Some)
+        .map_err(|e| anyhow::anyhow!("cannot detect default branch of {}: {}", bundle_path, e))
+}
+
+fn pick_default_branch(head: Option<String>, mut branches: Vec<String>) -> Result<String> {
+    if let Some(head) = head.filter(|h| branches.contains(h)) {
+        return Ok(head);
+    }
+    for preferred in ["main", "master"] {
+        if branches.iter().any(|b| b == preferred) {
+            return Ok(preferred.to_string());
+        }
+    }
+    match branches.len() {
+        0 => Err(anyhow::anyhow!("no branches found")),
+        1 => Ok(branches.remove(0)),
+        _ => {
+            branches.sort();
+            Err(anyhow::anyhow!(
+                "HEAD does not name a branch and several branches exist: {}",
+                branches.join(", ")
+            ))
+        }
+    }
+}
+
 pub fn get_latest_commit_time(path: &str) -> Result<i64> {
     let repo =
         Repository::open(path).map_err(|e| anyhow::anyhow!("failed to open git repo: {}", e))?;
@@ -85,3 +307,137 @@ pub fn get_latest_commit_time(path: &str) -> Result<i64> {
         .map_err(|e| anyhow::anyhow!("failed to peel to commit: {}", e))?;
     Ok(commit.time().seconds())
 }
+
+/// Default number of commits `history_stats` walks before giving up.
+pub const DEFAULT_HISTORY_LIMIT: usize = 10_000;
+
+/// Activity summary of the history reachable from HEAD.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct HistoryStats {
+    /// Commits walked, at most the limit passed to `history_stats`.
+    pub commits: u64,
+    /// Distinct author emails (case-insensitive) among the walked commits.
+    pub contributors: u64,
+    /// Whether the walk stopped at the limit before reaching the root commit.
+    pub truncated: bool,
+}
+
+/// Count the commits and distinct authors reachable from HEAD, walking at most `limit` commits.
+///
+/// Huge histories are cut off at `limit`, so the counts are lower bounds when `truncated` is set.
+pub fn history_stats(path: &str, limit: usize) -> Result<HistoryStats> {
+    let repo =
+        Repository::open(path).map_err(|e| anyhow::anyhow!("failed to open git repo: {}", e))?;
+    let mut revwalk = repo
+        .revwalk()
+        .map_err(|e| anyhow::anyhow!("revwalk error: {}", e))?;
+    revwalk
+        .push_head()
+        .map_err(|e| anyhow::anyhow!("push_head failed: {}", e))?;
+
+    let mut stats = HistoryStats::default();
+    let mut authors = std::collections::HashSet::new();
+    for oid in revwalk {
+        if stats.commits as usize >= limit {
+            stats.truncated = true;
+            break;
+        }
+        let oid = oid.map_err(|e| anyhow::anyhow!("revwalk entry error: {}", e))?;
+        let commit = repo
+            .find_commit(oid)
+            .map_err(|e| anyhow::anyhow!("failed to read commit {}: {}", oid, e))?;
+        let author = commit.author();
+        let key = match author.email() {
+            Some(email) if !email.is_empty() => email.to_lowercase(),
+            _ => author.name().unwrap_or_default().to_string(),
+        };
+        authors.insert(key);
+        stats.commits += 1;
+    }
+    stats.contributors = authors.len() as u64;
+    Ok(stats)
+}
+
+/// One entry of `recent_commits`, newest first.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct CommitSummary {
+    pub id: String,
+    pub author: String,
+    pub email: String,
+    /// Commit time in seconds since the Unix epoch.
+    pub time: i64,
+    /// First line of the commit message.
+    pub subject: String,
+}
+
+/// List the last `limit` commits reachable from HEAD of a working copy, newest first.
+pub fn recent_commits(path: &str, limit: usize) -> Result<Vec<CommitSummary>> {
+    let repo =
+        Repository::open(path).map_err(|e| anyhow::anyhow!("failed to open git repo: {}", e))?;
+    let head = repo
+        .head()
+        .and_then(|head| head.peel_to_commit())
+        .map_err(|e| anyhow::anyhow!("failed to get HEAD: {}", e))?;
+    walk_commits(&repo, head.id(), limit)
+}
+
+/// List the last `limit` commits of a bundle's default branch, newest first.
+///
+/// The bundle is unbundled into a scratch repository that is removed afterwards,
+/// so the bundle file itself is only read. Falls back to the bundle's `HEAD` when
+/// it carries no branches.
+pub fn bundle_recent_commits(bundle_path: &str, limit: usize) -> Result<Vec<CommitSummary>> {
+    if !std::path::Path::new(bundle_path).exists() {
+        return Err(anyhow::anyhow!("bundle file not found: {}", bundle_path));
+    }
+    let bundle_path = std::fs::canonicalize(bundle_path)?
+        .to_string_lossy()
+        .to_string();
+
+    let scratch = std::env::temp_dir().join(format!("megaengine-history-{}", uuid::Uuid::new_v4()));
+    let result = (|| {
+        let refs = crate::git::pack::unbundle_to_scratch(&bundle_path, &scratch)?;
+        let start = match bundle_default_branch(&bundle_path)? {
+            Some(branch) => refs.get(&format!("refs/heads/{}", branch)),
+            None => refs.get("HEAD"),
+        }
+        .ok_or_else(|| anyhow::anyhow!("bundle {} has no branches", bundle_path))?;
+        let start =
+            Oid::from_str(start).map_err(|e| anyhow::anyhow!("invalid commit {}: {}", start, e))?;
+        let repo = Repository::open_bare(&scratch)
+            .map_err(|e| anyhow::anyhow!("failed to open scratch repository: {}", e))?;
+        walk_commits(&repo, start, limit)
+    })();
+    let _ = std::fs::remove_dir_all(&scratch);
+    result
+}
+
+fn walk_commits(repo: &Repository, start: Oid, limit: usize) -> Result<Vec<CommitSummary>> {
+    let mut revwalk = repo
+        .revwalk()
+        .map_err(|e| anyhow::anyhow!("revwalk error: {}", e))?;
+    // 同一秒内的提交仅按时间排序时顺序不定，拓扑排序保证父提交在后
+    revwalk
+        .set_sorting(Sort::TOPOLOGICAL | Sort::TIME)
+        .map_err(|e| anyhow::anyhow!("revwalk error: {}", e))?;
+    revwalk
+        .push(start)
+        .map_err(|e| anyhow::anyhow!("failed to start history at {}: {}", start, e))?;
+
+    let mut commits = Vec::new();
+    for oid in revwalk.take(limit) {
+        let oid = oid.map_err(|e| anyhow::anyhow!("revwalk entry error: {}", e))?;
+        let commit = repo
+            .find_commit(oid)
+            .map_err(|e| anyhow::anyhow!("failed to read commit {}: {}", oid, e))?;
+        let author = commit.author();
+        commits.push(CommitSummary {
+            id: oid.to_string(),
+            author: author.name().unwrap_or_default().to_string(),
+            email: author.email().unwrap_or_default().to_string(),
+            time: commit.time().seconds(),
+            subject: commit.summary().unwrap_or_default().to_string(),
+        });
+    }
+    Ok(commits)
+}
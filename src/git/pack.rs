@@ -1,10 +1,17 @@
 use anyhow::Result;
 use git2::Repository;
+use std::collections::HashMap;
 use std::path::Path;
-use std::process::Command;
+use std::process::{Command, Stdio};
+use tokio::io::AsyncReadExt;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use crate::error::MegaError;
+use crate::git::git_repo::read_repo_refs;
 
 /// Pack a git repository into a single file using git bundle
-/// This creates a bundle file that contains all branches and commits
+/// This creates a bundle file that contains all branches, tags and commits
 ///
 /// # Arguments
 /// * `repo_path` - Path to the git repository to pack
@@ -15,18 +22,280 @@ use std::process::Command;
 /// pack_repo_bundle("/path/to/repo", "/tmp/repo.bundle")?;
 /// ```
 pub fn pack_repo_bundle(repo_path: &str, output_path: &str) -> Result<()> {
-    // 检查并创建 output_path 的目录
+    pack_full_bundle(repo_path, output_path, true)
+}
+
+/// Pack a git repository into a bundle with its branches only, leaving out tags
+///
+/// # Example
+/// ```ignore
+/// pack_repo_branches_bundle("/path/to/repo", "/tmp/repo.bundle")?;
+/// ```
+pub fn pack_repo_branches_bundle(repo_path: &str, output_path: &str) -> Result<()> {
+    pack_full_bundle(repo_path, output_path, false)
+}
+
+fn pack_full_bundle(repo_path: &str, output_path: &str, include_tags: bool) -> Result<()> {
+    ensure_output_dir(output_path)?;
+
+    let repo = Repository::open(repo_path)
+        .map_err(|e| anyhow::anyhow!("failed to open git repo: {}", e))?;
+    let branch_refs = bundle_refs(&repo, include_tags)?;
+
+    // Use git bundle command to create the bundle
+    let mut cmd = Command::new("git");
+    cmd.current_dir(repo_path)
+        .arg("bundle")
+        .arg("create")
+        .arg(output_path);
+
+    for branch_ref in &branch_refs {
+        cmd.arg(branch_ref);
+    }
+
+    let output = cmd
+        .output()
+        .map_err(|e| anyhow::anyhow!("failed to execute git bundle: {}", e))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow::anyhow!("git bundle failed: {}", stderr));
+    }
+
+    Ok(())
+}
+
+/// What `pack_repo_delta_bundle` wrote
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BundleKind {
+    /// A full bundle with every branch and tag
+    Full,
+    /// A thin bundle that needs the receiver's `have` commits
+    Delta,
+    /// The receiver already has every branch and tag; no bundle was written
+    UpToDate,
+}
+
+/// Pack only the commits missing on the receiver into a thin bundle
+/// The receiver's `have` commits become the bundle prerequisites (`--not <have>...`)
+///
+/// Falls back to a full bundle when `have` is empty or when any of the `have`
+/// commits is unknown here (histories diverged). When every branch and tag is
+/// already reachable from `have`, nothing is written.
+///
+/// # Example
+/// ```ignore
+/// let kind = pack_repo_delta_bundle("/path/to/repo", "/tmp/repo.bundle", &have)?;
+/// ```
+pub fn pack_repo_delta_bundle(
+    repo_path: &str,
+    output_path: &str,
+    have: &[String],
+) -> Result<BundleKind> {
+    if have.is_empty() {
+        tracing::info!(
+            "Receiver of {} has no commits yet, packing a full bundle",
+            repo_path
+        );
+        pack_repo_bundle(repo_path, output_path)?;
+        return Ok(BundleKind::Full);
+    }
+
+    let repo = Repository::open(repo_path)
+        .map_err(|e| anyhow::anyhow!("failed to open git repo: {}", e))?;
+
+    // 接收方拥有本仓库不存在的提交，说明历史已分叉，回退为完整 bundle
+    let known = have.iter().all(|commit| {
+        git2::Oid::from_str(commit)
+            .and_then(|oid| repo.find_object(oid, None))
+            .and_then(|obj| obj.peel_to_commit())
+            .is_ok()
+    });
+    if !known {
+        tracing::info!(
+            "Receiver of {} has commits unknown here (diverged history), falling back to a full bundle",
+            repo_path
+        );
+        pack_repo_bundle(repo_path, output_path)?;
+        return Ok(BundleKind::Full);
+    }
+
+    let branch_refs = bundle_refs(&repo, true)?;
+    let have_oids: Vec<git2::Oid> = have
+        .iter()
+        .filter_map(|commit| git2::Oid::from_str(commit).ok())
+        .collect();
+    let up_to_date = branch_refs.iter().all(|name| {
+        repo.revparse_single(name)
+            .and_then(|obj| obj.peel_to_commit())
+            .map(|tip| {
+                have_oids.iter().any(|&h| {
+                    h == tip.id() || repo.graph_descendant_of(h, tip.id()).unwrap_or(false)
+                })
+            })
+            .unwrap_or(false)
+    });
+    if up_to_date {
+        tracing::info!("Receiver of {} is up to date, nothing to pack", repo_path);
+        return Ok(BundleKind::UpToDate);
+    }
+
+    ensure_output_dir(output_path)?;
+
+    let mut cmd = Command::new("git");
+    cmd.current_dir(repo_path)
+        .arg("bundle")
+        .arg("create")
+        .arg(output_path);
+    for branch_ref in &branch_refs {
+        cmd.arg(branch_ref);
+    }
+    cmd.arg("--not");
+    for commit in have {
+        cmd.arg(commit);
+    }
+
+    let output = cmd
+        .output()
+        .map_err(|e| anyhow::anyhow!("failed to execute git bundle: {}", e))?;
+
+    if !output.status.success() {
+        tracing::info!(
+            "Cannot pack a delta bundle of {} ({}), falling back to a full bundle",
+            repo_path,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+        pack_repo_bundle(repo_path, output_path)?;
+        return Ok(BundleKind::Full);
+    }
+
+    Ok(BundleKind::Delta)
+}
+
+/// Pack a thin bundle containing only the refs that advanced relative to the receiver's refs
+///
+/// `have` maps ref names (e.g. `refs/heads/main`) to the commits the receiver has.
+/// Every local branch or tag that is new or points elsewhere is bundled, with the
+/// `have` commits known here as prerequisites, i.e. `<have>..<ref>` for each advanced ref.
+/// Refs the receiver already has (same commit, or reachable from one of its commits)
+/// are skipped, and refs deleted locally cannot be included.
+///
+/// Falls back to a full bundle when `have` is empty.
+///
+/// # Returns
+/// The refs actually included in the bundle, sorted; empty if nothing advanced,
+/// in which case no bundle file is written
+///
+/// # Example
+/// ```ignore
+/// let included = pack_repo_thin_bundle("/path/to/repo", "/tmp/repo.bundle", &have)?;
+/// ```
+pub fn pack_repo_thin_bundle(
+    repo_path: &str,
+    output_path: &str,
+    have: &HashMap<String, String>,
+) -> Result<Vec<String>> {
+    let repo = Repository::open(repo_path)
+        .map_err(|e| anyhow::anyhow!("failed to open git repo: {}", e))?;
+
+    if have.is_empty() {
+        pack_repo_bundle(repo_path, output_path)?;
+        return full_bundle_refs(&repo);
+    }
+
+    // 接收方拥有且本仓库也存在的提交，作为 bundle 的前置提交
+    let mut prerequisites: Vec<git2::Oid> = have
+        .values()
+        .filter_map(|commit| git2::Oid::from_str(commit).ok())
+        .filter(|oid| repo.find_commit(*oid).is_ok())
+        .collect();
+    prerequisites.sort();
+    prerequisites.dedup();
+
+    let local_refs = read_repo_refs(repo_path)?;
+    let mut names: Vec<&String> = local_refs.keys().collect();
+    names.sort();
+
+    let mut included = Vec::new();
+    for name in names {
+        if have.get(name) == local_refs.get(name) {
+            continue;
+        }
+        let tip = repo
+            .revparse_single(name)
+            .and_then(|obj| obj.peel_to_commit())
+            .map_err(|e| anyhow::anyhow!("failed to resolve {}: {}", name, e))?;
+        // 接收方已经拥有该提交（例如对方领先），bundle 中不会包含这个 ref
+        let already_have = prerequisites
+            .iter()
+            .any(|&p| p == tip.id() || repo.graph_descendant_of(p, tip.id()).unwrap_or(false));
+        if !already_have {
+            included.push(name.clone());
+        }
+    }
+
+    for name in have.keys().filter(|name| !local_refs.contains_key(*name)) {
+        tracing::debug!(
+            "Ref {} was deleted locally, not included in thin bundle",
+            name
+        );
+    }
+
+    if included.is_empty() {
+        return Ok(included);
+    }
+
+    // 前置提交都已知，git 会跳过接收方已拥有的 ref，只打包上面选出的 ref
+    let prerequisites: Vec<String> = prerequisites.iter().map(|oid| oid.to_string()).collect();
+    match pack_repo_delta_bundle(repo_path, output_path, &prerequisites)? {
+        BundleKind::Delta => Ok(included),
+        BundleKind::Full => full_bundle_refs(&repo),
+        BundleKind::UpToDate => Ok(Vec::new()),
+    }
+}
+
+/// 完整 bundle 中包含的 ref，排序后返回
+fn full_bundle_refs(repo: &Repository) -> Result<Vec<String>> {
+    let mut refs: Vec<String> = bundle_refs(repo, true)?
+        .into_iter()
+        .map(|name| {
+            if name == "HEAD" || name.starts_with("refs/") {
+                name
+            } else {
+                format!("refs/heads/{}", name)
+            }
+        })
+        .collect();
+    refs.sort();
+    Ok(refs)
+}
+
+/// Apply a (thin) bundle to an existing repository
+/// Imports the objects and moves the repository's refs to the bundle's refs, the same
+/// way as `pull_repo_from_bundle`. Fails if the repository is missing any of the
+/// bundle's prerequisite commits.
+///
+/// # Example
+/// ```ignore
+/// apply_delta_bundle("/path/to/repo", "/tmp/repo.bundle")?;
+/// ```
+pub fn apply_delta_bundle(repo_path: &str, bundle_path: &str) -> Result<PullReport> {
+    pull_repo_from_bundle(repo_path, bundle_path)
+}
+
+/// 检查并创建 output_path 的目录
+fn ensure_output_dir(output_path: &str) -> Result<()> {
     if let Some(parent_dir) = Path::new(output_path).parent() {
         if !parent_dir.as_os_str().is_empty() {
             std::fs::create_dir_all(parent_dir)
                 .map_err(|e| anyhow::anyhow!("failed to create output directory: {}", e))?;
         }
     }
+    Ok(())
+}
 
-    let repo = Repository::open(repo_path)
-        .map_err(|e| anyhow::anyhow!("failed to open git repo: {}", e))?;
-
-    // Get all branches to include in the bundle
+/// Get all branches (and optionally tags, as `refs/tags/*`) to include in the bundle
+fn bundle_refs(repo: &Repository, include_tags: bool) -> Result<Vec<String>> {
     let mut branch_refs = Vec::new();
     let branches = repo
         .branches(None)
@@ -49,27 +318,16 @@ pub fn pack_repo_bundle(repo_path: &str, output_path: &str) -> Result<()> {
         return Err(anyhow::anyhow!("no branches found to bundle"));
     }
 
-    // Use git bundle command to create the bundle
-    let mut cmd = Command::new("git");
-    cmd.current_dir(repo_path)
-        .arg("bundle")
-        .arg("create")
-        .arg(output_path);
-
-    for branch_ref in &branch_refs {
-        cmd.arg(branch_ref);
-    }
-
-    let output = cmd
-        .output()
-        .map_err(|e| anyhow::anyhow!("failed to execute git bundle: {}", e))?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(anyhow::anyhow!("git bundle failed: {}", stderr));
+    if include_tags {
+        let tag_names = repo
+            .tag_names(None)
+            .map_err(|e| anyhow::anyhow!("failed to list tags: {}", e))?;
+        for tag_name in tag_names.iter().flatten() {
+            branch_refs.push(format!("refs/tags/{}", tag_name));
+        }
     }
 
-    Ok(())
+    Ok(branch_refs)
 }
 
 /// Restore a git repository from a bundle file
@@ -84,6 +342,28 @@ pub fn pack_repo_bundle(repo_path: &str, output_path: &str) -> Result<()> {
 /// restore_repo_from_bundle("/tmp/repo.bundle", "/path/to/new/repo").await?;
 /// ```
 pub async fn restore_repo_from_bundle(bundle_path: &str, output_path: &str) -> Result<()> {
+    restore_repo_from_bundle_with_progress(bundle_path, output_path, None, CancellationToken::new())
+        .await
+}
+
+/// Restore a git repository from a bundle file, streaming `git clone --progress`
+/// output and aborting when `cancel` fires
+///
+/// Every line git writes to stderr is sent to `progress`. Cancelling kills the git
+/// process. On cancellation or failure the partially created `output_path` is removed.
+///
+/// # Example
+/// ```ignore
+/// let (tx, mut rx) = tokio::sync::mpsc::channel(64);
+/// let cancel = CancellationToken::new();
+/// restore_repo_from_bundle_with_progress("/tmp/repo.bundle", "/path/to/new/repo", Some(tx), cancel).await?;
+/// ```
+pub async fn restore_repo_from_bundle_with_progress(
+    bundle_path: &str,
+    output_path: &str,
+    progress: Option<mpsc::Sender<String>>,
+    cancel: CancellationToken,
+) -> Result<()> {
     // 检查 bundle 文件是否存在
     if !Path::new(bundle_path).exists() {
         return Err(anyhow::anyhow!("bundle file not found: {}", bundle_path));
@@ -106,48 +386,130 @@ pub async fn restore_repo_from_bundle(bundle_path: &str, output_path: &str) -> R
         }
     }
 
-    // 在线程中执行 git clone，避免阻塞 async 运行时
-    let bundle_path = bundle_path.to_string();
-    let output_path = output_path.to_string();
+    // 先确定要检出的分支，无法确定时不创建任何目录
+    let branch = crate::git::git_repo::bundle_default_branch(bundle_path)?;
 
-    tokio::task::spawn_blocking(move || {
-        // 使用 git clone 从 bundle 恢复仓库
-        // 注意：从 bundle 克隆时，git clone 可能不会自动 checkout 到 HEAD，
-        // 特别是当 bundle 包含多个 heads 时。
-        // 所以我们需要显式 clone，然后如果目录为空，尝试 checkout。
-        let output = Command::new("git")
-            .arg("clone")
-            .arg(&bundle_path)
-            .arg(&output_path)
-            .output()
-            .map_err(|e| anyhow::anyhow!("failed to execute git clone: {}", e))?;
+    let result = clone_from_bundle(bundle_path, output_path, branch, progress, &cancel).await;
+    if result.is_err() && output_dir.exists() {
+        // 输出目录在调用前不存在，失败或取消时整个删除，不留下半成品
+        if let Err(e) = tokio::fs::remove_dir_all(output_dir).await {
+            tracing::warn!("Failed to remove partial clone {}: {}", output_path, e);
+        }
+    }
+    result
+}
 
+async fn clone_from_bundle(
+    bundle_path: &str,
+    output_path: &str,
+    branch: Option<String>,
+    progress: Option<mpsc::Sender<String>>,
+    cancel: &CancellationToken,
+) -> Result<()> {
+    // 使用 git clone 从 bundle 恢复仓库
+    // 注意：从 bundle 克隆时，git clone 可能不会自动 checkout 到 HEAD，
+    // 特别是当 bundle 包含多个 heads 时。
+    // 所以我们需要显式 clone，然后如果目录为空，尝试 checkout。
+    let mut child = tokio::process::Command::new("git")
+        .args(["clone", "--progress", bundle_path, output_path])
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("failed to execute git clone: {}", e))?;
+    let stderr = child
+        .stderr
+        .take()
+        .ok_or_else(|| anyhow::anyhow!("failed to capture git clone output"))?;
+    let reader = tokio::spawn(forward_progress_lines(stderr, progress));
+
+    let status = tokio::select! {
+        biased;
+        _ = cancel.cancelled() => {
+            let _ = child.kill().await;
+            reader.abort();
+            return Err(anyhow::anyhow!("git clone from bundle cancelled"));
+        }
+        status = child.wait() => {
+            status.map_err(|e| anyhow::anyhow!("failed to wait for git clone: {}", e))?
+        }
+    };
+    let stderr = reader.await.unwrap_or_default();
+    if !status.success() {
+        return Err(anyhow::anyhow!("git clone from bundle failed: {}", stderr));
+    }
+    if cancel.is_cancelled() {
+        return Err(anyhow::anyhow!("git clone from bundle cancelled"));
+    }
+
+    // bundle 不含 HEAD 时 clone 不会检出工作区，显式检出默认分支
+    if let Some(branch) = branch {
+        let output = tokio::process::Command::new("git")
+            .current_dir(output_path)
+            .args(["checkout", &branch])
+            .output()
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to execute git checkout: {}", e))?;
         if !output.status.success() {
             let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(anyhow::anyhow!("git clone from bundle failed: {}", stderr));
+            return Err(anyhow::anyhow!(
+                "failed to check out branch {}: {}",
+                branch,
+                stderr.trim()
+            ));
         }
+    }
 
-        // 尝试自动检出分支 (clone bundle 有时不会自动检出工作区)
-        // 尝试常见分支名，忽略错误（可能分支不存在）
-        let _ = Command::new("git")
-            .current_dir(&output_path)
-            .args(["checkout", "main"])
-            .output();
-        let _ = Command::new("git")
-            .current_dir(&output_path)
-            .args(["checkout", "master"])
-            .output();
+    // 强制重置工作区到当前 HEAD，确保文件被检出
+    let _ = tokio::process::Command::new("git")
+        .current_dir(output_path)
+        .args(["reset", "--hard", "HEAD"])
+        .output()
+        .await;
 
-        // 强制重置工作区到当前 HEAD，确保文件被检出
-        let _ = Command::new("git")
-            .current_dir(&output_path)
-            .args(["reset", "--hard", "HEAD"])
-            .output();
+    Ok(())
+}
 
-        Ok(())
-    })
-    .await
-    .map_err(|e| anyhow::anyhow!("failed to spawn bundle restore task: {}", e))?
+/// Read git's stderr, sending each line to `progress` and returning the full output
+///
+/// git redraws progress lines with `\r`, so both `\r` and `\n` end a line.
+async fn forward_progress_lines(
+    mut stderr: tokio::process::ChildStderr,
+    progress: Option<mpsc::Sender<String>>,
+) -> String {
+    let mut output = String::new();
+    let mut pending = Vec::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        let n = match stderr.read(&mut buf).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => n,
+        };
+        for &byte in &buf[..n] {
+            if byte != b'\r' && byte != b'\n' {
+                pending.push(byte);
+                continue;
+            }
+            let line = String::from_utf8_lossy(&pending).trim().to_string();
+            pending.clear();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(tx) = &progress {
+                let _ = tx.send(line.clone()).await;
+            }
+            output.push_str(&line);
+            output.push('\n');
+        }
+    }
+    let line = String::from_utf8_lossy(&pending).trim().to_string();
+    if !line.is_empty() {
+        if let Some(tx) = &progress {
+            let _ = tx.send(line.clone()).await;
+        }
+        output.push_str(&line);
+    }
+    output.trim_end().to_string()
 }
 
 /// Extract refs information from a git bundle file
@@ -198,19 +560,121 @@ pub fn extract_bundle_refs(bundle_path: &str) -> Result<std::collections::HashMa
     Ok(refs)
 }
 
+/// Verify that a bundle file is intact and return the refs it contains
+/// The bundle header is checked with `git bundle verify` and the packfile is
+/// fully read by unbundling it into a scratch repository, so truncated or
+/// corrupted bundles are detected.
+///
+/// # Arguments
+/// * `bundle_path` - Path to the bundle file
+/// * `repo_path` - Optional local clone providing the prerequisite commits of a delta bundle;
+///   it is only read, never written
+///
+/// # Example
+/// ```ignore
+/// let refs = verify_bundle("/tmp/repo.bundle", Some("/path/to/clone"))?;
+/// ```
+pub fn verify_bundle(
+    bundle_path: &str,
+    repo_path: Option<&str>,
+) -> Result<std::collections::HashMap<String, String>> {
+    if !Path::new(bundle_path).exists() {
+        return Err(anyhow::anyhow!("bundle file not found: {}", bundle_path));
+    }
+    let bundle_path = std::fs::canonicalize(bundle_path)?
+        .to_string_lossy()
+        .to_string();
+
+    let scratch = std::env::temp_dir().join(format!("megaengine-verify-{}", uuid::Uuid::new_v4()));
+    let result = verify_bundle_in(&bundle_path, repo_path, &scratch);
+    let _ = std::fs::remove_dir_all(&scratch);
+    result
+}
+
+/// Unbundle a full bundle into a new bare repository at `scratch` and return the bundle's refs
+///
+/// Only the objects are written; `scratch` gets no refs, so callers look commits up by id.
+pub(crate) fn unbundle_to_scratch(
+    bundle_path: &str,
+    scratch: &Path,
+) -> Result<std::collections::HashMap<String, String>> {
+    verify_bundle_in(bundle_path, None, scratch)
+}
+
+fn verify_bundle_in(
+    bundle_path: &str,
+    repo_path: Option<&str>,
+    scratch: &Path,
+) -> Result<std::collections::HashMap<String, String>> {
+    let init = Command::new("git")
+        .args(["init", "--bare", "--quiet"])
+        .arg(scratch)
+        .output()
+        .map_err(|e| anyhow::anyhow!("failed to execute git init: {}", e))?;
+    if !init.status.success() {
+        return Err(anyhow::anyhow!(
+            "failed to create scratch repository: {}",
+            String::from_utf8_lossy(&init.stderr).trim()
+        ));
+    }
+
+    // 借用本地克隆的对象库解析增量 bundle 的前置提交
+    let alternates = repo_path
+        .and_then(|p| Repository::open(p).ok())
+        .map(|repo| repo.path().join("objects"));
+
+    for args in [
+        ["bundle", "verify", bundle_path],
+        ["bundle", "unbundle", bundle_path],
+    ] {
+        let mut cmd = Command::new("git");
+        cmd.current_dir(scratch).args(args);
+        if let Some(objects) = &alternates {
+            cmd.env("GIT_ALTERNATE_OBJECT_DIRECTORIES", objects);
+        }
+        let output = cmd
+            .output()
+            .map_err(|e| anyhow::anyhow!("failed to execute git {}: {}", args[1], e))?;
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "git bundle {} failed: {}",
+                args[1],
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+    }
+
+    extract_bundle_refs(bundle_path)
+}
+
+/// Refs changed by `pull_repo_from_bundle`
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PullReport {
+    /// Existing refs that moved: (ref_name, old_commit, new_commit)
+    pub advanced: Vec<(String, String, String)>,
+    /// Refs that did not exist locally before: (ref_name, commit)
+    pub created: Vec<(String, String)>,
+}
+
+impl PullReport {
+    pub fn is_up_to_date(&self) -> bool {
+        self.advanced.is_empty() && self.created.is_empty()
+    }
+}
+
 /// Pull updates from a git bundle file into an existing repository
-/// This updates the existing repository with commits from the bundle
+/// All refs in the bundle are fetched into the repository; the checked-out
+/// branch is fast-forwarded so the working tree follows it.
 ///
 /// # Arguments
 /// * `repo_path` - Path to the existing git repository
 /// * `bundle_path` - Path to the bundle file
-/// * `branch` - Branch name to pull from the bundle (e.g., "master" or "refs/heads/master")
 ///
 /// # Example
 /// ```ignore
-/// pull_repo_from_bundle("/path/to/repo", "/tmp/repo.bundle", "master")?;
+/// let report = pull_repo_from_bundle("/path/to/repo", "/tmp/repo.bundle")?;
 /// ```
-pub fn pull_repo_from_bundle(repo_path: &str, bundle_path: &str, branch: &str) -> Result<()> {
+pub fn pull_repo_from_bundle(repo_path: &str, bundle_path: &str) -> Result<PullReport> {
     // 检查 bundle 文件是否存在
     if !Path::new(bundle_path).exists() {
         return Err(anyhow::anyhow!("bundle file not found: {}", bundle_path));
@@ -221,30 +685,114 @@ pub fn pull_repo_from_bundle(repo_path: &str, bundle_path: &str, branch: &str) -
         return Err(anyhow::anyhow!("repository not found: {}", repo_path));
     }
 
-    Repository::open(repo_path).map_err(|e| anyhow::anyhow!("failed to open git repo: {}", e))?;
+    let repo = Repository::open(repo_path)
+        .map_err(|e| anyhow::anyhow!("failed to open git repo: {}", e))?;
+
+    // 当前检出的分支：git 不允许直接 fetch 到它，需要 fetch 后 fast-forward 合并
+    let head_ref = repo
+        .head()
+        .ok()
+        .filter(|head| head.is_branch())
+        .and_then(|head| head.name().map(|name| name.to_string()));
 
-    // 构建分支引用名称，确保格式正确
-    let _ref_spec = if branch.starts_with("refs/") {
-        branch.to_string()
-    } else if branch == "HEAD" {
-        "HEAD".to_string()
-    } else {
-        format!("refs/heads/{}", branch)
-    };
+    let before = read_repo_refs(repo_path)?;
+    let bundle_refs = extract_bundle_refs(bundle_path)?;
 
-    // 使用 git pull 从 bundle 拉取更新
-    let output = Command::new("git")
+    // 先校验 bundle 的前置提交是否都存在于本地仓库
+    let verify = Command::new("git")
         .current_dir(repo_path)
-        .arg("pull")
-        .arg(bundle_path)
-        .arg(branch)
+        .args(["bundle", "verify", bundle_path])
         .output()
-        .map_err(|e| anyhow::anyhow!("failed to execute git pull: {}", e))?;
+        .map_err(|e| anyhow::anyhow!("failed to execute git bundle verify: {}", e))?;
+    if !verify.status.success() {
+        return Err(bundle_fetch_error(
+            bundle_path,
+            &String::from_utf8_lossy(&verify.stderr),
+        ));
+    }
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(anyhow::anyhow!("git pull from bundle failed: {}", stderr));
+    let mut refspecs: Vec<String> = bundle_refs
+        .keys()
+        .filter(|name| name.starts_with("refs/") && Some(*name) != head_ref.as_ref())
+        .map(|name| format!("{}:{}", name, name))
+        .collect();
+    refspecs.sort();
+
+    if !refspecs.is_empty() {
+        let output = Command::new("git")
+            .current_dir(repo_path)
+            .arg("fetch")
+            .arg(bundle_path)
+            .args(&refspecs)
+            .output()
+            .map_err(|e| anyhow::anyhow!("failed to execute git fetch: {}", e))?;
+        if !output.status.success() {
+            return Err(bundle_fetch_error(
+                bundle_path,
+                &String::from_utf8_lossy(&output.stderr),
+            ));
+        }
     }
 
-    Ok(())
+    if let Some(head_ref) = head_ref.filter(|name| bundle_refs.contains_key(name)) {
+        let output = Command::new("git")
+            .current_dir(repo_path)
+            .arg("fetch")
+            .arg(bundle_path)
+            .arg(&head_ref)
+            .output()
+            .map_err(|e| anyhow::anyhow!("failed to execute git fetch: {}", e))?;
+        if !output.status.success() {
+            return Err(bundle_fetch_error(
+                bundle_path,
+                &String::from_utf8_lossy(&output.stderr),
+            ));
+        }
+
+        let output = Command::new("git")
+            .current_dir(repo_path)
+            .args(["merge", "--ff-only", "FETCH_HEAD"])
+            .output()
+            .map_err(|e| anyhow::anyhow!("failed to execute git merge: {}", e))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(anyhow::anyhow!(
+                "cannot fast-forward checked-out branch {}: {}",
+                head_ref,
+                stderr.trim()
+            ));
+        }
+    }
+
+    let after = read_repo_refs(repo_path)?;
+    let mut report = PullReport::default();
+    for (name, new_commit) in after {
+        match before.get(&name) {
+            Some(old_commit) if *old_commit != new_commit => {
+                report.advanced.push((name, old_commit.clone(), new_commit));
+            }
+            Some(_) => {}
+            None => report.created.push((name, new_commit)),
+        }
+    }
+    report.advanced.sort();
+    report.created.sort();
+
+    Ok(report)
+}
+
+/// 将 git fetch/verify 的错误转换为更清晰的提示
+///
+/// 缺少前置提交时返回 `MegaError::MissingPrerequisite`，调用方可以 downcast 后改为请求完整 bundle
+fn bundle_fetch_error(bundle_path: &str, stderr: &str) -> anyhow::Error {
+    if stderr.contains("prerequisite") || stderr.contains("does not contain") {
+        MegaError::MissingPrerequisite(format!(
+            "bundle {} does not contain the full history and the local repository is missing its prerequisite commits; a full bundle is required: {}",
+            bundle_path,
+            stderr.trim()
+        ))
+        .into()
+    } else {
+        anyhow::anyhow!("git fetch from bundle failed: {}", stderr.trim())
+    }
 }
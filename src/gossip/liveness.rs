@@ -0,0 +1,191 @@
+use crate::gossip::message::{Envelope, GossipMessage, SignedMessage};
+use crate::node::node::Node;
+use crate::node::node_id::NodeId;
+use crate::transport::quic::ConnectionManager;
+use anyhow::Result;
+use rand_core::{OsRng, RngCore};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+/// 连续多少次 Ping 未收到 Pong 后判定连接失效
+pub(crate) const MAX_MISSED_PINGS: u32 = 3;
+
+#[derive(Debug, Default)]
+struct PeerLiveness {
+    /// 最近一次发出、尚未收到回应的 Ping
+    outstanding: Option<u64>,
+    /// 连续未回应的 Ping 数
+    missed: u32,
+    /// 是否回应过 Ping；旧版本节点不认识 Ping，从不回应，不据此判定其失效
+    responsive: bool,
+    /// 最近一次回应对方 Ping 的时间
+    last_answered: Option<Instant>,
+}
+
+/// 记录与每个邻居的 Ping/Pong 往来，找出已失去响应的连接
+#[derive(Debug, Default)]
+pub(crate) struct LivenessTracker {
+    peers: HashMap<NodeId, PeerLiveness>,
+}
+
+impl LivenessTracker {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// 开始新一轮探测：上一轮的 Ping 仍未回应时记一次丢失
+    ///
+    /// 返回需要发送 Ping 的邻居及其 nonce，以及连续丢失 `MAX_MISSED_PINGS` 次的可疑邻居；
+    /// 可疑邻居和已断开的邻居的记录会被清除
+    pub(crate) fn next_round(&mut self, peers: &[NodeId]) -> (Vec<(NodeId, u64)>, Vec<NodeId>) {
+        self.peers.retain(|id, _| peers.contains(id));
+
+        let mut pings = Vec::new();
+        let mut suspects = Vec::new();
+        for peer in peers {
+            let state = self.peers.entry(peer.clone()).or_default();
+            if state.outstanding.is_some() {
+                state.missed = state.missed.saturating_add(1);
+            }
+            if state.responsive && state.missed >= MAX_MISSED_PINGS {
+                self.peers.remove(peer);
+                suspects.push(peer.clone());
+                continue;
+            }
+            let nonce = OsRng.next_u64();
+            state.outstanding = Some(nonce);
+            pings.push((peer.clone(), nonce));
+        }
+        (pings, suspects)
+    }
+
+    /// 记录收到的 Pong，nonce 与最近一次 Ping 一致时返回 true
+    pub(crate) fn record_pong(&mut self, from: &NodeId, nonce: u64) -> bool {
+        match self.peers.get_mut(from) {
+            Some(state) if state.outstanding == Some(nonce) => {
+                state.outstanding = None;
+                state.missed = 0;
+                state.responsive = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// 判断是否回应来自 `from` 的 Ping：距上次回应不足 `min_interval` 时返回 false，
+    /// 避免邻居频繁发送 Ping 让本节点不停签名回应
+    pub(crate) fn answer_ping(&mut self, from: &NodeId, min_interval: Duration) -> bool {
+        let state = self.peers.entry(from.clone()).or_default();
+        let now = Instant::now();
+        if let Some(last) = state.last_answered {
+            if now.duration_since(last) < min_interval {
+                return false;
+            }
+        }
+        state.last_answered = Some(now);
+        true
+    }
+}
+
+/// 向直连邻居发送一次 Ping，等待对应的 Pong 并返回往返时间
+///
+/// `gossip_rx` 是在 `manager` 上通过 `register_gossip_sender` 注册的接收端，等待期间
+/// 收到的其他 gossip 消息会被丢弃；对端不回应时一直等待，由调用方设置超时。
+/// 对端每个探测周期最多回应一次 Ping，间隔过近的调用不会得到回应。
+/// 连接建立时已校验对端身份，这里只核对回应方和 nonce
+pub async fn ping_peer(
+    manager: &ConnectionManager,
+    node: &Node,
+    gossip_rx: &mut mpsc::Receiver<(NodeId, Vec<u8>)>,
+    peer: &NodeId,
+) -> Result<Duration> {
+    let nonce = OsRng.next_u64();
+    let env = Envelope {
+        payload: SignedMessage::new_ping_sign_message(nonce, node.clone())?,
+        ttl: 0,
+    };
+    let data = serde_json::to_vec(&env)?;
+
+    let started = Instant::now();
+    manager.send_gossip_message(peer.clone(), data).await?;
+    while let Some((from, data)) = gossip_rx.recv().await {
+        if from != *peer {
+            continue;
+        }
+        let Ok(env) = serde_json::from_slice::<Envelope>(&data) else {
+            continue;
+        };
+        if let GossipMessage::Pong(pong) = &env.payload.message {
+            if pong.nonce == nonce && env.payload.node_id == *peer {
+                return Ok(started.elapsed());
+            }
+        }
+    }
+    anyhow::bail!("gossip channel closed while waiting for pong from {}", peer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::keypair::KeyPair;
+
+    fn node_id() -> NodeId {
+        NodeId::from_keypair(&KeyPair::generate().unwrap())
+    }
+
+    #[test]
+    fn test_unanswered_pings_mark_peer_suspect() {
+        let peer = node_id();
+        let peers = vec![peer.clone()];
+        let mut tracker = LivenessTracker::new();
+
+        let (pings, suspects) = tracker.next_round(&peers);
+        assert!(suspects.is_empty());
+        let nonce = pings[0].1;
+        assert!(!tracker.record_pong(&peer, nonce.wrapping_add(1)));
+        assert!(tracker.record_pong(&peer, nonce));
+        // 同一个 Pong 不能重复计数
+        assert!(!tracker.record_pong(&peer, nonce));
+
+        for _ in 0..MAX_MISSED_PINGS {
+            let (pings, suspects) = tracker.next_round(&peers);
+            assert_eq!(pings.len(), 1);
+            assert!(suspects.is_empty());
+        }
+        let (pings, suspects) = tracker.next_round(&peers);
+        assert!(pings.is_empty());
+        assert_eq!(suspects, peers);
+    }
+
+    #[test]
+    fn test_peer_that_never_answered_is_not_suspect() {
+        let peer = node_id();
+        let mut tracker = LivenessTracker::new();
+        for _ in 0..MAX_MISSED_PINGS * 2 {
+            let (pings, suspects) = tracker.next_round(std::slice::from_ref(&peer));
+            assert_eq!(pings.len(), 1);
+            assert!(suspects.is_empty());
+        }
+
+        // 断开的邻居不再保留记录
+        tracker.next_round(&[]);
+        assert!(tracker.peers.is_empty());
+    }
+
+    #[test]
+    fn test_pings_are_answered_at_most_once_per_interval() {
+        let peer = node_id();
+        let other = node_id();
+        let mut tracker = LivenessTracker::new();
+        let interval = Duration::from_millis(100);
+
+        assert!(tracker.answer_ping(&peer, interval));
+        assert!(!tracker.answer_ping(&peer, interval));
+        // 每个邻居单独计算
+        assert!(tracker.answer_ping(&other, interval));
+
+        std::thread::sleep(interval);
+        assert!(tracker.answer_ping(&peer, interval));
+    }
+}
@@ -2,10 +2,12 @@ use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::net::SocketAddr;
+use uuid::Uuid;
 
 use crate::{
+    identity::keypair::KeyPair,
     node::{
-        node::{Node, NodeType},
+        node::{Node, NodeInfo, NodeType},
         node_id::NodeId,
     },
     repo::repo::Repo,
@@ -23,6 +25,20 @@ pub enum GossipMessage {
     Chat(EncryptedChatMessage),
     /// 聊天消息送达确认
     ChatAck(ChatAckMessage),
+    /// 聊天消息已读回执（用户查看后发出）
+    ChatRead(ChatReadMessage),
+    /// 群聊消息（使用频道共享密钥加密）
+    GroupChat(GroupChatMessage),
+    /// 节点交换：分享当前已连接的节点列表
+    PeerExchange(PeerExchange),
+    /// 仓库删除公告（墓碑），必须由仓库创建者签名
+    RepoDeletion(RepoDeletion),
+    /// 存活探测，只发给直连邻居，不转发
+    Ping(Ping),
+    /// 存活探测的回应，携带相同的 nonce
+    Pong(Pong),
+    /// 节点主动离开（正常关闭前广播）
+    NodeLeave(NodeLeave),
 }
 
 /// 聊天消息 (加密)
@@ -38,6 +54,17 @@ pub struct EncryptedChatMessage {
     pub ciphertext: Vec<u8>,
 }
 
+/// 群聊消息 (使用带外分发的频道密钥加密)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupChatMessage {
+    pub channel_id: String,
+    pub sender_id: NodeId,
+    /// 消息 ID (用于去重)
+    pub msg_id: String,
+    /// 密文数据 (Nonce + Ciphertext)
+    pub ciphertext: Vec<u8>,
+}
+
 /// 聊天回执
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatAckMessage {
@@ -48,6 +75,15 @@ pub struct ChatAckMessage {
     pub signature: String,
 }
 
+/// 已读回执 - reader_id 为读者，target_id 为原消息发送者
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatReadMessage {
+    pub reader_id: NodeId,
+    pub target_id: NodeId,
+    pub msg_id: String,
+    pub timestamp: i64,
+}
+
 /// 节点公告
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NodeAnnouncement {
@@ -83,12 +119,64 @@ pub struct RepoAnnouncement {
     pub repos: Vec<Repo>,
 }
 
+/// 节点交换 - 发送方当前保持连接的节点信息，用于传递式发现节点
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeerExchange {
+    pub node_id: NodeId,
+    pub peers: Vec<NodeInfo>,
+}
+
+/// 仓库删除公告 - node_id 为仓库创建者
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepoDeletion {
+    pub node_id: NodeId,
+    pub repo_id: String,
+}
+
+/// 存活探测 - node_id 为发送方
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Ping {
+    pub node_id: NodeId,
+    pub nonce: u64,
+}
+
+/// 存活探测回应 - node_id 为回应方
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Pong {
+    pub node_id: NodeId,
+    pub nonce: u64,
+}
+
+/// 节点离开公告 - node_id 为离开的节点
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeLeave {
+    pub node_id: NodeId,
+}
+
+/// 准备发给其他节点的仓库：清空本地路径，本节点创建的仓库附上创建者签名
+///
+/// 他人创建的仓库保留数据库中保存的原始签名
+pub(crate) fn shareable_repo(mut repo: Repo, keypair: &KeyPair) -> Repo {
+    repo.path = std::path::PathBuf::new();
+    repo.bundle = std::path::PathBuf::new();
+    if !repo.is_external && repo.p2p_description.creator == NodeId::from_keypair(keypair).as_str() {
+        if let Err(e) = repo.sign(keypair) {
+            tracing::warn!("Failed to sign repo {}: {}", repo.repo_id, e);
+        }
+    }
+    repo
+}
+
 /// 带签名的消息包装
 #[allow(dead_code)]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SignedMessage {
+    /// 消息 ID，用于 gossip 去重（参与签名）；旧版本节点的消息没有该字段
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub msg_id: Option<Uuid>,
     pub node_id: NodeId,
     pub message: GossipMessage,
+    /// 签名时的 Unix 时间戳（秒），参与签名
     pub timestamp: i64,
     pub signature: String,
 }
@@ -98,6 +186,7 @@ impl SignedMessage {
         let message = GossipMessage::NodeAnnouncement(node.clone().into());
 
         let mut sign_message = SignedMessage {
+            msg_id: Some(Uuid::new_v4()),
             node_id: node.node_id().clone(),
             message,
             timestamp: timestamp_now(),
@@ -110,14 +199,10 @@ impl SignedMessage {
     }
 
     pub fn new_repo_sign_message(repos: Vec<Repo>, node: Node) -> Result<Self> {
-        // 转换 repos，清空 path
+        // 转换 repos，清空 path，并签名本节点创建的仓库
         let repos_with_empty_path = repos
             .into_iter()
-            .map(|mut repo| {
-                repo.path = std::path::PathBuf::new();
-                repo.bundle = std::path::PathBuf::new();
-                repo
-            })
+            .map(|repo| shareable_repo(repo, &node.keypair))
             .collect();
 
         let message = GossipMessage::RepoAnnouncement(RepoAnnouncement {
@@ -126,6 +211,84 @@ impl SignedMessage {
         });
 
         let mut sign_message = SignedMessage {
+            msg_id: Some(Uuid::new_v4()),
+            node_id: node.node_id().clone(),
+            message,
+            timestamp: timestamp_now(),
+            signature: "".to_string(),
+        };
+        let self_hash = sign_message.self_hash();
+        let sign = node.sign_message(self_hash.as_slice())?;
+        sign_message.signature = hex::encode(sign);
+        Ok(sign_message)
+    }
+
+    pub fn new_peer_exchange_sign_message(peers: Vec<NodeInfo>, node: Node) -> Result<Self> {
+        let message = GossipMessage::PeerExchange(PeerExchange {
+            node_id: node.node_id().clone(),
+            peers,
+        });
+
+        let mut sign_message = SignedMessage {
+            msg_id: Some(Uuid::new_v4()),
+            node_id: node.node_id().clone(),
+            message,
+            timestamp: timestamp_now(),
+            signature: "".to_string(),
+        };
+        let self_hash = sign_message.self_hash();
+        let sign = node.sign_message(self_hash.as_slice())?;
+        sign_message.signature = hex::encode(sign);
+        Ok(sign_message)
+    }
+
+    /// 仓库删除公告只需要创建者的密钥对签名，不依赖运行中的节点
+    pub fn new_repo_deletion_sign_message(repo_id: String, keypair: &KeyPair) -> Result<Self> {
+        let node_id = NodeId::from_keypair(keypair);
+        let message = GossipMessage::RepoDeletion(RepoDeletion {
+            node_id: node_id.clone(),
+            repo_id,
+        });
+
+        let mut sign_message = SignedMessage {
+            msg_id: Some(Uuid::new_v4()),
+            node_id,
+            message,
+            timestamp: timestamp_now(),
+            signature: "".to_string(),
+        };
+        let self_hash = sign_message.self_hash();
+        let sign = keypair.sign(self_hash.as_slice())?;
+        sign_message.signature = hex::encode(sign.to_bytes());
+        Ok(sign_message)
+    }
+
+    pub fn new_ping_sign_message(nonce: u64, node: Node) -> Result<Self> {
+        let message = GossipMessage::Ping(Ping {
+            node_id: node.node_id().clone(),
+            nonce,
+        });
+        Self::sign_with_node(message, &node)
+    }
+
+    pub fn new_pong_sign_message(nonce: u64, node: Node) -> Result<Self> {
+        let message = GossipMessage::Pong(Pong {
+            node_id: node.node_id().clone(),
+            nonce,
+        });
+        Self::sign_with_node(message, &node)
+    }
+
+    pub fn new_node_leave_sign_message(node: Node) -> Result<Self> {
+        let message = GossipMessage::NodeLeave(NodeLeave {
+            node_id: node.node_id().clone(),
+        });
+        Self::sign_with_node(message, &node)
+    }
+
+    fn sign_with_node(message: GossipMessage, node: &Node) -> Result<Self> {
+        let mut sign_message = SignedMessage {
+            msg_id: Some(Uuid::new_v4()),
             node_id: node.node_id().clone(),
             message,
             timestamp: timestamp_now(),
@@ -160,17 +323,27 @@ impl SignedMessage {
     pub fn self_hash(&self) -> Vec<u8> {
         let mut hasher = Sha256::new();
         // Canonicalize JSON by recursively sorting object keys before serialization.
-        let message_value =
-            serde_json::to_value(&self.message).unwrap_or(serde_json::Value::Null);
+        let message_value = serde_json::to_value(&self.message).unwrap_or(serde_json::Value::Null);
         let canonical_value = Self::canonicalize_value(message_value);
         let message_bytes = serde_json::to_vec(&canonical_value).unwrap_or_default();
 
+        if let Some(msg_id) = &self.msg_id {
+            hasher.update(msg_id.as_bytes());
+        }
         hasher.update(self.node_id.0.as_bytes());
         hasher.update(&message_bytes);
         hasher.update(self.timestamp.to_le_bytes());
         hasher.finalize().to_vec()
     }
 
+    /// 去重使用的消息 ID：没有 msg_id 的旧版本消息使用签名内容的哈希
+    pub fn dedup_id(&self) -> String {
+        match &self.msg_id {
+            Some(msg_id) => msg_id.to_string(),
+            None => hex::encode(self.self_hash()),
+        }
+    }
+
     /// 获取消息的时间戳
     pub fn timestamp(&self) -> i64 {
         self.timestamp
@@ -190,6 +363,13 @@ impl GossipMessage {
             GossipMessage::RepoAnnouncement(_) => "inventory_announcement",
             GossipMessage::Chat(_) => "chat",
             GossipMessage::ChatAck(_ack) => "chat_ack",
+            GossipMessage::ChatRead(_) => "chat_read",
+            GossipMessage::GroupChat(_) => "group_chat",
+            GossipMessage::PeerExchange(_) => "peer_exchange",
+            GossipMessage::RepoDeletion(_) => "repo_deletion",
+            GossipMessage::Ping(_) => "ping",
+            GossipMessage::Pong(_) => "pong",
+            GossipMessage::NodeLeave(_) => "node_leave",
         }
     }
 
@@ -200,6 +380,13 @@ impl GossipMessage {
             GossipMessage::RepoAnnouncement(ra) => &ra.node_id,
             GossipMessage::Chat(c) => &c.sender_id,
             GossipMessage::ChatAck(ack) => &ack.sender_id,
+            GossipMessage::ChatRead(read) => &read.reader_id,
+            GossipMessage::GroupChat(msg) => &msg.sender_id,
+            GossipMessage::PeerExchange(pex) => &pex.node_id,
+            GossipMessage::RepoDeletion(rd) => &rd.node_id,
+            GossipMessage::Ping(ping) => &ping.node_id,
+            GossipMessage::Pong(pong) => &pong.node_id,
+            GossipMessage::NodeLeave(leave) => &leave.node_id,
         }
     }
 }
@@ -236,6 +423,49 @@ mod tests {
         // self_hash is 32 bytes
         let h = signed.self_hash();
         assert_eq!(h.len(), 32);
+        // 只取决于消息内容，重复计算结果一致；时间戳参与哈希
+        assert_eq!(signed.clone().self_hash(), h);
+        let mut later = signed.clone();
+        later.timestamp += 1;
+        assert_ne!(later.self_hash(), h);
+    }
+
+    #[test]
+    fn test_bootstrap_node_announcement_roundtrip() {
+        let keypair = KeyPair::generate().expect("generate keypair");
+        let node = Node::from_keypair(
+            &keypair,
+            "bootstrap-node",
+            vec!["127.0.0.1:9000".parse().unwrap()],
+            crate::node::node::NodeType::Bootstrap,
+        );
+        let signed = SignedMessage::new_node_sign_message(node).expect("sign node message");
+        let bytes = serde_json::to_vec(&signed).expect("serialize");
+        let decoded: SignedMessage = serde_json::from_slice(&bytes).expect("deserialize");
+
+        if let GossipMessage::NodeAnnouncement(na) = &decoded.message {
+            assert_eq!(na.node_type, crate::node::node::NodeType::Bootstrap);
+        } else {
+            panic!("expected NodeAnnouncement");
+        }
+        assert_eq!(decoded.self_hash(), signed.self_hash());
+    }
+
+    #[test]
+    fn test_message_without_msg_id_parses() {
+        let node = make_node();
+        let mut signed = SignedMessage::new_node_sign_message(node.clone()).expect("sign");
+        // 旧版本节点：没有 msg_id，签名也不覆盖它
+        signed.msg_id = None;
+        signed.signature = hex::encode(node.sign_message(&signed.self_hash()).expect("sign"));
+        let mut value = serde_json::to_value(&signed).expect("serialize");
+        assert!(value.get("msg_id").is_none());
+        value.as_object_mut().unwrap().remove("msg_id");
+
+        let decoded: SignedMessage = serde_json::from_value(value).expect("deserialize");
+        assert!(decoded.msg_id.is_none());
+        assert_eq!(decoded.self_hash(), signed.self_hash());
+        assert_eq!(decoded.dedup_id(), hex::encode(signed.self_hash()));
     }
 
     #[test]
@@ -262,6 +492,9 @@ mod tests {
             language: "Rust".to_string(),
             latest_commit_at: 1000,
             size: 0,
+            tags: Vec::new(),
+            commit_count: 0,
+            contributors: 0,
         };
 
         let repo = Repo::new(
@@ -287,6 +520,76 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_new_peer_exchange_sign_message() {
+        let node = make_node();
+        let peer = make_node();
+
+        let signed =
+            SignedMessage::new_peer_exchange_sign_message(vec![peer.info.clone()], node.clone())
+                .expect("sign peer exchange message");
+
+        assert_eq!(signed.message_type(), "peer_exchange");
+        assert_eq!(signed.message.sender(), node.node_id());
+        let sig = hex::decode(&signed.signature).expect("decode hex");
+        assert_eq!(sig.len(), 64);
+
+        if let GossipMessage::PeerExchange(pex) = signed.message {
+            assert_eq!(pex.peers, vec![peer.info]);
+        } else {
+            panic!("expected PeerExchange");
+        }
+    }
+
+    #[test]
+    fn test_new_repo_deletion_sign_message() {
+        let keypair = KeyPair::generate().expect("generate keypair");
+        let signed =
+            SignedMessage::new_repo_deletion_sign_message("did:repo:test".to_string(), &keypair)
+                .expect("sign repo deletion message");
+
+        assert_eq!(signed.message_type(), "repo_deletion");
+        assert_eq!(signed.node_id, NodeId::from_keypair(&keypair));
+        let sig = hex::decode(&signed.signature).expect("decode hex");
+        let sig = ed25519_dalek::Signature::from_slice(&sig).expect("parse signature");
+        assert!(keypair.verify(&signed.self_hash(), &sig));
+    }
+
+    #[test]
+    fn test_new_ping_pong_sign_message() {
+        let node = make_node();
+        let ping = SignedMessage::new_ping_sign_message(7, node.clone()).expect("sign ping");
+        let pong = SignedMessage::new_pong_sign_message(7, node.clone()).expect("sign pong");
+
+        assert_eq!(ping.message_type(), "ping");
+        assert_eq!(pong.message_type(), "pong");
+        assert_eq!(ping.message.sender(), node.node_id());
+        assert_eq!(pong.message.sender(), node.node_id());
+
+        let bytes = serde_json::to_vec(&pong).expect("serialize");
+        let decoded: SignedMessage = serde_json::from_slice(&bytes).expect("deserialize");
+        match decoded.message {
+            GossipMessage::Pong(Pong { nonce, .. }) => assert_eq!(nonce, 7),
+            _ => panic!("expected Pong"),
+        }
+        assert_eq!(decoded.self_hash(), pong.self_hash());
+    }
+
+    #[test]
+    fn test_new_node_leave_sign_message() {
+        let node = make_node();
+        let signed = SignedMessage::new_node_leave_sign_message(node.clone()).expect("sign leave");
+
+        assert_eq!(signed.message_type(), "node_leave");
+        assert_eq!(signed.message.sender(), node.node_id());
+        assert_eq!(&signed.node_id, node.node_id());
+
+        let bytes = serde_json::to_vec(&signed).expect("serialize");
+        let decoded: SignedMessage = serde_json::from_slice(&bytes).expect("deserialize");
+        assert!(matches!(decoded.message, GossipMessage::NodeLeave(_)));
+        assert_eq!(decoded.self_hash(), signed.self_hash());
+    }
+
     fn node_keypair_bytes(kp: &KeyPair) -> Vec<u8> {
         kp.verifying_key.as_bytes().to_vec()
     }
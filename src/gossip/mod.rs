@@ -1,5 +1,12 @@
+mod liveness;
 pub mod message;
+mod rate_limit;
+pub mod reconcile;
 mod service;
+mod verify_cache;
 
+pub use liveness::ping_peer;
 pub use message::SignedMessage;
+pub use rate_limit::ForwardRateLimit;
 pub use service::GossipService;
+pub(crate) use service::{DEFAULT_MAX_CONNECTIONS, DEFAULT_PING_INTERVAL};
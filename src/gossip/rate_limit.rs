@@ -0,0 +1,181 @@
+use crate::node::node_id::NodeId;
+use std::collections::HashMap;
+use std::time::Instant;
+
+/// gossip 转发限速：每个邻居每秒最多转发的消息数和字节数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ForwardRateLimit {
+    pub messages_per_sec: u32,
+    pub bytes_per_sec: u64,
+}
+
+impl Default for ForwardRateLimit {
+    fn default() -> Self {
+        Self {
+            messages_per_sec: 100,
+            bytes_per_sec: 1024 * 1024,
+        }
+    }
+}
+
+/// 令牌桶：容量为一秒的额度，按速率持续补充
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+}
+
+impl TokenBucket {
+    fn new(rate: f64) -> Self {
+        Self {
+            capacity: rate,
+            tokens: rate,
+        }
+    }
+
+    fn refill(&mut self, elapsed_secs: f64) {
+        self.tokens = (self.tokens + elapsed_secs * self.capacity).min(self.capacity);
+    }
+
+    /// 超过容量的单个请求按容量计，避免大消息永远无法通过
+    fn cost(&self, amount: f64) -> f64 {
+        amount.min(self.capacity)
+    }
+
+    fn is_full(&self) -> bool {
+        self.tokens >= self.capacity
+    }
+}
+
+#[derive(Debug)]
+struct PeerBuckets {
+    messages: TokenBucket,
+    bytes: TokenBucket,
+    last_refill: Instant,
+}
+
+/// 按来源邻居分别限速，一个邻居刷屏不会占用其他邻居的转发额度
+#[derive(Debug)]
+pub(crate) struct PeerRateLimiter {
+    limit: ForwardRateLimit,
+    peers: HashMap<NodeId, PeerBuckets>,
+}
+
+impl PeerRateLimiter {
+    pub(crate) fn new(limit: ForwardRateLimit) -> Self {
+        Self {
+            limit,
+            peers: HashMap::new(),
+        }
+    }
+
+    /// 是否允许转发来自 peer 的一条 len 字节的消息，允许时扣除额度
+    pub(crate) fn allow(&mut self, peer: &NodeId, len: usize) -> bool {
+        self.allow_at(peer, len, Instant::now())
+    }
+
+    fn allow_at(&mut self, peer: &NodeId, len: usize, now: Instant) -> bool {
+        let limit = self.limit;
+        let buckets = self
+            .peers
+            .entry(peer.clone())
+            .or_insert_with(|| PeerBuckets {
+                messages: TokenBucket::new(limit.messages_per_sec as f64),
+                bytes: TokenBucket::new(limit.bytes_per_sec as f64),
+                last_refill: now,
+            });
+
+        let elapsed = now
+            .saturating_duration_since(buckets.last_refill)
+            .as_secs_f64();
+        buckets.messages.refill(elapsed);
+        buckets.bytes.refill(elapsed);
+        buckets.last_refill = now;
+
+        let message_cost = buckets.messages.cost(1.0);
+        let byte_cost = buckets.bytes.cost(len as f64);
+        if buckets.messages.tokens < message_cost || buckets.bytes.tokens < byte_cost {
+            return false;
+        }
+        buckets.messages.tokens -= message_cost;
+        buckets.bytes.tokens -= byte_cost;
+        true
+    }
+
+    /// 删除额度已经补满的邻居，避免断开的节点一直占用内存
+    pub(crate) fn prune_idle(&mut self) {
+        let now = Instant::now();
+        self.peers.retain(|_, buckets| {
+            let elapsed = now
+                .saturating_duration_since(buckets.last_refill)
+                .as_secs_f64();
+            buckets.messages.refill(elapsed);
+            buckets.bytes.refill(elapsed);
+            buckets.last_refill = now;
+            !(buckets.messages.is_full() && buckets.bytes.is_full())
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::keypair::KeyPair;
+    use std::time::Duration;
+
+    fn peer() -> NodeId {
+        NodeId::from_keypair(&KeyPair::generate().unwrap())
+    }
+
+    #[test]
+    fn test_burst_is_throttled_per_peer() {
+        let mut limiter = PeerRateLimiter::new(ForwardRateLimit {
+            messages_per_sec: 5,
+            bytes_per_sec: 1024 * 1024,
+        });
+        let flooder = peer();
+        let other = peer();
+        let now = Instant::now();
+
+        let allowed = (0..50)
+            .filter(|_| limiter.allow_at(&flooder, 100, now))
+            .count();
+        assert_eq!(allowed, 5);
+
+        // 其他邻居不受影响
+        assert!(limiter.allow_at(&other, 100, now));
+
+        // 额度随时间恢复
+        assert!(limiter.allow_at(&flooder, 100, now + Duration::from_millis(200)));
+        assert!(!limiter.allow_at(&flooder, 100, now + Duration::from_millis(200)));
+    }
+
+    #[test]
+    fn test_bytes_limit() {
+        let mut limiter = PeerRateLimiter::new(ForwardRateLimit {
+            messages_per_sec: 100,
+            bytes_per_sec: 1000,
+        });
+        let flooder = peer();
+        let now = Instant::now();
+
+        assert!(limiter.allow_at(&flooder, 600, now));
+        assert!(!limiter.allow_at(&flooder, 600, now));
+        assert!(limiter.allow_at(&flooder, 400, now));
+
+        // 超过每秒额度的单条消息在额度补满后仍可通过
+        assert!(limiter.allow_at(&flooder, 5000, now + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_prune_idle() {
+        let mut limiter = PeerRateLimiter::new(ForwardRateLimit {
+            messages_per_sec: 1000,
+            bytes_per_sec: 1024 * 1024,
+        });
+        let p = peer();
+        assert!(limiter.allow_at(&p, 10, Instant::now() - Duration::from_secs(5)));
+        limiter.prune_idle();
+        assert!(limiter.peers.is_empty());
+    }
+}
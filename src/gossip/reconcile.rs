@@ -0,0 +1,290 @@
+use crate::gossip::message::{shareable_repo, RepoAnnouncement};
+use crate::gossip::service::handle_repo_announcement;
+use crate::identity::keypair::KeyPair;
+use crate::node::node_id::NodeId;
+use crate::repo::repo::Repo;
+use crate::storage::repo_model;
+use crate::transport::quic::ConnectionManager;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+
+/// 单条对账消息最多携带的仓库数，避免超过请求流的读取上限；其余仓库等待下一轮或公告
+const MAX_RECONCILE_REPOS: usize = 200;
+
+/// 仓库集合摘要：排序后的 repo_id 和它们的哈希，哈希一致时无需比较列表
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RepoDigest {
+    pub repo_ids: Vec<String>,
+    pub hash: String,
+}
+
+impl RepoDigest {
+    pub fn from_repos(repos: &[Repo]) -> Self {
+        let mut repo_ids: Vec<String> = repos.iter().map(|r| r.repo_id.clone()).collect();
+        repo_ids.sort();
+        repo_ids.dedup();
+
+        let mut hasher = Sha256::new();
+        for id in &repo_ids {
+            hasher.update(id.as_bytes());
+            hasher.update(b"\n");
+        }
+        let hash = hex::encode(hasher.finalize());
+        Self { repo_ids, hash }
+    }
+}
+
+/// 直连邻居之间的对账请求，通过 `ConnectionManager::request` 发送
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ReconcileRequest {
+    /// 发起方的仓库摘要
+    Digest(RepoDigest),
+    /// 发起方补发应答方缺少的仓库
+    Push(Vec<Repo>),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ReconcileResponse {
+    /// 双方的仓库集合一致
+    InSync,
+    /// 应答方有而发起方没有的仓库，以及应答方缺少的 repo_id
+    Delta {
+        repos: Vec<Repo>,
+        missing: Vec<String>,
+    },
+    /// Push 已处理
+    Ack,
+}
+
+/// 一轮对账的结果
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReconcileReport {
+    /// 从对端收到的仓库数
+    pub received: usize,
+    /// 补发给对端的仓库数
+    pub sent: usize,
+}
+
+/// 与直连邻居进行一轮对账：发送摘要，保存对端多出的仓库，再补发对端缺少的仓库
+pub async fn reconcile_with_peer(
+    manager: &ConnectionManager,
+    keypair: &KeyPair,
+    peer: &NodeId,
+) -> Result<ReconcileReport> {
+    let local = repo_model::list_repos().await?;
+    let digest = ReconcileRequest::Digest(RepoDigest::from_repos(&local));
+    let response = manager
+        .request(peer.clone(), serde_json::to_vec(&digest)?)
+        .await?;
+
+    let (repos, missing) = match serde_json::from_slice(&response)? {
+        ReconcileResponse::InSync => return Ok(ReconcileReport::default()),
+        ReconcileResponse::Delta { repos, missing } => (repos, missing),
+        ReconcileResponse::Ack => anyhow::bail!("unexpected reconcile response from {}", peer),
+    };
+
+    let received = repos.len();
+    if !repos.is_empty() {
+        handle_repo_announcement(&RepoAnnouncement {
+            node_id: peer.clone(),
+            repos,
+        })
+        .await;
+    }
+
+    let missing: HashSet<String> = missing.into_iter().collect();
+    let push: Vec<Repo> = local
+        .into_iter()
+        .filter(|r| missing.contains(&r.repo_id))
+        .take(MAX_RECONCILE_REPOS)
+        .map(|r| shareable_repo(r, keypair))
+        .collect();
+    let sent = push.len();
+    if !push.is_empty() {
+        let request = ReconcileRequest::Push(push);
+        let response = manager
+            .request(peer.clone(), serde_json::to_vec(&request)?)
+            .await?;
+        if !matches!(serde_json::from_slice(&response)?, ReconcileResponse::Ack) {
+            anyhow::bail!("unexpected reconcile response from {}", peer);
+        }
+    }
+
+    Ok(ReconcileReport { received, sent })
+}
+
+/// 处理邻居发来的对账请求，返回序列化后的响应
+pub async fn handle_reconcile_request(
+    keypair: &KeyPair,
+    from: &NodeId,
+    payload: &[u8],
+) -> Result<Vec<u8>> {
+    let response = match serde_json::from_slice(payload)? {
+        ReconcileRequest::Digest(theirs) => {
+            let local = repo_model::list_repos().await?;
+            let ours = RepoDigest::from_repos(&local);
+            if ours.hash == theirs.hash {
+                ReconcileResponse::InSync
+            } else {
+                let known: HashSet<&String> = theirs.repo_ids.iter().collect();
+                let ours_set: HashSet<&String> = ours.repo_ids.iter().collect();
+                let missing = theirs
+                    .repo_ids
+                    .iter()
+                    .filter(|id| !ours_set.contains(id))
+                    .cloned()
+                    .collect();
+                let repos = local
+                    .into_iter()
+                    .filter(|r| !known.contains(&r.repo_id))
+                    .take(MAX_RECONCILE_REPOS)
+                    .map(|r| shareable_repo(r, keypair))
+                    .collect();
+                ReconcileResponse::Delta { repos, missing }
+            }
+        }
+        ReconcileRequest::Push(repos) => {
+            let repos = repos.into_iter().take(MAX_RECONCILE_REPOS).collect();
+            handle_repo_announcement(&RepoAnnouncement {
+                node_id: from.clone(),
+                repos,
+            })
+            .await;
+            ReconcileResponse::Ack
+        }
+    };
+    Ok(serde_json::to_vec(&response)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::keypair::KeyPair;
+    use crate::repo::repo::P2PDescription;
+    use crate::storage::with_test_db;
+    use crate::transport::config::QuicConfig;
+    use tokio::sync::{mpsc, oneshot};
+
+    fn local_repo(name: &str, creator: &NodeId) -> Repo {
+        let desc = P2PDescription {
+            creator: creator.to_string(),
+            name: name.to_string(),
+            description: String::new(),
+            language: "Rust".to_string(),
+            latest_commit_at: 0,
+            size: 0,
+            tags: Vec::new(),
+            commit_count: 0,
+            contributors: 0,
+        };
+        let mut repo = Repo::new(
+            format!("did:repo:reconcile-{}", name),
+            desc,
+            std::path::PathBuf::from(format!("/tmp/{}", name)),
+        );
+        repo.add_ref("refs/heads/main".to_string(), "a".repeat(40));
+        repo
+    }
+
+    async fn repo_ids() -> Result<Vec<String>> {
+        let mut ids: Vec<String> = repo_model::list_repos()
+            .await?
+            .into_iter()
+            .map(|r| r.repo_id)
+            .collect();
+        ids.sort();
+        Ok(ids)
+    }
+
+    async fn start_manager(kp: &KeyPair) -> Result<ConnectionManager> {
+        let config = QuicConfig::new(
+            "127.0.0.1:0".parse()?,
+            String::new(),
+            String::new(),
+            String::new(),
+        )
+        .with_identity(kp.clone())
+        .with_peer_verification(true);
+        ConnectionManager::run_server(config).await
+    }
+
+    #[test]
+    fn test_digest_ignores_order() {
+        let creator = NodeId::from_keypair(&KeyPair::generate().unwrap());
+        let a = local_repo("a", &creator);
+        let b = local_repo("b", &creator);
+        let digest = RepoDigest::from_repos(&[b.clone(), a.clone()]);
+        assert_eq!(digest, RepoDigest::from_repos(&[a.clone(), b]));
+        assert_eq!(digest.repo_ids[0], a.repo_id);
+        assert_ne!(digest.hash, RepoDigest::from_repos(&[a]).hash);
+    }
+
+    #[tokio::test]
+    async fn test_disjoint_repo_sets_converge_after_one_round() -> Result<()> {
+        let _ = rustls::crypto::ring::default_provider().install_default();
+        let kp_a = KeyPair::generate()?;
+        let kp_b = KeyPair::generate()?;
+        let id_a = NodeId::from_keypair(&kp_a);
+        let id_b = NodeId::from_keypair(&kp_b);
+        let mgr_a = start_manager(&kp_a).await?;
+        let mgr_b = start_manager(&kp_b).await?;
+        let addr_b = mgr_b.local_addrs();
+
+        let (request_tx, mut request_rx) = mpsc::channel(8);
+        mgr_b.register_request_handler(request_tx).await;
+
+        // 每个节点使用各自的内存数据库，B 在自己的数据库中处理请求
+        let (ready_tx, ready_rx) = oneshot::channel();
+        let creator_b = id_b.clone();
+        let node_b = tokio::spawn(with_test_db(async move {
+            repo_model::insert_repos(&[local_repo("b1", &creator_b), local_repo("b2", &creator_b)])
+                .await?;
+            let _ = ready_tx.send(());
+            // Digest + Push，随后第二轮只有 Digest
+            for _ in 0..3 {
+                let request = request_rx.recv().await.expect("request");
+                let response =
+                    handle_reconcile_request(&kp_b, &request.from, &request.payload).await?;
+                request.respond(response);
+            }
+            repo_ids().await
+        }));
+
+        let ids_a = with_test_db(async {
+            repo_model::insert_repos(&[local_repo("a1", &id_a)]).await?;
+            ready_rx.await?;
+            mgr_a.connect(id_a.clone(), id_b.clone(), addr_b).await?;
+
+            let report = reconcile_with_peer(&mgr_a, &kp_a, &id_b).await?;
+            assert_eq!(
+                report,
+                ReconcileReport {
+                    received: 2,
+                    sent: 1
+                }
+            );
+            let received = repo_model::load_repo_from_db(&local_repo("b1", &id_b).repo_id)
+                .await?
+                .expect("b1 received");
+            assert!(received.is_external);
+            assert!(received.path.as_os_str().is_empty());
+            assert!(received.verify_signature());
+
+            // 集合一致后，摘要哈希相同，不再交换仓库
+            let again = reconcile_with_peer(&mgr_a, &kp_a, &id_b).await?;
+            assert_eq!(again, ReconcileReport::default());
+            repo_ids().await
+        })
+        .await?;
+
+        let ids_b = node_b.await??;
+        assert_eq!(ids_a.len(), 3);
+        assert_eq!(ids_a, ids_b);
+
+        mgr_a.shutdown().await;
+        mgr_b.shutdown().await;
+        Ok(())
+    }
+}
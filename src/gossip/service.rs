@@ -1,19 +1,40 @@
-use crate::gossip::message::{Envelope, GossipMessage, SignedMessage};
-use crate::node::node::{Node, NodeInfo};
+use crate::gossip::liveness::{LivenessTracker, MAX_MISSED_PINGS};
+use crate::gossip::message::{
+    Envelope, GossipMessage, NodeLeave, PeerExchange, RepoAnnouncement, RepoDeletion, SignedMessage,
+};
+use crate::gossip::rate_limit::{ForwardRateLimit, PeerRateLimiter};
+use crate::gossip::reconcile;
+use crate::gossip::verify_cache::VerifiedCache;
+use crate::node::node::{Node, NodeInfo, NodeType};
 use crate::node::node_id::NodeId;
+use crate::repo::repo::Repo;
 use crate::repo::repo_manager::RepoManager;
 use crate::storage::node_model;
-use crate::transport::quic::ConnectionManager;
+use crate::transport::quic::{ConnectionEvent, ConnectionManager, IncomingRequest};
 use anyhow::Result;
 use ed25519_dalek::Signature;
 use hex;
-use std::collections::HashMap;
 use std::convert::TryInto;
 use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 use tokio::sync::{mpsc, Mutex};
 
 const DEFAULT_TTL: u8 = 16;
+// 关闭前等待邻居确认收到 NodeLeave 的最长时间
+const LEAVE_DELIVERY_TIMEOUT: Duration = Duration::from_secs(2);
+pub(crate) const DEFAULT_MAX_CONNECTIONS: usize = 32;
+// 去重记录保留时长（秒）
+const SEEN_RETENTION_SECS: i64 = 300;
+// 中继暂存的离线消息保留时长（秒）
+const RELAY_STORE_TTL_SECS: i64 = 24 * 60 * 60;
+// 超过该时长未收到公告的节点会从节点表中删除（秒）
+const NODE_RETENTION_SECS: i64 = 7 * 24 * 60 * 60;
+// 仓库删除墓碑的保留时间，过期后不再重新广播，也不再阻止仓库公告
+const TOMBSTONE_RETENTION_SECS: i64 = 30 * 24 * 60 * 60;
+// 验签结果缓存的最大条目数
+const VERIFIED_CACHE_CAPACITY: usize = 4096;
+// 向直连邻居发送存活探测的默认间隔
+pub(crate) const DEFAULT_PING_INTERVAL: Duration = Duration::from_secs(10);
 
 /// 简单的 gossip 服务：接收来自 QUIC 的 Gossip 控制消息，去重、验签、处理并转发给邻居
 #[allow(dead_code)]
@@ -21,7 +42,20 @@ pub struct GossipService {
     manager: Arc<Mutex<ConnectionManager>>,
     node: Node,
     repo_manager: Option<Arc<Mutex<RepoManager>>>,
-    seen: Arc<Mutex<HashMap<String, Instant>>>,
+    /// 是否主动连接通过 PeerExchange 学到的节点（被动节点只记录不连接）
+    pex_dial: bool,
+    /// 通过 PeerExchange 主动连接时的最大连接数
+    max_connections: usize,
+    /// 中继节点是否暂存无法投递的聊天消息，待接收者上线后重放
+    relay_store: bool,
+    /// 按来源邻居限制转发速率，超出额度的消息只在本地处理不再转发
+    forward_limiter: Mutex<PeerRateLimiter>,
+    /// 已验签消息缓存，转发来的重复副本不再重复验签
+    verified_cache: Mutex<VerifiedCache>,
+    /// 存活探测间隔，独立于公告周期
+    ping_interval: Duration,
+    /// 各邻居未回应的存活探测
+    liveness: Mutex<LivenessTracker>,
 }
 
 impl GossipService {
@@ -34,10 +68,46 @@ impl GossipService {
             manager,
             node,
             repo_manager,
-            seen: Arc::new(Mutex::new(HashMap::new())),
+            pex_dial: true,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            relay_store: false,
+            forward_limiter: Mutex::new(PeerRateLimiter::new(ForwardRateLimit::default())),
+            verified_cache: Mutex::new(VerifiedCache::new(VERIFIED_CACHE_CAPACITY)),
+            ping_interval: DEFAULT_PING_INTERVAL,
+            liveness: Mutex::new(LivenessTracker::new()),
         }
     }
 
+    /// 设置是否主动连接通过 PeerExchange 学到的节点
+    pub fn with_peer_exchange_dial(mut self, dial: bool) -> Self {
+        self.pex_dial = dial;
+        self
+    }
+
+    /// 设置通过 PeerExchange 主动连接时的最大连接数
+    pub fn with_max_connections(mut self, max_connections: usize) -> Self {
+        self.max_connections = max_connections;
+        self
+    }
+
+    /// 设置是否为离线接收者暂存聊天消息（store-and-forward）
+    pub fn with_relay_store(mut self, enabled: bool) -> Self {
+        self.relay_store = enabled;
+        self
+    }
+
+    /// 设置每个邻居的转发速率上限（消息数/秒、字节数/秒）
+    pub fn with_forward_rate_limit(mut self, limit: ForwardRateLimit) -> Self {
+        self.forward_limiter = Mutex::new(PeerRateLimiter::new(limit));
+        self
+    }
+
+    /// 设置向直连邻居发送存活探测的间隔
+    pub fn with_ping_interval(mut self, interval: Duration) -> Self {
+        self.ping_interval = interval;
+        self
+    }
+
     /// Start the gossip service: register gossip channel and spawn handler + periodic broadcaster
     pub async fn start(self: Arc<Self>) -> Result<()> {
         // 注册 Gossip 控制消息接收器
@@ -48,6 +118,45 @@ impl GossipService {
             mgr.register_gossip_sender(gossip_tx).await;
         }
 
+        // 直连邻居之间的仓库对账：处理对端的请求，并在新连接建立时发起一轮
+        let (request_tx, mut request_rx) = mpsc::channel::<IncomingRequest>(64);
+        let (event_tx, mut event_rx) = mpsc::channel::<ConnectionEvent>(64);
+        {
+            let mgr = self.manager.lock().await;
+            mgr.register_request_handler(request_tx).await;
+            mgr.register_connection_event_sender(event_tx).await;
+        }
+        let keypair = self.node.keypair.clone();
+        tokio::spawn(async move {
+            while let Some(request) = request_rx.recv().await {
+                let keypair = keypair.clone();
+                tokio::spawn(async move {
+                    match reconcile::handle_reconcile_request(
+                        &keypair,
+                        &request.from,
+                        &request.payload,
+                    )
+                    .await
+                    {
+                        Ok(response) => request.respond(response),
+                        Err(e) => tracing::warn!(
+                            "Failed to handle reconcile request from {}: {}",
+                            request.from.short(),
+                            e
+                        ),
+                    }
+                });
+            }
+        });
+        let s0 = Arc::clone(&self);
+        tokio::spawn(async move {
+            while let Some(event) = event_rx.recv().await {
+                if let ConnectionEvent::Connected(peer, _) = event {
+                    s0.spawn_reconcile(peer).await;
+                }
+            }
+        });
+
         // Gossip 消息处理任务
         let s = Arc::clone(&self);
         tokio::spawn(async move {
@@ -97,24 +206,465 @@ impl GossipService {
                     }
                 }
 
+                // 3. 重新广播仓库删除墓碑，让后加入的节点也能删除过期副本
+                if let Ok(tombstones) =
+                    crate::storage::repo_tombstone::list_tombstone_messages().await
+                {
+                    let mgr = s2.manager.lock().await;
+                    let peers = mgr.list_peers().await;
+                    for signed in tombstones {
+                        let env = Envelope {
+                            payload: signed,
+                            ttl: DEFAULT_TTL,
+                        };
+                        let data = serde_json::to_vec(&env).unwrap_or_default();
+                        for peer in peers.iter() {
+                            let _ = mgr.send_gossip_message(peer.clone(), data.clone()).await;
+                        }
+                    }
+                }
+
+                // 4. 发送 PeerExchange（当前已连接且已知信息的节点）
+                let peer_infos = s2.connected_peer_infos().await;
+                if !peer_infos.is_empty() {
+                    if let Ok(signed) =
+                        SignedMessage::new_peer_exchange_sign_message(peer_infos, s2.node.clone())
+                    {
+                        let env = Envelope {
+                            payload: signed,
+                            ttl: DEFAULT_TTL,
+                        };
+                        tracing::debug!("Broadcasting PeerExchange: {:?}", env);
+                        let data = serde_json::to_vec(&env).unwrap_or_default();
+                        let mgr = s2.manager.lock().await;
+                        let peers = mgr.list_peers().await;
+                        for peer in peers {
+                            let _ = mgr.send_gossip_message(peer.clone(), data.clone()).await;
+                        }
+                    }
+                }
+
                 tokio::time::sleep(Duration::from_secs(30)).await;
             }
         });
 
-        // spawn a cleanup task for seen map
-        let seen = Arc::clone(&self.seen);
+        // 存活探测：按固定间隔 Ping 直连邻居，连续未回应的连接被关闭
+        let s4 = Arc::clone(&self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(s4.ping_interval);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                s4.ping_peers().await;
+            }
+        });
+
+        // spawn a cleanup task for the persisted seen-set
+        let s3 = Arc::clone(&self);
         tokio::spawn(async move {
             loop {
                 tokio::time::sleep(Duration::from_secs(30)).await;
-                let mut guard = seen.lock().await;
-                let now = Instant::now();
-                guard.retain(|_, &mut v| v + Duration::from_secs(300) > now);
+                if let Err(e) =
+                    crate::storage::seen_message::cleanup_seen(SEEN_RETENTION_SECS).await
+                {
+                    tracing::warn!("Failed to clean up seen messages: {}", e);
+                }
+                if let Err(e) = crate::storage::pending_relay::cleanup_expired_relay().await {
+                    tracing::warn!("Failed to clean up expired relay messages: {}", e);
+                }
+                if let Err(e) =
+                    crate::storage::repo_tombstone::cleanup_tombstones(TOMBSTONE_RETENTION_SECS)
+                        .await
+                {
+                    tracing::warn!("Failed to clean up repo tombstones: {}", e);
+                }
+                match node_model::prune_stale_nodes(NODE_RETENTION_SECS).await {
+                    Ok(0) => {}
+                    Ok(n) => tracing::info!("Pruned {} nodes not seen for 7 days", n),
+                    Err(e) => tracing::warn!("Failed to prune stale nodes: {}", e),
+                }
+                s3.forward_limiter.lock().await.prune_idle();
+                s3.verified_cache.lock().await.clear();
             }
         });
 
         Ok(())
     }
 
+    /// 与新连接的邻居对账，双方中 NodeId 较小的一方发起，避免同一连接对账两次
+    async fn spawn_reconcile(&self, peer: NodeId) {
+        if self.node.node_id().as_str() >= peer.as_str() {
+            return;
+        }
+        let mgr = self.manager.lock().await.clone();
+        let keypair = self.node.keypair.clone();
+        tokio::spawn(async move {
+            match reconcile::reconcile_with_peer(&mgr, &keypair, &peer).await {
+                Ok(report) => tracing::info!(
+                    "Reconciled repos with {}: received {}, sent {}",
+                    peer.short(),
+                    report.received,
+                    report.sent
+                ),
+                // 旧版本节点不处理对账请求，依靠周期公告收敛
+                Err(e) => {
+                    tracing::debug!("Repo reconciliation with {} failed: {}", peer.short(), e)
+                }
+            }
+        });
+    }
+
+    /// 向每个直连邻居发送 Ping，并关闭连续 `MAX_MISSED_PINGS` 次未回应的连接
+    async fn ping_peers(&self) {
+        let mgr = self.manager.lock().await.clone();
+        let peers = mgr.list_peers().await;
+        let (pings, suspects) = self.liveness.lock().await.next_round(&peers);
+
+        for peer in suspects {
+            tracing::warn!(
+                "Peer {} did not answer {} pings, closing connection",
+                peer.short(),
+                MAX_MISSED_PINGS
+            );
+            mgr.close_unresponsive(&peer).await;
+        }
+
+        for (peer, nonce) in pings {
+            let signed = match SignedMessage::new_ping_sign_message(nonce, self.node.clone()) {
+                Ok(signed) => signed,
+                Err(e) => {
+                    tracing::warn!("Failed to sign ping to {}: {}", peer.short(), e);
+                    continue;
+                }
+            };
+            let env = Envelope {
+                payload: signed,
+                ttl: 0,
+            };
+            let data = serde_json::to_vec(&env).unwrap_or_default();
+            let _ = mgr.send_gossip_message(peer, data).await;
+        }
+    }
+
+    /// 处理直连邻居的 Ping/Pong：回应 Ping，匹配 Pong，并更新对端的 last_seen
+    async fn handle_liveness(&self, from: &NodeId, signed: &SignedMessage) {
+        // 探测只在直连邻居之间交换，签名者必须是发来消息的邻居
+        if signed.node_id != *from || signed.message.sender() != from {
+            tracing::debug!(
+                "Ignoring {} from {} signed by {}",
+                signed.message_type(),
+                from.short(),
+                signed.node_id.short()
+            );
+            return;
+        }
+
+        let alive = match &signed.message {
+            GossipMessage::Ping(ping) => {
+                // 每个邻居每个探测周期最多回应一次，过于频繁的 Ping 直接忽略
+                if !self
+                    .liveness
+                    .lock()
+                    .await
+                    .answer_ping(from, self.ping_interval)
+                {
+                    tracing::debug!("Ignoring ping flood from {}", from.short());
+                    return;
+                }
+                match SignedMessage::new_pong_sign_message(ping.nonce, self.node.clone()) {
+                    Ok(pong) => {
+                        let env = Envelope {
+                            payload: pong,
+                            ttl: 0,
+                        };
+                        let data = serde_json::to_vec(&env).unwrap_or_default();
+                        let mgr = self.manager.lock().await.clone();
+                        let _ = mgr.send_gossip_message(from.clone(), data).await;
+                    }
+                    Err(e) => tracing::warn!("Failed to sign pong: {}", e),
+                }
+                true
+            }
+            GossipMessage::Pong(pong) => self.liveness.lock().await.record_pong(from, pong.nonce),
+            _ => false,
+        };
+
+        if alive {
+            if let Err(e) = node_model::mark_node_seen(from.as_str()).await {
+                tracing::warn!("Failed to update last seen of {}: {}", from.short(), e);
+            }
+        }
+    }
+
+    /// 处理节点离开公告：在数据库中标记为离线（保留记录），并关闭与它的直连
+    ///
+    /// 签名者与离开的节点一致已在 handle_incoming 中校验
+    async fn handle_node_leave(&self, signed: &SignedMessage, leave: &NodeLeave) {
+        if leave.node_id == *self.node.node_id() {
+            return;
+        }
+        match node_model::mark_node_left(leave.node_id.as_str(), signed.timestamp()).await {
+            Ok(true) => {}
+            Ok(false) => tracing::debug!(
+                "Ignoring stale or unknown NodeLeave from {}",
+                leave.node_id.short()
+            ),
+            Err(e) => tracing::warn!("Failed to mark {} as left: {}", leave.node_id.short(), e),
+        }
+        let mgr = self.manager.lock().await.clone();
+        if mgr.close_departed(&leave.node_id).await {
+            tracing::info!(
+                "Closed connection to departed node {}",
+                leave.node_id.short()
+            );
+        }
+    }
+
+    /// 正常关闭前向所有直连邻居广播签名的 NodeLeave，并等待对端确认收到（最多 2 秒）
+    ///
+    /// 邻居会继续转发该公告，让整个网络立即把本节点标记为离线，而不必等待存活探测超时
+    pub async fn announce_leave(&self) -> Result<()> {
+        let signed = SignedMessage::new_node_leave_sign_message(self.node.clone())?;
+        let env = Envelope {
+            payload: signed,
+            ttl: DEFAULT_TTL,
+        };
+        let data = serde_json::to_vec(&env)?;
+        let mgr = self.manager.lock().await.clone();
+        let peers = mgr.list_peers().await;
+        let sends = peers
+            .iter()
+            .map(|peer| mgr.send_gossip_message_confirmed(peer.clone(), data.clone()));
+        match tokio::time::timeout(LEAVE_DELIVERY_TIMEOUT, futures::future::join_all(sends)).await {
+            Ok(results) => {
+                let delivered = results.iter().filter(|r| r.is_ok()).count();
+                tracing::info!("NodeLeave delivered to {}/{} peers", delivered, peers.len());
+            }
+            Err(_) => tracing::warn!(
+                "Timed out delivering NodeLeave to {} peers after {:?}",
+                peers.len(),
+                LEAVE_DELIVERY_TIMEOUT
+            ),
+        }
+        Ok(())
+    }
+
+    /// 收集当前已连接节点的 NodeInfo（仅包含数据库中已知的节点）
+    async fn connected_peer_infos(&self) -> Vec<NodeInfo> {
+        let peers = self.manager.lock().await.list_peers().await;
+        let mut infos = Vec::new();
+        for peer in peers {
+            match node_model::load_node_info_from_db(peer.as_str()).await {
+                Ok(Some(info)) => infos.push(info),
+                Ok(None) => {}
+                Err(e) => tracing::warn!("Failed to load node info for {}: {}", peer.short(), e),
+            }
+        }
+        infos
+    }
+
+    /// 处理 PeerExchange：记录未知节点，并在未超过连接上限时主动连接
+    ///
+    /// 发送方为 Relay 类型节点时，同时记录它报告的直连节点，供数据传输选择中继
+    async fn handle_peer_exchange(&self, pex: &PeerExchange) {
+        // 克隆出 ConnectionManager，避免在握手期间持有锁
+        let mgr = self.manager.lock().await.clone();
+        if let Ok(Some(sender)) = node_model::load_node_info_from_db(pex.node_id.as_str()).await {
+            if sender.node_type == NodeType::Relay {
+                mgr.record_relay_peers(
+                    pex.node_id.clone(),
+                    pex.peers.iter().map(|info| info.node_id.clone()),
+                )
+                .await;
+            }
+        }
+        let mut connected = mgr.list_peers().await;
+        let self_id = self.node.node_id().clone();
+
+        // 连接数有限时优先连接长期在线的引导节点
+        let mut peers: Vec<&NodeInfo> = pex.peers.iter().collect();
+        peers.sort_by_key(|info| info.node_type != NodeType::Bootstrap);
+
+        for info in peers {
+            if info.node_id == self_id || connected.contains(&info.node_id) {
+                continue;
+            }
+
+            match node_model::load_node_info_from_db(info.node_id.as_str()).await {
+                Ok(Some(_)) => {}
+                Ok(None) => {
+                    if let Err(e) = node_model::save_node_info_to_db(info).await {
+                        tracing::warn!("Failed to save node info to db: {}", e);
+                    }
+                }
+                Err(e) => tracing::warn!(
+                    "Failed to load node info for {}: {}",
+                    info.node_id.short(),
+                    e
+                ),
+            }
+
+            if !self.pex_dial || info.addresses.is_empty() {
+                continue;
+            }
+            if connected.len() >= self.max_connections {
+                tracing::debug!(
+                    "Max connections ({}) reached, not dialing peers from exchange",
+                    self.max_connections
+                );
+                break;
+            }
+
+            connected.push(info.node_id.clone());
+            let mgr = mgr.clone();
+            let self_id = self_id.clone();
+            let info = info.clone();
+            tokio::spawn(async move {
+                match mgr
+                    .connect(self_id, info.node_id.clone(), info.addresses.clone())
+                    .await
+                {
+                    Ok(_) => tracing::info!(
+                        "Connected to peer {} ({}) learned from peer exchange",
+                        info.node_id.short(),
+                        info.alias
+                    ),
+                    Err(e) => tracing::debug!(
+                        "Failed to connect to peer {} from peer exchange: {}",
+                        info.node_id.short(),
+                        e
+                    ),
+                }
+            });
+        }
+    }
+
+    /// 中继暂存：接收者当前未直接连接时保存消息，待其上线后重放
+    async fn store_for_offline_receiver(&self, signed: &SignedMessage, receiver: &NodeId) {
+        if receiver == self.node.node_id() {
+            return;
+        }
+        let peers = self.manager.lock().await.list_peers().await;
+        if peers.contains(receiver) {
+            return;
+        }
+
+        match crate::storage::pending_relay::save_pending_relay(
+            receiver.as_str(),
+            signed,
+            RELAY_STORE_TTL_SECS,
+        )
+        .await
+        {
+            Ok(_) => tracing::debug!(
+                "Stored chat message {} for offline receiver {}",
+                signed.dedup_id(),
+                receiver.short()
+            ),
+            Err(e) => tracing::warn!(
+                "Failed to store relay message for {}: {}",
+                receiver.short(),
+                e
+            ),
+        }
+    }
+
+    /// 接收者重新出现时，重放为其暂存的消息
+    async fn replay_pending_relay(&self, receiver: &NodeId) {
+        let messages =
+            match crate::storage::pending_relay::list_pending_relay(receiver.as_str()).await {
+                Ok(messages) => messages,
+                Err(e) => {
+                    tracing::warn!(
+                        "Failed to load relay messages for {}: {}",
+                        receiver.short(),
+                        e
+                    );
+                    return;
+                }
+            };
+        if messages.is_empty() {
+            return;
+        }
+
+        let mgr = self.manager.lock().await;
+        let peers = mgr.list_peers().await;
+        // 直接连接则只发给接收者，否则交给 gossip 泛洪
+        let targets: Vec<NodeId> = if peers.contains(receiver) {
+            vec![receiver.clone()]
+        } else {
+            peers
+        };
+
+        for signed in messages {
+            let msg_id = signed.dedup_id();
+            let env = Envelope {
+                payload: signed,
+                ttl: DEFAULT_TTL,
+            };
+            let data = serde_json::to_vec(&env).unwrap_or_default();
+            let mut delivered = false;
+            for peer in targets.iter() {
+                if mgr
+                    .send_gossip_message(peer.clone(), data.clone())
+                    .await
+                    .is_ok()
+                {
+                    delivered = true;
+                }
+            }
+            if delivered {
+                tracing::info!("Replayed stored message {} to {}", msg_id, receiver.short());
+                if let Err(e) = crate::storage::pending_relay::delete_pending_relay(&msg_id).await {
+                    tracing::warn!("Failed to delete relay message {}: {}", msg_id, e);
+                }
+            }
+        }
+    }
+
+    /// 用发送方 NodeId 对应的公钥验签，验签通过的结果会被缓存
+    async fn verify_signature(&self, signed: &SignedMessage) -> bool {
+        let hash = signed.self_hash();
+        if self
+            .verified_cache
+            .lock()
+            .await
+            .contains(&signed.node_id, &signed.signature, &hash)
+        {
+            return true;
+        }
+
+        let kp = match signed.node_id.to_keypair() {
+            Ok(kp) => kp,
+            Err(e) => {
+                tracing::error!("Invalid sender NodeId {}: {}", signed.node_id, e);
+                return false;
+            }
+        };
+        let sig_bytes = hex::decode(&signed.signature).unwrap_or_default();
+        let arr: [u8; 64] = match sig_bytes.as_slice().try_into() {
+            Ok(a) => a,
+            Err(e) => {
+                tracing::error!("Failed to convert signature bytes: {}", e);
+                return false;
+            }
+        };
+        let sig = Signature::from_bytes(&arr);
+        if !kp.verify(&hash, &sig) {
+            tracing::error!(
+                "signature verification failed for message from {}",
+                signed.node_id.short()
+            );
+            return false;
+        }
+
+        self.verified_cache
+            .lock()
+            .await
+            .insert(&signed.node_id, &signed.signature, hash);
+        true
+    }
+
     async fn handle_incoming(&self, from: NodeId, data: Vec<u8>) -> Result<()> {
         // Try parse as Envelope (with ttl). If not, fall back to raw SignedMessage.
 
@@ -126,35 +676,37 @@ impl GossipService {
             return Ok(());
         };
 
-        let id = hex::encode(signed.self_hash());
+        // Ping/Pong 只在直连邻居之间交换，不记录去重也不转发
+        let liveness = matches!(
+            signed.message,
+            GossipMessage::Ping(_) | GossipMessage::Pong(_)
+        );
+        let dedup_id = signed.dedup_id();
+        let self_id = self.node.node_id().as_str();
 
-        // dedup
-        {
-            let mut seen = self.seen.lock().await;
-            if seen.contains_key(&id) {
-                return Ok(());
+        // 已处理过的消息在验签之前丢弃，泛洪中的重复副本不再消耗验签开销
+        if !liveness {
+            match crate::storage::seen_message::is_seen(self_id, &dedup_id).await {
+                Ok(true) => return Ok(()),
+                Ok(false) => {}
+                Err(e) => tracing::warn!("Failed to look up seen message {}: {}", dedup_id, e),
             }
-            seen.insert(id.clone(), Instant::now());
         }
 
-        // verify signature using sender's NodeId -> verifying key
-        if let Ok(kp) = signed.node_id.to_keypair() {
-            let sig_bytes = hex::decode(&signed.signature).unwrap_or_default();
-            let arr: [u8; 64] = match sig_bytes.as_slice().try_into() {
-                Ok(a) => a,
-                Err(e) => {
-                    tracing::error!("Failed to convert signature bytes: {}", e);
-                    return Ok(());
-                }
-            };
-            let sig = Signature::from_bytes(&arr);
-            if !kp.verify(&signed.self_hash(), &sig) {
-                tracing::error!(
-                    "signature verification failed for message from {}",
-                    signed.node_id
-                );
-                return Ok(());
-            }
+        if !self.verify_signature(&signed).await {
+            return Ok(());
+        }
+
+        if liveness {
+            self.handle_liveness(&from, &signed).await;
+            return Ok(());
+        }
+
+        // 验签通过后才记录（持久化，重启后仍然有效），伪造的消息不能抢先占用真实消息的 msg_id
+        match crate::storage::seen_message::mark_seen(self_id, &dedup_id).await {
+            Ok(true) => {}
+            Ok(false) => return Ok(()),
+            Err(e) => tracing::warn!("Failed to record seen message {}: {}", dedup_id, e),
         }
 
         // Ensure outer signer identity matches the embedded payload sender identity.
@@ -162,8 +714,8 @@ impl GossipService {
         if signed.node_id != *signed.message.sender() {
             tracing::error!(
                 "message sender mismatch: signed node {} != payload sender {}",
-                signed.node_id,
-                signed.message.sender()
+                signed.node_id.short(),
+                signed.message.sender().short()
             );
             return Ok(());
         }
@@ -173,7 +725,7 @@ impl GossipService {
             GossipMessage::NodeAnnouncement(na) => {
                 tracing::info!(
                     "Gossip: NodeAnnouncement from {} (alias: {}, addresses: {:?}, timestamp: {})",
-                    na.node_id,
+                    na.node_id.short(),
                     na.alias,
                     na.addresses,
                     signed.timestamp(),
@@ -190,194 +742,32 @@ impl GossipService {
 
                 if let Err(e) = node_model::save_node_info_to_db(&node_info).await {
                     tracing::warn!("Failed to save node info to db: {}", e);
+                } else if let Err(e) = node_model::mark_node_seen(na.node_id.as_str()).await {
+                    tracing::warn!(
+                        "Failed to update last seen of {}: {}",
+                        na.node_id.short(),
+                        e
+                    );
+                }
+
+                if self.relay_store {
+                    self.replay_pending_relay(&na.node_id).await;
                 }
             }
             GossipMessage::RepoAnnouncement(ra) => {
                 tracing::info!(
                     "Gossip: RepoAnnouncement from {} with {} repos: {:?}",
-                    ra.node_id,
+                    ra.node_id.short(),
                     ra.repos.len(),
                     ra.repos.iter().map(|r| &r.repo_id).collect::<Vec<_>>()
                 );
-                // 将每个 repo 保存到数据库
-                for repo in &ra.repos {
-                    // 检查仓库是否已存在
-                    match crate::storage::repo_model::load_repo_from_db(&repo.repo_id).await {
-                        Ok(Some(local_repo)) => {
-                            // 如果是本地仓库，不更新
-                            if !local_repo.is_external {
-                                tracing::debug!(
-                                    "Repo {} is a local repository, skipping update",
-                                    &repo.repo_id
-                                );
-                                continue;
-                            }
-
-                            // Repo 已存在，检查是否需要更新
-                            tracing::debug!(
-                                "Repo {} already exists, checking if update needed",
-                                &repo.repo_id
-                            );
-
-                            // 比较 refs：从 bundle 中提取本地 refs
-                            let local_refs = if !local_repo.bundle.as_os_str().is_empty() {
-                                // Bundle 存在，从 bundle 中提取 refs
-                                let bundle_path = local_repo.bundle.to_string_lossy().to_string();
-                                match crate::git::pack::extract_bundle_refs(&bundle_path) {
-                                    Ok(refs) => {
-                                        tracing::debug!(
-                                            "Extracted {} refs from bundle for repo {}",
-                                            refs.len(),
-                                            &repo.repo_id
-                                        );
-                                        refs
-                                    }
-                                    Err(e) => {
-                                        tracing::warn!(
-                                            "Failed to extract refs from bundle for repo {}: {}",
-                                            &repo.repo_id,
-                                            e
-                                        );
-                                        // 如果 bundle 提取失败，从数据库读取 refs 作为备份
-                                        match crate::storage::ref_model::load_refs_for_repo(
-                                            &repo.repo_id,
-                                        )
-                                        .await
-                                        {
-                                            Ok(refs) => refs,
-                                            Err(e2) => {
-                                                tracing::warn!(
-                                                    "Failed to load local refs for repo {}: {}",
-                                                    &repo.repo_id,
-                                                    e2
-                                                );
-                                                continue;
-                                            }
-                                        }
-                                    }
-                                }
-                            } else {
-                                // Bundle 不存在，从数据库读取 refs
-                                match crate::storage::ref_model::load_refs_for_repo(&repo.repo_id)
-                                    .await
-                                {
-                                    Ok(refs) => refs,
-                                    Err(e) => {
-                                        tracing::warn!(
-                                            "Failed to load local refs for repo {}: {}",
-                                            &repo.repo_id,
-                                            e
-                                        );
-                                        continue;
-                                    }
-                                }
-                            };
-
-                            // 检查 2：如果远端 refs 与本地相同，不更新
-                            if local_refs == repo.refs {
-                                tracing::debug!("Repo {} refs are up-to-date", &repo.repo_id);
-                                continue;
-                            }
-
-                            // 有新的 refs 更新，清空 bundle 等待重新同步
-                            tracing::info!(
-                                "Detected ref updates for repo {} from node {}. local refs: {:?}, remote refs: {:?}",
-                                &repo.repo_id,
-                                ra.node_id,
-                                local_refs,
-                                repo.refs
-                            );
-
-                            // 删除旧的 bundle 文件
-                            if !local_repo.bundle.as_os_str().is_empty() {
-                                let bundle_path = local_repo.bundle.to_string_lossy().to_string();
-                                match tokio::fs::remove_file(&bundle_path).await {
-                                    Ok(_) => {
-                                        tracing::info!(
-                                            "Deleted outdated bundle for repo {}",
-                                            &repo.repo_id
-                                        );
-                                    }
-                                    Err(e) => {
-                                        tracing::warn!(
-                                            "Failed to delete bundle file {}: {}",
-                                            bundle_path,
-                                            e
-                                        );
-                                    }
-                                }
-                            }
-
-                            // 清空旧的 refs 并添加最新的 refs
-                            if let Err(e) =
-                                crate::storage::ref_model::delete_refs_for_repo(&repo.repo_id).await
-                            {
-                                tracing::warn!(
-                                    "Failed to delete refs for repo {}: {}",
-                                    &repo.repo_id,
-                                    e
-                                );
-                            }
-
-                            // 添加最新的 refs
-                            if let Err(e) = crate::storage::ref_model::batch_save_refs(
-                                &repo.repo_id,
-                                &repo.refs,
-                            )
-                            .await
-                            {
-                                tracing::warn!(
-                                    "Failed to save new refs for repo {}: {}",
-                                    &repo.repo_id,
-                                    e
-                                );
-                            } else {
-                                tracing::info!(
-                                    "Updated refs for repo {} with {} new refs",
-                                    &repo.repo_id,
-                                    repo.refs.len()
-                                );
-                            }
-
-                            // 更新 repo 表：清空 bundle 字段
-                            if let Err(e) =
-                                crate::storage::repo_model::update_repo_bundle(&repo.repo_id, "")
-                                    .await
-                            {
-                                tracing::warn!(
-                                    "Failed to clear bundle for repo {}: {}",
-                                    &repo.repo_id,
-                                    e
-                                );
-                            }
-
-                            tracing::info!(
-                                "Cleared bundle and refs for repo {}, waiting for automatic sync",
-                                &repo.repo_id
-                            );
-                        }
-                        Ok(None) => {
-                            // Repo 不存在，插入为 external repo
-                            tracing::debug!("Repo {} is new, adding as external", &repo.repo_id);
-                            let mut new_repo = repo.clone();
-                            new_repo.is_external = true;
-                            if let Err(e) =
-                                crate::storage::repo_model::save_repo_to_db(&new_repo).await
-                            {
-                                tracing::warn!(
-                                    "Failed to save remote repo {} to db: {}",
-                                    &repo.repo_id,
-                                    e
-                                );
-                            }
-                        }
-                        Err(e) => {
-                            tracing::warn!("Failed to load repo {}: {}", &repo.repo_id, e);
-                        }
-                    }
-                }
+                handle_signed_repo_announcement(ra, signed.timestamp()).await;
             }
             GossipMessage::Chat(c) => {
+                if self.relay_store {
+                    self.store_for_offline_receiver(&signed, &c.receiver_id)
+                        .await;
+                }
                 if let Err(e) = crate::chat::service::process_incoming_chat(
                     c.clone(),
                     self.manager.clone(),
@@ -399,6 +789,53 @@ impl GossipService {
                     tracing::error!("Error processing chat ack: {}", e);
                 }
             }
+            GossipMessage::ChatRead(read) => {
+                if let Err(e) = crate::chat::service::process_read(
+                    read.clone(),
+                    &signed.node_id,
+                    self.node.clone(),
+                )
+                .await
+                {
+                    tracing::error!("Error processing chat read receipt: {}", e);
+                }
+            }
+            GossipMessage::GroupChat(msg) => {
+                if let Err(e) =
+                    crate::chat::service::process_group_chat(msg.clone(), self.node.clone()).await
+                {
+                    tracing::error!("Error processing group chat message: {}", e);
+                }
+            }
+            GossipMessage::PeerExchange(pex) => {
+                tracing::info!(
+                    "Gossip: PeerExchange from {} with {} peers",
+                    pex.node_id.short(),
+                    pex.peers.len()
+                );
+                self.handle_peer_exchange(pex).await;
+            }
+            GossipMessage::RepoDeletion(rd) => {
+                tracing::info!(
+                    "Gossip: RepoDeletion of {} from {}",
+                    rd.repo_id,
+                    rd.node_id.short()
+                );
+                if !handle_repo_deletion(&signed, rd).await {
+                    return Ok(());
+                }
+            }
+            GossipMessage::NodeLeave(leave) => {
+                tracing::info!("Gossip: NodeLeave from {}", leave.node_id.short());
+                self.handle_node_leave(&signed, leave).await;
+            }
+            // 已在去重之前处理
+            GossipMessage::Ping(_) | GossipMessage::Pong(_) => return Ok(()),
+        }
+
+        // PeerExchange 只描述发送方的直接连接，不再转发
+        if matches!(signed.message, GossipMessage::PeerExchange(_)) {
+            return Ok(());
         }
 
         // forward if ttl > 0
@@ -409,6 +846,14 @@ impl GossipService {
                 ttl,
             };
             let data = serde_json::to_vec(&fwd).unwrap_or_default();
+            if !self.forward_limiter.lock().await.allow(&from, data.len()) {
+                tracing::debug!(
+                    "Forward rate limit exceeded for {}, not forwarding message {}",
+                    from.short(),
+                    signed.dedup_id()
+                );
+                return Ok(());
+            }
             let mgr = self.manager.lock().await;
             let peers = mgr.list_peers().await;
             for peer in peers {
@@ -422,3 +867,647 @@ impl GossipService {
         Ok(())
     }
 }
+
+/// 处理 RepoDeletion：仅当签名者是仓库创建者时删除本地外部副本
+///
+/// 本地没有该仓库时无法核对创建者，以签名者为创建者保存墓碑并继续转发，
+/// 使中继和后加入的节点也能把删除传给其后拥有副本的节点；墓碑只拦截该签名者创建的仓库。
+/// 返回 false 表示该删除请求无效，不应继续转发
+pub(super) async fn handle_repo_deletion(signed: &SignedMessage, rd: &RepoDeletion) -> bool {
+    let repo = match crate::storage::repo_model::load_repo_from_db(&rd.repo_id).await {
+        Ok(Some(repo)) => repo,
+        Ok(None) => {
+            // 已有其他签名者的墓碑时保留原墓碑，不允许他人覆盖；
+            // 删除仍然转发，由拥有仓库的节点核对创建者
+            match crate::storage::repo_tombstone::tombstone_creator(&rd.repo_id).await {
+                Ok(Some(creator)) if creator != signed.node_id.as_str() => {
+                    tracing::warn!(
+                        "Keeping tombstone of repo {} by {}, not replacing it with deletion from {}",
+                        rd.repo_id,
+                        creator,
+                        signed.node_id.short()
+                    );
+                    return true;
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    tracing::warn!("Failed to load tombstone of repo {}: {}", rd.repo_id, e);
+                    return true;
+                }
+            }
+            if let Err(e) = crate::storage::repo_tombstone::save_tombstone(
+                &rd.repo_id,
+                signed.node_id.as_str(),
+                signed,
+            )
+            .await
+            {
+                tracing::warn!("Failed to save tombstone for repo {}: {}", rd.repo_id, e);
+            }
+            tracing::debug!(
+                "Repo {} not found locally, keeping its deletion and forwarding it",
+                rd.repo_id
+            );
+            return true;
+        }
+        Err(e) => {
+            tracing::warn!("Failed to load repo {}: {}", rd.repo_id, e);
+            return true;
+        }
+    };
+
+    if repo.p2p_description.creator != signed.node_id.to_string() {
+        tracing::warn!(
+            "Ignoring deletion of repo {} from non-creator {} (creator: {})",
+            rd.repo_id,
+            signed.node_id.short(),
+            repo.p2p_description.creator
+        );
+        return false;
+    }
+
+    if !repo.is_external {
+        tracing::warn!(
+            "Ignoring deletion of local repository {} received via gossip",
+            rd.repo_id
+        );
+        return true;
+    }
+
+    if let Err(e) = crate::storage::repo_model::delete_repo_from_db(&rd.repo_id).await {
+        tracing::warn!("Failed to delete repo {}: {}", rd.repo_id, e);
+        return true;
+    }
+    if !repo.bundle.as_os_str().is_empty() {
+        if let Err(e) = tokio::fs::remove_file(&repo.bundle).await {
+            tracing::warn!(
+                "Failed to delete bundle file {}: {}",
+                repo.bundle.display(),
+                e
+            );
+        }
+    }
+    if let Err(e) = crate::storage::repo_tombstone::save_tombstone(
+        &rd.repo_id,
+        &repo.p2p_description.creator,
+        signed,
+    )
+    .await
+    {
+        tracing::warn!("Failed to save tombstone for repo {}: {}", rd.repo_id, e);
+    }
+
+    tracing::info!(
+        "Deleted external repo {} as requested by its creator {}",
+        rd.repo_id,
+        signed.node_id.short()
+    );
+    true
+}
+
+/// 处理仓库公告：批量查询已有仓库和墓碑，新仓库在一个事务中批量插入
+///
+/// 启动时每个节点都会重新公告全部仓库，逐个查询会让每次洪泛产生大量数据库往返
+pub(super) async fn handle_repo_announcement(ra: &RepoAnnouncement) {
+    handle_repo_announcement_at(ra, None).await
+}
+
+/// 处理带签名时间的仓库公告：创建者在删除之后重新公告的仓库覆盖其墓碑
+async fn handle_signed_repo_announcement(ra: &RepoAnnouncement, announced_at: i64) {
+    handle_repo_announcement_at(ra, Some(announced_at)).await
+}
+
+async fn handle_repo_announcement_at(ra: &RepoAnnouncement, announced_at: Option<i64>) {
+    // 每个仓库附带 repo_id 是否已由根提交校验
+    let repos: Vec<(&Repo, bool)> = ra
+        .repos
+        .iter()
+        .filter_map(|repo| {
+            if !is_vouched_repo(repo, &ra.node_id) {
+                tracing::warn!(
+                    "Rejecting repo {} from {}: creator {} did not sign it",
+                    &repo.repo_id,
+                    ra.node_id.short(),
+                    &repo.p2p_description.creator
+                );
+                return None;
+            }
+            match repo.verify_repo_id() {
+                Ok(verified) => Some((repo, verified)),
+                Err(e) => {
+                    tracing::warn!(
+                        "Rejecting repo {} from {}: {}",
+                        &repo.repo_id,
+                        ra.node_id.short(),
+                        e
+                    );
+                    None
+                }
+            }
+        })
+        .collect();
+    if repos.is_empty() {
+        return;
+    }
+
+    let repo_ids: Vec<String> = repos.iter().map(|(r, _)| r.repo_id.clone()).collect();
+    let tombstoned = match crate::storage::repo_tombstone::tombstone_times(&repo_ids).await {
+        Ok(ids) => ids,
+        Err(e) => {
+            tracing::warn!("Failed to load repo tombstones: {}", e);
+            return;
+        }
+    };
+    let existing = match crate::storage::repo_model::load_repos_by_ids(&repo_ids).await {
+        Ok(repos) => repos,
+        Err(e) => {
+            tracing::warn!("Failed to load announced repos: {}", e);
+            return;
+        }
+    };
+
+    let mut new_repos: Vec<Repo> = Vec::new();
+    for (repo, verified) in repos {
+        // 已被创建者删除的仓库不再接收，除非创建者在删除之后重新公告了它
+        // 墓碑只对同一创建者的仓库有效：本地没有仓库时保存的墓碑未经核对
+        let tombstone = tombstoned
+            .get(&repo.repo_id)
+            .filter(|(creator, _)| *creator == repo.p2p_description.creator);
+        if let Some(&(_, deleted_at)) = tombstone {
+            let readded = repo.p2p_description.creator == ra.node_id.as_str()
+                && announced_at.is_some_and(|at| at > deleted_at);
+            if !readded {
+                tracing::debug!("Repo {} has been deleted, skipping", &repo.repo_id);
+                continue;
+            }
+            tracing::info!(
+                "Repo {} re-announced by its creator after deletion",
+                &repo.repo_id
+            );
+            if let Err(e) = crate::storage::repo_tombstone::delete_tombstone(&repo.repo_id).await {
+                tracing::warn!(
+                    "Failed to clear tombstone for repo {}: {}",
+                    &repo.repo_id,
+                    e
+                );
+                continue;
+            }
+        }
+
+        match existing.get(&repo.repo_id) {
+            Some(local_repo) => sync_announced_repo(local_repo, repo, verified, &ra.node_id).await,
+            None if new_repos.iter().any(|r| r.repo_id == repo.repo_id) => {}
+            None => {
+                // Repo 不存在，插入为 external repo
+                tracing::debug!("Repo {} is new, adding as external", &repo.repo_id);
+                let mut new_repo = repo.clone();
+                new_repo.is_external = true;
+                new_repo.verified = verified;
+                new_repos.push(new_repo);
+            }
+        }
+    }
+
+    if !new_repos.is_empty() {
+        if let Err(e) = crate::storage::repo_model::insert_repos(&new_repos).await {
+            tracing::warn!(
+                "Failed to save {} remote repos from {} to db: {}",
+                new_repos.len(),
+                ra.node_id.short(),
+                e
+            );
+        }
+    }
+}
+
+/// 仓库带有创建者的有效签名，或者未签名但由创建者本人公告（旧版本节点不签名仓库）
+fn is_vouched_repo(repo: &Repo, announcer: &NodeId) -> bool {
+    if repo.signature.is_empty() {
+        repo.p2p_description.creator == announcer.as_str()
+    } else {
+        repo.verify_signature()
+    }
+}
+
+/// 已知的外部仓库：同步标签，refs 有变化时清空 bundle 等待重新同步
+async fn sync_announced_repo(local_repo: &Repo, repo: &Repo, verified: bool, from: &NodeId) {
+    // 如果是本地仓库，不更新
+    if !local_repo.is_external {
+        tracing::debug!(
+            "Repo {} is a local repository, skipping update",
+            &repo.repo_id
+        );
+        return;
+    }
+
+    // Repo 已存在，检查是否需要更新
+    tracing::debug!(
+        "Repo {} already exists, checking if update needed",
+        &repo.repo_id
+    );
+
+    // 标签可能单独变化（refs 不变），先同步标签
+    if local_repo.p2p_description.tags != repo.p2p_description.tags {
+        if let Err(e) =
+            crate::storage::repo_model::set_repo_tags(&repo.repo_id, &repo.p2p_description.tags)
+                .await
+        {
+            tracing::warn!("Failed to update tags for repo {}: {}", &repo.repo_id, e);
+        }
+    }
+
+    // 旧版本保存的仓库没有签名，补上创建者的签名以便转发给其他节点
+    if local_repo.signature.is_empty() && !repo.signature.is_empty() {
+        let mut signed = local_repo.clone();
+        signed.signature = repo.signature.clone();
+        if signed.verify_signature() {
+            if let Err(e) =
+                crate::storage::repo_model::set_repo_signature(&repo.repo_id, &repo.signature).await
+            {
+                tracing::warn!("Failed to save signature for repo {}: {}", &repo.repo_id, e);
+            }
+        }
+    }
+
+    // 之前收到的公告没有根提交，现在能校验 repo_id 了
+    if verified
+        && !local_repo.verified
+        && local_repo.p2p_description.creator == repo.p2p_description.creator
+    {
+        if let Err(e) =
+            crate::storage::repo_model::set_repo_root_commit(&repo.repo_id, &repo.root_commit, true)
+                .await
+        {
+            tracing::warn!(
+                "Failed to save root commit for repo {}: {}",
+                &repo.repo_id,
+                e
+            );
+        }
+    }
+
+    // 比较 refs：从 bundle 中提取本地 refs
+    let local_refs = if !local_repo.bundle.as_os_str().is_empty() {
+        // Bundle 存在，从 bundle 中提取 refs
+        let bundle_path = local_repo.bundle.to_string_lossy().to_string();
+        match crate::git::pack::extract_bundle_refs(&bundle_path) {
+            Ok(refs) => {
+                tracing::debug!(
+                    "Extracted {} refs from bundle for repo {}",
+                    refs.len(),
+                    &repo.repo_id
+                );
+                refs
+            }
+            Err(e) => {
+                tracing::warn!(
+                    "Failed to extract refs from bundle for repo {}: {}",
+                    &repo.repo_id,
+                    e
+                );
+                // 如果 bundle 提取失败，使用数据库中的 refs 作为备份
+                local_repo.refs.clone()
+            }
+        }
+    } else {
+        // Bundle 不存在，使用数据库中的 refs
+        local_repo.refs.clone()
+    };
+
+    // 检查 2：如果远端 refs 与本地相同，不更新
+    if local_refs == repo.refs {
+        tracing::debug!("Repo {} refs are up-to-date", &repo.repo_id);
+        return;
+    }
+
+    // 有新的 refs 更新，清空 bundle 等待重新同步
+    tracing::info!(
+        "Detected ref updates for repo {} from node {}. local refs: {:?}, remote refs: {:?}",
+        &repo.repo_id,
+        from.short(),
+        local_refs,
+        repo.refs
+    );
+
+    // 删除旧的 bundle 文件
+    if !local_repo.bundle.as_os_str().is_empty() {
+        let bundle_path = local_repo.bundle.to_string_lossy().to_string();
+        match tokio::fs::remove_file(&bundle_path).await {
+            Ok(_) => {
+                tracing::info!("Deleted outdated bundle for repo {}", &repo.repo_id);
+            }
+            Err(e) => {
+                tracing::warn!("Failed to delete bundle file {}: {}", bundle_path, e);
+            }
+        }
+    }
+
+    // 清空旧的 refs 并添加最新的 refs
+    if let Err(e) = crate::storage::ref_model::delete_refs_for_repo(&repo.repo_id).await {
+        tracing::warn!("Failed to delete refs for repo {}: {}", &repo.repo_id, e);
+    }
+
+    // 添加最新的 refs
+    if let Err(e) = crate::storage::ref_model::batch_save_refs(&repo.repo_id, &repo.refs).await {
+        tracing::warn!("Failed to save new refs for repo {}: {}", &repo.repo_id, e);
+    } else {
+        tracing::info!(
+            "Updated refs for repo {} with {} new refs",
+            &repo.repo_id,
+            repo.refs.len()
+        );
+    }
+
+    // 更新 repo 表：清空 bundle 字段
+    if let Err(e) = crate::storage::repo_model::update_repo_bundle(&repo.repo_id, "").await {
+        tracing::warn!("Failed to clear bundle for repo {}: {}", &repo.repo_id, e);
+    }
+
+    tracing::info!(
+        "Cleared bundle and refs for repo {}, waiting for automatic sync",
+        &repo.repo_id
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::keypair::KeyPair;
+    use crate::repo::repo::P2PDescription;
+    use crate::storage::repo_model;
+
+    fn announced_repo(i: usize, creator: &NodeId) -> Repo {
+        let desc = P2PDescription {
+            creator: creator.to_string(),
+            name: format!("bench-{}", i),
+            description: String::new(),
+            language: "Rust".to_string(),
+            latest_commit_at: 0,
+            size: 0,
+            tags: Vec::new(),
+            commit_count: 0,
+            contributors: 0,
+        };
+        let mut repo = Repo::new(
+            format!("did:repo:announce-bench-{}", i),
+            desc,
+            std::path::PathBuf::new(),
+        );
+        repo.add_ref("refs/heads/main".to_string(), format!("{:040x}", i));
+        repo.is_external = true;
+        repo
+    }
+
+    #[tokio::test]
+    async fn test_large_repo_announcement_is_batched() -> Result<()> {
+        use std::sync::atomic::Ordering;
+
+        crate::storage::with_counted_test_db(|queries| async move {
+            let node_id = NodeId::from_keypair(&KeyPair::generate()?);
+            let repos: Vec<Repo> = (0..500).map(|i| announced_repo(i, &node_id)).collect();
+            let ids: Vec<String> = repos.iter().map(|r| r.repo_id.clone()).collect();
+
+            // 预先存入一部分，模拟重复公告中大部分仓库已知的情况
+            repo_model::insert_repos(&repos[..100]).await?;
+
+            let ra = RepoAnnouncement {
+                node_id,
+                repos: repos.clone(),
+            };
+            // 语句数只随批次增长，不随仓库数增长：500 个仓库逐个查询和插入需要上千条语句
+            let before = queries.load(Ordering::Relaxed);
+            handle_repo_announcement(&ra).await;
+            let first = queries.load(Ordering::Relaxed) - before;
+            assert!(first <= 40, "first announcement ran {} queries", first);
+
+            // 再次公告全部仓库，应全部命中已有记录，不再写入
+            let before = queries.load(Ordering::Relaxed);
+            handle_repo_announcement(&ra).await;
+            let repeated = queries.load(Ordering::Relaxed) - before;
+            assert!(
+                repeated <= 10,
+                "repeated announcement ran {} queries",
+                repeated
+            );
+
+            let stored = repo_model::load_repos_by_ids(&ids).await?;
+            assert_eq!(stored.len(), 500);
+            assert!(stored.values().all(|r| r.is_external));
+            assert_eq!(
+                stored["did:repo:announce-bench-42"].get_ref("refs/heads/main"),
+                Some(&format!("{:040x}", 42))
+            );
+            Ok(())
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_forwarded_repo_requires_creator_signature() -> Result<()> {
+        crate::storage::with_test_db(async {
+            let creator_kp = KeyPair::generate()?;
+            let creator = NodeId::from_keypair(&creator_kp);
+            let forwarder = NodeId::from_keypair(&KeyPair::generate()?);
+
+            // 转发节点冒用他人身份公告未签名的仓库
+            let spoofed = announced_repo(1, &creator);
+            // 创建者签过名的仓库可以由任意节点转发
+            let mut signed = announced_repo(2, &creator);
+            signed.sign(&creator_kp)?;
+            // 签名之后篡改 creator
+            let mut tampered = announced_repo(3, &creator);
+            tampered.sign(&creator_kp)?;
+            tampered.p2p_description.creator = forwarder.to_string();
+
+            handle_repo_announcement(&RepoAnnouncement {
+                node_id: forwarder.clone(),
+                repos: vec![spoofed.clone(), signed.clone(), tampered.clone()],
+            })
+            .await;
+
+            let ids = [
+                spoofed.repo_id.clone(),
+                signed.repo_id.clone(),
+                tampered.repo_id.clone(),
+            ];
+            let stored = repo_model::load_repos_by_ids(&ids).await?;
+            assert_eq!(stored.len(), 1);
+            assert!(stored[&signed.repo_id].verify_signature());
+
+            // 创建者本人公告的未签名仓库（旧版本节点）仍然接收
+            handle_repo_announcement(&RepoAnnouncement {
+                node_id: creator,
+                repos: vec![spoofed.clone()],
+            })
+            .await;
+            assert!(repo_model::load_repo_from_db(&spoofed.repo_id)
+                .await?
+                .is_some());
+            Ok(())
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_newer_creator_announcement_overrides_tombstone() -> Result<()> {
+        crate::storage::with_test_db(async {
+            let creator_kp = KeyPair::generate()?;
+            let creator = NodeId::from_keypair(&creator_kp);
+            let forwarder = NodeId::from_keypair(&KeyPair::generate()?);
+            let mut repo = announced_repo(1, &creator);
+            repo.sign(&creator_kp)?;
+
+            repo_model::insert_repos(std::slice::from_ref(&repo)).await?;
+            repo_model::delete_repo_from_db(&repo.repo_id).await?;
+            assert!(
+                crate::storage::ref_model::get_ref(&repo.repo_id, "refs/heads/main")
+                    .await?
+                    .is_none()
+            );
+            let deletion =
+                SignedMessage::new_repo_deletion_sign_message(repo.repo_id.clone(), &creator_kp)?;
+            crate::storage::repo_tombstone::save_tombstone(
+                &repo.repo_id,
+                creator.as_str(),
+                &deletion,
+            )
+            .await?;
+            let deleted_at = deletion.timestamp();
+
+            // 较早的公告、以及非创建者的公告都不能覆盖墓碑
+            let ra = RepoAnnouncement {
+                node_id: creator.clone(),
+                repos: vec![repo.clone()],
+            };
+            handle_signed_repo_announcement(&ra, deleted_at).await;
+            handle_repo_announcement(&ra).await;
+            handle_signed_repo_announcement(
+                &RepoAnnouncement {
+                    node_id: forwarder,
+                    repos: vec![repo.clone()],
+                },
+                deleted_at + 1,
+            )
+            .await;
+            assert!(repo_model::load_repo_from_db(&repo.repo_id)
+                .await?
+                .is_none());
+
+            handle_signed_repo_announcement(&ra, deleted_at + 1).await;
+            assert!(repo_model::load_repo_from_db(&repo.repo_id)
+                .await?
+                .is_some());
+            assert!(!crate::storage::repo_tombstone::has_tombstone(&repo.repo_id).await?);
+            Ok(())
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_deletion_of_unknown_repo_is_forwarded_and_tombstoned() -> Result<()> {
+        crate::storage::with_test_db(async {
+            let creator_kp = KeyPair::generate()?;
+            let creator = NodeId::from_keypair(&creator_kp);
+            let other_kp = KeyPair::generate()?;
+            let mut repo = announced_repo(1, &creator);
+            repo.sign(&creator_kp)?;
+
+            // 本地没有该仓库：保存墓碑并继续转发
+            let deletion =
+                SignedMessage::new_repo_deletion_sign_message(repo.repo_id.clone(), &creator_kp)?;
+            let GossipMessage::RepoDeletion(rd) = &deletion.message else {
+                unreachable!()
+            };
+            assert!(handle_repo_deletion(&deletion, rd).await);
+            assert_eq!(
+                crate::storage::repo_tombstone::tombstone_creator(&repo.repo_id).await?,
+                Some(creator.to_string())
+            );
+
+            // 其他节点的删除同样转发，但不能覆盖已有墓碑
+            let forged =
+                SignedMessage::new_repo_deletion_sign_message(repo.repo_id.clone(), &other_kp)?;
+            let GossipMessage::RepoDeletion(rd) = &forged.message else {
+                unreachable!()
+            };
+            assert!(handle_repo_deletion(&forged, rd).await);
+            assert_eq!(
+                crate::storage::repo_tombstone::tombstone_creator(&repo.repo_id).await?,
+                Some(creator.to_string())
+            );
+
+            // 之后到达的旧公告被墓碑拦截
+            let ra = RepoAnnouncement {
+                node_id: creator.clone(),
+                repos: vec![repo.clone()],
+            };
+            handle_signed_repo_announcement(&ra, deletion.timestamp()).await;
+            assert!(repo_model::load_repo_from_db(&repo.repo_id)
+                .await?
+                .is_none());
+
+            // 非创建者签名的墓碑不拦截其他创建者的仓库
+            let other = NodeId::from_keypair(&other_kp);
+            let mut other_repo = announced_repo(2, &other);
+            other_repo.sign(&other_kp)?;
+            let forged = SignedMessage::new_repo_deletion_sign_message(
+                other_repo.repo_id.clone(),
+                &creator_kp,
+            )?;
+            let GossipMessage::RepoDeletion(rd) = &forged.message else {
+                unreachable!()
+            };
+            assert!(handle_repo_deletion(&forged, rd).await);
+            handle_repo_announcement(&RepoAnnouncement {
+                node_id: other,
+                repos: vec![other_repo.clone()],
+            })
+            .await;
+            assert!(repo_model::load_repo_from_db(&other_repo.repo_id)
+                .await?
+                .is_some());
+            Ok(())
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_repo_id_is_checked_against_root_commit() -> Result<()> {
+        crate::storage::with_test_db(async {
+            let creator_kp = KeyPair::generate()?;
+            let creator = NodeId::from_keypair(&creator_kp);
+            let root_commit = [7u8; 20];
+            let repo_id = crate::repo::repo_id::RepoId::generate(
+                &root_commit,
+                &creator_kp.verifying_key_bytes(),
+            )?;
+
+            let mut genuine = announced_repo(1, &creator);
+            genuine.repo_id = repo_id.to_string();
+            genuine.root_commit = hex::encode(root_commit);
+            // 根提交推导不出公告的 repo_id
+            let mut forged = announced_repo(2, &creator);
+            forged.root_commit = hex::encode(root_commit);
+            // 旧版本节点不携带根提交
+            let legacy = announced_repo(3, &creator);
+
+            handle_repo_announcement(&RepoAnnouncement {
+                node_id: creator.clone(),
+                repos: vec![genuine.clone(), forged.clone(), legacy.clone()],
+            })
+            .await;
+
+            let ids = [
+                genuine.repo_id.clone(),
+                forged.repo_id.clone(),
+                legacy.repo_id.clone(),
+            ];
+            let stored = repo_model::load_repos_by_ids(&ids).await?;
+            assert_eq!(stored.len(), 2);
+            assert!(stored[&genuine.repo_id].verified);
+            assert!(!stored[&legacy.repo_id].verified);
+            Ok(())
+        })
+        .await
+    }
+}
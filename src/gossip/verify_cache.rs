@@ -0,0 +1,103 @@
+use crate::node::node_id::NodeId;
+use std::collections::{BTreeMap, HashMap};
+
+type CacheKey = (NodeId, String);
+
+/// 已验签消息的 LRU 缓存：按 (node_id, signature) 记录验签通过的消息哈希
+///
+/// 同一消息经不同邻居转发多次时，只有第一次需要真正做 Ed25519 验签
+#[derive(Debug)]
+pub(crate) struct VerifiedCache {
+    capacity: usize,
+    entries: HashMap<CacheKey, (Vec<u8>, u64)>,
+    // 最近使用序号 -> key，序号最小的是最久未使用的
+    order: BTreeMap<u64, CacheKey>,
+    tick: u64,
+}
+
+impl VerifiedCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+
+    /// 该签名是否已对相同的消息哈希验证通过，命中时刷新其使用顺序
+    pub(crate) fn contains(&mut self, node_id: &NodeId, signature: &str, hash: &[u8]) -> bool {
+        let key = (node_id.clone(), signature.to_string());
+        self.tick += 1;
+        let tick = self.tick;
+        match self.entries.get_mut(&key) {
+            Some((verified_hash, last_used)) if verified_hash.as_slice() == hash => {
+                self.order.remove(last_used);
+                *last_used = tick;
+                self.order.insert(tick, key);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// 记录一次验签通过的结果，超出容量时淘汰最久未使用的条目
+    pub(crate) fn insert(&mut self, node_id: &NodeId, signature: &str, hash: Vec<u8>) {
+        let key = (node_id.clone(), signature.to_string());
+        self.tick += 1;
+        if let Some((_, last_used)) = self.entries.insert(key.clone(), (hash, self.tick)) {
+            self.order.remove(&last_used);
+        }
+        self.order.insert(self.tick, key);
+
+        while self.entries.len() > self.capacity {
+            let Some((_, oldest)) = self.order.pop_first() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::keypair::KeyPair;
+
+    #[test]
+    fn test_hit_requires_same_hash() {
+        let node_id = NodeId::from_keypair(&KeyPair::generate().unwrap());
+        let mut cache = VerifiedCache::new(8);
+        assert!(!cache.contains(&node_id, "sig", b"hash"));
+
+        cache.insert(&node_id, "sig", b"hash".to_vec());
+        assert!(cache.contains(&node_id, "sig", b"hash"));
+        assert!(!cache.contains(&node_id, "sig", b"other"));
+        assert!(!cache.contains(&node_id, "other", b"hash"));
+
+        cache.clear();
+        assert!(!cache.contains(&node_id, "sig", b"hash"));
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let node_id = NodeId::from_keypair(&KeyPair::generate().unwrap());
+        let mut cache = VerifiedCache::new(2);
+        cache.insert(&node_id, "a", b"a".to_vec());
+        cache.insert(&node_id, "b", b"b".to_vec());
+
+        // 访问 a 后，b 成为最久未使用的条目
+        assert!(cache.contains(&node_id, "a", b"a"));
+        cache.insert(&node_id, "c", b"c".to_vec());
+
+        assert_eq!(cache.entries.len(), 2);
+        assert!(cache.contains(&node_id, "a", b"a"));
+        assert!(!cache.contains(&node_id, "b", b"b"));
+        assert!(cache.contains(&node_id, "c", b"c"));
+    }
+}
@@ -59,6 +59,7 @@ impl KeyPair {
 
     /// Encrypt a message for a specific recipient (identified by their Ed25519 VerifyingKey)
     /// Returns: Ephemeral_PK (32) + Nonce (12) + Ciphertext (N)
+    #[allow(deprecated)]
     pub fn encrypt_to_node(&self, recipient_vk: &VerifyingKey, message: &[u8]) -> Result<Vec<u8>> {
         // 1. Convert Recipient Ed25519 PK -> X25519 PK (Montgomery)
         let recipient_ed_y = CompressedEdwardsY::from_slice(recipient_vk.as_bytes())?;
@@ -114,6 +115,7 @@ impl KeyPair {
     }
 
     /// Decrypt a message addressed to this keypair
+    #[allow(deprecated)]
     pub fn decrypt_message(&self, payload: &[u8]) -> Result<Vec<u8>> {
         if payload.len() < 32 + 12 {
             return Err(anyhow!("Message too short"));
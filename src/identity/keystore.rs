@@ -0,0 +1,151 @@
+use anyhow::{anyhow, Result};
+use argon2::Argon2;
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use multibase::Base;
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+
+use crate::identity::keypair::KeyPair;
+
+/// 当前加密密钥文件格式版本
+pub const ENCRYPTED_KEYPAIR_VERSION: u8 = 1;
+const KDF_ARGON2ID: &str = "argon2id";
+const CIPHER_CHACHA20POLY1305: &str = "chacha20poly1305";
+
+/// 口令加密的密钥文件（JSON 信封）
+///
+/// 签名私钥由 Argon2id 从口令派生的密钥通过 ChaCha20-Poly1305 加密，
+/// 公钥以明文保存，便于在不解密的情况下识别身份。
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct EncryptedKeyPair {
+    pub version: u8,
+    pub kdf: String,
+    pub cipher: String,
+    /// hex 编码的 KDF salt
+    pub salt: String,
+    /// hex 编码的 nonce
+    pub nonce: String,
+    /// hex 编码的密文（签名私钥 + tag）
+    pub ciphertext: String,
+    /// hex 编码的公钥
+    pub verifying_key: String,
+}
+
+impl EncryptedKeyPair {
+    /// 使用口令加密密钥对
+    pub fn seal(kp: &KeyPair, passphrase: &str) -> Result<Self> {
+        let signing_key = kp.signing_key_bytes()?;
+
+        let mut salt = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        let mut nonce_bytes = [0u8; 12];
+        OsRng.fill_bytes(&mut nonce_bytes);
+
+        let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, &salt)?);
+        let ciphertext = cipher
+            .encrypt(&Nonce::from(nonce_bytes), signing_key.as_slice())
+            .map_err(|e| anyhow!("Encryption failed: {}", e))?;
+
+        Ok(Self {
+            version: ENCRYPTED_KEYPAIR_VERSION,
+            kdf: KDF_ARGON2ID.to_string(),
+            cipher: CIPHER_CHACHA20POLY1305.to_string(),
+            salt: hex::encode(salt),
+            nonce: hex::encode(nonce_bytes),
+            ciphertext: hex::encode(ciphertext),
+            verifying_key: hex::encode(kp.verifying_key_bytes()),
+        })
+    }
+
+    /// 使用口令解密出密钥对
+    pub fn open(&self, passphrase: &str) -> Result<KeyPair> {
+        if self.version != ENCRYPTED_KEYPAIR_VERSION {
+            return Err(anyhow!(
+                "Unsupported encrypted keypair version {}",
+                self.version
+            ));
+        }
+        if self.kdf != KDF_ARGON2ID || self.cipher != CIPHER_CHACHA20POLY1305 {
+            return Err(anyhow!(
+                "Unsupported encrypted keypair algorithms: {}/{}",
+                self.kdf,
+                self.cipher
+            ));
+        }
+
+        let salt = hex::decode(&self.salt)?;
+        let nonce_bytes: [u8; 12] = hex::decode(&self.nonce)?
+            .try_into()
+            .map_err(|_| anyhow!("Invalid nonce length"))?;
+        let ciphertext = hex::decode(&self.ciphertext)?;
+
+        let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, &salt)?);
+        let plaintext = cipher
+            .decrypt(&Nonce::from(nonce_bytes), ciphertext.as_slice())
+            .map_err(|_| anyhow!("Failed to decrypt keypair: wrong passphrase?"))?;
+        let signing_key: [u8; 32] = plaintext
+            .try_into()
+            .map_err(|_| anyhow!("Invalid signing key length"))?;
+
+        let kp = KeyPair::from_signing_key_bytes(signing_key)?;
+        if hex::encode(kp.verifying_key_bytes()) != self.verifying_key {
+            return Err(anyhow!("Decrypted key does not match stored public key"));
+        }
+        Ok(kp)
+    }
+}
+
+/// 导出签名私钥为 base58 文本（用于在机器间迁移身份）
+pub fn export_signing_key(kp: &KeyPair) -> Result<String> {
+    Ok(Base::Base58Btc.encode(kp.signing_key_bytes()?))
+}
+
+/// 从 base58 文本导入签名私钥
+pub fn import_signing_key(encoded: &str) -> Result<KeyPair> {
+    let bytes = Base::Base58Btc
+        .decode(encoded.trim())
+        .map_err(|e| anyhow!("Invalid base58 signing key: {}", e))?;
+    let bytes: [u8; 32] = bytes
+        .try_into()
+        .map_err(|b: Vec<u8>| anyhow!("Invalid signing key length: {} bytes", b.len()))?;
+    KeyPair::from_signing_key_bytes(bytes)
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<Key> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| anyhow!("Key derivation failed: {}", e))?;
+    Ok(Key::from(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_and_open() -> Result<()> {
+        let kp = KeyPair::generate()?;
+        let sealed = EncryptedKeyPair::seal(&kp, "correct horse")?;
+        assert_eq!(sealed.version, ENCRYPTED_KEYPAIR_VERSION);
+
+        let opened = sealed.open("correct horse")?;
+        assert_eq!(opened, kp);
+        assert!(sealed.open("wrong").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_export_import_signing_key() -> Result<()> {
+        let kp = KeyPair::generate()?;
+        let exported = export_signing_key(&kp)?;
+        assert_eq!(import_signing_key(&format!("{}\n", exported))?, kp);
+
+        assert!(import_signing_key("not-base58-0OIl").is_err());
+        assert!(import_signing_key(&Base::Base58Btc.encode([1u8; 16])).is_err());
+        Ok(())
+    }
+}
@@ -1 +1,2 @@
 pub mod keypair;
+pub mod keystore;
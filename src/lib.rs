@@ -1,5 +1,7 @@
 pub mod bundle;
 pub mod chat;
+pub mod config;
+pub mod error;
 pub mod git;
 pub mod gossip;
 pub mod identity;
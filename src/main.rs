@@ -2,7 +2,9 @@ use anyhow::Result;
 use clap::{Parser, Subcommand};
 
 mod cli;
-use cli::{handle_auth, handle_node, handle_repo};
+use cli::{
+    handle_auth, handle_auth_export, handle_auth_import, handle_auth_list, handle_node, handle_repo,
+};
 use megaengine::mcp::start_mcp_server;
 
 #[derive(Parser)]
@@ -10,8 +12,16 @@ use megaengine::mcp::start_mcp_server;
 #[command(about = "MegaEngine P2P Git", long_about = None)]
 struct Cli {
     /// Root data directory (overrides $MEGAENGINE_ROOT). Defaults to ~/.megaengine
-    #[arg(long, global = true, default_value = "~/.megaengine")]
-    root: String,
+    #[arg(long, global = true)]
+    root: Option<String>,
+
+    /// Identity profile to use (keypair-<profile>.json). Defaults to keypair.json
+    #[arg(long, global = true, value_parser = parse_profile)]
+    profile: Option<String>,
+
+    /// Show timestamps as RFC 3339 UTC (e.g. 2024-05-01T12:00:00Z) instead of local time
+    #[arg(long, global = true, alias = "iso")]
+    utc: bool,
 
     #[command(subcommand)]
     command: Commands,
@@ -46,27 +56,97 @@ enum Commands {
 #[derive(Subcommand)]
 enum AuthAction {
     /// Generate and save a new keypair
-    Init,
+    Init {
+        /// Encrypt the signing key with a passphrase (or $MEGAENGINE_PASSPHRASE)
+        #[arg(long, default_value = "false")]
+        encrypt: bool,
+    },
+    /// List available identity profiles
+    List,
+    /// Export the identity (base58 signing key, or the encrypted envelope)
+    Export {
+        /// Output file
+        #[arg(long)]
+        out: String,
+    },
+    /// Import an identity previously written by `auth export`
+    Import {
+        /// Input file
+        #[arg(long = "in")]
+        input: String,
+        /// Overwrite an existing keypair
+        #[arg(long, default_value = "false")]
+        force: bool,
+    },
 }
 
 #[derive(Subcommand)]
 enum NodeAction {
     /// Start node (initialization)
     Start {
-        /// node alias
-        #[arg(long, default_value = "mega-node")]
-        alias: String,
-        /// one or more listen/announce addresses, e.g. 0.0.0.0:9000
-        #[arg(short, long, default_value = "0.0.0.0:9000")]
-        addr: String,
+        /// node alias [config: node.alias, default: mega-node]
+        #[arg(long)]
+        alias: Option<String>,
+        /// Listen/announce address, e.g. 0.0.0.0:9000; repeat to listen on several (IPv4 and IPv6, LAN and VPN)
+        /// [config: node.listen, default: 0.0.0.0:9000]
+        #[arg(short, long)]
+        addr: Vec<String>,
+
+        /// Address announced to other nodes instead of the bound ones, e.g. a public 203.0.113.5:9000 behind NAT (repeatable)
+        /// [config: node.announce]
+        #[arg(long)]
+        announce_addr: Vec<String>,
 
-        #[arg(short, long, default_value = "cert")]
-        cert_path: String,
+        /// Certificate directory, relative to the root directory [config: node.cert_dir, default: cert]
+        #[arg(short, long)]
+        cert_path: Option<String>,
 
-        /// Bootstrap node address to connect to on startup (e.g., 127.0.0.1:9000)
+        /// Bootstrap node to connect to on startup, as peer_id@address[,address...] (addresses are tried in order)
+        /// [config: node.bootstrap]
         #[arg(long)]
         bootstrap_node: Option<String>,
 
+        /// File with starter bootstrap nodes, one peer_id@address per line (default: <root>/bootstrap.txt if present)
+        /// [config: node.bootstrap_file]
+        #[arg(long)]
+        bootstrap_file: Option<String>,
+
+        /// Announce this node as a long-lived bootstrap node [config: node.as_bootstrap]
+        #[arg(long, default_value = "false", conflicts_with = "enable_relay_store")]
+        as_bootstrap: bool,
+
+        /// Do not reconnect to peers already known from the nodes table [config: node.reconnect = false]
+        #[arg(long, default_value = "false")]
+        no_reconnect: bool,
+
+        /// Passive mode: learn peers from peer exchange but do not dial them [config: node.passive]
+        #[arg(long, default_value = "false")]
+        passive: bool,
+
+        /// Run as a relay: store chat messages for offline recipients and forward bundle
+        /// traffic between connected peers that cannot reach each other directly [config: node.relay]
+        #[arg(long, default_value = "false")]
+        enable_relay_store: bool,
+
+        /// Present the CA-issued certificate from the cert directory instead of one derived
+        /// from the node identity, and do not pin peer certificates [config: node.identity_tls = false]
+        #[arg(long, default_value = "false", conflicts_with = "strict_peer_certs")]
+        legacy_tls: bool,
+
+        /// Reject peers that still present a CA-issued certificate instead of one derived
+        /// from their NodeId [config: node.accept_legacy_certs = false]
+        #[arg(long, default_value = "false")]
+        strict_peer_certs: bool,
+
+        /// Compress outgoing bundle transfers with zstd (receivers must support it) [config: bundle.compress]
+        #[arg(long, default_value = "false")]
+        compress_bundles: bool,
+
+        /// Seconds between checks of local repositories for new commits (changed ones are repacked)
+        /// [config: node.repo_check_interval_secs, default: 60]
+        #[arg(long)]
+        repo_check_interval: Option<u64>,
+
         /// Deprecated for node start: stdio MCP must run as a separate process via `megaengine mcp`
         #[arg(long, default_value = "false")]
         mcp: bool,
@@ -76,7 +156,61 @@ enum NodeAction {
         mcp_sse_port: Option<u16>,
     },
     /// Print node id using stored keypair
-    Id,
+    Id {
+        /// Also print the full node address (as accepted by --bootstrap-node) for these
+        /// reachable addresses, e.g. 203.0.113.5:9000[,address...]
+        #[arg(long)]
+        addr: Option<String>,
+        /// Render the output as a terminal QR code (requires the `qr` feature)
+        #[arg(long, default_value = "false")]
+        qr: bool,
+    },
+    /// Show connection and traffic metrics reported by the running node
+    Stats,
+    /// Measure the round-trip time to a peer with gossip Ping/Pong over QUIC
+    Ping {
+        /// Peer address, e.g. 203.0.113.5:9000[,address...] (addresses are tried in order)
+        addr: String,
+        /// Peer node ID or alias
+        peer_id: String,
+        /// Number of pings to send
+        #[arg(long, default_value = "4", value_parser = clap::value_parser!(u32).range(1..))]
+        count: u32,
+    },
+    /// List known peer nodes
+    List {
+        /// Only nodes of this type
+        #[arg(long = "type", value_parser = ["normal", "relay", "bootstrap"])]
+        node_type: Option<String>,
+        /// Only bootstrap nodes (same as --type bootstrap)
+        #[arg(long, default_value = "false", conflicts_with = "node_type")]
+        bootstrap: bool,
+        /// Print the nodes as a JSON array instead of formatted blocks
+        #[arg(long, default_value = "false")]
+        json: bool,
+    },
+    /// Check the keypair, certificates, database, listen addresses and bootstrap nodes
+    Doctor {
+        /// Also check that this bootstrap node is reachable, as peer_id@address[,address...]
+        /// (default: node.bootstrap from the config file)
+        #[arg(long)]
+        bootstrap_node: Option<String>,
+    },
+    /// Manage the node's TLS certificates
+    Cert {
+        #[command(subcommand)]
+        action: CertAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum CertAction {
+    /// Regenerate the server certificate if it expires soon (the CA too, if it is expiring)
+    Renew {
+        /// Rotate the server certificate even if it is still valid
+        #[arg(long, default_value = "false")]
+        force: bool,
+    },
 }
 
 #[derive(Subcommand)]
@@ -90,15 +224,120 @@ enum RepoAction {
         /// Description
         #[arg(long, default_value = "")]
         description: String,
+
+        /// Topic tag (repeatable), e.g. --tag rust --tag p2p
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+
+        /// Print the RepoId and metadata that would be recorded, without writing anything
+        #[arg(long, default_value = "false")]
+        dry_run: bool,
+
+        /// Overwrite the stored record if this RepoId is already registered, and replace
+        /// any record of another RepoId at the same path
+        #[arg(long, default_value = "false")]
+        force: bool,
+    },
+    /// List repositories
+    List {
+        /// Only repositories in this language (case-insensitive)
+        #[arg(long)]
+        language: Option<String>,
+        /// Only repositories created by this node
+        #[arg(long, default_value = "false")]
+        mine: bool,
+        /// Only repositories carrying this tag
+        #[arg(long)]
+        tag: Option<String>,
+        /// Maximum number of repositories to show
+        #[arg(long)]
+        limit: Option<u64>,
+        /// Page number (1-based), used together with --limit
+        #[arg(long, default_value = "1", requires = "limit")]
+        page: u64,
+        /// Print the repositories as a JSON array instead of formatted blocks
+        #[arg(long, default_value = "false")]
+        json: bool,
+    },
+    /// Re-pack the bundle and refresh refs from the local working tree
+    Update {
+        /// Repository ID
+        repo_id: String,
+    },
+    /// Add or remove topic tags of a local repository
+    Tag {
+        /// Repository ID
+        repo_id: String,
+        /// Tags to add (repeatable)
+        #[arg(long)]
+        add: Vec<String>,
+        /// Tags to remove (repeatable)
+        #[arg(long)]
+        remove: Vec<String>,
+    },
+    /// Restrict which peers may pull a local repository; without options, show the allow-list
+    Share {
+        /// Repository ID
+        repo_id: String,
+        /// Node ID or alias allowed to pull (repeatable)
+        #[arg(long = "with")]
+        with: Vec<String>,
+        /// Node ID or alias to remove from the allow-list (repeatable)
+        #[arg(long)]
+        revoke: Vec<String>,
+    },
+    /// Summarize the repositories on this node: counts, bundle storage, languages
+    Stats {
+        /// Number of most recently updated repositories to list
+        #[arg(long, default_value = "5")]
+        recent: usize,
+        /// Print the summary as JSON
+        #[arg(long, default_value = "false")]
+        json: bool,
+    },
+    /// Search repositories by name, description and tags
+    Search {
+        /// Search words; all of them must match the name or description
+        query: String,
     },
-    /// List all repositories
-    List,
     /// Update repository from bundle (like git pull)
     Pull {
         /// Repository ID
         #[arg(long)]
         repo_id: String,
     },
+    /// Ask a peer to send a bundle of the repository (processed by the running node)
+    Fetch {
+        /// Repository ID
+        repo_id: String,
+        /// Node ID or alias of the peer to fetch from
+        #[arg(long)]
+        from: String,
+    },
+    /// Check that the stored bundle is intact and matches the stored refs
+    Verify {
+        /// Repository ID
+        repo_id: String,
+    },
+    /// Show the most recent commits without cloning, from the working copy or bundle
+    History {
+        /// Repository ID
+        repo_id: String,
+        /// Number of commits to show
+        #[arg(long, default_value = "10")]
+        limit: usize,
+    },
+    /// Copy the stored bundle to a chosen path
+    Export {
+        /// Repository ID
+        repo_id: String,
+        /// Destination file; must not exist yet
+        #[arg(long)]
+        out: String,
+        /// Check that the bundle is intact before copying it
+        #[arg(long, default_value = "false")]
+        verify: bool,
+    },
     Clone {
         #[arg(long)]
         output: String,
@@ -106,6 +345,16 @@ enum RepoAction {
         #[arg(long)]
         repo_id: String,
     },
+    /// Remove a repository record (and its stored bundle) from the database
+    Remove {
+        /// Repository ID
+        #[arg(long)]
+        repo_id: String,
+
+        /// Keep the stored bundle file on disk
+        #[arg(long, default_value = "false")]
+        keep_bundle: bool,
+    },
 }
 
 #[tokio::main]
@@ -125,22 +374,33 @@ async fn main() -> Result<()> {
 
     let cli = Cli::parse();
 
-    let root_path = resolve_root_path(&cli.root)?;
+    let root_path = resolve_root_path(cli.root.as_deref());
+    let profile = cli.profile.as_deref();
+    megaengine::util::set_utc_timestamps(cli.utc);
 
     match cli.command {
         Commands::Auth { action } => match action {
-            AuthAction::Init => {
-                handle_auth().await?;
+            AuthAction::Init { encrypt } => {
+                handle_auth(encrypt, profile).await?;
+            }
+            AuthAction::List => {
+                handle_auth_list().await?;
+            }
+            AuthAction::Export { out } => {
+                handle_auth_export(out, profile).await?;
+            }
+            AuthAction::Import { input, force } => {
+                handle_auth_import(input, force, profile).await?;
             }
         },
         Commands::Node { action } => {
-            handle_node(root_path, action).await?;
+            handle_node(root_path, action, profile).await?;
         }
         Commands::Repo { action } => {
-            handle_repo(action).await?;
+            handle_repo(action, profile).await?;
         }
         Commands::Chat { action } => {
-            crate::cli::handle_chat(action).await?;
+            crate::cli::handle_chat(action, profile).await?;
         }
         Commands::Mcp => {
             start_mcp_server().await?;
@@ -150,20 +410,14 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
-fn resolve_root_path(root_arg: &str) -> Result<String> {
-    if let Ok(env_root) = std::env::var("MEGAENGINE_ROOT") {
-        return Ok(env_root);
-    }
-
-    let path = if root_arg.starts_with("~/") {
-        let home = std::env::var("HOME")
-            .or_else(|_| std::env::var("USERPROFILE"))
-            .unwrap_or_else(|_| ".".to_string());
-        root_arg.replace("~", &home)
-    } else {
-        root_arg.to_string()
-    };
+fn parse_profile(name: &str) -> Result<String, String> {
+    megaengine::storage::validate_profile_name(name).map_err(|e| e.to_string())?;
+    Ok(name.to_string())
+}
 
+/// 解析数据目录并写回 `MEGAENGINE_ROOT`，使库中的 `storage::data_dir` 得到相同的结果
+fn resolve_root_path(root_arg: Option<&str>) -> String {
+    let path = megaengine::config::resolve_data_dir(root_arg);
     std::env::set_var("MEGAENGINE_ROOT", &path);
-    Ok(path)
+    path.to_string_lossy().to_string()
 }
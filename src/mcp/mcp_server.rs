@@ -1,9 +1,14 @@
+use crate::error::MegaError;
+use crate::git::git_repo::RefFilter;
+use crate::util::{format_iso8601, format_relative_time};
 use crate::{git::pack, storage};
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use std::io::{self, BufRead, BufReader, Write};
 
+const PROTOCOL_VERSION: &str = "2024-11-05";
+
 // --- 1. 定义符合 JSON-RPC 2.0 标准的结构 ---
 
 #[derive(Deserialize, Debug)]
@@ -14,7 +19,7 @@ struct JsonRpcRequest {
 }
 
 #[derive(Serialize, Debug)]
-struct JsonRpcResponse {
+pub(crate) struct JsonRpcResponse {
     jsonrpc: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     result: Option<Value>,
@@ -31,6 +36,30 @@ struct JsonRpcError {
     data: Option<Value>,
 }
 
+impl JsonRpcResponse {
+    fn result(id: Value, result: Value) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            result: Some(result),
+            error: None,
+            id: Some(id),
+        }
+    }
+
+    fn error(id: Option<Value>, code: i32, message: String) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            result: None,
+            error: Some(JsonRpcError {
+                code,
+                message,
+                data: None,
+            }),
+            id,
+        }
+    }
+}
+
 /// MCP Server implementation for repository operations
 pub struct RepoMcpServer;
 
@@ -39,13 +68,56 @@ impl RepoMcpServer {
         vec![
             json!({
                 "name": "list_repos",
-                "description": "List all repositories with their details and refs",
+                "description": "List repositories with their details and refs, optionally filtered and paged",
                 "inputSchema": {
                     "type": "object",
-                    "properties": {},
+                    "properties": {
+                        "creator": {
+                            "type": "string",
+                            "description": "Only repositories created by this node ID"
+                        },
+                        "language": {
+                            "type": "string",
+                            "description": "Only repositories in this language (case-insensitive)"
+                        },
+                        "is_external": {
+                            "type": "boolean",
+                            "description": "true for repositories learned from peers, false for local ones"
+                        },
+                        "name": {
+                            "type": "string",
+                            "description": "Substring of the repository name"
+                        },
+                        "tag": {
+                            "type": "string",
+                            "description": "Only repositories carrying this tag"
+                        },
+                        "limit": {
+                            "type": "integer",
+                            "description": "Maximum number of repositories to return"
+                        },
+                        "page": {
+                            "type": "integer",
+                            "description": "Page number (1-based), used together with limit"
+                        }
+                    },
                     "required": []
                 }
             }),
+            json!({
+                "name": "search_repos",
+                "description": "Search repositories by topic; matches words against names and descriptions and returns results ranked by relevance",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "query": {
+                            "type": "string",
+                            "description": "Search words; all of them must appear in the name or description"
+                        }
+                    },
+                    "required": ["query"]
+                }
+            }),
             json!({
                 "name": "get_repo_details",
                 "description": "Get detailed information about a specific repository",
@@ -78,12 +150,84 @@ impl RepoMcpServer {
                     "required": ["repo_id", "output_path"]
                 }
             }),
+            json!({
+                "name": "list_nodes",
+                "description": "List known peer nodes with their aliases, addresses, type and last-seen time",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {}
+                }
+            }),
+            json!({
+                "name": "repo_stats",
+                "description": "Summarize the repositories on this node: local and external counts, bundle storage on disk, languages, and the most recently updated repositories",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "recent": {
+                            "type": "integer",
+                            "description": "Number of most recently updated repositories to include (default 5)"
+                        }
+                    }
+                }
+            }),
+            json!({
+                "name": "repo_history",
+                "description": "List the most recent commits of a repository without cloning it, read from its working copy or stored bundle",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "repo_id": {
+                            "type": "string",
+                            "description": "The ID of the repository"
+                        },
+                        "limit": {
+                            "type": "integer",
+                            "description": "Number of commits to return (default 10)"
+                        }
+                    },
+                    "required": ["repo_id"]
+                }
+            }),
+            json!({
+                "name": "pull_repo",
+                "description": "Update an already cloned repository from its latest bundle and report which refs advanced",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "repo_id": {
+                            "type": "string",
+                            "description": "The ID of the repository to update"
+                        }
+                    },
+                    "required": ["repo_id"]
+                }
+            }),
         ]
     }
 
     pub async fn execute_tool(name: &str, args: Value) -> Result<Value> {
         match name {
-            "list_repos" => Self::list_repos().await,
+            "list_repos" => {
+                let text = |key: &str| args.get(key).and_then(|v| v.as_str()).map(String::from);
+                let filter = storage::repo_model::RepoFilter {
+                    creator: text("creator"),
+                    language: text("language"),
+                    is_external: args.get("is_external").and_then(|v| v.as_bool()),
+                    name_contains: text("name"),
+                    tag: text("tag"),
+                };
+                let limit = args.get("limit").and_then(|v| v.as_u64()).unwrap_or(0);
+                let page = args.get("page").and_then(|v| v.as_u64()).unwrap_or(1);
+                Self::list_repos(page.saturating_sub(1) * limit, limit, filter).await
+            }
+            "search_repos" => {
+                let query = args
+                    .get("query")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing query parameter"))?;
+                Self::search_repos(query).await
+            }
             "get_repo_details" => {
                 let repo_id = args
                     .get("repo_id")
@@ -102,12 +246,59 @@ impl RepoMcpServer {
                     .ok_or_else(|| anyhow::anyhow!("Missing output_path parameter"))?;
                 Self::clone_repo(repo_id, output_path).await
             }
+            "list_nodes" => Self::list_nodes().await,
+            "repo_stats" => {
+                let recent = args.get("recent").and_then(|v| v.as_u64()).unwrap_or(5);
+                Self::repo_stats(recent as usize).await
+            }
+            "repo_history" => {
+                let repo_id = args
+                    .get("repo_id")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing repo_id parameter"))?;
+                let limit = args.get("limit").and_then(|v| v.as_u64()).unwrap_or(10);
+                Self::repo_history(repo_id, limit as usize).await
+            }
+            "pull_repo" => {
+                let repo_id = args
+                    .get("repo_id")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing repo_id parameter"))?;
+                Self::pull_repo(repo_id).await
+            }
             _ => Err(anyhow::anyhow!("Unknown tool: {}", name)),
         }
     }
 
-    async fn list_repos() -> Result<Value> {
-        match storage::repo_model::list_repos().await {
+    async fn search_repos(query: &str) -> Result<Value> {
+        let repos = storage::repo_model::search_repos(query).await?;
+        let results: Vec<Value> = repos
+            .iter()
+            .map(|repo| {
+                json!({
+                    "repo_id": repo.repo_id,
+                    "name": repo.p2p_description.name,
+                    "description": repo.p2p_description.description,
+                    "creator": repo.p2p_description.creator,
+                    "language": repo.p2p_description.language,
+                    "tags": repo.p2p_description.tags,
+                    "is_external": repo.is_external,
+                    "has_bundle": !repo.bundle.as_os_str().is_empty(),
+                })
+            })
+            .collect();
+        Ok(tool_result(
+            serde_json::to_string(&results)?,
+            json!({ "repos": results }),
+        ))
+    }
+
+    async fn list_repos(
+        offset: u64,
+        limit: u64,
+        filter: storage::repo_model::RepoFilter,
+    ) -> Result<Value> {
+        match storage::repo_model::list_repos_paged(offset, limit, filter).await {
             Ok(repos) => {
                 let repo_list: Vec<Value> = repos
                     .iter()
@@ -117,11 +308,17 @@ impl RepoMcpServer {
                             "name": repo.p2p_description.name,
                             "creator": repo.p2p_description.creator,
                             "language": repo.p2p_description.language,
+                            "tags": repo.p2p_description.tags,
                             "size": repo.p2p_description.size,
+                            "commit_count": repo.p2p_description.commit_count,
+                            "contributors": repo.p2p_description.contributors,
                             "description": repo.p2p_description.description,
                             "path": repo.path.display().to_string(),
                             "bundle": repo.bundle.display().to_string(),
                             "latest_commit_at": repo.p2p_description.latest_commit_at,
+                            "latest_commit_at_iso": format_iso8601(repo.p2p_description.latest_commit_at),
+                            "updated_ago": format_relative_time(repo.p2p_description.latest_commit_at),
+                            "verified": repo.verified,
                         });
 
                         // 恢复 refs 处理逻辑
@@ -142,10 +339,12 @@ impl RepoMcpServer {
 
                                 let mut has_updates = false;
                                 if !repo.path.as_os_str().is_empty() && repo.path.exists() {
-                                    if let Ok(current_refs) = crate::git::git_repo::read_repo_refs(
+                                    if let Ok(pending) = crate::git::git_repo::pending_ref_updates(
                                         repo.path.to_str().unwrap_or(""),
+                                        &local_refs,
+                                        RefFilter::default(),
                                     ) {
-                                        has_updates = current_refs != local_refs;
+                                        has_updates = !pending.is_empty();
                                     }
                                 }
                                 repo_info["has_updates"] = Value::Bool(has_updates);
@@ -155,12 +354,10 @@ impl RepoMcpServer {
                         repo_info
                     })
                     .collect();
-                Ok(json!({
-                   "content": [{
-                       "type": "text",
-                       "text": serde_json::to_string(&repo_list)?
-                   }]
-                }))
+                Ok(tool_result(
+                    serde_json::to_string(&repo_list)?,
+                    json!({ "repos": repo_list }),
+                ))
             }
             Err(e) => Err(e),
         }
@@ -177,6 +374,9 @@ impl RepoMcpServer {
                     "path": repo.path.display().to_string(),
                     "bundle": repo.bundle.display().to_string(),
                     "latest_commit_at": repo.p2p_description.latest_commit_at,
+                    "latest_commit_at_iso": format_iso8601(repo.p2p_description.latest_commit_at),
+                    "commit_count": repo.p2p_description.commit_count,
+                    "contributors": repo.p2p_description.contributors,
                 });
 
                 // Check for updates if this is a local repo
@@ -187,7 +387,14 @@ impl RepoMcpServer {
                         if let Ok(local_refs) =
                             pack::extract_bundle_refs(&repo.bundle.to_string_lossy())
                         {
-                            repo_info["has_updates"] = Value::Bool(current_refs != local_refs);
+                            let has_updates = crate::git::git_repo::pending_ref_updates(
+                                repo.path.to_str().unwrap_or(""),
+                                &local_refs,
+                                RefFilter::default(),
+                            )
+                            .map(|pending| !pending.is_empty())
+                            .unwrap_or(true);
+                            repo_info["has_updates"] = Value::Bool(has_updates);
 
                             let local_ref_list: Vec<Value> = local_refs
                                 .iter()
@@ -213,14 +420,12 @@ impl RepoMcpServer {
                         }
                     }
                 }
-                Ok(json!({
-                   "content": [{
-                       "type": "text",
-                       "text": serde_json::to_string_pretty(&repo_info)?
-                   }]
-                }))
+                Ok(tool_result(
+                    serde_json::to_string_pretty(&repo_info)?,
+                    repo_info,
+                ))
             }
-            Ok(None) => Err(anyhow::anyhow!("Repository not found")),
+            Ok(None) => Err(MegaError::NotFound(format!("Repository {}", repo_id)).into()),
             Err(e) => Err(e),
         }
     }
@@ -231,7 +436,11 @@ impl RepoMcpServer {
             Ok(Some(mut repo)) => {
                 let bundle_path = repo.bundle.to_string_lossy().to_string();
                 if bundle_path.is_empty() || !std::path::Path::new(&bundle_path).exists() {
-                    return Err(anyhow::anyhow!("Bundle file not found for repository"));
+                    return Err(MegaError::NotFound(format!(
+                        "Bundle file for repository {}",
+                        repo_id
+                    ))
+                    .into());
                 }
 
                 pack::restore_repo_from_bundle(&bundle_path, output).await?;
@@ -245,17 +454,97 @@ impl RepoMcpServer {
                 repo.path = PathBuf::from(output);
                 let _ = storage::repo_model::save_repo_to_db(&repo).await;
 
-                Ok(json!({
-                   "content": [{
-                       "type": "text",
-                       "text": format!("Successfully cloned repository {} to {}", repo_id, output)
-                   }]
-                }))
+                Ok(tool_result(
+                    format!("Successfully cloned repository {} to {}", repo_id, output),
+                    json!({ "repo_id": repo_id, "path": output }),
+                ))
             }
-            Ok(None) => Err(anyhow::anyhow!("Repository not found")),
+            Ok(None) => Err(MegaError::NotFound(format!("Repository {}", repo_id)).into()),
             Err(e) => Err(e),
         }
     }
+
+    async fn list_nodes() -> Result<Value> {
+        let nodes = storage::node_model::list_nodes_with_last_seen().await?;
+        let left = storage::node_model::left_nodes().await?;
+        let node_list: Vec<Value> = nodes
+            .iter()
+            .map(|(info, last_seen)| {
+                let left_at = left.get(info.node_id.as_str()).copied().unwrap_or(0);
+                json!({
+                    "node_id": info.node_id.to_string(),
+                    "alias": info.alias,
+                    "addresses": info.addresses.iter().map(|a| a.to_string()).collect::<Vec<_>>(),
+                    "node_type": format!("{:?}", info.node_type),
+                    "version": info.version,
+                    "last_seen": last_seen,
+                    // 从未直接联系过的节点没有 last_seen
+                    "last_seen_iso": (*last_seen > 0).then(|| format_iso8601(*last_seen)),
+                    "last_seen_ago": (*last_seen > 0).then(|| format_relative_time(*last_seen)),
+                    "left_at": left_at,
+                    "left_at_iso": (left_at > 0).then(|| format_iso8601(left_at)),
+                })
+            })
+            .collect();
+        Ok(tool_result(
+            serde_json::to_string(&node_list)?,
+            json!({ "nodes": node_list }),
+        ))
+    }
+
+    async fn repo_stats(recent: usize) -> Result<Value> {
+        let stats = crate::repo::stats::collect_repo_stats(recent).await?;
+        let result = serde_json::to_value(&stats)?;
+        Ok(tool_result(serde_json::to_string_pretty(&result)?, result))
+    }
+
+    async fn repo_history(repo_id: &str, limit: usize) -> Result<Value> {
+        let repo = storage::repo_model::load_repo_from_db(repo_id)
+            .await?
+            .ok_or_else(|| MegaError::NotFound(format!("Repository {}", repo_id)))?;
+        let commits = tokio::task::spawn_blocking(move || repo.recent_commits(limit)).await??;
+        let result = json!({ "commits": commits });
+        Ok(tool_result(serde_json::to_string(&commits)?, result))
+    }
+
+    async fn pull_repo(repo_id: &str) -> Result<Value> {
+        let repo = storage::repo_model::load_repo_from_db(repo_id)
+            .await?
+            .ok_or_else(|| MegaError::NotFound(format!("Repository {}", repo_id)))?;
+
+        let path = repo.path.to_string_lossy().to_string();
+        let report = tokio::task::spawn_blocking(move || repo.pull_from_bundle()).await??;
+        let advanced: Vec<Value> = report
+            .advanced
+            .iter()
+            .map(|(name, old, new)| json!({ "name": name, "old": old, "new": new }))
+            .collect();
+        let created: Vec<Value> = report
+            .created
+            .iter()
+            .map(|(name, commit)| json!({ "name": name, "commit": commit }))
+            .collect();
+        let result = json!({
+            "repo_id": repo_id,
+            "path": path,
+            "up_to_date": report.is_up_to_date(),
+            "advanced": advanced,
+            "created": created,
+        });
+        Ok(tool_result(serde_json::to_string_pretty(&result)?, result))
+    }
+}
+
+/// 构造工具返回值：`content` 中的文本供人阅读（兼容旧客户端），
+/// `structuredContent` 携带同样的数据，客户端无需再解析字符串
+fn tool_result(text: String, structured: Value) -> Value {
+    json!({
+        "content": [{
+            "type": "text",
+            "text": text
+        }],
+        "structuredContent": structured
+    })
 }
 
 pub async fn start_mcp_server() -> Result<()> {
@@ -272,73 +561,21 @@ pub async fn start_mcp_server() -> Result<()> {
             continue;
         }
 
-        // 1. 解析请求
-        let req: JsonRpcRequest = match serde_json::from_str(&line) {
-            Ok(r) => r,
+        // 1. 解析请求：无法解析的 JSON 按规范回复 Parse error
+        let response = match serde_json::from_str::<Value>(&line) {
+            Ok(request) => handle_request(request).await,
             Err(e) => {
                 eprintln!("Failed to parse JSON: {}", e);
-                line.clear();
-                continue;
+                Some(JsonRpcResponse::error(
+                    None,
+                    -32700,
+                    format!("Parse error: {}", e),
+                ))
             }
         };
 
-        eprintln!("Received method: {}", req.method);
-
-        // 2. 处理请求并获取 Result 或 Error
-        // 注意：这里返回元组 (Option<Result>, Option<Error>)
-        let (result, error) = match req.method.as_str() {
-            // A. 初始化握手 (必须响应 initialize)
-            "initialize" => (
-                Some(json!({
-                    "protocolVersion": "2024-11-05",
-                    "capabilities": { "tools": {} },
-                    "serverInfo": {
-                        "name": "megaengine-repo-mcp",
-                        "version": "1.0"
-                    }
-                })),
-                None,
-            ),
-
-            // B. 初始化完成通知 (不需要响应)
-            "notifications/initialized" => {
-                eprintln!("Client initialized.");
-                line.clear();
-                continue;
-            }
-
-            // C. 列出工具
-            "tools/list" => {
-                let tools = RepoMcpServer::get_tools();
-                (Some(json!({ "tools": tools })), None)
-            }
-
-            // D. 调用工具
-            "tools/call" => handle_tool_call(&req.params).await,
-
-            // E. 心跳
-            "ping" => (Some(json!({})), None),
-
-            // F. 未知方法
-            _ => (
-                None,
-                Some(JsonRpcError {
-                    code: -32601,
-                    message: format!("Method not found: {}", req.method),
-                    data: None,
-                }),
-            ),
-        };
-
-        // 3. 构建并发送响应
-        if let Some(req_id) = req.id {
-            let resp = JsonRpcResponse {
-                jsonrpc: "2.0".to_string(),
-                result,
-                error,
-                id: Some(req_id),
-            };
-
+        // 2. 通知没有响应
+        if let Some(resp) = response {
             let resp_str = serde_json::to_string(&resp)?;
             writeln!(stdout, "{}", resp_str)?;
             stdout.flush()?;
@@ -350,48 +587,200 @@ pub async fn start_mcp_server() -> Result<()> {
     Ok(())
 }
 
-// 辅助函数：处理工具调用
-async fn handle_tool_call(params: &Option<Value>) -> (Option<Value>, Option<JsonRpcError>) {
-    let params = match params {
-        Some(p) => p,
-        None => {
-            return (
-                None,
-                Some(JsonRpcError {
-                    code: -32602,
-                    message: "Missing params".into(),
-                    data: None,
-                }),
-            )
+/// 处理一个原始 JSON-RPC 请求对象，缺少 method 时返回 Invalid Request
+pub(crate) async fn handle_request(request: Value) -> Option<JsonRpcResponse> {
+    match serde_json::from_value::<JsonRpcRequest>(request.clone()) {
+        Ok(req) => dispatch(&req.method, req.params, req.id).await,
+        Err(_) => {
+            tracing::warn!("Received invalid JSON-RPC request: missing method");
+            Some(JsonRpcResponse::error(
+                request.get("id").cloned(),
+                -32600,
+                "Invalid Request: Method missing".to_string(),
+            ))
         }
-    };
+    }
+}
 
-    let name = match params.get("name").and_then(|n| n.as_str()) {
-        Some(n) => n,
-        None => {
-            return (
-                None,
-                Some(JsonRpcError {
-                    code: -32602,
-                    message: "Missing tool name".into(),
-                    data: None,
-                }),
-            )
+/// stdio 与 SSE 共用的 JSON-RPC 方法分发；通知（无 id）不返回响应
+pub(crate) async fn dispatch(
+    method: &str,
+    params: Option<Value>,
+    id: Option<Value>,
+) -> Option<JsonRpcResponse> {
+    let outcome = match method {
+        // 初始化握手
+        "initialize" => Ok(json!({
+            "protocolVersion": PROTOCOL_VERSION,
+            "capabilities": { "tools": {} },
+            "serverInfo": {
+                "name": "megaengine",
+                "version": env!("CARGO_PKG_VERSION")
+            }
+        })),
+
+        // 初始化完成通知
+        "notifications/initialized" => {
+            tracing::debug!("MCP client initialized");
+            return None;
         }
+
+        "tools/list" => Ok(json!({ "tools": RepoMcpServer::get_tools() })),
+
+        "tools/call" => handle_tool_call(params).await,
+
+        // 心跳
+        "ping" => Ok(json!({})),
+
+        _ => Err((-32601, format!("Method not found: {}", method))),
     };
 
+    // 没有 id 的是通知，不需要响应
+    let id = id?;
+    Some(match outcome {
+        Ok(result) => JsonRpcResponse::result(id, result),
+        Err((code, message)) => JsonRpcResponse::error(Some(id), code, message),
+    })
+}
+
+// 辅助函数：处理工具调用
+//
+// 工具自身的错误以 isError 结果返回，让模型能看到错误信息；
+// 库返回的 MegaError 额外在 structuredContent 中带上错误类型，
+// 只有参数缺失才是协议级错误
+async fn handle_tool_call(params: Option<Value>) -> Result<Value, (i32, String)> {
+    let params = params.ok_or((-32602, "Missing params".to_string()))?;
+    let name = params
+        .get("name")
+        .and_then(|n| n.as_str())
+        .ok_or((-32602, "Missing tool name".to_string()))?;
     let args = params.get("arguments").cloned().unwrap_or(json!({}));
 
-    // 调用业务逻辑
     match RepoMcpServer::execute_tool(name, args).await {
-        Ok(res) => (Some(res), None), // 这里的 res 必须符合 CallToolResult 结构
-        Err(e) => (
-            None,
-            Some(JsonRpcError {
-                code: -32000, // 应用级错误
-                message: e.to_string(),
-                data: None,
-            }),
-        ),
+        Ok(res) => Ok(res),
+        Err(e) => Ok(tool_error(&e)),
+    }
+}
+
+fn tool_error(e: &anyhow::Error) -> Value {
+    let mut result = json!({
+        "content": [{
+            "type": "text",
+            "text": e.to_string()
+        }],
+        "isError": true
+    });
+    if let Some(err) = e.downcast_ref::<MegaError>() {
+        result["structuredContent"] = json!({
+            "error": { "kind": err.kind(), "message": err.to_string() }
+        });
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn call(request: Value) -> Value {
+        let response = handle_request(request)
+            .await
+            .expect("request should be answered");
+        serde_json::to_value(response).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_protocol_methods() {
+        let init =
+            call(json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {}})).await;
+        assert_eq!(init["id"], 1);
+        assert_eq!(init["result"]["protocolVersion"], PROTOCOL_VERSION);
+        assert!(init.get("error").is_none());
+
+        let tools = call(json!({"jsonrpc": "2.0", "id": "t", "method": "tools/list"})).await;
+        let names: Vec<&str> = tools["result"]["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|t| t["name"].as_str())
+            .collect();
+        assert!(names.contains(&"list_repos"));
+        assert!(names.contains(&"pull_repo"));
+        assert!(names.contains(&"repo_stats"));
+        assert!(names.contains(&"repo_history"));
+
+        let ping = call(json!({"jsonrpc": "2.0", "id": 2, "method": "ping"})).await;
+        assert_eq!(ping["result"], json!({}));
+
+        let unknown = call(json!({"jsonrpc": "2.0", "id": 3, "method": "nope"})).await;
+        assert_eq!(unknown["error"]["code"], -32601);
+        assert!(unknown.get("result").is_none());
+
+        let invalid = call(json!({"jsonrpc": "2.0", "id": 4})).await;
+        assert_eq!(invalid["error"]["code"], -32600);
+        assert_eq!(invalid["id"], 4);
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_tool_calls_and_notifications() {
+        // 通知不需要响应
+        assert!(
+            handle_request(json!({"jsonrpc": "2.0", "method": "notifications/initialized"}))
+                .await
+                .is_none()
+        );
+        assert!(handle_request(json!({"jsonrpc": "2.0", "method": "ping"}))
+            .await
+            .is_none());
+
+        let missing = call(json!({"jsonrpc": "2.0", "id": 5, "method": "tools/call"})).await;
+        assert_eq!(missing["error"]["code"], -32602);
+
+        // 工具错误以 isError 结果返回，而不是协议错误
+        let failed = call(json!({
+            "jsonrpc": "2.0",
+            "id": 6,
+            "method": "tools/call",
+            "params": {"name": "no_such_tool", "arguments": {}}
+        }))
+        .await;
+        assert_eq!(failed["result"]["isError"], true);
+        assert!(failed["result"]["content"][0]["text"]
+            .as_str()
+            .unwrap()
+            .contains("Unknown tool"));
+    }
+
+    #[test]
+    fn test_tool_error_carries_error_kind() {
+        let err: anyhow::Error = MegaError::NotFound("Repository did:repo:x".to_string()).into();
+        let result = tool_error(&err);
+        assert_eq!(result["isError"], true);
+        assert_eq!(result["structuredContent"]["error"]["kind"], "not_found");
+        assert_eq!(
+            result["content"][0]["text"],
+            "Repository did:repo:x not found"
+        );
+
+        // 未归类的错误只返回文本
+        let result = tool_error(&anyhow::anyhow!("boom"));
+        assert!(result.get("structuredContent").is_none());
+    }
+
+    #[test]
+    fn test_tool_result_keeps_text_and_structured_content() {
+        let repos = json!([{ "repo_id": "did:repo:x", "refs": [{ "name": "refs/heads/main" }] }]);
+        let result = tool_result(
+            serde_json::to_string(&repos).unwrap(),
+            json!({ "repos": repos }),
+        );
+
+        let text = result["content"][0]["text"].as_str().unwrap();
+        assert_eq!(serde_json::from_str::<Value>(text).unwrap(), repos);
+        assert_eq!(result["content"][0]["type"], "text");
+        assert_eq!(
+            result["structuredContent"]["repos"][0]["refs"][0]["name"],
+            "refs/heads/main"
+        );
     }
 }
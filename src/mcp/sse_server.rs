@@ -1,4 +1,4 @@
-use crate::mcp::mcp_server::RepoMcpServer;
+use crate::mcp::mcp_server::handle_request;
 use axum::{
     extract::{Query, State},
     response::{
@@ -11,17 +11,43 @@ use axum::{
 };
 use futures::stream::Stream;
 use serde::Deserialize;
-use serde_json::{json, Value};
+use serde_json::Value;
 use std::{collections::HashMap, net::SocketAddr, sync::Arc, time::Duration};
 use tokio::sync::{mpsc, RwLock};
 use tower_http::cors::{Any, CorsLayer};
 use uuid::Uuid;
 
+// 定期清理已断开但未被移除的会话
+const SESSION_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
 // App state to hold active sessions
 struct AppState {
     sessions: RwLock<HashMap<String, mpsc::UnboundedSender<Result<Event, axum::Error>>>>,
 }
 
+impl AppState {
+    /// 移除会话并记录日志（会话可能已被其他路径移除）
+    async fn remove_session(&self, session_id: &str, reason: &str) {
+        if self.sessions.write().await.remove(session_id).is_some() {
+            tracing::info!("SSE session {} closed: {}", session_id, reason);
+        }
+    }
+
+    /// 移除接收端已经关闭的会话，返回移除的数量
+    async fn sweep_closed_sessions(&self) -> usize {
+        let mut sessions = self.sessions.write().await;
+        let before = sessions.len();
+        sessions.retain(|session_id, tx| {
+            let closed = tx.is_closed();
+            if closed {
+                tracing::info!("SSE session {} closed: swept after disconnect", session_id);
+            }
+            !closed
+        });
+        before - sessions.len()
+    }
+}
+
 #[derive(Deserialize)]
 struct SessionParam {
     session_id: String,
@@ -43,12 +69,14 @@ impl Drop for SessionCleanup {
         let state = Arc::clone(&self.state);
         let session_id = self.session_id.clone();
 
-        tokio::spawn(async move {
-            let removed = state.sessions.write().await.remove(&session_id);
-            if removed.is_some() {
-                tracing::info!("SSE session cleaned up: {}", session_id);
-            }
-        });
+        // 运行时已关闭时无法再清理，交给进程退出处理
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            handle.spawn(async move {
+                state
+                    .remove_session(&session_id, "client disconnected")
+                    .await;
+            });
+        }
     }
 }
 
@@ -62,6 +90,15 @@ pub async fn start_sse_server(addr: SocketAddr) -> anyhow::Result<()> {
         .allow_methods([Method::GET, Method::POST])
         .allow_headers(Any);
 
+    let sweep_state = Arc::clone(&state);
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(SESSION_SWEEP_INTERVAL);
+        loop {
+            tick.tick().await;
+            sweep_state.sweep_closed_sessions().await;
+        }
+    });
+
     let app = Router::new()
         .route("/sse", get(sse_handler))
         .route("/messages", post(message_handler))
@@ -119,130 +156,17 @@ async fn message_handler(
         // Handle the MCP request (JSON-RPC)
         // We spawn a task to process it so we don't block
         tokio::spawn(async move {
-            if let Some(method) = request.get("method").and_then(|v| v.as_str()) {
-                let response = match method {
-                    "initialize" => Some(json!({
-                        "jsonrpc": "2.0",
-                        "id": request.get("id"),
-                        "result": {
-                            "protocolVersion": "2024-11-05",
-                            "capabilities": {
-                                "tools": {}
-                            },
-                            "serverInfo": {
-                                "name": "megaengine",
-                                "version": "0.1.0"
-                            }
-                        }
-                    })),
-                    "notifications/initialized" => {
-                        // Client initialized, no response needed for notification
-                        None
-                    }
-                    "ping" => Some(json!({
-                        "jsonrpc": "2.0",
-                        "id": request.get("id"),
-                        "result": {}
-                    })),
-                    "tools/list" => {
-                        let tools = RepoMcpServer::get_tools();
-                        Some(json!({
-                            "jsonrpc": "2.0",
-                            "id": request.get("id"),
-                            "result": {
-                                "tools": tools
-                            }
-                        }))
-                    }
-                    "tools/call" => {
-                        if let Some(params) = request.get("params") {
-                            let name = params.get("name").and_then(|v| v.as_str());
-                            let args = params.get("arguments").cloned().unwrap_or(json!({}));
-
-                            if let Some(name) = name {
-                                match RepoMcpServer::execute_tool(name, args).await {
-                                    Ok(result_value) => Some(json!({
-                                        "jsonrpc": "2.0",
-                                        "id": request.get("id"),
-                                        "result": result_value
-                                    })),
-                                    Err(e) => Some(json!({
-                                        "jsonrpc": "2.0",
-                                        "id": request.get("id"),
-                                        "result": {
-                                            "content": [{
-                                                "type": "text",
-                                                "text": e.to_string()
-                                            }],
-                                            "isError": true
-                                        }
-                                    })),
-                                }
-                            } else {
-                                Some(json!({
-                                    "jsonrpc": "2.0",
-                                    "id": request.get("id"),
-                                    "error": {
-                                        "code": -32602,
-                                        "message": "Missing 'name' in params"
-                                    }
-                                }))
-                            }
-                        } else {
-                            Some(json!({
-                                "jsonrpc": "2.0",
-                                "id": request.get("id"),
-                                "error": {
-                                    "code": -32602,
-                                    "message": "Missing 'params'"
-                                }
-                            }))
-                        }
-                    }
-                    // For unknown methods, reply only when this is a request (has id).
-                    _ => {
-                        if request.get("id").is_some() {
-                            Some(json!({
-                                "jsonrpc": "2.0",
-                                "id": request.get("id"),
-                                "error": {
-                                    "code": -32601,
-                                    "message": "Method not found"
-                                }
-                            }))
-                        } else {
-                            None
-                        }
-                    }
-                };
-
-                if let Some(response) = response {
-                    if let Ok(data) = serde_json::to_string(&response) {
-                        if tx
-                            .send(Ok(Event::default().event("message").data(data)))
-                            .is_err()
-                        {
-                            state.sessions.write().await.remove(&session_id);
-                        }
-                    }
-                }
-            } else {
-                tracing::warn!("Received invalid JSON-RPC request: missing method");
-                let error_response = json!({
-                    "jsonrpc": "2.0",
-                    "id": request.get("id"),
-                    "error": {
-                        "code": -32600,
-                        "message": "Invalid Request: Method missing"
-                    }
-                });
-                if let Ok(data) = serde_json::to_string(&error_response) {
-                    if tx
-                        .send(Ok(Event::default().event("message").data(data)))
-                        .is_err()
-                    {
-                        state.sessions.write().await.remove(&session_id);
-                    }
+            let Some(response) = handle_request(request).await else {
+                return;
+            };
+            if let Ok(data) = serde_json::to_string(&response) {
+                if tx
+                    .send(Ok(Event::default().event("message").data(data)))
+                    .is_err()
+                {
+                    state
+                        .remove_session(&session_id, "failed to deliver response")
+                        .await;
                 }
             }
         });
@@ -252,3 +176,28 @@ async fn message_handler(
         axum::http::StatusCode::NOT_FOUND
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sweep_removes_disconnected_sessions() {
+        let state = AppState {
+            sessions: RwLock::new(HashMap::new()),
+        };
+        let (live_tx, _live_rx) = mpsc::unbounded_channel();
+        let (dead_tx, dead_rx) = mpsc::unbounded_channel();
+        drop(dead_rx);
+        {
+            let mut sessions = state.sessions.write().await;
+            sessions.insert("live".to_string(), live_tx);
+            sessions.insert("dead".to_string(), dead_tx);
+        }
+
+        assert_eq!(state.sweep_closed_sessions().await, 1);
+        let sessions = state.sessions.read().await;
+        assert!(sessions.contains_key("live"));
+        assert!(!sessions.contains_key("dead"));
+    }
+}
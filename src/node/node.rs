@@ -13,6 +13,8 @@ use tokio::sync::Mutex;
 pub enum NodeType {
     Normal,
     Relay,
+    /// 长期在线的引导节点，新节点通过它加入网络，其公告在节点表中保留更久
+    Bootstrap,
 }
 
 /// 节点信息（可序列化的部分）
@@ -79,7 +81,11 @@ impl Node {
     }
 
     /// 启动 QUIC 服务端
-    pub async fn start_quic_server(&mut self, config: QuicConfig) -> Result<()> {
+    pub async fn start_quic_server(&mut self, mut config: QuicConfig) -> Result<()> {
+        // 连接握手需要用节点身份密钥签名
+        if config.identity.is_none() {
+            config.identity = Some(self.keypair.clone());
+        }
         let manager = ConnectionManager::run_server(config).await?;
         self.connection_manager = Some(std::sync::Arc::new(tokio::sync::Mutex::new(manager)));
         Ok(())
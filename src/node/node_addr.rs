@@ -2,50 +2,149 @@ use crate::node::node_id::NodeId;
 use anyhow::{anyhow, Result};
 use std::net::SocketAddr;
 
-/// Represents a node address in the format: peer_id@address
-/// Example: did:key:z2DeZG8TuHkTvrJ7jijysNsQTpTiu9tRQkxcPmmem1tHvVP@127.0.0.1:9000
+/// Represents a node address in the format: peer_id@address[,address...]
+/// Example: did:key:z2DeZG8TuHkTvrJ7jijysNsQTpTiu9tRQkxcPmmem1tHvVP@127.0.0.1:9000,[::1]:9000
 #[derive(Debug, Clone)]
 pub struct NodeAddr {
     pub peer_id: NodeId,
-    pub address: SocketAddr,
+    /// Candidate addresses, tried in order when connecting (never empty)
+    pub addresses: Vec<SocketAddr>,
 }
 
 impl NodeAddr {
-    /// Parse node address from string format: "peer_id@address"
+    /// Parse node address from string format: "peer_id@address[,address...]"
+    ///
+    /// IPv6 addresses must use the bracketed socket form, e.g. `[::1]:9000`
     pub fn parse(s: &str) -> Result<Self> {
-        let parts: Vec<&str> = s.split('@').collect();
-        if parts.len() != 2 {
-            return Err(anyhow!(
+        let (peer_id_str, addresses_str) = s.split_once('@').ok_or_else(|| {
+            anyhow!(
                 "Invalid node address format. Expected 'peer_id@address', got '{}'",
                 s
-            ));
-        }
-
-        let peer_id_str = parts[0];
-        let address_str = parts[1];
+            )
+        })?;
 
         let peer_id = peer_id_str
             .parse::<NodeId>()
             .map_err(|_| anyhow!("Invalid peer_id: {}", peer_id_str))?;
 
-        let address = address_str
-            .parse::<SocketAddr>()
-            .map_err(|_| anyhow!("Invalid address: {}", address_str))?;
+        let addresses = addresses_str
+            .split(',')
+            .map(|a| {
+                a.trim()
+                    .parse::<SocketAddr>()
+                    .map_err(|_| anyhow!("Invalid address: {}", a))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(NodeAddr { peer_id, addresses })
+    }
 
-        Ok(NodeAddr { peer_id, address })
+    /// Parse a bootstrap list: one "peer_id@address[,address...]" per line
+    ///
+    /// Blank lines and lines starting with `#` are ignored
+    pub fn parse_list(content: &str) -> Result<Vec<Self>> {
+        content
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .map(|(line_no, line)| {
+                NodeAddr::parse(line).map_err(|e| anyhow!("line {}: {}", line_no, e))
+            })
+            .collect()
     }
 
     /// Create  node address from peer_id and address
     pub fn new(peer_id: NodeId, address: SocketAddr) -> Self {
-        NodeAddr { peer_id, address }
+        NodeAddr {
+            peer_id,
+            addresses: vec![address],
+        }
+    }
+
+    /// Create node address with several candidate addresses
+    pub fn with_addresses(peer_id: NodeId, addresses: Vec<SocketAddr>) -> Result<Self> {
+        if addresses.is_empty() {
+            return Err(anyhow!("Node address of {} has no addresses", peer_id));
+        }
+        Ok(NodeAddr { peer_id, addresses })
     }
 }
 
 impl std::fmt::Display for NodeAddr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}@{}", self.peer_id, self.address)
+        let addresses: Vec<String> = self.addresses.iter().map(|a| a.to_string()).collect();
+        write!(f, "{}@{}", self.peer_id, addresses.join(","))
     }
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use super::*;
+    use crate::identity::keypair::KeyPair;
+
+    fn peer_id() -> NodeId {
+        NodeId::from_keypair(&KeyPair::generate().unwrap())
+    }
+
+    #[test]
+    fn test_parse_single_address() {
+        let id = peer_id();
+        let addr = NodeAddr::parse(&format!("{}@127.0.0.1:9000", id)).unwrap();
+        assert_eq!(addr.peer_id, id);
+        assert_eq!(addr.addresses, vec!["127.0.0.1:9000".parse().unwrap()]);
+    }
+
+    #[test]
+    fn test_parse_ipv6_and_multiple_addresses() {
+        let id = peer_id();
+        let addr = NodeAddr::parse(&format!(
+            "{}@[::1]:9000, 10.0.0.2:9001,[fe80::1%2]:9002",
+            id
+        ))
+        .unwrap();
+        let expected: Vec<SocketAddr> = vec![
+            "[::1]:9000".parse().unwrap(),
+            "10.0.0.2:9001".parse().unwrap(),
+            "[fe80::1%2]:9002".parse().unwrap(),
+        ];
+        assert_eq!(addr.addresses, expected);
+
+        // Display 的结果可以再次解析
+        let reparsed = NodeAddr::parse(&addr.to_string()).unwrap();
+        assert_eq!(reparsed.peer_id, id);
+        assert_eq!(reparsed.addresses, expected);
+    }
+
+    #[test]
+    fn test_parse_rejects_invalid_addresses() {
+        let id = peer_id();
+        for bad in [
+            id.to_string(),
+            format!("{}@", id),
+            format!("{}@::1:9000", id),
+            format!("{}@127.0.0.1:9000,", id),
+            format!("{}@127.0.0.1:9000@127.0.0.1:9001", id),
+            "not-a-did@127.0.0.1:9000".to_string(),
+        ] {
+            assert!(NodeAddr::parse(&bad).is_err(), "should reject {}", bad);
+        }
+        assert!(NodeAddr::with_addresses(id, Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_parse_list_skips_comments() {
+        let (a, b) = (peer_id(), peer_id());
+        let content = format!(
+            "# starter bootstrap nodes\n\n{}@127.0.0.1:9000\n  {}@[::1]:9001,10.0.0.2:9001  \n",
+            a, b
+        );
+        let list = NodeAddr::parse_list(&content).unwrap();
+        assert_eq!(list.len(), 2);
+        assert_eq!(list[0].peer_id, a);
+        assert_eq!(list[1].addresses.len(), 2);
+
+        let err = NodeAddr::parse_list(&format!("{}@127.0.0.1:9000\nbogus\n", a)).unwrap_err();
+        assert!(err.to_string().starts_with("line 2:"));
+    }
+}
@@ -4,6 +4,7 @@ use anyhow::Result;
 use multibase::Base;
 use multibase::{decode, encode};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::fmt;
 use std::str::FromStr;
 
@@ -47,6 +48,29 @@ impl NodeId {
     }
 
     pub fn to_keypair(&self) -> Result<KeyPair> {
+        let keypair = KeyPair::from_verifying_key_bytes(self.verifying_key_bytes()?)?;
+        Ok(keypair)
+    }
+
+    /// 日志用的短格式，保留前 8 位和后 4 位编码字符，例如 `did:key:z2De…HvVP`
+    pub fn short(&self) -> String {
+        let encoded = self.0.strip_prefix(DID_KEY_PREFIX).unwrap_or(&self.0);
+        let chars: Vec<char> = encoded.chars().collect();
+        if chars.len() <= 12 {
+            return self.0.clone();
+        }
+        let head: String = chars[..8].iter().collect();
+        let tail: String = chars[chars.len() - 4..].iter().collect();
+        format!("{}{}…{}", DID_KEY_PREFIX, head, tail)
+    }
+
+    /// 公钥指纹：verifying key 的 SHA-256 十六进制串，便于带外核对节点身份
+    pub fn fingerprint(&self) -> Result<String> {
+        let digest = Sha256::digest(self.verifying_key_bytes()?);
+        Ok(hex::encode(digest))
+    }
+
+    fn verifying_key_bytes(&self) -> Result<[u8; 32]> {
         if !self.0.starts_with(DID_KEY_PREFIX) {
             return Err(anyhow!("invalid NodeId prefix"));
         }
@@ -65,8 +89,7 @@ impl NodeId {
         if pubkey_bytes.len() != 32 {
             return Err(anyhow!("invalid key length"));
         }
-        let keypair = KeyPair::from_verifying_key_bytes(<[u8; 32]>::try_from(pubkey_bytes)?)?;
-        Ok(keypair)
+        Ok(<[u8; 32]>::try_from(pubkey_bytes)?)
     }
 
     pub fn as_str(&self) -> &str {
@@ -185,4 +208,27 @@ mod tests {
         let result = node_id.to_keypair();
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_short_and_fingerprint() -> Result<()> {
+        let node_id =
+            NodeId::from_string("did:key:z2DXbAovGq5vNKpXVFyrhVLppMdUCmV1hCNjbUydLMEWasE")?;
+        assert_eq!(node_id.short(), "did:key:z2DXbAov…WasE");
+        assert_eq!(node_id.to_string(), node_id.0);
+
+        let fingerprint = node_id.fingerprint()?;
+        assert_eq!(fingerprint.len(), 64);
+        assert_eq!(fingerprint, node_id.fingerprint()?);
+
+        let other = NodeId::from_keypair(&KeyPair::generate()?);
+        assert_ne!(other.fingerprint()?, fingerprint);
+        Ok(())
+    }
+
+    #[test]
+    fn test_short_keeps_short_ids() {
+        let node_id = NodeId("did:key:zabc".into());
+        assert_eq!(node_id.short(), "did:key:zabc");
+        assert!(node_id.fingerprint().is_err());
+    }
 }
@@ -0,0 +1,245 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+/// 最多统计的文件数
+const MAX_FILES: usize = 2000;
+/// 待扫描目录栈的上限
+const MAX_PENDING_DIRS: usize = 50;
+/// 每个文件最多读取的字节数，超出部分不计行数
+const MAX_FILE_BYTES: u64 = 1024 * 1024;
+/// 不参与主语言判定和占比的类别
+const NON_CODE: &[&str] = &["Config/Data", "Markdown"];
+
+/// 仓库语言统计：按代码行数而不是文件数加权
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LanguageStats {
+    /// 行数最多的编程语言，没有可识别的代码时为 "Unknown"
+    pub primary: String,
+    /// 语言 -> 行数，包含配置和文档类
+    pub lines: HashMap<String, u64>,
+}
+
+impl LanguageStats {
+    /// 各编程语言的行数占比（百分比），按占比降序；配置和文档类不计入
+    pub fn breakdown(&self) -> Vec<(String, f64)> {
+        let code: Vec<(&String, u64)> = self
+            .lines
+            .iter()
+            .filter(|(lang, lines)| !NON_CODE.contains(&lang.as_str()) && **lines > 0)
+            .map(|(lang, lines)| (lang, *lines))
+            .collect();
+        let total: u64 = code.iter().map(|(_, lines)| lines).sum();
+        if total == 0 {
+            return Vec::new();
+        }
+
+        let mut breakdown: Vec<(String, f64)> = code
+            .into_iter()
+            .map(|(lang, lines)| (lang.clone(), lines as f64 * 100.0 / total as f64))
+            .collect();
+        breakdown.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        breakdown
+    }
+
+    /// 形如 "Rust 78%, Shell 12%" 的摘要，最多列出 top 种语言
+    pub fn summary(&self, top: usize) -> String {
+        self.breakdown()
+            .into_iter()
+            .take(top)
+            .map(|(lang, percent)| format!("{} {:.0}%", lang, percent))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// 扫描工作区并按代码行数统计语言
+///
+/// 跳过隐藏目录和常见的构建产物目录，最多统计 2000 个文件；
+/// `.h` 头文件通过查找 C++ 关键字区分 C 和 C++
+pub fn detect_language(path: &Path) -> LanguageStats {
+    let mut lines: HashMap<String, u64> = HashMap::new();
+    let mut stack = vec![path.to_path_buf()];
+    let mut files_scanned = 0;
+
+    while let Some(dir) = stack.pop() {
+        if files_scanned >= MAX_FILES {
+            break;
+        }
+
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            if files_scanned >= MAX_FILES {
+                break;
+            }
+            let path = entry.path();
+            if path.is_dir() {
+                if !is_skipped_dir(&path) && stack.len() < MAX_PENDING_DIRS {
+                    stack.push(path);
+                }
+                continue;
+            }
+            let Some(ext) = path.extension().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            let ext = ext.to_lowercase();
+            if language_for_extension(&ext).is_none() {
+                continue;
+            }
+            files_scanned += 1;
+
+            let Some(content) = read_text_prefix(&path) else {
+                continue;
+            };
+            let lang = if ext == "h" {
+                header_language(&content)
+            } else {
+                language_for_extension(&ext).unwrap_or_default()
+            };
+            *lines.entry(lang.to_string()).or_insert(0) += count_lines(&content);
+        }
+    }
+
+    let primary = lines
+        .iter()
+        .filter(|(lang, count)| !NON_CODE.contains(&lang.as_str()) && **count > 0)
+        .max_by(|a, b| a.1.cmp(b.1).then_with(|| b.0.cmp(a.0)))
+        .map(|(lang, _)| lang.clone())
+        .unwrap_or_else(|| "Unknown".to_string());
+
+    LanguageStats { primary, lines }
+}
+
+fn is_skipped_dir(path: &Path) -> bool {
+    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+    name.starts_with('.')
+        || name == "target"
+        || name == "node_modules"
+        || name == "dist"
+        || name == "build"
+}
+
+fn language_for_extension(ext: &str) -> Option<&'static str> {
+    let lang = match ext {
+        "rs" => "Rust",
+        "go" => "Go",
+        "py" => "Python",
+        "js" => "JavaScript",
+        "ts" | "tsx" => "TypeScript",
+        "java" => "Java",
+        "c" | "h" => "C",
+        "cpp" | "hpp" | "cc" | "cxx" => "C++",
+        "cs" => "C#",
+        "rb" => "Ruby",
+        "php" => "PHP",
+        "html" => "HTML",
+        "css" | "scss" | "less" => "CSS",
+        "swift" => "Swift",
+        "kt" | "kts" => "Kotlin",
+        "scala" => "Scala",
+        "lua" => "Lua",
+        "sh" | "bash" | "zsh" => "Shell",
+        "sql" => "SQL",
+        "md" => "Markdown",
+        "json" | "yaml" | "yml" | "toml" | "xml" => "Config/Data",
+        _ => return None,
+    };
+    Some(lang)
+}
+
+/// `.h` 文件中出现 C++ 特有的语法时归为 C++，否则归为 C
+fn header_language(content: &[u8]) -> &'static str {
+    const CPP_MARKERS: &[&str] = &[
+        "class ",
+        "namespace ",
+        "template<",
+        "template <",
+        "std::",
+        "public:",
+        "private:",
+        "protected:",
+        "virtual ",
+        "#include <iostream>",
+    ];
+    let text = String::from_utf8_lossy(content);
+    if CPP_MARKERS.iter().any(|marker| text.contains(marker)) {
+        "C++"
+    } else {
+        "C"
+    }
+}
+
+/// 读取文件开头最多 1 MiB，包含 NUL 字节的视为二进制文件并跳过
+fn read_text_prefix(path: &Path) -> Option<Vec<u8>> {
+    let file = fs::File::open(path).ok()?;
+    let mut content = Vec::new();
+    file.take(MAX_FILE_BYTES).read_to_end(&mut content).ok()?;
+    (!content.contains(&0)).then_some(content)
+}
+
+fn count_lines(content: &[u8]) -> u64 {
+    let newlines = content.iter().filter(|&&b| b == b'\n').count() as u64;
+    if content.last().is_some_and(|&b| b != b'\n') {
+        newlines + 1
+    } else {
+        newlines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_language_weights_by_lines() {
+        let root = std::env::temp_dir().join(format!("megaengine-lang-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::create_dir_all(root.join("include")).unwrap();
+        fs::create_dir_all(root.join("target")).unwrap();
+
+        // 一个大的 Rust 文件胜过多个小的 Shell 脚本
+        fs::write(root.join("src/main.rs"), "fn main() {}\n".repeat(30)).unwrap();
+        for i in 0..5 {
+            fs::write(root.join(format!("s{}.sh", i)), "echo hi\n").unwrap();
+        }
+        // 构建目录和配置文件不影响主语言
+        fs::write(root.join("target/gen.go"), "package x\n".repeat(500)).unwrap();
+        fs::write(root.join("data.json"), "{}\n".repeat(1000)).unwrap();
+        // 头文件按内容区分 C / C++
+        fs::write(root.join("include/a.h"), "int add(int a, int b);\n").unwrap();
+        fs::write(
+            root.join("include/b.h"),
+            "namespace x {\nclass Foo {};\n}\n",
+        )
+        .unwrap();
+
+        let stats = detect_language(&root);
+        assert_eq!(stats.primary, "Rust");
+        assert_eq!(stats.lines["Rust"], 30);
+        assert_eq!(stats.lines["Shell"], 5);
+        assert_eq!(stats.lines["C"], 1);
+        assert_eq!(stats.lines["C++"], 3);
+        assert_eq!(stats.lines["Config/Data"], 1000);
+        assert!(!stats.lines.contains_key("Go"));
+        assert_eq!(stats.summary(2), "Rust 77%, Shell 13%");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_detect_language_unknown() {
+        let root = std::env::temp_dir().join(format!("megaengine-lang-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("README.md"), "# hi\n").unwrap();
+
+        let stats = detect_language(&root);
+        assert_eq!(stats.primary, "Unknown");
+        assert!(stats.breakdown().is_empty());
+        assert_eq!(stats.summary(3), "");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
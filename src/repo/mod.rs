@@ -1,7 +1,9 @@
 #![allow(clippy::module_inception)]
+pub mod language;
 pub mod repo;
 pub mod repo_id;
 pub mod repo_manager;
 pub mod repo_sync;
+pub mod stats;
 
 pub use repo_sync::start_repo_sync_task;
@@ -1,4 +1,8 @@
+use crate::identity::keypair::KeyPair;
+use crate::node::node_id::NodeId;
+use crate::repo::repo_id::RepoId;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use std::path::PathBuf;
 
@@ -11,6 +15,39 @@ pub struct P2PDescription {
     pub language: String,
     pub latest_commit_at: i64,
     pub size: u64,
+    /// 主题标签（小写、去重、排序）
+    ///
+    /// 为空时不参与序列化，未打标签的仓库公告与旧版本节点的签名哈希保持一致
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// HEAD 可达的提交数，历史过长时最多统计到遍历上限
+    ///
+    /// 与 tags 一样为 0 时不参与序列化
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub commit_count: u64,
+    /// 不同作者（按邮箱区分）的数量
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub contributors: u64,
+}
+
+fn is_zero(n: &u64) -> bool {
+    *n == 0
+}
+
+/// 规范化标签：去除首尾空白、转为小写、去掉空标签，并去重排序
+pub fn normalize_tags<I, S>(tags: I) -> Vec<String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let mut tags: Vec<String> = tags
+        .into_iter()
+        .map(|t| t.as_ref().trim().to_lowercase())
+        .filter(|t| !t.is_empty())
+        .collect();
+    tags.sort();
+    tags.dedup();
+    tags
 }
 
 /// P2P 仓库
@@ -22,6 +59,20 @@ pub struct Repo {
     pub path: PathBuf,
     pub is_external: bool,
     pub bundle: PathBuf,
+    /// 创建者对仓库身份（repo_id、creator、name）的签名，十六进制编码
+    ///
+    /// 转发他人仓库的节点原样携带该签名，接收方据此确认公告中的 creator 没有被冒用；
+    /// 为空时不参与序列化，与旧版本节点的公告签名哈希保持一致
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub signature: String,
+    /// 十六进制的根提交 ID，与 creator 的公钥一起推导出 repo_id；旧版本节点不携带
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub root_commit: String,
+    /// 本节点是否已确认 repo_id 由 root_commit 和 creator 推导而来
+    ///
+    /// 只保存在本地数据库中，不随公告发送，接收方自行校验
+    #[serde(skip)]
+    pub verified: bool,
 }
 
 impl Repo {
@@ -34,6 +85,9 @@ impl Repo {
             path,
             is_external: false,
             bundle: PathBuf::new(),
+            signature: String::new(),
+            root_commit: String::new(),
+            verified: false,
         }
     }
 
@@ -65,6 +119,125 @@ impl Repo {
             .collect()
     }
 
+    /// 读取最近 `limit` 个提交，优先使用本地工作区，没有时读取 bundle
+    ///
+    /// 会读取 git 对象，在异步上下文中应放到阻塞线程池执行
+    pub fn recent_commits(
+        &self,
+        limit: usize,
+    ) -> anyhow::Result<Vec<crate::git::git_repo::CommitSummary>> {
+        if !self.path.as_os_str().is_empty() && self.path.exists() {
+            return crate::git::git_repo::recent_commits(&self.path.to_string_lossy(), limit);
+        }
+        if !self.bundle.as_os_str().is_empty() && self.bundle.exists() {
+            return crate::git::git_repo::bundle_recent_commits(
+                &self.bundle.to_string_lossy(),
+                limit,
+            );
+        }
+        Err(anyhow::anyhow!(
+            "repository {} has no local working copy or bundle yet",
+            self.repo_id
+        ))
+    }
+
+    /// 用已收到的 bundle 更新本地工作区，返回 ref 的变化
+    ///
+    /// 会执行 git 命令，在异步上下文中应放到阻塞线程池执行
+    pub fn pull_from_bundle(&self) -> anyhow::Result<crate::git::pack::PullReport> {
+        if self.path.as_os_str().is_empty() || !self.path.exists() {
+            return Err(anyhow::anyhow!(
+                "repository {} has no local working copy; clone it first",
+                self.repo_id
+            ));
+        }
+        if self.bundle.as_os_str().is_empty() || !self.bundle.exists() {
+            return Err(anyhow::anyhow!(
+                "no bundle available for repository {} yet; wait for it to be fetched from a peer",
+                self.repo_id
+            ));
+        }
+        crate::git::pack::pull_repo_from_bundle(
+            &self.path.to_string_lossy(),
+            &self.bundle.to_string_lossy(),
+        )
+    }
+
+    /// 创建者签名的内容；refs 等会随提交变化的字段不参与签名
+    fn identity_hash(&self) -> Vec<u8> {
+        let mut hasher = Sha256::new();
+        for field in [
+            &self.repo_id,
+            &self.p2p_description.creator,
+            &self.p2p_description.name,
+        ] {
+            hasher.update(field.as_bytes());
+            hasher.update(b"\n");
+        }
+        hasher.finalize().to_vec()
+    }
+
+    /// 以创建者身份签名，keypair 必须与 creator 对应
+    pub fn sign(&mut self, keypair: &KeyPair) -> anyhow::Result<()> {
+        if NodeId::from_keypair(keypair).as_str() != self.p2p_description.creator {
+            anyhow::bail!(
+                "cannot sign repo {}: keypair does not belong to its creator",
+                self.repo_id
+            );
+        }
+        let signature = keypair.sign(&self.identity_hash())?;
+        self.signature = hex::encode(signature.to_bytes());
+        Ok(())
+    }
+
+    /// 签名存在且由 creator 的密钥签出
+    pub fn verify_signature(&self) -> bool {
+        let Ok(creator) = NodeId::from_string(&self.p2p_description.creator) else {
+            return false;
+        };
+        let Ok(keypair) = creator.to_keypair() else {
+            return false;
+        };
+        let Some(signature) = hex::decode(&self.signature)
+            .ok()
+            .and_then(|bytes| ed25519_dalek::Signature::from_slice(&bytes).ok())
+        else {
+            return false;
+        };
+        keypair.verify(&self.identity_hash(), &signature)
+    }
+
+    /// 校验 repo_id 是否由 root_commit 和 creator 的公钥推导而来
+    ///
+    /// creator 不是合法的 did:key，或者携带的根提交推导不出 repo_id 时返回错误；
+    /// 没有携带根提交时无法校验，返回 false
+    pub fn verify_repo_id(&self) -> anyhow::Result<bool> {
+        let creator = NodeId::from_string(&self.p2p_description.creator).map_err(|e| {
+            anyhow::anyhow!(
+                "creator {} is not a valid did:key: {}",
+                self.p2p_description.creator,
+                e
+            )
+        })?;
+        let public_key = creator.to_keypair()?.verifying_key_bytes();
+        if self.root_commit.is_empty() {
+            return Ok(false);
+        }
+
+        let root_commit = hex::decode(&self.root_commit)
+            .map_err(|e| anyhow::anyhow!("invalid root commit {}: {}", self.root_commit, e))?;
+        let expected = RepoId::generate(&root_commit, &public_key)?;
+        if expected.as_str() != self.repo_id {
+            anyhow::bail!(
+                "repo_id {} does not match root commit {} and creator {}",
+                self.repo_id,
+                self.root_commit,
+                self.p2p_description.creator
+            );
+        }
+        Ok(true)
+    }
+
     /// 获取仓库地址（P2P 格式）
     pub fn p2p_address(&self) -> String {
         format!("git+p2p://{}", self.repo_id)
@@ -84,6 +257,9 @@ mod tests {
             language: "Rust".to_string(),
             latest_commit_at: 1000,
             size: 0,
+            tags: Vec::new(),
+            commit_count: 0,
+            contributors: 0,
         };
 
         let repo = Repo::new(
@@ -105,6 +281,9 @@ mod tests {
             language: "Rust".to_string(),
             latest_commit_at: 1000,
             size: 0,
+            tags: Vec::new(),
+            commit_count: 0,
+            contributors: 0,
         };
 
         let mut repo = Repo::new(
@@ -119,4 +298,85 @@ mod tests {
             Some(&"commit1".to_string())
         );
     }
+
+    #[test]
+    fn test_tags_are_normalized_and_backward_compatible() {
+        assert_eq!(
+            normalize_tags(["P2P", " rust ", "", "p2p"]),
+            vec!["p2p".to_string(), "rust".to_string()]
+        );
+
+        // 旧版本节点的描述没有 tags 字段
+        let legacy = r#"{"creator":"c","name":"n","description":"d","language":"Rust","latest_commit_at":0,"size":0}"#;
+        let desc: P2PDescription = serde_json::from_str(legacy).unwrap();
+        assert!(desc.tags.is_empty());
+        // 没有标签时序列化结果与旧版本一致
+        assert!(!serde_json::to_string(&desc).unwrap().contains("tags"));
+    }
+
+    #[test]
+    fn test_sign_and_verify_creator_signature() {
+        let kp = KeyPair::generate().unwrap();
+        let desc = P2PDescription {
+            creator: NodeId::from_keypair(&kp).to_string(),
+            name: "signed-repo".to_string(),
+            description: String::new(),
+            language: "Rust".to_string(),
+            latest_commit_at: 0,
+            size: 0,
+            tags: Vec::new(),
+            commit_count: 0,
+            contributors: 0,
+        };
+        let mut repo = Repo::new("did:repo:signed".to_string(), desc, PathBuf::new());
+        assert!(!repo.verify_signature());
+
+        // 只有创建者的密钥可以签名
+        assert!(repo.sign(&KeyPair::generate().unwrap()).is_err());
+        repo.sign(&kp).unwrap();
+        assert!(repo.verify_signature());
+
+        // refs 变化不影响签名，篡改 creator 或 name 则失效
+        repo.add_ref("refs/heads/main".to_string(), "a".repeat(40));
+        assert!(repo.verify_signature());
+        let mut renamed = repo.clone();
+        renamed.p2p_description.name = "other".to_string();
+        assert!(!renamed.verify_signature());
+        let mut reassigned = repo.clone();
+        reassigned.p2p_description.creator =
+            NodeId::from_keypair(&KeyPair::generate().unwrap()).to_string();
+        assert!(!reassigned.verify_signature());
+    }
+
+    #[test]
+    fn test_verify_repo_id_against_root_commit() {
+        let kp = KeyPair::generate().unwrap();
+        let root_commit = [1u8; 20];
+        let repo_id = RepoId::generate(&root_commit, &kp.verifying_key_bytes()).unwrap();
+        let desc = P2PDescription {
+            creator: NodeId::from_keypair(&kp).to_string(),
+            name: "rooted".to_string(),
+            description: String::new(),
+            language: "Rust".to_string(),
+            latest_commit_at: 0,
+            size: 0,
+            tags: Vec::new(),
+            commit_count: 0,
+            contributors: 0,
+        };
+        let mut repo = Repo::new(repo_id.to_string(), desc, PathBuf::new());
+        // 没有根提交时无法校验
+        assert!(!repo.verify_repo_id().unwrap());
+
+        repo.root_commit = hex::encode(root_commit);
+        assert!(repo.verify_repo_id().unwrap());
+
+        let mut other_root = repo.clone();
+        other_root.root_commit = hex::encode([2u8; 20]);
+        assert!(other_root.verify_repo_id().is_err());
+
+        let mut malformed = repo.clone();
+        malformed.p2p_description.creator = "not-a-did".to_string();
+        assert!(malformed.verify_repo_id().is_err());
+    }
 }
@@ -1,16 +1,26 @@
-use crate::git::git_repo::read_repo_refs;
+use crate::git::git_repo::{
+    get_latest_commit_time, history_stats, read_repo_refs, repo_root_commit_bytes,
+};
+use crate::git::pack::pack_repo_bundle;
+use crate::repo::repo::Repo;
 use crate::storage::{ref_model, repo_model};
-use anyhow::Result;
+use crate::util::{calculate_directory_size, get_repo_id_last_part};
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::time::Duration;
 use tokio::time::interval;
 use tracing::{debug, info, warn};
 
-const REPO_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// 默认的本地仓库 refs 检查间隔
+pub const DEFAULT_REPO_CHECK_INTERVAL: Duration = Duration::from_secs(60);
 
-/// 后台任务：定时检查本地 repos 的 refs 是否有更新
-pub async fn start_repo_sync_task() {
+/// 后台任务：定时检查本地 repos 的 refs 是否有更新，有更新时重新打包 bundle
+///
+/// `history_limit` 为重新统计提交数和贡献者时最多遍历的提交数
+pub async fn start_repo_sync_task(check_interval: Duration, history_limit: usize) {
     tokio::spawn(async move {
-        let mut tick = interval(REPO_CHECK_INTERVAL);
+        let mut tick = interval(check_interval);
 
         loop {
             tick.tick().await;
@@ -20,10 +30,12 @@ pub async fn start_repo_sync_task() {
             // 查询所有 repos
             match repo_model::list_repos().await {
                 Ok(repos) => {
-                    for repo in repos {
+                    for mut repo in repos {
                         // 只检查本地 repos (is_external=false)
                         if !repo.is_external {
-                            if let Err(e) = check_and_update_repo_refs(&repo).await {
+                            if let Err(e) =
+                                check_and_update_repo_refs(&mut repo, history_limit).await
+                            {
                                 warn!("Failed to check refs for repo {}: {}", repo.repo_id, e);
                             }
                         }
@@ -37,8 +49,8 @@ pub async fn start_repo_sync_task() {
     });
 }
 
-/// 检查仓库的 refs 是否有更新，如果有则更新数据库
-async fn check_and_update_repo_refs(repo: &crate::repo::repo::Repo) -> Result<()> {
+/// 检查仓库的 refs 是否有更新，如果有则重新打包 bundle 并更新数据库
+async fn check_and_update_repo_refs(repo: &mut Repo, history_limit: usize) -> Result<()> {
     let repo_path = repo.path.to_string_lossy().to_string();
 
     // 从 git 仓库读取最新的 refs
@@ -47,22 +59,18 @@ async fn check_and_update_repo_refs(repo: &crate::repo::repo::Repo) -> Result<()
     // 检查是否有变化
     if ref_model::has_refs_changed(&repo.repo_id, &current_refs).await? {
         info!(
-            "Detected refs change in local repo {}, updating database",
+            "Detected refs change in local repo {}, repacking bundle",
             repo.repo_id
         );
 
-        // 更新 refs 到数据库
-        ref_model::batch_save_refs(&repo.repo_id, &current_refs).await?;
+        repack_local_repo(repo, current_refs, history_limit).await?;
 
-        // 这里后续可以扩展：
-        // 1. 重新生成 bundle
-        // 2. 广播 repo announcement 告知其他节点有更新
-        // TODO: trigger bundle generation and gossip announcement
-
-        debug!(
-            "Successfully updated refs for repo {} ({} refs)",
+        // 下一轮 RepoAnnouncement 会携带新的 refs，其他节点据此重新拉取
+        info!(
+            "Repacked repo {} ({} refs) into {}",
             repo.repo_id,
-            current_refs.len()
+            repo.refs.len(),
+            repo.bundle.display()
         );
     } else {
         debug!("No changes detected in repo {}", repo.repo_id);
@@ -71,15 +79,152 @@ async fn check_and_update_repo_refs(repo: &crate::repo::repo::Repo) -> Result<()
     Ok(())
 }
 
+/// 本地仓库 bundle 的默认存放路径：`<root>/bundles/local/<repo>.bundle`
+///
+/// 与响应请求时生成的临时 bundle 分开存放，避免被增量 bundle 覆盖
+pub fn local_bundle_path(repo_id: &str) -> PathBuf {
+    crate::storage::data_dir()
+        .join("bundles")
+        .join("local")
+        .join(format!("{}.bundle", get_repo_id_last_part(repo_id)))
+}
+
+/// 从本地工作目录重新打包仓库 bundle，并用给定的 refs 刷新数据库记录
+///
+/// 同时更新 latest_commit_at、size 以及提交数和贡献者（最多遍历 `history_limit` 个提交）；
+/// 旧 refs 会被清除，已删除的分支不再保留
+pub async fn repack_local_repo(
+    repo: &mut Repo,
+    refs: HashMap<String, String>,
+    history_limit: usize,
+) -> Result<()> {
+    if repo.bundle.as_os_str().is_empty() {
+        repo.bundle = local_bundle_path(&repo.repo_id);
+    }
+
+    let repo_path = repo.path.clone();
+    let bundle_path = repo.bundle.clone();
+    let needs_root_commit = repo.root_commit.is_empty();
+    let (latest_commit_at, size, history, root_commit) =
+        tokio::task::spawn_blocking(move || -> Result<_> {
+            let path = repo_path.to_string_lossy().to_string();
+            pack_repo_bundle(&path, &bundle_path.to_string_lossy())?;
+            let git_dir = repo_path.join(".git");
+            let size = git_dir.exists().then(|| calculate_directory_size(&git_dir));
+            let root_commit = needs_root_commit
+                .then(|| repo_root_commit_bytes(&path).ok())
+                .flatten();
+            Ok((
+                get_latest_commit_time(&path).ok(),
+                size,
+                history_stats(&path, history_limit).ok(),
+                root_commit,
+            ))
+        })
+        .await
+        .context("Failed to spawn bundle packing task")??;
+
+    if let Some(t) = latest_commit_at {
+        repo.p2p_description.latest_commit_at = t;
+    }
+    if let Some(size) = size {
+        repo.p2p_description.size = size;
+    }
+    if let Some(history) = history {
+        repo.p2p_description.commit_count = history.commits;
+        repo.p2p_description.contributors = history.contributors;
+    }
+    repo.refs = refs;
+
+    // 旧版本添加的仓库没有记录根提交，能推导出 repo_id 时补上
+    if let Some(root_commit) = root_commit {
+        repo.root_commit = hex::encode(root_commit);
+        repo.verified = matches!(repo.verify_repo_id(), Ok(true));
+        if !repo.verified {
+            warn!(
+                "Root commit of repo {} no longer matches its id",
+                repo.repo_id
+            );
+            repo.root_commit.clear();
+        }
+    }
+
+    ref_model::delete_refs_for_repo(&repo.repo_id).await?;
+    repo_model::save_repo_to_db(repo).await
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::git::git_repo::DEFAULT_HISTORY_LIMIT;
+    use crate::storage::with_test_db;
 
     #[tokio::test]
     async fn test_repo_sync_task_spawns() {
         // 只测试任务能否正常启动，不测试实际功能
-        start_repo_sync_task().await;
+        start_repo_sync_task(DEFAULT_REPO_CHECK_INTERVAL, DEFAULT_HISTORY_LIMIT).await;
         // 任务已在后台运行，测试通过
         tokio::time::sleep(Duration::from_millis(100)).await;
     }
+
+    #[tokio::test]
+    async fn test_repack_local_repo_refreshes_bundle_and_refs() -> Result<()> {
+        with_test_db(async {
+            let dir =
+                std::env::temp_dir().join(format!("megaengine-repack-{}", uuid::Uuid::new_v4()));
+            let work = dir.join("work");
+            std::fs::create_dir_all(&work)?;
+            let git = |args: &[&str]| {
+                let status = std::process::Command::new("git")
+                    .current_dir(&work)
+                    .args(args)
+                    .status()
+                    .unwrap();
+                assert!(status.success(), "git {:?} failed", args);
+            };
+            git(&["init", "--quiet"]);
+            git(&["config", "user.email", "test@example.com"]);
+            git(&["config", "user.name", "Test User"]);
+            std::fs::write(work.join("README.md"), "hello")?;
+            git(&["add", "."]);
+            git(&["commit", "--quiet", "-m", "init"]);
+
+            let repo_id = "did:repo:repack-test";
+            let desc = crate::repo::repo::P2PDescription {
+                creator: "did:node:repack-test".to_string(),
+                name: "repack".to_string(),
+                description: String::new(),
+                language: "Markdown".to_string(),
+                latest_commit_at: 0,
+                size: 0,
+                tags: Vec::new(),
+                commit_count: 0,
+                contributors: 0,
+            };
+            let mut repo = Repo::new(repo_id.to_string(), desc, work.clone());
+            repo.bundle = dir.join("repack.bundle");
+            // 数据库中残留一个已删除的分支
+            repo.refs
+                .insert("refs/heads/gone".to_string(), "0".repeat(40));
+            repo_model::save_repo_to_db(&repo).await?;
+
+            let refs = read_repo_refs(&work.to_string_lossy())?;
+            assert!(ref_model::has_refs_changed(repo_id, &refs).await?);
+            repack_local_repo(&mut repo, refs.clone(), DEFAULT_HISTORY_LIMIT).await?;
+
+            assert!(repo.bundle.exists());
+            assert!(repo.p2p_description.latest_commit_at > 0);
+            assert!(repo.p2p_description.size > 0);
+            assert_eq!(repo.p2p_description.commit_count, 1);
+            assert_eq!(repo.p2p_description.contributors, 1);
+            assert_eq!(ref_model::load_refs_for_repo(repo_id).await?, refs);
+            assert!(!ref_model::has_refs_changed(repo_id, &refs).await?);
+
+            repo_model::delete_repo_from_db(repo_id).await?;
+            ref_model::delete_refs_for_repo(repo_id).await?;
+            std::fs::remove_dir_all(&dir).ok();
+            Ok(())
+        })
+        .await
+    }
 }
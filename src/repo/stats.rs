@@ -0,0 +1,173 @@
+use crate::repo::repo::Repo;
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// 本节点仓库的汇总统计，供 `repo stats` 和 MCP `repo_stats` 使用
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RepoStats {
+    pub total: usize,
+    /// 本地添加的仓库数
+    pub local: usize,
+    /// 从其他节点学到的仓库数
+    pub external: usize,
+    /// 磁盘上存在 bundle 文件的仓库数
+    pub bundles: usize,
+    /// bundle 文件的总字节数
+    pub bundle_bytes: u64,
+    /// 各主语言的仓库数，按数量降序
+    pub languages: Vec<LanguageCount>,
+    /// 最近有提交的仓库，按最新提交时间降序
+    pub recent: Vec<RecentRepo>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LanguageCount {
+    pub language: String,
+    pub repos: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RecentRepo {
+    pub repo_id: String,
+    pub name: String,
+    pub language: String,
+    pub is_external: bool,
+    pub latest_commit_at: i64,
+}
+
+impl RepoStats {
+    /// 汇总仓库列表，`recent` 为最多列出的最近更新仓库数
+    pub fn from_repos(repos: &[Repo], recent: usize) -> Self {
+        let external = repos.iter().filter(|r| r.is_external).count();
+
+        let mut bundles = 0;
+        let mut bundle_bytes = 0;
+        for repo in repos {
+            if repo.bundle.as_os_str().is_empty() {
+                continue;
+            }
+            if let Ok(meta) = std::fs::metadata(&repo.bundle) {
+                bundles += 1;
+                bundle_bytes += meta.len();
+            }
+        }
+
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for repo in repos {
+            let language = repo.p2p_description.language.as_str();
+            let language = if language.is_empty() {
+                "Unknown"
+            } else {
+                language
+            };
+            *counts.entry(language).or_insert(0) += 1;
+        }
+        let mut languages: Vec<LanguageCount> = counts
+            .into_iter()
+            .map(|(language, repos)| LanguageCount {
+                language: language.to_string(),
+                repos,
+            })
+            .collect();
+        languages.sort_by(|a, b| {
+            b.repos
+                .cmp(&a.repos)
+                .then_with(|| a.language.cmp(&b.language))
+        });
+
+        let mut by_commit: Vec<&Repo> = repos
+            .iter()
+            .filter(|r| r.p2p_description.latest_commit_at > 0)
+            .collect();
+        by_commit.sort_by(|a, b| {
+            b.p2p_description
+                .latest_commit_at
+                .cmp(&a.p2p_description.latest_commit_at)
+                .then_with(|| a.repo_id.cmp(&b.repo_id))
+        });
+        let recent = by_commit
+            .into_iter()
+            .take(recent)
+            .map(|repo| RecentRepo {
+                repo_id: repo.repo_id.clone(),
+                name: repo.p2p_description.name.clone(),
+                language: repo.p2p_description.language.clone(),
+                is_external: repo.is_external,
+                latest_commit_at: repo.p2p_description.latest_commit_at,
+            })
+            .collect();
+
+        RepoStats {
+            total: repos.len(),
+            local: repos.len() - external,
+            external,
+            bundles,
+            bundle_bytes,
+            languages,
+            recent,
+        }
+    }
+}
+
+/// 读取数据库中的全部仓库并汇总，只读
+pub async fn collect_repo_stats(recent: usize) -> Result<RepoStats> {
+    let repos = crate::storage::repo_model::list_repos().await?;
+    Ok(RepoStats::from_repos(&repos, recent))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::repo::P2PDescription;
+    use std::path::PathBuf;
+
+    fn repo(id: &str, language: &str, latest_commit_at: i64, is_external: bool) -> Repo {
+        let desc = P2PDescription {
+            creator: "did:key:test".to_string(),
+            name: id.to_string(),
+            description: String::new(),
+            language: language.to_string(),
+            latest_commit_at,
+            size: 0,
+            tags: Vec::new(),
+            commit_count: 0,
+            contributors: 0,
+        };
+        let mut repo = Repo::new(format!("did:repo:{}", id), desc, PathBuf::new());
+        repo.is_external = is_external;
+        repo
+    }
+
+    #[test]
+    fn test_repo_stats_from_repos() {
+        let bundle =
+            std::env::temp_dir().join(format!("megaengine-stats-{}.bundle", uuid::Uuid::new_v4()));
+        std::fs::write(&bundle, vec![0u8; 100]).unwrap();
+
+        let mut with_bundle = repo("a", "Rust", 300, false);
+        with_bundle.bundle = bundle.clone();
+        let mut missing_bundle = repo("b", "Rust", 100, true);
+        missing_bundle.bundle = PathBuf::from("/nonexistent/megaengine.bundle");
+        let repos = vec![
+            with_bundle,
+            missing_bundle,
+            repo("c", "Go", 200, false),
+            repo("d", "", 0, false),
+        ];
+
+        let stats = RepoStats::from_repos(&repos, 2);
+        assert_eq!((stats.total, stats.local, stats.external), (4, 3, 1));
+        assert_eq!((stats.bundles, stats.bundle_bytes), (1, 100));
+        let languages: Vec<(&str, usize)> = stats
+            .languages
+            .iter()
+            .map(|l| (l.language.as_str(), l.repos))
+            .collect();
+        assert_eq!(languages, vec![("Rust", 2), ("Go", 1), ("Unknown", 1)]);
+        let recent: Vec<&str> = stats.recent.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(recent, vec!["a", "c"]);
+
+        std::fs::remove_file(&bundle).unwrap();
+    }
+}
@@ -0,0 +1,78 @@
+use anyhow::Result;
+use sea_orm::entity::prelude::*;
+use sea_orm::Set;
+
+use crate::storage::get_db_conn;
+
+/// 已加入的群聊频道及其共享密钥（带外分发）
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "channels")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub channel_id: String,
+    /// 十六进制编码的 32 字节共享密钥
+    pub key: String,
+    pub joined_at: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+/// 保存（或覆盖）频道密钥
+pub async fn save_channel(channel_id: &str, key_hex: &str) -> Result<()> {
+    let db = get_db_conn().await?;
+    let now = chrono::Local::now().timestamp();
+
+    let _ = Entity::delete_by_id(channel_id).exec(&db).await;
+
+    let active = ActiveModel {
+        channel_id: Set(channel_id.to_string()),
+        key: Set(key_hex.to_string()),
+        joined_at: Set(now),
+    };
+    Entity::insert(active).exec(&db).await?;
+    Ok(())
+}
+
+/// 获取频道的十六进制密钥，未加入则返回 None
+pub async fn get_channel_key(channel_id: &str) -> Result<Option<String>> {
+    let db = get_db_conn().await?;
+    Ok(Entity::find_by_id(channel_id)
+        .one(&db)
+        .await?
+        .map(|model| model.key))
+}
+
+/// 列出所有已加入的频道
+pub async fn list_channels() -> Result<Vec<Model>> {
+    let db = get_db_conn().await?;
+    Ok(Entity::find().all(&db).await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::with_test_db;
+
+    #[tokio::test]
+    async fn test_save_and_get_channel() -> Result<()> {
+        with_test_db(async {
+            let channel_id = "channel-storage-test";
+            save_channel(channel_id, "aa").await?;
+            save_channel(channel_id, "bb").await?;
+            assert_eq!(get_channel_key(channel_id).await?, Some("bb".to_string()));
+            assert!(list_channels()
+                .await?
+                .iter()
+                .any(|c| c.channel_id == channel_id));
+
+            let db = get_db_conn().await?;
+            Entity::delete_by_id(channel_id).exec(&db).await?;
+            assert_eq!(get_channel_key(channel_id).await?, None);
+            Ok(())
+        })
+        .await
+    }
+}
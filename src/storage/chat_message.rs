@@ -12,6 +12,21 @@ pub enum MessageStatus {
     Delivered,
     #[sea_orm(string_value = "Failed")]
     Failed,
+    #[sea_orm(string_value = "Read")]
+    Read,
+}
+
+impl MessageStatus {
+    /// Status icon for CLI rendering
+    pub fn icon(&self) -> &'static str {
+        match self {
+            MessageStatus::Sending => "…",
+            MessageStatus::Sent => "✓",
+            MessageStatus::Delivered => "✓✓",
+            MessageStatus::Read => "✓✓ read",
+            MessageStatus::Failed => "✗",
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
@@ -19,11 +34,16 @@ pub enum MessageStatus {
 pub struct Model {
     #[sea_orm(primary_key, auto_increment = false)]
     pub id: String, // UUID
-    pub from: String,    // Sender NodeId
-    pub to: String,      // Receiver NodeId
-    pub content: String, // Plaintext content (local storage is trusted for now)
-    pub created_at: i64, // Timestamp
+    pub from: String,       // Sender NodeId
+    pub to: String,         // Receiver NodeId
+    pub content: String,    // Plaintext content (local storage is trusted for now)
+    pub created_at: i64,    // Unix timestamp (seconds)
+    pub created_at_ms: i64, // Local ordering key (milliseconds), strictly increasing per process
     pub status: MessageStatus,
+    pub retry_count: i32,           // Failed send attempts so far
+    pub next_retry_at: i64,         // Earliest timestamp for the next send attempt
+    pub receipt_pending: bool,      // Read receipt queued but not yet sent
+    pub channel_id: Option<String>, // Group channel, None for 1:1 messages
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -32,7 +52,18 @@ pub enum Relation {}
 impl ActiveModelBehavior for ActiveModel {}
 
 use anyhow::Result;
-use sea_orm::{ActiveModelTrait, Set};
+use sea_orm::{ActiveModelTrait, Condition, QueryOrder, Set};
+
+/// Maximum send attempts before a message is marked `Failed`
+pub const MAX_SEND_ATTEMPTS: i32 = 10;
+/// Upper bound of the retry backoff (seconds)
+const MAX_RETRY_BACKOFF_SECS: i64 = 300;
+
+/// Exponential backoff after the given number of failed attempts: 1s, 2s, 4s ... capped at 5min
+pub fn retry_backoff_secs(retry_count: i32) -> i64 {
+    let exp = retry_count.saturating_sub(1).clamp(0, 30) as u32;
+    (1i64 << exp).min(MAX_RETRY_BACKOFF_SECS)
+}
 
 pub async fn save_message(
     id: String,
@@ -49,7 +80,39 @@ pub async fn save_message(
         to: Set(to),
         content: Set(content),
         created_at: Set(created_at),
+        created_at_ms: Set(crate::util::timestamp_now_millis()),
+        status: Set(status),
+        retry_count: Set(0),
+        next_retry_at: Set(0),
+        receipt_pending: Set(false),
+        channel_id: Set(None),
+    };
+    model.insert(&db).await?;
+    Ok(())
+}
+
+/// Save a group channel message; `to` holds the channel id as well
+pub async fn save_channel_message(
+    id: String,
+    from: String,
+    channel_id: String,
+    content: String,
+    created_at: i64,
+    status: MessageStatus,
+) -> Result<()> {
+    let db = crate::storage::get_db_conn().await?;
+    let model = ActiveModel {
+        id: Set(id),
+        from: Set(from),
+        to: Set(channel_id.clone()),
+        content: Set(content),
+        created_at: Set(created_at),
+        created_at_ms: Set(crate::util::timestamp_now_millis()),
         status: Set(status),
+        retry_count: Set(0),
+        next_retry_at: Set(0),
+        receipt_pending: Set(false),
+        channel_id: Set(Some(channel_id)),
     };
     model.insert(&db).await?;
     Ok(())
@@ -65,3 +128,285 @@ pub async fn update_message_status(msg_id: &str, status: MessageStatus) -> Resul
     }
     Ok(())
 }
+
+/// Record a failed send attempt: schedule the next retry with backoff, or mark the
+/// message `Failed` once `MAX_SEND_ATTEMPTS` is reached. Returns the new status.
+pub async fn record_send_failure(msg_id: &str) -> Result<Option<MessageStatus>> {
+    let db = crate::storage::get_db_conn().await?;
+    let Some(m) = Entity::find_by_id(msg_id).one(&db).await? else {
+        return Ok(None);
+    };
+
+    let retry_count = m.retry_count + 1;
+    let now = crate::util::timestamp_now();
+    let mut active: ActiveModel = m.into();
+    active.retry_count = Set(retry_count);
+    let status = if retry_count >= MAX_SEND_ATTEMPTS {
+        MessageStatus::Failed
+    } else {
+        active.next_retry_at = Set(now + retry_backoff_secs(retry_count));
+        MessageStatus::Sending
+    };
+    active.status = Set(status.clone());
+    active.update(&db).await?;
+    Ok(Some(status))
+}
+
+/// Reset a `Failed` message back to `Sending` so the sender picks it up immediately.
+/// Returns false if the message does not exist or is not `Failed`.
+pub async fn reset_for_retry(msg_id: &str) -> Result<bool> {
+    let db = crate::storage::get_db_conn().await?;
+    let Some(m) = Entity::find_by_id(msg_id).one(&db).await? else {
+        return Ok(false);
+    };
+    if m.status != MessageStatus::Failed {
+        return Ok(false);
+    }
+
+    let mut active: ActiveModel = m.into();
+    active.status = Set(MessageStatus::Sending);
+    active.retry_count = Set(0);
+    active.next_retry_at = Set(0);
+    active.update(&db).await?;
+    Ok(true)
+}
+
+/// Mark a received message as read and queue a read receipt for its sender.
+/// Returns false if the message does not exist or was not addressed to `my_node_id`.
+pub async fn mark_read(msg_id: &str, my_node_id: &str) -> Result<bool> {
+    let db = crate::storage::get_db_conn().await?;
+    let Some(m) = Entity::find_by_id(msg_id).one(&db).await? else {
+        return Ok(false);
+    };
+    if m.to != my_node_id {
+        return Ok(false);
+    }
+    if m.status == MessageStatus::Read {
+        return Ok(true);
+    }
+
+    let mut active: ActiveModel = m.into();
+    active.status = Set(MessageStatus::Read);
+    active.receipt_pending = Set(true);
+    active.update(&db).await?;
+    Ok(true)
+}
+
+/// Clear the pending read-receipt flag once the receipt has been sent
+pub async fn clear_receipt_pending(msg_id: &str) -> Result<()> {
+    let db = crate::storage::get_db_conn().await?;
+    if let Some(m) = Entity::find_by_id(msg_id).one(&db).await? {
+        let mut active: ActiveModel = m.into();
+        active.receipt_pending = Set(false);
+        active.update(&db).await?;
+    }
+    Ok(())
+}
+
+/// All messages in ascending order, optionally limited to the 1:1 conversation with `peer`
+pub async fn list_messages(peer: Option<&str>) -> Result<Vec<Model>> {
+    let db = crate::storage::get_db_conn().await?;
+    let mut query = Entity::find();
+    if let Some(peer) = peer {
+        query = query.filter(Column::ChannelId.is_null()).filter(
+            Condition::any()
+                .add(Column::From.eq(peer))
+                .add(Column::To.eq(peer)),
+        );
+    }
+    Ok(query.order_by_asc(Column::CreatedAtMs).all(&db).await?)
+}
+
+/// Insert exported messages, skipping ids that already exist. Returns the number inserted.
+///
+/// Messages still `Sending` are imported as `Failed` so they are not sent a second time,
+/// and pending read receipts are dropped; `chat retry` re-queues a message explicitly.
+pub async fn import_messages(messages: Vec<Model>) -> Result<usize> {
+    let db = crate::storage::get_db_conn().await?;
+    let mut inserted = 0;
+    for mut m in messages {
+        if Entity::find_by_id(m.id.clone()).one(&db).await?.is_some() {
+            continue;
+        }
+        if m.status == MessageStatus::Sending {
+            m.status = MessageStatus::Failed;
+        }
+        m.receipt_pending = false;
+        let active: ActiveModel = m.into();
+        active.reset_all().insert(&db).await?;
+        inserted += 1;
+    }
+    Ok(inserted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::with_test_db;
+
+    #[test]
+    fn test_retry_backoff_secs() {
+        assert_eq!(retry_backoff_secs(1), 1);
+        assert_eq!(retry_backoff_secs(2), 2);
+        assert_eq!(retry_backoff_secs(4), 8);
+        assert_eq!(retry_backoff_secs(9), 256);
+        assert_eq!(retry_backoff_secs(10), 300);
+        assert_eq!(retry_backoff_secs(100), 300);
+    }
+
+    #[tokio::test]
+    async fn test_send_failure_until_failed_then_retry() -> Result<()> {
+        with_test_db(async {
+            let msg_id = uuid::Uuid::new_v4().to_string();
+            save_message(
+                msg_id.clone(),
+                "did:key:from".to_string(),
+                "did:key:to".to_string(),
+                "hello".to_string(),
+                crate::util::timestamp_now(),
+                MessageStatus::Sending,
+            )
+            .await?;
+
+            for _ in 1..MAX_SEND_ATTEMPTS {
+                assert_eq!(
+                    record_send_failure(&msg_id).await?,
+                    Some(MessageStatus::Sending)
+                );
+            }
+            assert_eq!(
+                record_send_failure(&msg_id).await?,
+                Some(MessageStatus::Failed)
+            );
+
+            assert!(reset_for_retry(&msg_id).await?);
+            assert!(!reset_for_retry(&msg_id).await?);
+
+            let db = crate::storage::get_db_conn().await?;
+            let m = Entity::find_by_id(msg_id.clone()).one(&db).await?.unwrap();
+            assert_eq!(m.status, MessageStatus::Sending);
+            assert_eq!(m.retry_count, 0);
+            assert_eq!(m.next_retry_at, 0);
+
+            Entity::delete_by_id(msg_id).exec(&db).await?;
+            Ok(())
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_mark_read_queues_receipt() -> Result<()> {
+        with_test_db(async {
+            let msg_id = uuid::Uuid::new_v4().to_string();
+            save_message(
+                msg_id.clone(),
+                "did:key:from".to_string(),
+                "did:key:me".to_string(),
+                "hello".to_string(),
+                crate::util::timestamp_now(),
+                MessageStatus::Delivered,
+            )
+            .await?;
+
+            assert!(!mark_read(&msg_id, "did:key:other").await?);
+            assert!(mark_read(&msg_id, "did:key:me").await?);
+
+            let db = crate::storage::get_db_conn().await?;
+            let m = Entity::find_by_id(msg_id.clone()).one(&db).await?.unwrap();
+            assert_eq!(m.status, MessageStatus::Read);
+            assert!(m.receipt_pending);
+
+            clear_receipt_pending(&msg_id).await?;
+            let m = Entity::find_by_id(msg_id.clone()).one(&db).await?.unwrap();
+            assert!(!m.receipt_pending);
+
+            Entity::delete_by_id(msg_id).exec(&db).await?;
+            Ok(())
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_export_import_skips_existing_ids() -> Result<()> {
+        with_test_db(async {
+            let peer = format!("did:key:{}", uuid::Uuid::new_v4());
+            let ids: Vec<String> = (0..2).map(|_| uuid::Uuid::new_v4().to_string()).collect();
+            save_message(
+                ids[0].clone(),
+                "did:key:me".to_string(),
+                peer.clone(),
+                "hi".to_string(),
+                crate::util::timestamp_now(),
+                MessageStatus::Sending,
+            )
+            .await?;
+            save_message(
+                ids[1].clone(),
+                peer.clone(),
+                "did:key:me".to_string(),
+                "hello".to_string(),
+                crate::util::timestamp_now(),
+                MessageStatus::Delivered,
+            )
+            .await?;
+
+            let exported = list_messages(Some(&peer)).await?;
+            assert_eq!(
+                exported.iter().map(|m| &m.id).collect::<Vec<_>>(),
+                vec![&ids[0], &ids[1]]
+            );
+            let json = serde_json::to_string(&exported)?;
+
+            // 已存在的消息全部跳过
+            let parsed: Vec<Model> = serde_json::from_str(&json)?;
+            assert_eq!(import_messages(parsed.clone()).await?, 0);
+
+            let db = crate::storage::get_db_conn().await?;
+            Entity::delete_by_id(ids[0].clone()).exec(&db).await?;
+            assert_eq!(import_messages(parsed).await?, 1);
+            let restored = Entity::find_by_id(ids[0].clone()).one(&db).await?.unwrap();
+            assert_eq!(restored.content, "hi");
+            assert_eq!(restored.created_at_ms, exported[0].created_at_ms);
+            assert_eq!(restored.status, MessageStatus::Failed);
+
+            for id in ids {
+                Entity::delete_by_id(id).exec(&db).await?;
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_messages_in_same_second_keep_order() -> Result<()> {
+        use sea_orm::QueryOrder;
+
+        with_test_db(async {
+            let created_at = crate::util::timestamp_now();
+            let ids: Vec<String> = (0..3).map(|_| uuid::Uuid::new_v4().to_string()).collect();
+            for id in &ids {
+                save_message(
+                    id.clone(),
+                    "did:key:from".to_string(),
+                    "did:key:to".to_string(),
+                    "hello".to_string(),
+                    created_at,
+                    MessageStatus::Sending,
+                )
+                .await?;
+            }
+
+            let db = crate::storage::get_db_conn().await?;
+            let ordered: Vec<String> = Entity::find()
+                .order_by_asc(Column::CreatedAtMs)
+                .all(&db)
+                .await?
+                .into_iter()
+                .map(|m| m.id)
+                .collect();
+            assert_eq!(ordered, ids);
+            Ok(())
+        })
+        .await
+    }
+}
@@ -0,0 +1,79 @@
+use anyhow::Result;
+use sea_orm::entity::prelude::*;
+use sea_orm::Set;
+
+use crate::storage::get_db_conn;
+
+/// 待发送的 bundle 拉取请求（由 CLI 写入，节点进程负责发送）
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "fetch_requests")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub repo_id: String,
+    /// 被请求的节点
+    pub peer_id: String,
+    pub requested_at: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+/// 记录一条拉取请求，同一仓库的旧请求会被覆盖
+pub async fn save_fetch_request(repo_id: &str, peer_id: &str) -> Result<()> {
+    let db = get_db_conn().await?;
+    let _ = Entity::delete_by_id(repo_id).exec(&db).await;
+
+    let active = ActiveModel {
+        repo_id: Set(repo_id.to_string()),
+        peer_id: Set(peer_id.to_string()),
+        requested_at: Set(chrono::Local::now().timestamp()),
+    };
+    Entity::insert(active).exec(&db).await?;
+    Ok(())
+}
+
+/// 列出所有待发送的拉取请求
+pub async fn list_fetch_requests() -> Result<Vec<Model>> {
+    let db = get_db_conn().await?;
+    Ok(Entity::find().all(&db).await?)
+}
+
+/// 删除已发送的拉取请求
+pub async fn delete_fetch_request(repo_id: &str) -> Result<()> {
+    let db = get_db_conn().await?;
+    Entity::delete_by_id(repo_id).exec(&db).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::with_test_db;
+
+    #[tokio::test]
+    async fn test_fetch_request_replaces_previous_peer() -> Result<()> {
+        with_test_db(async {
+            let repo_id = "did:repo:fetch-request-test";
+            save_fetch_request(repo_id, "did:key:first").await?;
+            save_fetch_request(repo_id, "did:key:second").await?;
+
+            let pending: Vec<Model> = list_fetch_requests()
+                .await?
+                .into_iter()
+                .filter(|r| r.repo_id == repo_id)
+                .collect();
+            assert_eq!(pending.len(), 1);
+            assert_eq!(pending[0].peer_id, "did:key:second");
+
+            delete_fetch_request(repo_id).await?;
+            assert!(list_fetch_requests()
+                .await?
+                .iter()
+                .all(|r| r.repo_id != repo_id));
+            Ok(())
+        })
+        .await
+    }
+}
@@ -1,11 +1,18 @@
+pub mod channel;
 pub mod chat_message;
+pub mod fetch_request;
 pub mod node_model;
+pub mod pending_relay;
 pub mod ref_model;
+pub mod repo_access;
 pub mod repo_model;
+pub mod repo_tombstone;
+pub mod seen_message;
 
 use anyhow::{anyhow, Result};
 use sea_orm::{
     ConnectOptions, ConnectionTrait, Database, DatabaseConnection, DbBackend, Statement,
+    TransactionTrait,
 };
 use std::fs;
 use std::path::PathBuf;
@@ -13,40 +20,61 @@ use std::time::Duration;
 use tokio::sync::OnceCell;
 
 use crate::identity::keypair::KeyPair;
+use crate::identity::keystore::EncryptedKeyPair;
 
 /// 默认根目录：`~/.megaengine`，可由 `MEGAENGINE_ROOT` 环境变量覆盖
+///
+/// 解析规则见 [`crate::config::resolve_data_dir`]，CLI 会把 `--root` 写入该环境变量
 pub fn data_dir() -> PathBuf {
-    if let Some(dir) = std::env::var_os("MEGAENGINE_ROOT") {
-        return PathBuf::from(dir);
-    }
-
-    if let Some(home) = std::env::var_os("HOME") {
-        let mut p = PathBuf::from(home);
-        p.push(".megaengine");
-        return p;
-    }
-
-    // Windows fallback
-    if let Some(profile) = std::env::var_os("USERPROFILE") {
-        let mut p = PathBuf::from(profile);
-        p.push(".megaengine");
-        return p;
-    }
-
-    // As a last resort fall back to cwd/.megaengine
-    let mut p = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
-    p.push(".megaengine");
-    p
+    crate::config::resolve_data_dir(None)
 }
 
 /// keypair 存放到根目录下
-pub fn keypair_path() -> PathBuf {
+///
+/// 默认身份为 `keypair.json`，命名身份为 `keypair-<profile>.json`
+pub fn keypair_path(profile: Option<&str>) -> PathBuf {
     let mut p = data_dir();
     fs::create_dir_all(&p).ok();
-    p.push("keypair.json");
+    match profile {
+        Some(name) => p.push(format!("keypair-{}.json", name)),
+        None => p.push("keypair.json"),
+    }
     p
 }
 
+/// 校验身份名称：只允许字母、数字、`-` 和 `_`
+pub fn validate_profile_name(name: &str) -> Result<()> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(anyhow::anyhow!(
+            "Invalid profile name '{}': use letters, digits, '-' or '_'",
+            name
+        ));
+    }
+    Ok(())
+}
+
+/// 列出根目录下所有可用的身份，默认身份记为 `default`
+pub fn list_profiles() -> Result<Vec<String>> {
+    let mut profiles = Vec::new();
+    for entry in fs::read_dir(data_dir())? {
+        let name = entry?.file_name().to_string_lossy().to_string();
+        if name == "keypair.json" {
+            profiles.push("default".to_string());
+        } else if let Some(profile) = name
+            .strip_prefix("keypair-")
+            .and_then(|n| n.strip_suffix(".json"))
+        {
+            profiles.push(profile.to_string());
+        }
+    }
+    profiles.sort();
+    Ok(profiles)
+}
+
 /// 证书路径（默认放到根目录）
 pub fn cert_path() -> PathBuf {
     let mut p = data_dir();
@@ -69,6 +97,14 @@ pub fn ca_cert_path() -> PathBuf {
     p
 }
 
+/// 运行中节点定期写出的指标快照路径（供 `node stats` 读取）
+pub fn metrics_path() -> PathBuf {
+    let mut p = data_dir();
+    fs::create_dir_all(&p).ok();
+    p.push("metrics.json");
+    p
+}
+
 /// SQLite DB 路径
 pub fn db_path() -> PathBuf {
     let mut p = data_dir();
@@ -142,6 +178,11 @@ async fn migrate_repos_table(db: &DatabaseConnection) -> Result<()> {
         "ALTER TABLE repos ADD COLUMN latest_commit_at INTEGER NOT NULL DEFAULT 0",
     )
     .await?;
+    execute_sql_ignore_duplicate_column(
+        db,
+        "ALTER TABLE repos ADD COLUMN tags TEXT NOT NULL DEFAULT '[]'",
+    )
+    .await?;
 
     if repos_table_needs_rebuild(db).await? {
         rebuild_repos_table(db).await?;
@@ -150,6 +191,96 @@ async fn migrate_repos_table(db: &DatabaseConnection) -> Result<()> {
     Ok(())
 }
 
+async fn migrate_repo_history_columns(db: &DatabaseConnection) -> Result<()> {
+    execute_sql_ignore_duplicate_column(
+        db,
+        "ALTER TABLE repos ADD COLUMN commit_count INTEGER NOT NULL DEFAULT 0",
+    )
+    .await?;
+    execute_sql_ignore_duplicate_column(
+        db,
+        "ALTER TABLE repos ADD COLUMN contributors INTEGER NOT NULL DEFAULT 0",
+    )
+    .await
+}
+
+async fn migrate_repo_signature(db: &DatabaseConnection) -> Result<()> {
+    execute_sql_ignore_duplicate_column(
+        db,
+        "ALTER TABLE repos ADD COLUMN signature TEXT NOT NULL DEFAULT ''",
+    )
+    .await
+}
+
+async fn migrate_repo_root_commit(db: &DatabaseConnection) -> Result<()> {
+    execute_sql_ignore_duplicate_column(
+        db,
+        "ALTER TABLE repos ADD COLUMN root_commit TEXT NOT NULL DEFAULT ''",
+    )
+    .await?;
+    execute_sql_ignore_duplicate_column(
+        db,
+        "ALTER TABLE repos ADD COLUMN verified BOOLEAN NOT NULL DEFAULT 0",
+    )
+    .await
+}
+
+async fn migrate_nodes_left_at(db: &DatabaseConnection) -> Result<()> {
+    execute_sql_ignore_duplicate_column(
+        db,
+        "ALTER TABLE nodes ADD COLUMN left_at INTEGER NOT NULL DEFAULT 0",
+    )
+    .await
+}
+
+async fn create_repo_access_table(db: &DatabaseConnection) -> Result<()> {
+    db.execute_unprepared(
+        "CREATE TABLE IF NOT EXISTS repo_access (
+            repo_id TEXT NOT NULL,
+            node_id TEXT NOT NULL,
+            created_at INTEGER NOT NULL,
+            PRIMARY KEY (repo_id, node_id)
+        )",
+    )
+    .await?;
+    Ok(())
+}
+
+async fn migrate_chat_messages_table(db: &DatabaseConnection) -> Result<()> {
+    execute_sql_ignore_duplicate_column(
+        db,
+        "ALTER TABLE chat_messages ADD COLUMN retry_count INTEGER NOT NULL DEFAULT 0",
+    )
+    .await?;
+    execute_sql_ignore_duplicate_column(
+        db,
+        "ALTER TABLE chat_messages ADD COLUMN next_retry_at INTEGER NOT NULL DEFAULT 0",
+    )
+    .await?;
+    execute_sql_ignore_duplicate_column(
+        db,
+        "ALTER TABLE chat_messages ADD COLUMN receipt_pending INTEGER NOT NULL DEFAULT 0",
+    )
+    .await?;
+    execute_sql_ignore_duplicate_column(db, "ALTER TABLE chat_messages ADD COLUMN channel_id TEXT")
+        .await?;
+    Ok(())
+}
+
+/// 聊天消息的毫秒排序键，已有消息按秒级时间戳补齐
+async fn migrate_chat_messages_created_at_ms(db: &DatabaseConnection) -> Result<()> {
+    execute_sql_ignore_duplicate_column(
+        db,
+        "ALTER TABLE chat_messages ADD COLUMN created_at_ms INTEGER NOT NULL DEFAULT 0",
+    )
+    .await?;
+    db.execute_unprepared(
+        "UPDATE chat_messages SET created_at_ms = created_at * 1000 WHERE created_at_ms = 0",
+    )
+    .await?;
+    Ok(())
+}
+
 async fn repos_table_needs_rebuild(db: &DatabaseConnection) -> Result<bool> {
     // Legacy schema had a `timestamp` column that can block inserts now that
     // repo writes no longer set it. Rebuild to canonical schema when present.
@@ -160,6 +291,7 @@ async fn rebuild_repos_table(db: &DatabaseConnection) -> Result<()> {
     let has_language = sqlite_has_column(db, "repos", "language").await?;
     let has_size = sqlite_has_column(db, "repos", "size").await?;
     let has_latest_commit_at = sqlite_has_column(db, "repos", "latest_commit_at").await?;
+    let has_tags = sqlite_has_column(db, "repos", "tags").await?;
     let has_bundle = sqlite_has_column(db, "repos", "bundle").await?;
     let has_is_external = sqlite_has_column(db, "repos", "is_external").await?;
     let has_created_at = sqlite_has_column(db, "repos", "created_at").await?;
@@ -176,12 +308,18 @@ async fn rebuild_repos_table(db: &DatabaseConnection) -> Result<()> {
 
     let size_expr = if has_size { "COALESCE(size, 0)" } else { "0" };
 
-    let latest_commit_expr = if has_latest_commit_at {
-        "COALESCE(latest_commit_at, 0)"
-    } else if has_timestamp {
-        "COALESCE(timestamp, 0)"
+    // migrate_repos_table 会先以默认值 0 补上 latest_commit_at，此时应回退到旧的 timestamp
+    let latest_commit_expr = match (has_latest_commit_at, has_timestamp) {
+        (true, true) => "COALESCE(NULLIF(latest_commit_at, 0), timestamp, 0)",
+        (true, false) => "COALESCE(latest_commit_at, 0)",
+        (false, true) => "COALESCE(timestamp, 0)",
+        (false, false) => "0",
+    };
+
+    let tags_expr = if has_tags {
+        "COALESCE(tags, '[]')"
     } else {
-        "0"
+        "'[]'"
     };
 
     let bundle_expr = if has_bundle {
@@ -214,57 +352,36 @@ async fn rebuild_repos_table(db: &DatabaseConnection) -> Result<()> {
         now_expr.to_string()
     };
 
-    let create_sql = "\
-        CREATE TABLE repos_new (\
-            id TEXT PRIMARY KEY,\
-            name TEXT NOT NULL,\
-            creator TEXT NOT NULL,\
-            description TEXT NOT NULL,\
-            language TEXT NOT NULL DEFAULT '',\
-            size INTEGER NOT NULL DEFAULT 0,\
-            latest_commit_at INTEGER NOT NULL DEFAULT 0,\
-            path TEXT NOT NULL,\
-            bundle TEXT NOT NULL DEFAULT '',\
-            is_external INTEGER NOT NULL DEFAULT 0,\
-            created_at INTEGER NOT NULL,\
-            updated_at INTEGER NOT NULL\
+    let create_sql = "CREATE TABLE repos_new (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            creator TEXT NOT NULL,
+            description TEXT NOT NULL,
+            language TEXT NOT NULL DEFAULT '',
+            size INTEGER NOT NULL DEFAULT 0,
+            latest_commit_at INTEGER NOT NULL DEFAULT 0,
+            tags TEXT NOT NULL DEFAULT '[]',
+            path TEXT NOT NULL,
+            bundle TEXT NOT NULL DEFAULT '',
+            is_external INTEGER NOT NULL DEFAULT 0,
+            created_at INTEGER NOT NULL,
+            updated_at INTEGER NOT NULL
         );";
 
     let insert_sql = format!(
-        "\
-        INSERT OR REPLACE INTO repos_new (\
-            id,\
-            name,\
-            creator,\
-            description,\
-            language,\
-            size,\
-            latest_commit_at,\
-            path,\
-            bundle,\
-            is_external,\
-            created_at,\
-            updated_at\
-        )\
-        SELECT\
-            id,\
-            name,\
-            creator,\
-            description,\
-            {language_expr},\
-            {size_expr},\
-            {latest_commit_expr},\
-            path,\
-            {bundle_expr},\
-            {is_external_expr},\
-            {created_expr},\
-            {updated_expr}\
-        FROM repos\
-        WHERE id IS NOT NULL\
-          AND name IS NOT NULL\
-          AND creator IS NOT NULL\
-          AND description IS NOT NULL\
-          AND path IS NOT NULL;"
+        "INSERT OR REPLACE INTO repos_new (
+            id, name, creator, description, language, size, latest_commit_at,
+            tags, path, bundle, is_external, created_at, updated_at
+         )
+         SELECT
+            id, name, creator, description, {language_expr}, {size_expr}, {latest_commit_expr},
+            {tags_expr}, path, {bundle_expr}, {is_external_expr}, {created_expr}, {updated_expr}
+         FROM repos
+         WHERE id IS NOT NULL
+           AND name IS NOT NULL
+           AND creator IS NOT NULL
+           AND description IS NOT NULL
+           AND path IS NOT NULL;"
     );
 
     let drop_sql = "DROP TABLE repos;";
@@ -361,6 +478,18 @@ async fn rebuild_refs_table(db: &DatabaseConnection) -> Result<()> {
     Ok(())
 }
 
+async fn migrate_nodes_table(db: &DatabaseConnection) -> Result<()> {
+    if sqlite_has_column(db, "nodes", "last_seen").await? {
+        return Ok(());
+    }
+    db.execute_unprepared("ALTER TABLE nodes ADD COLUMN last_seen INTEGER NOT NULL DEFAULT 0")
+        .await?;
+    // 旧版本每次收到节点公告都会重写整行，updated_at 即最后一次收到公告的时间
+    db.execute_unprepared("UPDATE nodes SET last_seen = updated_at")
+        .await?;
+    Ok(())
+}
+
 async fn migrate_refs_table(db: &DatabaseConnection) -> Result<()> {
     if !refs_table_needs_rebuild(db).await? {
         return Ok(());
@@ -368,7 +497,110 @@ async fn migrate_refs_table(db: &DatabaseConnection) -> Result<()> {
     rebuild_refs_table(db).await
 }
 
+/// 按顺序执行的数据库迁移，第 i 项执行完后 schema 版本为 i + 1
+///
+/// 只能在末尾追加新迁移；在写入版本号之前中断的迁移会在下次启动时重新执行，
+/// 所以每个迁移都必须可以重复执行。引入版本表之前的数据库从版本 0 开始升级。
+const MIGRATIONS: &[&str] = &[
+    "create base tables",
+    "add repo description columns and drop legacy repos.timestamp",
+    "rebuild refs with (repo_id, ref_name) primary key",
+    "add chat message retry and channel columns",
+    "add nodes.last_seen",
+    "index repos.creator and repos.path",
+    "add repos.commit_count and repos.contributors",
+    "add nodes.left_at",
+    "create repo_access table",
+    "add repos.signature",
+    "add repos.root_commit and repos.verified",
+    "add chat_messages.created_at_ms",
+];
+
+/// 当前代码对应的数据库 schema 版本
+pub const SCHEMA_VERSION: i64 = MIGRATIONS.len() as i64;
+
+async fn apply_migration(db: &DatabaseConnection, version: i64) -> Result<()> {
+    match version {
+        1 => create_base_tables(db).await,
+        2 => migrate_repos_table(db).await,
+        3 => {
+            migrate_refs_table(db).await?;
+            // Align old refs rows that may have default timestamps after ALTER/rebuild.
+            db.execute_unprepared(
+                "UPDATE refs
+                 SET created_at = updated_at
+                 WHERE created_at = 0 AND updated_at > 0",
+            )
+            .await?;
+            Ok(())
+        }
+        4 => migrate_chat_messages_table(db).await,
+        5 => migrate_nodes_table(db).await,
+        // refs 的主键 (repo_id, ref_name) 已可用于按 repo_id 查询，无需单独建索引
+        6 => {
+            db.execute_unprepared("CREATE INDEX IF NOT EXISTS idx_repos_creator ON repos(creator)")
+                .await?;
+            db.execute_unprepared("CREATE INDEX IF NOT EXISTS idx_repos_path ON repos(path)")
+                .await?;
+            Ok(())
+        }
+        7 => migrate_repo_history_columns(db).await,
+        8 => migrate_nodes_left_at(db).await,
+        9 => create_repo_access_table(db).await,
+        10 => migrate_repo_signature(db).await,
+        11 => migrate_repo_root_commit(db).await,
+        12 => migrate_chat_messages_created_at_ms(db).await,
+        _ => Err(anyhow!("unknown schema migration {}", version)),
+    }
+}
+
+/// 读取数据库当前的 schema 版本，没有任何迁移记录时为 0
+pub async fn schema_version(db: &DatabaseConnection) -> Result<i64> {
+    db.execute_unprepared(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            applied_at INTEGER NOT NULL
+        )",
+    )
+    .await?;
+    sqlite_query_one_i64(
+        db,
+        "SELECT COALESCE(MAX(version), 0) FROM schema_version".to_string(),
+    )
+    .await
+}
+
+/// 依次执行尚未应用的迁移，并在 schema_version 表中记录每个迁移
 async fn ensure_schema(db: &DatabaseConnection) -> Result<()> {
+    let current = schema_version(db).await?;
+    if current > SCHEMA_VERSION {
+        return Err(anyhow!(
+            "database schema version {} is newer than the supported version {}",
+            current,
+            SCHEMA_VERSION
+        ));
+    }
+
+    for version in (current + 1)..=SCHEMA_VERSION {
+        let name = MIGRATIONS[(version - 1) as usize];
+        apply_migration(db, version)
+            .await
+            .map_err(|e| anyhow!("schema migration {} ({}) failed: {}", version, name, e))?;
+        db.execute(Statement::from_sql_and_values(
+            DbBackend::Sqlite,
+            "INSERT INTO schema_version (version, name, applied_at)
+             VALUES (?, ?, CAST(strftime('%s','now') AS INTEGER))",
+            [version.into(), name.into()],
+        ))
+        .await?;
+        tracing::info!("Applied schema migration {}: {}", version, name);
+    }
+
+    Ok(())
+}
+
+async fn create_base_tables(db: &DatabaseConnection) -> Result<()> {
     db.execute_unprepared(
         "CREATE TABLE IF NOT EXISTS repos (
             id TEXT PRIMARY KEY,
@@ -378,6 +610,7 @@ async fn ensure_schema(db: &DatabaseConnection) -> Result<()> {
             language TEXT NOT NULL DEFAULT '',
             size INTEGER NOT NULL DEFAULT 0,
             latest_commit_at INTEGER NOT NULL DEFAULT 0,
+            tags TEXT NOT NULL DEFAULT '[]',
             path TEXT NOT NULL,
             bundle TEXT NOT NULL DEFAULT '',
             is_external INTEGER NOT NULL DEFAULT 0,
@@ -395,7 +628,8 @@ async fn ensure_schema(db: &DatabaseConnection) -> Result<()> {
             node_type INTEGER NOT NULL,
             version INTEGER NOT NULL,
             created_at INTEGER NOT NULL,
-            updated_at INTEGER NOT NULL
+            updated_at INTEGER NOT NULL,
+            last_seen INTEGER NOT NULL DEFAULT 0
         )",
     )
     .await?;
@@ -419,96 +653,291 @@ async fn ensure_schema(db: &DatabaseConnection) -> Result<()> {
             \"to\" TEXT NOT NULL,
             content TEXT NOT NULL,
             created_at INTEGER NOT NULL,
-            status TEXT NOT NULL
+            status TEXT NOT NULL,
+            retry_count INTEGER NOT NULL DEFAULT 0,
+            next_retry_at INTEGER NOT NULL DEFAULT 0,
+            receipt_pending INTEGER NOT NULL DEFAULT 0,
+            channel_id TEXT
+        )",
+    )
+    .await?;
+
+    db.execute_unprepared(
+        "CREATE TABLE IF NOT EXISTS channels (
+            channel_id TEXT PRIMARY KEY,
+            key TEXT NOT NULL,
+            joined_at INTEGER NOT NULL
+        )",
+    )
+    .await?;
+
+    db.execute_unprepared(
+        "CREATE TABLE IF NOT EXISTS repo_tombstones (
+            repo_id TEXT PRIMARY KEY,
+            creator TEXT NOT NULL,
+            message TEXT NOT NULL,
+            created_at INTEGER NOT NULL
+        )",
+    )
+    .await?;
+
+    db.execute_unprepared(
+        "CREATE TABLE IF NOT EXISTS pending_relay (
+            msg_id TEXT PRIMARY KEY,
+            receiver_id TEXT NOT NULL,
+            message TEXT NOT NULL,
+            created_at INTEGER NOT NULL,
+            expires_at INTEGER NOT NULL
         )",
     )
     .await?;
 
-    migrate_repos_table(db).await?;
-    migrate_refs_table(db).await?;
+    db.execute_unprepared(
+        "CREATE TABLE IF NOT EXISTS fetch_requests (
+            repo_id TEXT PRIMARY KEY,
+            peer_id TEXT NOT NULL,
+            requested_at INTEGER NOT NULL
+        )",
+    )
+    .await?;
 
-    // Align old refs rows that may have default timestamps after ALTER/rebuild.
     db.execute_unprepared(
-        "UPDATE refs
-         SET created_at = updated_at
-         WHERE created_at = 0 AND updated_at > 0",
+        "CREATE TABLE IF NOT EXISTS seen (
+            node_id TEXT NOT NULL,
+            msg_id TEXT NOT NULL,
+            ts INTEGER NOT NULL,
+            PRIMARY KEY (node_id, msg_id)
+        )",
     )
     .await?;
 
     Ok(())
 }
 
-/// 初始化数据库连接并创建表
+/// 当前 schema 下应存在的数据表
+const EXPECTED_TABLES: &[&str] = &[
+    "schema_version",
+    "repos",
+    "nodes",
+    "refs",
+    "chat_messages",
+    "channels",
+    "repo_tombstones",
+    "pending_relay",
+    "fetch_requests",
+    "seen",
+    "repo_access",
+];
+
+/// 数据库的检查结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DatabaseStatus {
+    /// 已应用的 schema 版本
+    pub schema_version: i64,
+    /// 缺失的数据表
+    pub missing_tables: Vec<String>,
+}
+
+/// 以只读方式打开数据库，检查 schema 版本和数据表，不执行迁移
+pub async fn inspect_database(path: &std::path::Path) -> Result<DatabaseStatus> {
+    let mut opt = ConnectOptions::new(format!("sqlite://{}?mode=ro", path.display()));
+    opt.max_connections(1)
+        .connect_timeout(Duration::from_secs(8))
+        .sqlx_logging(false);
+    let db = Database::connect(opt).await?;
+
+    let mut missing_tables = Vec::new();
+    for table in EXPECTED_TABLES {
+        let sql = format!(
+            "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = '{}'",
+            table
+        );
+        if sqlite_query_one_i64(&db, sql).await? == 0 {
+            missing_tables.push(table.to_string());
+        }
+    }
+    let schema_version = if missing_tables.iter().any(|t| t == "schema_version") {
+        0
+    } else {
+        sqlite_query_one_i64(
+            &db,
+            "SELECT COALESCE(MAX(version), 0) FROM schema_version".to_string(),
+        )
+        .await?
+    };
+    db.close().await?;
+
+    Ok(DatabaseStatus {
+        schema_version,
+        missing_tables,
+    })
+}
+
+/// 覆盖数据库连接 URL 的环境变量，例如 `sqlite::memory:`
+pub const DB_URL_ENV: &str = "MEGAENGINE_DB_URL";
+
+#[cfg(test)]
+tokio::task_local! {
+    /// 测试用的独立数据库，设置后 get_db_conn 绕过全局缓存直接返回它
+    static TEST_DB: DatabaseConnection;
+}
+
+/// 数据库连接 URL：优先使用 `MEGAENGINE_DB_URL`，否则为数据目录下的 `megaengine.db`
+pub fn db_url() -> String {
+    match std::env::var(DB_URL_ENV) {
+        Ok(url) if !url.is_empty() => url,
+        _ => format!("sqlite://{}?mode=rwc", db_path().display()),
+    }
+}
+
+/// 连接数据库并执行 schema 迁移
+async fn open_db(url: &str) -> Result<DatabaseConnection> {
+    let mut opt = ConnectOptions::new(url.to_string());
+    opt.max_connections(8)
+        .min_connections(1)
+        .connect_timeout(Duration::from_secs(8))
+        .sqlx_logging(false);
+    // 内存数据库在最后一个连接关闭时销毁，不能回收空闲连接
+    if !(url.contains(":memory:") || url.contains("mode=memory")) {
+        opt.idle_timeout(Duration::from_secs(8));
+    }
+
+    let db = Database::connect(opt).await?;
+
+    // 运行迁移/建表，兼容已有数据库结构升级
+    ensure_schema(&db).await?;
+    Ok(db)
+}
+
+/// 获取数据库连接，库内代码访问数据库的唯一入口
+///
+/// 每个连接 URL 只初始化一次：第一次调用时连接并执行 schema 迁移，
+/// 启动时多个任务并发调用会在 `OnceCell::get_or_try_init` 上等待同一次初始化，
+/// 初始化失败时下一次调用会重试。
 pub async fn get_db_conn() -> Result<DatabaseConnection> {
+    #[cfg(test)]
+    if let Ok(db) = TEST_DB.try_with(|db| db.clone()) {
+        return Ok(db);
+    }
+
+    cached_db_conn(db_url()).await
+}
+
+/// 按连接 URL 缓存的数据库连接；连接或迁移失败时返回错误，不缓存失败结果
+async fn cached_db_conn(url: String) -> Result<DatabaseConnection> {
     use std::collections::HashMap;
     use std::sync::Arc;
     use tokio::sync::Mutex;
 
-    static DB_POOL: OnceCell<Mutex<HashMap<PathBuf, Arc<OnceCell<DatabaseConnection>>>>> =
+    static DB_POOL: OnceCell<Mutex<HashMap<String, Arc<OnceCell<DatabaseConnection>>>>> =
         OnceCell::const_new();
 
     let pool = DB_POOL
         .get_or_init(|| async { Mutex::new(HashMap::new()) })
         .await;
 
-    let path = db_path();
-
-    // 为每个数据库路径维护一个独立的初始化单元，确保每个路径的初始化只执行一次
+    // 为每个连接 URL 维护一个独立的初始化单元，确保每个数据库的初始化只执行一次
     let cell = {
         let mut map = pool.lock().await;
-        map.entry(path.clone())
+        map.entry(url.clone())
             .or_insert_with(|| Arc::new(OnceCell::const_new()))
             .clone()
     };
 
     // 延迟初始化并缓存全局连接（仅第一次会执行创建表操作）
     let db = cell
-        .get_or_try_init(|| {
-            let db_path = path.clone();
-            async move {
-                // 确保目录存在
-                if let Some(parent) = db_path.parent() {
-                    fs::create_dir_all(parent).ok();
-                }
-
-                // 使用合适的 SQLite URL 格式
-                let db_url = format!("sqlite://{}?mode=rwc", db_path.display());
-
-                let mut opt = ConnectOptions::new(db_url);
-                opt.max_connections(8)
-                    .min_connections(1)
-                    .connect_timeout(Duration::from_secs(8))
-                    .idle_timeout(Duration::from_secs(8))
-                    .sqlx_logging(false);
-
-                let db = Database::connect(opt).await?;
-
-                // 运行迁移/建表，兼容已有数据库结构升级
-                ensure_schema(&db).await?;
-
-                Ok::<DatabaseConnection, anyhow::Error>(db)
-            }
-        })
-        .await?
+        .get_or_try_init(|| open_db(&url))
+        .await
+        .map_err(|e| anyhow!("failed to open database {}: {:#}", url, e))?
         .clone();
 
     Ok(db)
 }
 
+/// 在独立的内存数据库中运行 `f`，其中调用的 get_db_conn 都返回该数据库
+///
+/// 只对当前任务生效，`tokio::spawn` 出的任务仍使用全局数据库
+#[cfg(test)]
+pub(crate) async fn with_test_db<F: std::future::Future>(f: F) -> F::Output {
+    let db = open_db("sqlite::memory:")
+        .await
+        .expect("open in-memory test database");
+    TEST_DB.scope(db, f).await
+}
+
+/// 与 `with_test_db` 相同，`f` 收到一个计数器，记录该数据库上已执行的 SQL 语句数
+#[cfg(test)]
+pub(crate) async fn with_counted_test_db<F, Fut>(f: F) -> Fut::Output
+where
+    F: FnOnce(std::sync::Arc<std::sync::atomic::AtomicUsize>) -> Fut,
+    Fut: std::future::Future,
+{
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let mut db = open_db("sqlite::memory:")
+        .await
+        .expect("open in-memory test database");
+    let queries = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&queries);
+    db.set_metric_callback(move |_| {
+        counter.fetch_add(1, Ordering::Relaxed);
+    });
+    TEST_DB.scope(db, f(queries)).await
+}
+
 /// 保存密钥对到文件（JSON）
-pub fn save_keypair(kp: &KeyPair) -> Result<()> {
+pub fn save_keypair(kp: &KeyPair, profile: Option<&str>) -> Result<()> {
     let dir = data_dir();
     fs::create_dir_all(&dir)?;
-    let path = keypair_path();
+    let path = keypair_path(profile);
     let s = serde_json::to_string_pretty(kp)?;
     fs::write(path, s)?;
     Ok(())
 }
 
+/// 口令环境变量，设置后加载加密密钥时不再交互式提示
+pub const PASSPHRASE_ENV: &str = "MEGAENGINE_PASSPHRASE";
+
+/// 使用口令加密保存密钥对（Argon2 + ChaCha20-Poly1305）
+pub fn save_keypair_encrypted(kp: &KeyPair, passphrase: &str, profile: Option<&str>) -> Result<()> {
+    save_sealed_keypair(&EncryptedKeyPair::seal(kp, passphrase)?, profile)
+}
+
+/// 保存已加密的密钥信封（例如导入时原样安装）
+pub fn save_sealed_keypair(sealed: &EncryptedKeyPair, profile: Option<&str>) -> Result<()> {
+    let dir = data_dir();
+    fs::create_dir_all(&dir)?;
+    let path = keypair_path(profile);
+    let s = serde_json::to_string_pretty(sealed)?;
+    fs::write(path, s)?;
+    Ok(())
+}
+
+/// 读取密钥文件中的加密信封；明文格式返回 None
+pub fn load_sealed_keypair(profile: Option<&str>) -> Result<Option<EncryptedKeyPair>> {
+    let s = fs::read_to_string(keypair_path(profile))?;
+    Ok(serde_json::from_str::<EncryptedKeyPair>(&s).ok())
+}
+
+/// 读取口令：优先使用 `MEGAENGINE_PASSPHRASE`，否则在终端提示输入
+pub fn read_passphrase() -> Result<String> {
+    match std::env::var(PASSPHRASE_ENV) {
+        Ok(p) => Ok(p),
+        Err(_) => Ok(rpassword::prompt_password("Keypair passphrase: ")?),
+    }
+}
+
 /// 从文件加载密钥对
-pub fn load_keypair() -> Result<KeyPair> {
-    let path = keypair_path();
+///
+/// 自动识别加密格式：口令优先读取 `MEGAENGINE_PASSPHRASE`，否则在终端提示输入。
+/// 明文格式保持兼容。
+pub fn load_keypair(profile: Option<&str>) -> Result<KeyPair> {
+    let path = keypair_path(profile);
     let s = fs::read_to_string(path)?;
+    if let Ok(sealed) = serde_json::from_str::<EncryptedKeyPair>(&s) {
+        return sealed.open(&read_passphrase()?);
+    }
     let kp: KeyPair = serde_json::from_str(&s)?;
     Ok(kp)
 }
@@ -518,6 +947,264 @@ mod tests {
 
     use super::*;
 
+    #[tokio::test]
+    async fn test_inspect_database() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("megaengine-db-{}.db", uuid::Uuid::new_v4()));
+        let db = Database::connect(format!("sqlite://{}?mode=rwc", path.display())).await?;
+        db.execute_unprepared("CREATE TABLE repos (id TEXT PRIMARY KEY)")
+            .await?;
+
+        let status = inspect_database(&path).await?;
+        assert_eq!(status.schema_version, 0);
+        assert!(status.missing_tables.contains(&"nodes".to_string()));
+        assert!(!status.missing_tables.contains(&"repos".to_string()));
+
+        db.execute_unprepared("DROP TABLE repos").await?;
+        ensure_schema(&db).await?;
+        let status = inspect_database(&path).await?;
+        assert_eq!(status.schema_version, SCHEMA_VERSION);
+        assert!(status.missing_tables.is_empty());
+
+        db.close().await?;
+        fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_migrate_legacy_repos_table() -> Result<()> {
+        use sea_orm::EntityTrait;
+
+        let db = Database::connect("sqlite::memory:").await?;
+        // 早期版本的 repos 表：只有 creator/name/description/timestamp
+        db.execute_unprepared(
+            "CREATE TABLE repos (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                creator TEXT NOT NULL,
+                description TEXT NOT NULL,
+                path TEXT NOT NULL,
+                timestamp INTEGER NOT NULL
+            )",
+        )
+        .await?;
+        db.execute_unprepared(
+            "INSERT INTO repos (id, name, creator, description, path, timestamp)
+             VALUES ('did:repo:legacy', 'legacy', 'did:key:legacy', 'old repo', '/tmp/legacy', 1234)",
+        )
+        .await?;
+
+        migrate_repos_table(&db).await?;
+        migrate_repo_history_columns(&db).await?;
+        migrate_repo_signature(&db).await?;
+        migrate_repo_root_commit(&db).await?;
+
+        assert!(!sqlite_has_column(&db, "repos", "timestamp").await?);
+        // 迁移后的表必须能被当前的 Model 完整读取
+        let model = repo_model::Entity::find_by_id("did:repo:legacy")
+            .one(&db)
+            .await?
+            .expect("legacy row kept");
+        assert_eq!(model.name, "legacy");
+        assert_eq!(model.language, "");
+        assert_eq!(model.size, 0);
+        assert_eq!(model.latest_commit_at, 1234);
+        assert_eq!(model.tags, "[]");
+        assert_eq!((model.commit_count, model.contributors), (0, 0));
+        assert_eq!(model.bundle, "");
+        assert!(!model.is_external);
+        assert_eq!(model.created_at, 1234);
+        assert_eq!(model.signature, "");
+        assert_eq!(model.root_commit, "");
+        assert!(!model.verified);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_migrate_old_schema_forward() -> Result<()> {
+        use sea_orm::EntityTrait;
+
+        let db = Database::connect("sqlite::memory:").await?;
+        // 引入版本表之前的数据库：chat_messages 没有重试列，nodes 没有 last_seen，refs 没有复合主键
+        db.execute_unprepared(
+            "CREATE TABLE chat_messages (
+                id TEXT PRIMARY KEY,
+                \"from\" TEXT NOT NULL,
+                \"to\" TEXT NOT NULL,
+                content TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                status TEXT NOT NULL
+            )",
+        )
+        .await?;
+        db.execute_unprepared(
+            "CREATE TABLE nodes (
+                id TEXT PRIMARY KEY,
+                alias TEXT NOT NULL,
+                addresses TEXT NOT NULL,
+                node_type INTEGER NOT NULL,
+                version INTEGER NOT NULL,
+                created_at INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL
+            )",
+        )
+        .await?;
+        db.execute_unprepared(
+            "CREATE TABLE refs (
+                repo_id TEXT NOT NULL,
+                ref_name TEXT NOT NULL,
+                commit_hash TEXT NOT NULL,
+                updated_at INTEGER NOT NULL
+            )",
+        )
+        .await?;
+        db.execute_unprepared(
+            "INSERT INTO chat_messages (id, \"from\", \"to\", content, created_at, status)
+             VALUES ('msg-1', 'did:key:a', 'did:key:b', 'hi', 10, 'Sent')",
+        )
+        .await?;
+        db.execute_unprepared(
+            "INSERT INTO nodes (id, alias, addresses, node_type, version, created_at, updated_at)
+             VALUES ('did:key:a', 'a', '[]', 0, 1, 10, 20)",
+        )
+        .await?;
+        db.execute_unprepared(
+            "INSERT INTO refs (repo_id, ref_name, commit_hash, updated_at)
+             VALUES ('did:repo:x', 'refs/heads/main', 'abc', 30)",
+        )
+        .await?;
+        assert_eq!(schema_version(&db).await?, 0);
+
+        ensure_schema(&db).await?;
+        assert_eq!(schema_version(&db).await?, SCHEMA_VERSION);
+
+        let message = chat_message::Entity::find_by_id("msg-1")
+            .one(&db)
+            .await?
+            .expect("message kept");
+        assert_eq!(message.retry_count, 0);
+        assert!(!message.receipt_pending);
+        assert_eq!(message.channel_id, None);
+        let node = node_model::Entity::find_by_id("did:key:a")
+            .one(&db)
+            .await?
+            .expect("node kept");
+        assert_eq!(node.last_seen, 20);
+        let r = ref_model::Entity::find_by_id((
+            "did:repo:x".to_string(),
+            "refs/heads/main".to_string(),
+        ))
+        .one(&db)
+        .await?
+        .expect("ref kept");
+        assert_eq!(r.created_at, 30);
+        assert!(sqlite_has_column(&db, "seen", "msg_id").await?);
+        assert_eq!(
+            sqlite_query_one_i64(
+                &db,
+                "SELECT COUNT(*) FROM sqlite_master
+                 WHERE type = 'index' AND name IN ('idx_repos_creator', 'idx_repos_path')"
+                    .to_string()
+            )
+            .await?,
+            2
+        );
+
+        // 已是最新版本时不再重复执行迁移
+        ensure_schema(&db).await?;
+        assert_eq!(
+            sqlite_query_one_i64(&db, "SELECT COUNT(*) FROM schema_version".to_string()).await?,
+            SCHEMA_VERSION
+        );
+
+        // 比当前代码更新的数据库拒绝打开
+        db.execute_unprepared(
+            "INSERT INTO schema_version (version, name, applied_at) VALUES (999, 'future', 0)",
+        )
+        .await?;
+        assert!(ensure_schema(&db).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_fresh_db_accepts_chat_messages_and_refs() -> Result<()> {
+        use sea_orm::{ActiveModelTrait, EntityTrait, Set};
+
+        let db = Database::connect("sqlite::memory:").await?;
+        ensure_schema(&db).await?;
+
+        // 与 chat_message::save_message 写入的列一致
+        chat_message::ActiveModel {
+            id: Set("msg-fresh".to_string()),
+            from: Set("did:key:from".to_string()),
+            to: Set("did:key:to".to_string()),
+            content: Set("hello".to_string()),
+            created_at: Set(10),
+            created_at_ms: Set(10_000),
+            status: Set(chat_message::MessageStatus::Sending),
+            retry_count: Set(0),
+            next_retry_at: Set(0),
+            receipt_pending: Set(false),
+            channel_id: Set(None),
+        }
+        .insert(&db)
+        .await?;
+        ref_model::ActiveModel {
+            repo_id: Set("did:repo:fresh".to_string()),
+            ref_name: Set("refs/heads/main".to_string()),
+            commit_hash: Set("abc".to_string()),
+            created_at: Set(10),
+            updated_at: Set(10),
+        }
+        .insert(&db)
+        .await?;
+
+        let message = chat_message::Entity::find_by_id("msg-fresh")
+            .one(&db)
+            .await?
+            .expect("message saved");
+        assert_eq!(message.content, "hello");
+        assert_eq!(ref_model::Entity::find().all(&db).await?.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_get_db_conn() -> Result<()> {
+        // 使用临时数据库，不触碰用户数据目录下的数据库
+        let dir = std::env::temp_dir().join(format!("megaengine-conn-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir)?;
+        let url = format!("sqlite://{}?mode=rwc", dir.join("megaengine.db").display());
+
+        let handles: Vec<_> = (0..16)
+            .map(|_| tokio::spawn(cached_db_conn(url.clone())))
+            .collect();
+        for handle in handles {
+            let db = handle.await??;
+            assert_eq!(schema_version(&db).await?, SCHEMA_VERSION);
+        }
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_unwritable_db_path_returns_err() -> Result<()> {
+        // 父路径是普通文件，数据库无法创建
+        let blocker =
+            std::env::temp_dir().join(format!("megaengine-blocker-{}", uuid::Uuid::new_v4()));
+        fs::write(&blocker, b"not a directory")?;
+        let url = format!(
+            "sqlite://{}?mode=rwc",
+            blocker.join("megaengine.db").display()
+        );
+
+        assert!(cached_db_conn(url.clone()).await.is_err());
+        // 失败不会被缓存，再次调用仍然返回错误而不是 panic
+        assert!(cached_db_conn(url).await.is_err());
+
+        fs::remove_file(&blocker)?;
+        Ok(())
+    }
+
     #[test]
     fn test_data_dir() {
         let dir = data_dir();
@@ -526,16 +1213,22 @@ mod tests {
 
     #[test]
     fn test_keypair_path() {
-        let path = keypair_path();
+        let path = keypair_path(None);
         assert!(path.to_string_lossy().contains("keypair.json"));
+
+        let path = keypair_path(Some("work"));
+        assert!(path.ends_with("keypair-work.json"));
+        assert!(validate_profile_name("work_2").is_ok());
+        assert!(validate_profile_name("../work").is_err());
+        assert!(validate_profile_name("").is_err());
     }
 
     #[test]
     fn test_save_and_load_keypair() -> Result<()> {
         let kp = KeyPair::generate()?;
-        save_keypair(&kp)?;
+        save_keypair(&kp, None)?;
 
-        let loaded = load_keypair()?;
+        let loaded = load_keypair(None)?;
         assert_eq!(
             kp.verifying_key_bytes(),
             loaded.verifying_key_bytes(),
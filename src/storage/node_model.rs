@@ -1,10 +1,12 @@
+use std::collections::HashMap;
 use std::net::SocketAddr;
 
 use anyhow::Result;
 use sea_orm::entity::prelude::*;
-use sea_orm::Set;
+use sea_orm::{Set, Unchanged};
 
 use crate::node::node::{NodeInfo, NodeType};
+use crate::node::node_id::NodeId;
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
 #[sea_orm(table_name = "nodes")]
@@ -17,6 +19,10 @@ pub struct Model {
     pub version: i32,
     pub created_at: i64,
     pub updated_at: i64,
+    /// 最后一次直接收到该节点公告的时间，0 表示只是间接得知（如 peer exchange）
+    pub last_seen: i64,
+    /// 节点主动离开（NodeLeave）的时间，之后再次收到其公告时清零
+    pub left_at: i64,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -24,47 +30,135 @@ pub enum Relation {}
 
 impl ActiveModelBehavior for ActiveModel {}
 
-/// 将 NodeInfo 保存到数据库
+/// 引导节点相对普通节点的保留时长倍数
+const BOOTSTRAP_RETENTION_FACTOR: i64 = 4;
+
+fn node_type_to_int(node_type: &NodeType) -> i32 {
+    match node_type {
+        NodeType::Normal => 0,
+        NodeType::Relay => 1,
+        NodeType::Bootstrap => 2,
+    }
+}
+
+fn node_type_from_int(value: i32) -> NodeType {
+    match value {
+        0 => NodeType::Normal,
+        2 => NodeType::Bootstrap,
+        _ => NodeType::Relay,
+    }
+}
+
+/// 将 NodeInfo 保存到数据库：已存在时更新，保留 created_at 和 last_seen
 pub async fn save_node_info_to_db(info: &NodeInfo) -> Result<()> {
     let db = crate::storage::get_db_conn().await?;
 
     let addresses_json = serde_json::to_string(&info.addresses)?;
     let now = chrono::Local::now().timestamp();
 
-    // 删除旧记录（如果存在）
-    let _ = Entity::delete_by_id(info.node_id.to_string())
-        .exec(&db)
-        .await;
+    let node_type_int = node_type_to_int(&info.node_type);
 
-    let node_type_int = match info.node_type {
-        NodeType::Normal => 0,
-        NodeType::Relay => 1,
-    };
-
-    let active = ActiveModel {
-        id: Set(info.node_id.to_string()),
-        alias: Set(info.alias.clone()),
-        addresses: Set(addresses_json),
-        node_type: Set(node_type_int),
-        version: Set(info.version as i32),
-        created_at: Set(now),
-        updated_at: Set(now),
-    };
-
-    Entity::insert(active).exec(&db).await?;
+    let existing = Entity::find_by_id(info.node_id.to_string())
+        .one(&db)
+        .await?;
+
+    if let Some(existing_model) = existing {
+        let active = ActiveModel {
+            id: Unchanged(existing_model.id),
+            alias: Set(info.alias.clone()),
+            addresses: Set(addresses_json),
+            node_type: Set(node_type_int),
+            version: Set(info.version as i32),
+            created_at: Unchanged(existing_model.created_at),
+            updated_at: Set(now),
+            last_seen: Unchanged(existing_model.last_seen),
+            left_at: Unchanged(existing_model.left_at),
+        };
+        Entity::update(active).exec(&db).await?;
+    } else {
+        let active = ActiveModel {
+            id: Set(info.node_id.to_string()),
+            alias: Set(info.alias.clone()),
+            addresses: Set(addresses_json),
+            node_type: Set(node_type_int),
+            version: Set(info.version as i32),
+            created_at: Set(now),
+            updated_at: Set(now),
+            last_seen: Set(0),
+            left_at: Set(0),
+        };
+        Entity::insert(active).exec(&db).await?;
+    }
     Ok(())
 }
 
+/// 记录刚刚收到该节点的公告，节点之前离开过时视为重新上线
+pub async fn mark_node_seen(node_id: &str) -> Result<()> {
+    let db = crate::storage::get_db_conn().await?;
+    let now = chrono::Local::now().timestamp();
+    Entity::update_many()
+        .col_expr(Column::LastSeen, Expr::value(now))
+        .col_expr(Column::LeftAt, Expr::value(0))
+        .filter(Column::Id.eq(node_id))
+        .exec(&db)
+        .await?;
+    Ok(())
+}
+
+/// 记录节点在 `left_at` 时主动离开，保留节点记录；返回是否有记录被更新
+///
+/// 离开时间早于最后一次收到公告时不更新，重放的旧 NodeLeave 不会把已重新上线的节点标记为离线
+pub async fn mark_node_left(node_id: &str, left_at: i64) -> Result<bool> {
+    let db = crate::storage::get_db_conn().await?;
+    let res = Entity::update_many()
+        .col_expr(Column::LeftAt, Expr::value(left_at))
+        .filter(Column::Id.eq(node_id))
+        .filter(Column::LastSeen.lte(left_at))
+        .exec(&db)
+        .await?;
+    Ok(res.rows_affected > 0)
+}
+
+/// 已主动离开的节点及其离开时间
+pub async fn left_nodes() -> Result<HashMap<String, i64>> {
+    let db = crate::storage::get_db_conn().await?;
+    let models = Entity::find().filter(Column::LeftAt.gt(0)).all(&db).await?;
+    Ok(models.into_iter().map(|m| (m.id, m.left_at)).collect())
+}
+
+/// 删除超过 max_age_secs 未收到公告的节点（从未直接收到公告的按 created_at 计算），返回删除数量
+///
+/// 引导节点的保留时长为普通节点的 `BOOTSTRAP_RETENTION_FACTOR` 倍
+pub async fn prune_stale_nodes(max_age_secs: i64) -> Result<u64> {
+    let db = crate::storage::get_db_conn().await?;
+    let now = chrono::Local::now().timestamp();
+    let cutoff = now - max_age_secs;
+    let bootstrap_cutoff = now - max_age_secs.saturating_mul(BOOTSTRAP_RETENTION_FACTOR);
+    let bootstrap = node_type_to_int(&NodeType::Bootstrap);
+
+    let mut removed = 0;
+    for (filter, cutoff) in [
+        (Column::NodeType.ne(bootstrap), cutoff),
+        (Column::NodeType.eq(bootstrap), bootstrap_cutoff),
+    ] {
+        let res = Entity::delete_many()
+            .filter(filter)
+            .filter(Column::LastSeen.lt(cutoff))
+            .filter(Column::CreatedAt.lt(cutoff))
+            .exec(&db)
+            .await?;
+        removed += res.rows_affected;
+    }
+    Ok(removed)
+}
+
 /// 从数据库加载 NodeInfo
 pub async fn load_node_info_from_db(node_id: &str) -> Result<Option<NodeInfo>> {
     let db = crate::storage::get_db_conn().await?;
 
     if let Some(m) = Entity::find_by_id(node_id).one(&db).await? {
         let addresses: Vec<SocketAddr> = serde_json::from_str(&m.addresses)?;
-        let node_type = match m.node_type {
-            0 => NodeType::Normal,
-            _ => NodeType::Relay,
-        };
+        let node_type = node_type_from_int(m.node_type);
 
         let info = NodeInfo {
             node_id: crate::node::node_id::NodeId::from_string(&m.id)
@@ -89,16 +183,22 @@ pub async fn delete_node_from_db(node_id: &str) -> Result<()> {
 
 /// 列出所有节点
 pub async fn list_nodes() -> Result<Vec<NodeInfo>> {
+    Ok(list_nodes_with_last_seen()
+        .await?
+        .into_iter()
+        .map(|(info, _)| info)
+        .collect())
+}
+
+/// 列出所有节点及其 last_seen 时间（0 表示从未直接收到公告）
+pub async fn list_nodes_with_last_seen() -> Result<Vec<(NodeInfo, i64)>> {
     let db = crate::storage::get_db_conn().await?;
     let models = Entity::find().all(&db).await?;
 
     let mut out = Vec::new();
     for m in models {
         let addresses: Vec<SocketAddr> = serde_json::from_str(&m.addresses).unwrap_or_default();
-        let node_type = match m.node_type {
-            0 => NodeType::Normal,
-            _ => NodeType::Relay,
-        };
+        let node_type = node_type_from_int(m.node_type);
         let info = NodeInfo {
             node_id: crate::node::node_id::NodeId::from_string(&m.id)
                 .unwrap_or_else(|_| crate::node::node_id::NodeId::from_string("").unwrap()),
@@ -107,7 +207,168 @@ pub async fn list_nodes() -> Result<Vec<NodeInfo>> {
             node_type,
             version: m.version as u8,
         };
-        out.push(info);
+        out.push((info, m.last_seen));
     }
     Ok(out)
 }
+
+/// 按别名查找节点 ID（别名不唯一，可能返回多个）
+pub async fn find_node_ids_by_alias(alias: &str) -> Result<Vec<NodeId>> {
+    let db = crate::storage::get_db_conn().await?;
+    let models = Entity::find()
+        .filter(Column::Alias.eq(alias))
+        .all(&db)
+        .await?;
+
+    Ok(models
+        .into_iter()
+        .filter_map(|m| NodeId::from_string(&m.id).ok())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::keypair::KeyPair;
+    use crate::storage::with_test_db;
+
+    fn test_node_info(alias: &str) -> NodeInfo {
+        let kp = KeyPair::generate().unwrap();
+        NodeInfo {
+            node_id: NodeId::from_keypair(&kp),
+            alias: alias.to_string(),
+            addresses: vec!["127.0.0.1:9000".parse().unwrap()],
+            node_type: NodeType::Normal,
+            version: 1,
+        }
+    }
+
+    async fn load_model(node_id: &str) -> Result<Model> {
+        let db = crate::storage::get_db_conn().await?;
+        Ok(Entity::find_by_id(node_id).one(&db).await?.unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_save_node_preserves_created_at_and_last_seen() -> Result<()> {
+        with_test_db(async {
+            let mut info = test_node_info("before");
+            let id = info.node_id.to_string();
+            save_node_info_to_db(&info).await?;
+            assert_eq!(load_model(&id).await?.last_seen, 0);
+
+            // 伪造较早的创建时间，确认更新不会覆盖
+            let db = crate::storage::get_db_conn().await?;
+            Entity::update_many()
+                .col_expr(Column::CreatedAt, Expr::value(100))
+                .filter(Column::Id.eq(id.as_str()))
+                .exec(&db)
+                .await?;
+            mark_node_seen(&id).await?;
+            let seen = load_model(&id).await?.last_seen;
+            assert!(seen > 0);
+
+            info.alias = "after".to_string();
+            save_node_info_to_db(&info).await?;
+            let model = load_model(&id).await?;
+            assert_eq!(model.alias, "after");
+            assert_eq!(model.created_at, 100);
+            assert_eq!(model.last_seen, seen);
+
+            delete_node_from_db(&id).await?;
+            Ok(())
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_mark_node_left_and_rejoin() -> Result<()> {
+        with_test_db(async {
+            let info = test_node_info("leaver");
+            let id = info.node_id.to_string();
+            save_node_info_to_db(&info).await?;
+            mark_node_seen(&id).await?;
+            let seen = load_model(&id).await?.last_seen;
+
+            // 早于最后一次公告的离开消息（如重放）被忽略
+            assert!(!mark_node_left(&id, seen - 10).await?);
+            assert!(left_nodes().await?.is_empty());
+
+            assert!(mark_node_left(&id, seen + 1).await?);
+            assert_eq!(left_nodes().await?.get(&id), Some(&(seen + 1)));
+            // 离开只做标记，节点记录仍然保留
+            assert!(load_node_info_from_db(&id).await?.is_some());
+
+            mark_node_seen(&id).await?;
+            assert!(left_nodes().await?.is_empty());
+
+            delete_node_from_db(&id).await?;
+            Ok(())
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_prune_stale_nodes() -> Result<()> {
+        with_test_db(async {
+            let stale = test_node_info("stale");
+            let fresh = test_node_info("fresh");
+            save_node_info_to_db(&stale).await?;
+            save_node_info_to_db(&fresh).await?;
+            mark_node_seen(fresh.node_id.as_str()).await?;
+
+            let db = crate::storage::get_db_conn().await?;
+            Entity::update_many()
+                .col_expr(Column::CreatedAt, Expr::value(100))
+                .col_expr(Column::LastSeen, Expr::value(200))
+                .filter(Column::Id.eq(stale.node_id.as_str()))
+                .exec(&db)
+                .await?;
+
+            // 截止时间取 1000，只会删除本测试伪造的旧记录
+            let max_age = chrono::Local::now().timestamp() - 1000;
+            assert!(prune_stale_nodes(max_age).await? >= 1);
+            assert!(load_node_info_from_db(stale.node_id.as_str())
+                .await?
+                .is_none());
+            assert!(load_node_info_from_db(fresh.node_id.as_str())
+                .await?
+                .is_some());
+
+            delete_node_from_db(fresh.node_id.as_str()).await?;
+            Ok(())
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_bootstrap_node_roundtrip_and_retention() -> Result<()> {
+        with_test_db(async {
+            let mut info = test_node_info("bootstrap");
+            info.node_type = NodeType::Bootstrap;
+            save_node_info_to_db(&info).await?;
+            assert_eq!(load_model(info.node_id.as_str()).await?.node_type, 2);
+            let loaded = load_node_info_from_db(info.node_id.as_str())
+                .await?
+                .unwrap();
+            assert_eq!(loaded.node_type, NodeType::Bootstrap);
+
+            // 超过普通节点的保留时长但仍在引导节点的保留时长内
+            let db = crate::storage::get_db_conn().await?;
+            Entity::update_many()
+                .col_expr(Column::CreatedAt, Expr::value(100))
+                .col_expr(Column::LastSeen, Expr::value(200))
+                .filter(Column::Id.eq(info.node_id.as_str()))
+                .exec(&db)
+                .await?;
+            let max_age = chrono::Local::now().timestamp() - 1000;
+            prune_stale_nodes(max_age).await?;
+            assert!(load_node_info_from_db(info.node_id.as_str())
+                .await?
+                .is_some());
+
+            delete_node_from_db(info.node_id.as_str()).await?;
+            Ok(())
+        })
+        .await
+    }
+}
@@ -0,0 +1,121 @@
+use anyhow::Result;
+use sea_orm::entity::prelude::*;
+use sea_orm::Set;
+
+use crate::gossip::message::SignedMessage;
+use crate::storage::get_db_conn;
+
+/// 中继节点暂存的无法投递的加密聊天消息（store-and-forward）
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "pending_relay")]
+pub struct Model {
+    /// 原始 SignedMessage 的 msg_id
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub msg_id: String,
+    pub receiver_id: String,
+    /// 序列化后的 SignedMessage（JSON），保留发送者签名，内容仍为端到端密文
+    pub message: String,
+    pub created_at: i64,
+    pub expires_at: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+/// 暂存一条发往 receiver_id 的消息，ttl_secs 后过期
+pub async fn save_pending_relay(
+    receiver_id: &str,
+    message: &SignedMessage,
+    ttl_secs: i64,
+) -> Result<()> {
+    let db = get_db_conn().await?;
+    let msg_id = message.dedup_id();
+    if Entity::find_by_id(msg_id.clone()).one(&db).await?.is_some() {
+        return Ok(());
+    }
+
+    let now = chrono::Local::now().timestamp();
+    let active = ActiveModel {
+        msg_id: Set(msg_id),
+        receiver_id: Set(receiver_id.to_string()),
+        message: Set(serde_json::to_string(message)?),
+        created_at: Set(now),
+        expires_at: Set(now + ttl_secs),
+    };
+    Entity::insert(active).exec(&db).await?;
+    Ok(())
+}
+
+/// 列出发往 receiver_id 且未过期的暂存消息
+pub async fn list_pending_relay(receiver_id: &str) -> Result<Vec<SignedMessage>> {
+    let db = get_db_conn().await?;
+    let now = chrono::Local::now().timestamp();
+    let models = Entity::find()
+        .filter(Column::ReceiverId.eq(receiver_id))
+        .filter(Column::ExpiresAt.gt(now))
+        .all(&db)
+        .await?;
+
+    let mut messages = Vec::new();
+    for model in models {
+        match serde_json::from_str::<SignedMessage>(&model.message) {
+            Ok(msg) => messages.push(msg),
+            Err(e) => {
+                tracing::warn!("Invalid relay message {}: {}", model.msg_id, e);
+            }
+        }
+    }
+    Ok(messages)
+}
+
+/// 删除已成功转发的暂存消息
+pub async fn delete_pending_relay(msg_id: &str) -> Result<()> {
+    let db = get_db_conn().await?;
+    Entity::delete_by_id(msg_id).exec(&db).await?;
+    Ok(())
+}
+
+/// 删除已过期的暂存消息，返回删除的条数
+pub async fn cleanup_expired_relay() -> Result<u64> {
+    let db = get_db_conn().await?;
+    let now = chrono::Local::now().timestamp();
+    let result = Entity::delete_many()
+        .filter(Column::ExpiresAt.lte(now))
+        .exec(&db)
+        .await?;
+    Ok(result.rows_affected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::keypair::KeyPair;
+    use crate::storage::with_test_db;
+
+    #[tokio::test]
+    async fn test_pending_relay_store_and_expire() -> Result<()> {
+        with_test_db(async {
+            let keypair = KeyPair::generate()?;
+            let receiver = "did:key:pending-relay-receiver";
+            let fresh =
+                SignedMessage::new_repo_deletion_sign_message("did:repo:a".into(), &keypair)?;
+            let expired =
+                SignedMessage::new_repo_deletion_sign_message("did:repo:b".into(), &keypair)?;
+
+            save_pending_relay(receiver, &fresh, 3600).await?;
+            save_pending_relay(receiver, &expired, -1).await?;
+
+            let pending = list_pending_relay(receiver).await?;
+            assert_eq!(pending.len(), 1);
+            assert_eq!(pending[0].msg_id, fresh.msg_id);
+
+            cleanup_expired_relay().await?;
+            delete_pending_relay(&fresh.dedup_id()).await?;
+            assert!(list_pending_relay(receiver).await?.is_empty());
+            Ok(())
+        })
+        .await
+    }
+}
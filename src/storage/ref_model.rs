@@ -5,6 +5,9 @@ use sea_orm::{Set, Unchanged};
 use crate::storage::get_db_conn;
 
 /// Refs table entity for tracking branch and tag commits
+///
+/// This table is the only place refs are stored: `repos` has no refs column and
+/// `Repo::refs` is always loaded from here, keyed by `(repo_id, ref_name)`.
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
 #[sea_orm(table_name = "refs")]
 pub struct Model {
@@ -117,6 +120,34 @@ pub async fn load_refs_for_repo(
     Ok(result)
 }
 
+/// Load refs of several repositories with a single query, keyed by repo_id
+///
+/// Repositories without refs are absent from the result
+pub async fn load_refs_for_repos(
+    repo_ids: &[String],
+) -> Result<std::collections::HashMap<String, std::collections::HashMap<String, String>>> {
+    let mut result: std::collections::HashMap<_, std::collections::HashMap<_, _>> =
+        std::collections::HashMap::new();
+    if repo_ids.is_empty() {
+        return Ok(result);
+    }
+
+    let db = get_db_conn().await?;
+    let refs = Entity::find()
+        .filter(Column::RepoId.is_in(repo_ids.iter().cloned()))
+        .all(&db)
+        .await?;
+
+    for ref_record in refs {
+        result
+            .entry(ref_record.repo_id)
+            .or_default()
+            .insert(ref_record.ref_name, ref_record.commit_hash);
+    }
+
+    Ok(result)
+}
+
 /// Get a specific ref by repo_id and ref_name
 pub async fn get_ref(repo_id: &str, ref_name: &str) -> Result<Option<String>> {
     let db = get_db_conn().await?;
@@ -189,72 +220,82 @@ pub async fn has_refs_changed(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::storage::with_test_db;
 
     #[tokio::test]
     async fn test_save_and_load_ref() -> Result<()> {
-        let repo_id = "did:repo:test-ref-001";
-        let ref_name = "refs/heads/main";
-        let commit_hash = "abc123def456";
-
-        // Save ref
-        save_ref(repo_id, ref_name, commit_hash).await?;
-
-        // Load ref
-        let loaded = get_ref(repo_id, ref_name).await?;
-        assert!(loaded.is_some());
-        assert_eq!(loaded.unwrap(), commit_hash);
-
-        // Cleanup
-        delete_ref(repo_id, ref_name).await?;
-        Ok(())
+        with_test_db(async {
+            let repo_id = "did:repo:test-ref-001";
+            let ref_name = "refs/heads/main";
+            let commit_hash = "abc123def456";
+
+            // Save ref
+            save_ref(repo_id, ref_name, commit_hash).await?;
+
+            // Load ref
+            let loaded = get_ref(repo_id, ref_name).await?;
+            assert!(loaded.is_some());
+            assert_eq!(loaded.unwrap(), commit_hash);
+
+            // Cleanup
+            delete_ref(repo_id, ref_name).await?;
+            Ok(())
+        })
+        .await
     }
 
     #[tokio::test]
     async fn test_batch_save_refs() -> Result<()> {
-        let repo_id = "did:repo:test-ref-002";
-        let mut refs = std::collections::HashMap::new();
-        refs.insert("refs/heads/main".to_string(), "abc123".to_string());
-        refs.insert("refs/heads/develop".to_string(), "def456".to_string());
-        refs.insert("refs/tags/v1.0".to_string(), "ghi789".to_string());
-
-        // Save all refs
-        batch_save_refs(repo_id, &refs).await?;
-
-        // Load and verify
-        let loaded = load_refs_for_repo(repo_id).await?;
-        assert_eq!(loaded.len(), 3);
-        assert_eq!(loaded.get("refs/heads/main"), Some(&"abc123".to_string()));
-        assert_eq!(
-            loaded.get("refs/heads/develop"),
-            Some(&"def456".to_string())
-        );
-        assert_eq!(loaded.get("refs/tags/v1.0"), Some(&"ghi789".to_string()));
-
-        // Cleanup
-        delete_refs_for_repo(repo_id).await?;
-        Ok(())
+        with_test_db(async {
+            let repo_id = "did:repo:test-ref-002";
+            let mut refs = std::collections::HashMap::new();
+            refs.insert("refs/heads/main".to_string(), "abc123".to_string());
+            refs.insert("refs/heads/develop".to_string(), "def456".to_string());
+            refs.insert("refs/tags/v1.0".to_string(), "ghi789".to_string());
+
+            // Save all refs
+            batch_save_refs(repo_id, &refs).await?;
+
+            // Load and verify
+            let loaded = load_refs_for_repo(repo_id).await?;
+            assert_eq!(loaded.len(), 3);
+            assert_eq!(loaded.get("refs/heads/main"), Some(&"abc123".to_string()));
+            assert_eq!(
+                loaded.get("refs/heads/develop"),
+                Some(&"def456".to_string())
+            );
+            assert_eq!(loaded.get("refs/tags/v1.0"), Some(&"ghi789".to_string()));
+
+            // Cleanup
+            delete_refs_for_repo(repo_id).await?;
+            Ok(())
+        })
+        .await
     }
 
     #[tokio::test]
     async fn test_has_refs_changed() -> Result<()> {
-        let repo_id = "did:repo:test-ref-003";
-        let mut refs = std::collections::HashMap::new();
-        refs.insert("refs/heads/main".to_string(), "abc123".to_string());
-
-        // Save initial refs
-        batch_save_refs(repo_id, &refs).await?;
-
-        // No change
-        let changed = has_refs_changed(repo_id, &refs).await?;
-        assert!(!changed);
-
-        // Change commit hash
-        refs.insert("refs/heads/main".to_string(), "def456".to_string());
-        let changed = has_refs_changed(repo_id, &refs).await?;
-        assert!(changed);
-
-        // Cleanup
-        delete_refs_for_repo(repo_id).await?;
-        Ok(())
+        with_test_db(async {
+            let repo_id = "did:repo:test-ref-003";
+            let mut refs = std::collections::HashMap::new();
+            refs.insert("refs/heads/main".to_string(), "abc123".to_string());
+
+            // Save initial refs
+            batch_save_refs(repo_id, &refs).await?;
+
+            // No change
+            let changed = has_refs_changed(repo_id, &refs).await?;
+            assert!(!changed);
+
+            // Change commit hash
+            refs.insert("refs/heads/main".to_string(), "def456".to_string());
+            let changed = has_refs_changed(repo_id, &refs).await?;
+            assert!(changed);
+
+            // Cleanup
+            delete_refs_for_repo(repo_id).await?;
+            Ok(())
+        })
+        .await
     }
 }
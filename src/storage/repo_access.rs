@@ -0,0 +1,110 @@
+use anyhow::Result;
+use sea_orm::entity::prelude::*;
+use sea_orm::Set;
+
+use crate::storage::get_db_conn;
+
+/// 仓库的拉取白名单：列出的节点才能请求该仓库的 bundle，没有任何记录时对所有节点开放
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "repo_access")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub repo_id: String,
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub node_id: String,
+    pub created_at: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+/// 允许节点拉取仓库，返回是否为新增记录
+pub async fn allow_peer(repo_id: &str, node_id: &str) -> Result<bool> {
+    let db = get_db_conn().await?;
+    let existing = Entity::find_by_id((repo_id.to_string(), node_id.to_string()))
+        .one(&db)
+        .await?;
+    if existing.is_some() {
+        return Ok(false);
+    }
+
+    let active = ActiveModel {
+        repo_id: Set(repo_id.to_string()),
+        node_id: Set(node_id.to_string()),
+        created_at: Set(chrono::Local::now().timestamp()),
+    };
+    Entity::insert(active).exec(&db).await?;
+    Ok(true)
+}
+
+/// 从白名单中移除节点，返回是否有记录被删除；移除最后一个节点后仓库重新对所有节点开放
+pub async fn revoke_peer(repo_id: &str, node_id: &str) -> Result<bool> {
+    let db = get_db_conn().await?;
+    let res = Entity::delete_by_id((repo_id.to_string(), node_id.to_string()))
+        .exec(&db)
+        .await?;
+    Ok(res.rows_affected > 0)
+}
+
+/// 列出允许拉取仓库的节点，空列表表示对所有节点开放
+pub async fn allowed_peers(repo_id: &str) -> Result<Vec<String>> {
+    let db = get_db_conn().await?;
+    let models = Entity::find()
+        .filter(Column::RepoId.eq(repo_id))
+        .all(&db)
+        .await?;
+    let mut peers: Vec<String> = models.into_iter().map(|m| m.node_id).collect();
+    peers.sort();
+    Ok(peers)
+}
+
+/// 节点是否可以拉取仓库
+pub async fn is_allowed(repo_id: &str, node_id: &str) -> Result<bool> {
+    let peers = allowed_peers(repo_id).await?;
+    Ok(peers.is_empty() || peers.iter().any(|p| p == node_id))
+}
+
+/// 删除仓库的全部白名单记录（仓库被删除时）
+pub async fn delete_access_for_repo(repo_id: &str) -> Result<()> {
+    let db = get_db_conn().await?;
+    Entity::delete_many()
+        .filter(Column::RepoId.eq(repo_id))
+        .exec(&db)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::with_test_db;
+
+    #[tokio::test]
+    async fn test_allow_list_restricts_and_revokes() -> Result<()> {
+        with_test_db(async {
+            let repo_id = "did:repo:access-test";
+            // 默认对所有节点开放
+            assert!(is_allowed(repo_id, "did:key:alice").await?);
+
+            assert!(allow_peer(repo_id, "did:key:alice").await?);
+            assert!(!allow_peer(repo_id, "did:key:alice").await?);
+            assert!(is_allowed(repo_id, "did:key:alice").await?);
+            assert!(!is_allowed(repo_id, "did:key:bob").await?);
+            // 白名单只作用于对应仓库
+            assert!(is_allowed("did:repo:other", "did:key:bob").await?);
+
+            assert!(revoke_peer(repo_id, "did:key:alice").await?);
+            assert!(!revoke_peer(repo_id, "did:key:alice").await?);
+            assert!(allowed_peers(repo_id).await?.is_empty());
+            assert!(is_allowed(repo_id, "did:key:bob").await?);
+
+            allow_peer(repo_id, "did:key:alice").await?;
+            delete_access_for_repo(repo_id).await?;
+            assert!(allowed_peers(repo_id).await?.is_empty());
+            Ok(())
+        })
+        .await
+    }
+}
@@ -1,8 +1,10 @@
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 
 use anyhow::Result;
 use sea_orm::entity::prelude::*;
-use sea_orm::{Set, Unchanged};
+use sea_orm::sea_query::OnConflict;
+use sea_orm::{Condition, QueryOrder, QuerySelect, Set, TransactionTrait, Unchanged};
 
 use crate::{repo::repo::Repo, storage::get_db_conn};
 
@@ -20,6 +22,16 @@ pub struct Model {
     pub is_external: bool,
     pub size: i64,
     pub latest_commit_at: i64,
+    pub commit_count: i64,
+    pub contributors: i64,
+    /// JSON 数组形式的标签
+    pub tags: String,
+    /// 创建者对仓库身份的签名，旧版本节点公告的仓库为空
+    pub signature: String,
+    /// 十六进制的根提交 ID，旧版本节点公告的仓库为空
+    pub root_commit: String,
+    /// repo_id 是否已由 root_commit 和 creator 校验
+    pub verified: bool,
     pub created_at: i64,
     pub updated_at: i64,
 }
@@ -29,6 +41,9 @@ pub enum Relation {}
 
 impl ActiveModelBehavior for ActiveModel {}
 
+// 批量插入时每条 INSERT 语句的最大行数，避免超出 SQLite 的参数个数上限
+const INSERT_BATCH_ROWS: usize = 100;
+
 /// 保存或更新 Repo 到数据库
 pub async fn save_repo_to_db(repo: &Repo) -> Result<()> {
     let db = get_db_conn().await?;
@@ -50,6 +65,12 @@ pub async fn save_repo_to_db(repo: &Repo) -> Result<()> {
             is_external: Set(repo.is_external),
             size: Set(repo.p2p_description.size as i64),
             latest_commit_at: Set(repo.p2p_description.latest_commit_at),
+            commit_count: Set(repo.p2p_description.commit_count as i64),
+            contributors: Set(repo.p2p_description.contributors as i64),
+            tags: Set(tags_to_json(&repo.p2p_description.tags)),
+            signature: Set(repo.signature.clone()),
+            root_commit: Set(repo.root_commit.clone()),
+            verified: Set(repo.verified),
             created_at: Unchanged(existing_model.created_at),
             updated_at: Set(now),
         };
@@ -67,6 +88,12 @@ pub async fn save_repo_to_db(repo: &Repo) -> Result<()> {
             is_external: Set(repo.is_external),
             size: Set(repo.p2p_description.size as i64),
             latest_commit_at: Set(repo.p2p_description.latest_commit_at),
+            commit_count: Set(repo.p2p_description.commit_count as i64),
+            contributors: Set(repo.p2p_description.contributors as i64),
+            tags: Set(tags_to_json(&repo.p2p_description.tags)),
+            signature: Set(repo.signature.clone()),
+            root_commit: Set(repo.root_commit.clone()),
+            verified: Set(repo.verified),
             created_at: Set(now),
             updated_at: Set(now),
         };
@@ -85,68 +112,399 @@ pub async fn load_repo_from_db(repo_id: &str) -> Result<Option<Repo>> {
 
     // 使用 find_by_id 直接查询
     if let Some(model) = Entity::find_by_id(repo_id).one(&db).await? {
-        // Load refs from ref_model table
-        let refs = crate::storage::ref_model::load_refs_for_repo(&model.id).await?;
-
-        let repo = Repo {
-            repo_id: model.id,
-            refs,
-            p2p_description: crate::repo::repo::P2PDescription {
-                creator: model.creator,
-                name: model.name,
-                description: model.description,
-                language: model.language,
-                latest_commit_at: model.latest_commit_at,
-                size: model.size as u64,
-            },
-            path: PathBuf::from(model.path),
-            bundle: PathBuf::from(model.bundle),
-            is_external: model.is_external,
-        };
-        return Ok(Some(repo));
+        return Ok(Some(model_to_repo(model).await?));
     }
 
     Ok(None)
 }
 
+/// 按本地路径查找仓库 ID，同一路径有多个仓库时返回最早创建的一个
+pub async fn find_repo_id_by_path(path: &Path) -> Result<Option<String>> {
+    let db = get_db_conn().await?;
+    let repo_id = Entity::find()
+        .select_only()
+        .column(Column::Id)
+        .filter(Column::Path.eq(path.to_string_lossy().to_string()))
+        .order_by_asc(Column::CreatedAt)
+        .order_by_asc(Column::Id)
+        .into_tuple::<String>()
+        .one(&db)
+        .await?;
+    Ok(repo_id)
+}
+
+/// 按本地路径查找所有仓库 ID，按创建时间排序
+pub async fn find_repo_ids_by_path(path: &Path) -> Result<Vec<String>> {
+    let db = get_db_conn().await?;
+    let repo_ids = Entity::find()
+        .select_only()
+        .column(Column::Id)
+        .filter(Column::Path.eq(path.to_string_lossy().to_string()))
+        .order_by_asc(Column::CreatedAt)
+        .order_by_asc(Column::Id)
+        .into_tuple::<String>()
+        .all(&db)
+        .await?;
+    Ok(repo_ids)
+}
+
+/// 批量加载 Repos（连同 refs），按 repo_id 索引；不存在的仓库不会出现在结果中
+///
+/// 仓库和 refs 各只查询一次，用于处理包含大量仓库的公告
+pub async fn load_repos_by_ids(repo_ids: &[String]) -> Result<HashMap<String, Repo>> {
+    if repo_ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let db = get_db_conn().await?;
+    let models = Entity::find()
+        .filter(Column::Id.is_in(repo_ids.iter().cloned()))
+        .all(&db)
+        .await?;
+    if models.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let found: Vec<String> = models.iter().map(|m| m.id.clone()).collect();
+    let mut refs = crate::storage::ref_model::load_refs_for_repos(&found).await?;
+    Ok(models
+        .into_iter()
+        .map(|model| {
+            let repo_refs = refs.remove(&model.id).unwrap_or_default();
+            (model.id.clone(), model_into_repo(model, repo_refs))
+        })
+        .collect())
+}
+
+/// 在一个事务中批量插入新的 Repos 及其 refs，任何一条失败时全部回滚
+///
+/// 数据库中已存在的仓库（例如并发的公告刚刚插入）被跳过，其记录和 refs 保持不变
+pub async fn insert_repos(repos: &[Repo]) -> Result<()> {
+    if repos.is_empty() {
+        return Ok(());
+    }
+    let db = get_db_conn().await?;
+    let now = chrono::Local::now().timestamp();
+
+    let txn = db.begin().await?;
+    let mut existing = HashSet::new();
+    for chunk in repos.chunks(INSERT_BATCH_ROWS) {
+        let ids = chunk.iter().map(|repo| repo.repo_id.clone());
+        let found = Entity::find()
+            .filter(Column::Id.is_in(ids))
+            .all(&txn)
+            .await?;
+        existing.extend(found.into_iter().map(|model| model.id));
+    }
+    let repos: Vec<&Repo> = repos
+        .iter()
+        .filter(|repo| !existing.contains(&repo.repo_id))
+        .collect();
+
+    for chunk in repos.chunks(INSERT_BATCH_ROWS) {
+        let models = chunk.iter().map(|repo| ActiveModel {
+            id: Set(repo.repo_id.clone()),
+            name: Set(repo.p2p_description.name.clone()),
+            creator: Set(repo.p2p_description.creator.clone()),
+            description: Set(repo.p2p_description.description.clone()),
+            language: Set(repo.p2p_description.language.clone()),
+            path: Set(repo.path.to_string_lossy().to_string()),
+            bundle: Set(repo.bundle.to_string_lossy().to_string()),
+            is_external: Set(repo.is_external),
+            size: Set(repo.p2p_description.size as i64),
+            latest_commit_at: Set(repo.p2p_description.latest_commit_at),
+            commit_count: Set(repo.p2p_description.commit_count as i64),
+            contributors: Set(repo.p2p_description.contributors as i64),
+            tags: Set(tags_to_json(&repo.p2p_description.tags)),
+            signature: Set(repo.signature.clone()),
+            root_commit: Set(repo.root_commit.clone()),
+            verified: Set(repo.verified),
+            created_at: Set(now),
+            updated_at: Set(now),
+        });
+        Entity::insert_many(models)
+            .on_conflict(OnConflict::column(Column::Id).do_nothing().to_owned())
+            .do_nothing()
+            .exec(&txn)
+            .await?;
+    }
+
+    let refs: Vec<crate::storage::ref_model::ActiveModel> = repos
+        .iter()
+        .flat_map(|repo| {
+            repo.refs
+                .iter()
+                .map(|(name, hash)| crate::storage::ref_model::ActiveModel {
+                    repo_id: Set(repo.repo_id.clone()),
+                    ref_name: Set(name.clone()),
+                    commit_hash: Set(hash.clone()),
+                    created_at: Set(now),
+                    updated_at: Set(now),
+                })
+        })
+        .collect();
+    for chunk in refs.chunks(INSERT_BATCH_ROWS) {
+        crate::storage::ref_model::Entity::insert_many(chunk.to_vec())
+            .on_conflict(
+                OnConflict::columns([
+                    crate::storage::ref_model::Column::RepoId,
+                    crate::storage::ref_model::Column::RefName,
+                ])
+                .do_nothing()
+                .to_owned(),
+            )
+            .do_nothing()
+            .exec(&txn)
+            .await?;
+    }
+
+    txn.commit().await?;
+    Ok(())
+}
+
 /// 删除 Repo 从数据库
 pub async fn delete_repo_from_db(repo_id: &str) -> Result<()> {
+    use crate::storage::{ref_model, repo_access};
+
     let db = get_db_conn().await?;
-    Entity::delete_by_id(repo_id).exec(&db).await?;
-    // Delete associated refs
-    crate::storage::ref_model::delete_refs_for_repo(repo_id).await?;
+    // 仓库及其 refs、访问列表在同一事务中删除
+    let txn = db.begin().await?;
+    Entity::delete_by_id(repo_id).exec(&txn).await?;
+    ref_model::Entity::delete_many()
+        .filter(ref_model::Column::RepoId.eq(repo_id))
+        .exec(&txn)
+        .await?;
+    repo_access::Entity::delete_many()
+        .filter(repo_access::Column::RepoId.eq(repo_id))
+        .exec(&txn)
+        .await?;
+    txn.commit().await?;
     Ok(())
 }
 
+/// list_repos_paged 的过滤条件，字段为 None 时不限制
+#[derive(Debug, Clone, Default)]
+pub struct RepoFilter {
+    pub creator: Option<String>,
+    /// 语言（不区分大小写）
+    pub language: Option<String>,
+    pub is_external: Option<bool>,
+    /// 名称子串匹配（不区分大小写）
+    pub name_contains: Option<String>,
+    /// 包含该标签
+    pub tag: Option<String>,
+}
+
 /// 列出所有 Repos
 pub async fn list_repos() -> Result<Vec<Repo>> {
+    list_repos_paged(0, 0, RepoFilter::default()).await
+}
+
+/// 按条件分页列出 Repos，按创建时间排序；limit 为 0 时不限制条数
+pub async fn list_repos_paged(offset: u64, limit: u64, filter: RepoFilter) -> Result<Vec<Repo>> {
     let db = get_db_conn().await?;
-    let models = Entity::find().all(&db).await?;
+    let mut query = Entity::find()
+        .order_by_asc(Column::CreatedAt)
+        .order_by_asc(Column::Id);
+    if let Some(creator) = filter.creator {
+        query = query.filter(Column::Creator.eq(creator));
+    }
+    if let Some(language) = filter.language {
+        // SQLite 的 LIKE 对 ASCII 不区分大小写
+        query = query.filter(Column::Language.like(language));
+    }
+    if let Some(is_external) = filter.is_external {
+        query = query.filter(Column::IsExternal.eq(is_external));
+    }
+    if let Some(name) = filter.name_contains {
+        query = query.filter(Column::Name.contains(name));
+    }
+    if let Some(tag) = filter.tag {
+        query = query.filter(tag_condition(&tag));
+    }
+    if offset > 0 {
+        query = query.offset(offset);
+    }
+    if limit > 0 {
+        query = query.limit(limit);
+    }
+    let models = query.all(&db).await?;
 
     let mut repos = Vec::new();
     for model in models {
-        // Load refs from ref_model table
-        let refs = crate::storage::ref_model::load_refs_for_repo(&model.id).await?;
-
-        repos.push(Repo {
-            repo_id: model.id,
-            refs,
-            p2p_description: crate::repo::repo::P2PDescription {
-                creator: model.creator,
-                name: model.name,
-                description: model.description,
-                language: model.language,
-                latest_commit_at: model.latest_commit_at,
-                size: model.size as u64,
-            },
-            path: PathBuf::from(model.path),
-            bundle: PathBuf::from(model.bundle),
-            is_external: model.is_external,
-        });
+        repos.push(model_to_repo(model).await?);
+    }
+    Ok(repos)
+}
+
+/// 按名称、描述和标签搜索 Repos，返回按相关度排序的结果
+///
+/// 查询按空白拆分为多个词，每个词都需出现在名称、描述或标签中（不区分大小写）。
+/// 名称命中的权重最高，其次是标签完全匹配，再次是描述命中；
+/// 名称与查询完全相同的排在最前。
+pub async fn search_repos(query: &str) -> Result<Vec<Repo>> {
+    let terms: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+    if terms.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let db = get_db_conn().await?;
+    let mut condition = Condition::all();
+    for term in &terms {
+        condition = condition.add(
+            Condition::any()
+                .add(Column::Name.contains(term))
+                .add(Column::Description.contains(term))
+                .add(Column::Tags.contains(term)),
+        );
+    }
+    let models = Entity::find().filter(condition).all(&db).await?;
+
+    let whole_query = terms.join(" ");
+    let mut scored: Vec<(u32, Model)> = models
+        .into_iter()
+        .map(|model| {
+            let name = model.name.to_lowercase();
+            let description = model.description.to_lowercase();
+            let tags = parse_tags(&model.tags);
+            let mut score = 0;
+            if name == whole_query {
+                score += 100;
+            }
+            for term in &terms {
+                if name.contains(term.as_str()) {
+                    score += 10;
+                }
+                if tags.iter().any(|t| t == term) {
+                    score += 5;
+                }
+                if description.contains(term.as_str()) {
+                    score += 1;
+                }
+            }
+            (score, model)
+        })
+        .collect();
+    scored.sort_by(|(a_score, a), (b_score, b)| {
+        b_score.cmp(a_score).then_with(|| a.name.cmp(&b.name))
+    });
+
+    let mut repos = Vec::new();
+    for (_, model) in scored {
+        repos.push(model_to_repo(model).await?);
     }
     Ok(repos)
 }
 
+/// 更新本地 Repo 的标签，返回更新后的标签；仓库不存在时返回 None
+pub async fn update_repo_tags(
+    repo_id: &str,
+    add: &[String],
+    remove: &[String],
+) -> Result<Option<Vec<String>>> {
+    let db = get_db_conn().await?;
+    let Some(model) = Entity::find_by_id(repo_id).one(&db).await? else {
+        return Ok(None);
+    };
+
+    let remove = crate::repo::repo::normalize_tags(remove);
+    let tags = crate::repo::repo::normalize_tags(
+        parse_tags(&model.tags)
+            .into_iter()
+            .chain(crate::repo::repo::normalize_tags(add))
+            .filter(|t| !remove.contains(t)),
+    );
+
+    set_repo_tags(repo_id, &tags).await?;
+    Ok(Some(tags))
+}
+
+/// 覆盖 Repo 的标签（用于同步远端公告中的标签）
+pub async fn set_repo_tags(repo_id: &str, tags: &[String]) -> Result<()> {
+    let db = get_db_conn().await?;
+    let active_model = ActiveModel {
+        id: Unchanged(repo_id.to_string()),
+        tags: Set(tags_to_json(&crate::repo::repo::normalize_tags(tags))),
+        updated_at: Set(chrono::Local::now().timestamp()),
+        ..Default::default()
+    };
+    Entity::update(active_model).exec(&db).await?;
+    Ok(())
+}
+
+/// 保存创建者对仓库的签名
+pub async fn set_repo_signature(repo_id: &str, signature: &str) -> Result<()> {
+    let db = get_db_conn().await?;
+    let active_model = ActiveModel {
+        id: Unchanged(repo_id.to_string()),
+        signature: Set(signature.to_string()),
+        ..Default::default()
+    };
+    Entity::update(active_model).exec(&db).await?;
+    Ok(())
+}
+
+/// 保存仓库的根提交及 repo_id 的校验结果
+pub async fn set_repo_root_commit(repo_id: &str, root_commit: &str, verified: bool) -> Result<()> {
+    let db = get_db_conn().await?;
+    let active_model = ActiveModel {
+        id: Unchanged(repo_id.to_string()),
+        root_commit: Set(root_commit.to_string()),
+        verified: Set(verified),
+        ..Default::default()
+    };
+    Entity::update(active_model).exec(&db).await?;
+    Ok(())
+}
+
+/// 匹配包含指定标签的记录（标签以 JSON 字符串形式存储，带引号匹配避免前缀误中）
+fn tag_condition(tag: &str) -> Condition {
+    let tag = crate::repo::repo::normalize_tags([tag]);
+    match tag.first() {
+        Some(tag) => Condition::all().add(Column::Tags.contains(format!("\"{}\"", tag))),
+        None => Condition::all(),
+    }
+}
+
+fn tags_to_json(tags: &[String]) -> String {
+    serde_json::to_string(tags).unwrap_or_else(|_| "[]".to_string())
+}
+
+/// 解析标签列；旧数据或格式错误时视为空
+fn parse_tags(raw: &str) -> Vec<String> {
+    serde_json::from_str(raw).unwrap_or_default()
+}
+
+/// 将数据库记录转换为 Repo，并加载其 refs
+async fn model_to_repo(model: Model) -> Result<Repo> {
+    // Load refs from ref_model table
+    let refs = crate::storage::ref_model::load_refs_for_repo(&model.id).await?;
+    Ok(model_into_repo(model, refs))
+}
+
+fn model_into_repo(model: Model, refs: HashMap<String, String>) -> Repo {
+    Repo {
+        repo_id: model.id,
+        refs,
+        p2p_description: crate::repo::repo::P2PDescription {
+            creator: model.creator,
+            name: model.name,
+            description: model.description,
+            language: model.language,
+            latest_commit_at: model.latest_commit_at,
+            size: model.size as u64,
+            tags: parse_tags(&model.tags),
+            commit_count: model.commit_count.max(0) as u64,
+            contributors: model.contributors.max(0) as u64,
+        },
+        path: PathBuf::from(model.path),
+        bundle: PathBuf::from(model.bundle),
+        is_external: model.is_external,
+        signature: model.signature,
+        root_commit: model.root_commit,
+        verified: model.verified,
+    }
+}
+
 /// 更新 Repo 的 bundle 路径
 pub async fn update_repo_bundle(repo_id: &str, bundle_path: &str) -> Result<()> {
     let db = get_db_conn().await?;
@@ -167,6 +525,12 @@ pub async fn update_repo_bundle(repo_id: &str, bundle_path: &str) -> Result<()>
             is_external: Unchanged(model.is_external),
             size: Unchanged(model.size),
             latest_commit_at: Unchanged(model.latest_commit_at),
+            commit_count: Unchanged(model.commit_count),
+            contributors: Unchanged(model.contributors),
+            tags: Unchanged(model.tags),
+            signature: Unchanged(model.signature),
+            root_commit: Unchanged(model.root_commit),
+            verified: Unchanged(model.verified),
             created_at: Unchanged(model.created_at),
         };
         Entity::update(active_model).exec(&db).await?;
@@ -178,76 +542,334 @@ pub async fn update_repo_bundle(repo_id: &str, bundle_path: &str) -> Result<()>
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::storage::with_test_db;
 
     #[tokio::test]
     async fn test_save_and_load_repo() -> Result<()> {
-        // 创建测试 Repo
-        let desc = crate::repo::repo::P2PDescription {
-            creator: "did:node:test333".to_string(),
-            name: "test-repo".to_string(),
-            description: "A test repository".to_string(),
-            language: "Rust".to_string(),
-            latest_commit_at: 1000,
-            size: 0,
-        };
+        with_test_db(async {
+            // 创建测试 Repo
+            let desc = crate::repo::repo::P2PDescription {
+                creator: "did:node:test333".to_string(),
+                name: "test-repo".to_string(),
+                description: "A test repository".to_string(),
+                language: "Rust".to_string(),
+                latest_commit_at: 1000,
+                size: 0,
+                tags: Vec::new(),
+                commit_count: 0,
+                contributors: 0,
+            };
 
-        let mut repo = Repo::new(
-            "did:repo:test333".to_string(),
-            desc,
-            PathBuf::from("/tmp/test-repo"),
-        );
-        repo.add_ref("refs/heads/main".to_string(), "abc123".to_string());
+            let mut repo = Repo::new(
+                "did:repo:test333".to_string(),
+                desc,
+                PathBuf::from("/tmp/test-repo"),
+            );
+            repo.add_ref("refs/heads/main".to_string(), "abc123".to_string());
 
-        // 保存到数据库
-        save_repo_to_db(&repo).await?;
+            // 保存到数据库
+            save_repo_to_db(&repo).await?;
 
-        // 从数据库加载
-        let loaded = load_repo_from_db("did:repo:test333").await?;
-        assert!(loaded.is_some());
+            // 从数据库加载
+            let loaded = load_repo_from_db("did:repo:test333").await?;
+            assert!(loaded.is_some());
 
-        let loaded_repo = loaded.unwrap();
-        assert_eq!(loaded_repo.repo_id, repo.repo_id);
-        assert_eq!(loaded_repo.p2p_description.name, repo.p2p_description.name);
-        assert_eq!(
-            loaded_repo.get_ref("refs/heads/main"),
-            Some(&"abc123".to_string())
-        );
+            let loaded_repo = loaded.unwrap();
+            assert_eq!(loaded_repo.repo_id, repo.repo_id);
+            assert_eq!(loaded_repo.p2p_description.name, repo.p2p_description.name);
+            assert_eq!(
+                loaded_repo.get_ref("refs/heads/main"),
+                Some(&"abc123".to_string())
+            );
 
-        // 清理
-        delete_repo_from_db("did:repo:test333").await?;
-        Ok(())
+            // 清理
+            delete_repo_from_db("did:repo:test333").await?;
+            Ok(())
+        })
+        .await
     }
 
     #[tokio::test]
-    async fn test_list_repos() -> Result<()> {
-        // 创建多个测试 Repos
-        for i in 0..3 {
+    async fn test_insert_repos_skips_existing() -> Result<()> {
+        with_test_db(async {
+            let repo = |id: &str, name: &str, commit: &str| {
+                let desc = crate::repo::repo::P2PDescription {
+                    creator: "did:node:insert-existing".to_string(),
+                    name: name.to_string(),
+                    description: String::new(),
+                    language: String::new(),
+                    latest_commit_at: 0,
+                    size: 0,
+                    tags: Vec::new(),
+                    commit_count: 0,
+                    contributors: 0,
+                };
+                let mut repo = Repo::new(id.to_string(), desc, PathBuf::new());
+                repo.add_ref("refs/heads/main".to_string(), commit.to_string());
+                repo
+            };
+            let existing = repo("did:repo:insert-existing", "original", "abc123");
+            save_repo_to_db(&existing).await?;
+
+            // 一个已存在的仓库不会让整批插入回滚
+            insert_repos(&[
+                repo("did:repo:insert-existing", "replaced", "def456"),
+                repo("did:repo:insert-new", "new", "789abc"),
+            ])
+            .await?;
+
+            let loaded = load_repo_from_db("did:repo:insert-existing")
+                .await?
+                .unwrap();
+            assert_eq!(loaded.p2p_description.name, "original");
+            assert_eq!(
+                loaded.get_ref("refs/heads/main"),
+                Some(&"abc123".to_string())
+            );
+            assert!(load_repo_from_db("did:repo:insert-new").await?.is_some());
+            Ok(())
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_find_repo_id_by_path() -> Result<()> {
+        with_test_db(async {
+            let repo_id = "did:repo:by-path-test";
+            let path = PathBuf::from("/tmp/megaengine-by-path-test");
             let desc = crate::repo::repo::P2PDescription {
-                creator: "did:node:test".to_string(),
-                name: format!("test-repo-{}", i),
-                description: format!("Test repository {}", i),
-                language: "Rust".to_string(),
-                latest_commit_at: 1000 + i,
+                creator: "did:node:by-path-test".to_string(),
+                name: "by-path".to_string(),
+                description: String::new(),
+                language: String::new(),
+                latest_commit_at: 0,
                 size: 0,
+                tags: Vec::new(),
+                commit_count: 0,
+                contributors: 0,
             };
+            save_repo_to_db(&Repo::new(repo_id.to_string(), desc, path.clone())).await?;
 
-            let repo = Repo::new(
-                format!("did:repo:test-{}", i),
-                desc,
-                PathBuf::from(format!("/tmp/test-repo-{}", i)),
+            assert_eq!(
+                find_repo_id_by_path(&path).await?,
+                Some(repo_id.to_string())
+            );
+            assert_eq!(
+                find_repo_id_by_path(Path::new("/tmp/megaengine-by-path-missing")).await?,
+                None
+            );
+            assert_eq!(
+                find_repo_ids_by_path(&path).await?,
+                vec![repo_id.to_string()]
             );
 
-            save_repo_to_db(&repo).await?;
-        }
+            delete_repo_from_db(repo_id).await?;
+            Ok(())
+        })
+        .await
+    }
 
-        // 列出所有 Repos
-        let repos = list_repos().await?;
-        assert!(repos.len() >= 3);
+    #[tokio::test]
+    async fn test_repo_tags_update_and_filter() -> Result<()> {
+        with_test_db(async {
+            let repo_id = "did:repo:tags-test";
+            let desc = crate::repo::repo::P2PDescription {
+                creator: "did:node:tags-test".to_string(),
+                name: "tagged-repo".to_string(),
+                description: String::new(),
+                language: "Rust".to_string(),
+                latest_commit_at: 0,
+                size: 0,
+                tags: crate::repo::repo::normalize_tags(["Rust", " p2p "]),
+                commit_count: 42,
+                contributors: 3,
+            };
+            save_repo_to_db(&Repo::new(repo_id.to_string(), desc, PathBuf::new())).await?;
+
+            let loaded = load_repo_from_db(repo_id).await?.unwrap();
+            assert_eq!(loaded.p2p_description.tags, vec!["p2p", "rust"]);
+            assert_eq!(loaded.p2p_description.commit_count, 42);
+            assert_eq!(loaded.p2p_description.contributors, 3);
+
+            let tags = update_repo_tags(repo_id, &["gossip".to_string()], &["RUST".to_string()])
+                .await?
+                .unwrap();
+            assert_eq!(tags, vec!["gossip", "p2p"]);
+
+            let by_tag = |tag: &str| RepoFilter {
+                creator: Some("did:node:tags-test".to_string()),
+                tag: Some(tag.to_string()),
+                ..Default::default()
+            };
+            assert_eq!(list_repos_paged(0, 0, by_tag("Gossip")).await?.len(), 1);
+            assert!(list_repos_paged(0, 0, by_tag("rust")).await?.is_empty());
+            // 带引号匹配，不会命中标签前缀
+            assert!(list_repos_paged(0, 0, by_tag("gos")).await?.is_empty());
+
+            assert!(search_repos("gossip")
+                .await?
+                .iter()
+                .any(|r| r.repo_id == repo_id));
+            assert!(update_repo_tags("did:repo:missing", &[], &[])
+                .await?
+                .is_none());
+
+            delete_repo_from_db(repo_id).await?;
+            Ok(())
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_list_repos_paged_filters() -> Result<()> {
+        with_test_db(async {
+            let creator = "did:node:paged-filter-test";
+            for (i, (name, language)) in [
+                ("alpha-core", "Rust"),
+                ("beta-web", "Go"),
+                ("alpha-cli", "rust"),
+            ]
+            .iter()
+            .enumerate()
+            {
+                let desc = crate::repo::repo::P2PDescription {
+                    creator: creator.to_string(),
+                    name: name.to_string(),
+                    description: String::new(),
+                    language: language.to_string(),
+                    latest_commit_at: 0,
+                    size: 0,
+                    tags: Vec::new(),
+                    commit_count: 0,
+                    contributors: 0,
+                };
+                let repo = Repo::new(format!("did:repo:paged{}", i), desc, PathBuf::new());
+                save_repo_to_db(&repo).await?;
+            }
+
+            let mine = RepoFilter {
+                creator: Some(creator.to_string()),
+                ..Default::default()
+            };
+            assert_eq!(list_repos_paged(0, 0, mine.clone()).await?.len(), 3);
+
+            let rust = RepoFilter {
+                language: Some("RUST".to_string()),
+                ..mine.clone()
+            };
+            assert_eq!(list_repos_paged(0, 0, rust).await?.len(), 2);
 
-        // 清理
-        for i in 0..3 {
-            delete_repo_from_db(&format!("did:repo:test-{}", i)).await?;
-        }
-        Ok(())
+            let alpha = RepoFilter {
+                name_contains: Some("alpha".to_string()),
+                is_external: Some(false),
+                ..mine.clone()
+            };
+            assert_eq!(list_repos_paged(0, 0, alpha).await?.len(), 2);
+
+            let first_page = list_repos_paged(0, 2, mine.clone()).await?;
+            let second_page = list_repos_paged(2, 2, mine).await?;
+            assert_eq!(first_page.len(), 2);
+            assert_eq!(second_page.len(), 1);
+            assert!(first_page
+                .iter()
+                .all(|r| r.repo_id != second_page[0].repo_id));
+
+            for i in 0..3 {
+                delete_repo_from_db(&format!("did:repo:paged{}", i)).await?;
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_search_repos_ranks_name_matches_first() -> Result<()> {
+        with_test_db(async {
+            let repos = [
+                ("did:repo:search0", "zephyr-parser", "A parser toolkit"),
+                (
+                    "did:repo:search1",
+                    "toolkit",
+                    "Parser helpers for zephyr configs",
+                ),
+                ("did:repo:search2", "unrelated", "Nothing to see"),
+            ];
+            for (id, name, description) in repos {
+                let desc = crate::repo::repo::P2PDescription {
+                    creator: "did:node:search-test".to_string(),
+                    name: name.to_string(),
+                    description: description.to_string(),
+                    language: "Rust".to_string(),
+                    latest_commit_at: 0,
+                    size: 0,
+                    tags: Vec::new(),
+                    commit_count: 0,
+                    contributors: 0,
+                };
+                save_repo_to_db(&Repo::new(id.to_string(), desc, PathBuf::new())).await?;
+            }
+
+            let found: Vec<String> = search_repos("Zephyr parser")
+                .await?
+                .into_iter()
+                .map(|r| r.repo_id)
+                .filter(|id| id.starts_with("did:repo:search"))
+                .collect();
+            assert_eq!(found, vec!["did:repo:search0", "did:repo:search1"]);
+
+            let exact: Vec<String> = search_repos("toolkit")
+                .await?
+                .into_iter()
+                .map(|r| r.repo_id)
+                .filter(|id| id.starts_with("did:repo:search"))
+                .collect();
+            assert_eq!(exact, vec!["did:repo:search1", "did:repo:search0"]);
+            assert!(search_repos("   ").await?.is_empty());
+
+            for (id, _, _) in repos {
+                delete_repo_from_db(id).await?;
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_list_repos() -> Result<()> {
+        with_test_db(async {
+            // 创建多个测试 Repos
+            for i in 0..3 {
+                let desc = crate::repo::repo::P2PDescription {
+                    creator: "did:node:test".to_string(),
+                    name: format!("test-repo-{}", i),
+                    description: format!("Test repository {}", i),
+                    language: "Rust".to_string(),
+                    latest_commit_at: 1000 + i,
+                    size: 0,
+                    tags: Vec::new(),
+                    commit_count: 0,
+                    contributors: 0,
+                };
+
+                let repo = Repo::new(
+                    format!("did:repo:test-{}", i),
+                    desc,
+                    PathBuf::from(format!("/tmp/test-repo-{}", i)),
+                );
+
+                save_repo_to_db(&repo).await?;
+            }
+
+            // 列出所有 Repos
+            let repos = list_repos().await?;
+            assert!(repos.len() >= 3);
+
+            // 清理
+            for i in 0..3 {
+                delete_repo_from_db(&format!("did:repo:test-{}", i)).await?;
+            }
+            Ok(())
+        })
+        .await
     }
 }
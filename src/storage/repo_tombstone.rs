@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use sea_orm::entity::prelude::*;
+use sea_orm::Set;
+
+use crate::gossip::message::SignedMessage;
+use crate::storage::get_db_conn;
+
+/// 仓库删除墓碑：保存创建者签名的 RepoDeletion 消息，用于周期性重新广播
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "repo_tombstones")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub repo_id: String,
+    pub creator: String,
+    /// 序列化后的 SignedMessage（JSON）
+    pub message: String,
+    pub created_at: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+/// 保存（或覆盖）仓库删除墓碑
+pub async fn save_tombstone(repo_id: &str, creator: &str, message: &SignedMessage) -> Result<()> {
+    let db = get_db_conn().await?;
+    let now = chrono::Local::now().timestamp();
+
+    let _ = Entity::delete_by_id(repo_id).exec(&db).await;
+
+    let active = ActiveModel {
+        repo_id: Set(repo_id.to_string()),
+        creator: Set(creator.to_string()),
+        message: Set(serde_json::to_string(message)?),
+        created_at: Set(now),
+    };
+    Entity::insert(active).exec(&db).await?;
+    Ok(())
+}
+
+/// 判断仓库是否已有删除墓碑
+pub async fn has_tombstone(repo_id: &str) -> Result<bool> {
+    let db = get_db_conn().await?;
+    Ok(Entity::find_by_id(repo_id).one(&db).await?.is_some())
+}
+
+/// 查询仓库墓碑记录的创建者（删除消息的签名者）
+pub async fn tombstone_creator(repo_id: &str) -> Result<Option<String>> {
+    let db = get_db_conn().await?;
+    Ok(Entity::find_by_id(repo_id)
+        .one(&db)
+        .await?
+        .map(|model| model.creator))
+}
+
+/// 批量查询仓库的删除墓碑（单次查询），返回 repo_id 到 (创建者, 删除消息签名时间) 的映射
+pub async fn tombstone_times(repo_ids: &[String]) -> Result<HashMap<String, (String, i64)>> {
+    if repo_ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let db = get_db_conn().await?;
+    let models = Entity::find()
+        .filter(Column::RepoId.is_in(repo_ids.iter().cloned()))
+        .all(&db)
+        .await?;
+    Ok(models
+        .into_iter()
+        .map(|m| {
+            let deleted_at = serde_json::from_str::<SignedMessage>(&m.message)
+                .map(|msg| msg.timestamp())
+                .unwrap_or(m.created_at);
+            (m.repo_id, (m.creator, deleted_at))
+        })
+        .collect())
+}
+
+/// 删除仓库墓碑（例如仓库被重新添加时）
+pub async fn delete_tombstone(repo_id: &str) -> Result<()> {
+    let db = get_db_conn().await?;
+    Entity::delete_by_id(repo_id).exec(&db).await?;
+    Ok(())
+}
+
+/// 删除保存超过 max_age_secs 的墓碑，返回删除的条数
+pub async fn cleanup_tombstones(max_age_secs: i64) -> Result<u64> {
+    let db = get_db_conn().await?;
+    let cutoff = chrono::Local::now().timestamp() - max_age_secs;
+    let result = Entity::delete_many()
+        .filter(Column::CreatedAt.lt(cutoff))
+        .exec(&db)
+        .await?;
+    Ok(result.rows_affected)
+}
+
+/// 列出所有墓碑中保存的已签名删除消息
+pub async fn list_tombstone_messages() -> Result<Vec<SignedMessage>> {
+    let db = get_db_conn().await?;
+    let models = Entity::find().all(&db).await?;
+
+    let mut messages = Vec::new();
+    for model in models {
+        match serde_json::from_str::<SignedMessage>(&model.message) {
+            Ok(msg) => messages.push(msg),
+            Err(e) => {
+                tracing::warn!(
+                    "Invalid tombstone message for repo {}: {}",
+                    model.repo_id,
+                    e
+                );
+            }
+        }
+    }
+    Ok(messages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::keypair::KeyPair;
+    use crate::storage::with_test_db;
+
+    #[tokio::test]
+    async fn test_save_and_list_tombstone() -> Result<()> {
+        with_test_db(async {
+            let keypair = KeyPair::generate()?;
+            let repo_id = "did:repo:tombstone-test";
+            let signed =
+                SignedMessage::new_repo_deletion_sign_message(repo_id.to_string(), &keypair)?;
+
+            save_tombstone(repo_id, signed.node_id.as_str(), &signed).await?;
+            assert!(has_tombstone(repo_id).await?);
+
+            let messages = list_tombstone_messages().await?;
+            assert!(messages
+                .iter()
+                .any(|m| m.signature == signed.signature && m.node_id == signed.node_id));
+
+            let times = tombstone_times(&[repo_id.to_string()]).await?;
+            assert_eq!(
+                times.get(repo_id),
+                Some(&(signed.node_id.to_string(), signed.timestamp()))
+            );
+            assert_eq!(
+                tombstone_creator(repo_id).await?,
+                Some(signed.node_id.to_string())
+            );
+
+            delete_tombstone(repo_id).await?;
+            assert!(!has_tombstone(repo_id).await?);
+            Ok(())
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_cleanup_tombstones() -> Result<()> {
+        with_test_db(async {
+            let keypair = KeyPair::generate()?;
+            let repo_id = "did:repo:tombstone-expired";
+            let signed =
+                SignedMessage::new_repo_deletion_sign_message(repo_id.to_string(), &keypair)?;
+            save_tombstone(repo_id, signed.node_id.as_str(), &signed).await?;
+
+            cleanup_tombstones(3600).await?;
+            assert!(has_tombstone(repo_id).await?);
+            cleanup_tombstones(-1).await?;
+            assert!(!has_tombstone(repo_id).await?);
+            Ok(())
+        })
+        .await
+    }
+}
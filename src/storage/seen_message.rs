@@ -0,0 +1,75 @@
+use anyhow::Result;
+use sea_orm::{ConnectionTrait, DbBackend, Statement};
+
+use crate::storage::get_db_conn;
+
+/// 记录节点已处理过的 gossip 消息 ID
+///
+/// 返回 true 表示首次见到该消息，false 表示已处理过（应丢弃）
+pub async fn mark_seen(node_id: &str, msg_id: &str) -> Result<bool> {
+    let db = get_db_conn().await?;
+    let now = chrono::Local::now().timestamp();
+
+    let result = db
+        .execute(Statement::from_sql_and_values(
+            DbBackend::Sqlite,
+            "INSERT OR IGNORE INTO seen (node_id, msg_id, ts) VALUES (?, ?, ?)",
+            [node_id.into(), msg_id.into(), now.into()],
+        ))
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// 节点是否已处理过该消息（只查询，不记录）
+pub async fn is_seen(node_id: &str, msg_id: &str) -> Result<bool> {
+    let db = get_db_conn().await?;
+    let row = db
+        .query_one(Statement::from_sql_and_values(
+            DbBackend::Sqlite,
+            "SELECT 1 FROM seen WHERE node_id = ? AND msg_id = ?",
+            [node_id.into(), msg_id.into()],
+        ))
+        .await?;
+    Ok(row.is_some())
+}
+
+/// 删除早于 max_age_secs 的去重记录，返回删除的条数
+pub async fn cleanup_seen(max_age_secs: i64) -> Result<u64> {
+    let db = get_db_conn().await?;
+    let cutoff = chrono::Local::now().timestamp() - max_age_secs;
+
+    let result = db
+        .execute(Statement::from_sql_and_values(
+            DbBackend::Sqlite,
+            "DELETE FROM seen WHERE ts < ?",
+            [cutoff.into()],
+        ))
+        .await?;
+    Ok(result.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::with_test_db;
+
+    #[tokio::test]
+    async fn test_mark_seen_dedups_per_node() -> Result<()> {
+        with_test_db(async {
+            let msg_id = uuid::Uuid::new_v4().to_string();
+
+            assert!(!is_seen("did:node:seen-a", &msg_id).await?);
+            assert!(mark_seen("did:node:seen-a", &msg_id).await?);
+            assert!(is_seen("did:node:seen-a", &msg_id).await?);
+            assert!(!mark_seen("did:node:seen-a", &msg_id).await?);
+            // 同一数据库中的不同节点各自去重
+            assert!(mark_seen("did:node:seen-b", &msg_id).await?);
+
+            // 负的保留时长会清理掉所有记录，消息可以再次被接收
+            cleanup_seen(-1).await?;
+            assert!(mark_seen("did:node:seen-a", &msg_id).await?);
+            Ok(())
+        })
+        .await
+    }
+}
@@ -1,7 +1,13 @@
+use crate::identity::keypair::KeyPair;
 use anyhow::{anyhow, Result};
 use libvault::utils::cert::Certificate;
-use openssl::pkey::{PKey, Private};
-use openssl::x509::X509;
+use openssl::asn1::Asn1Time;
+use openssl::bn::{BigNum, MsbOption};
+use openssl::hash::MessageDigest;
+use openssl::pkey::{Id, PKey, Private};
+use openssl::x509::extension::SubjectAlternativeName;
+use openssl::x509::{X509NameBuilder, X509};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
 use std::fs;
 use std::path::Path;
 use std::time::{Duration, SystemTime};
@@ -118,8 +124,108 @@ pub fn generate_server_cert(
     Ok(())
 }
 
+/// Build a self-signed Ed25519 certificate from the node's identity keypair.
+///
+/// The certificate's public key is the node's verifying key, so peers can pin it
+/// against the expected NodeId instead of trusting a CA.
+pub fn build_node_certificate(
+    keypair: &KeyPair,
+) -> Result<(CertificateDer<'static>, PrivateKeyDer<'static>)> {
+    let pkey = PKey::private_key_from_raw_bytes(&keypair.signing_key_bytes()?, Id::ED25519)
+        .map_err(|e| anyhow!("Failed to load Ed25519 key: {}", e))?;
+
+    let mut name = X509NameBuilder::new()?;
+    name.append_entry_by_text("CN", "megaengine-node")?;
+    let name = name.build();
+
+    let mut serial = BigNum::new()?;
+    serial.rand(128, MsbOption::MAYBE_ZERO, false)?;
+
+    let mut builder = X509::builder()?;
+    builder.set_version(2)?;
+    let serial = serial.to_asn1_integer()?;
+    builder.set_serial_number(&serial)?;
+    builder.set_subject_name(&name)?;
+    builder.set_issuer_name(&name)?;
+    builder.set_pubkey(&pkey)?;
+    let not_before = Asn1Time::days_from_now(0)?;
+    let not_after = Asn1Time::days_from_now(3650)?;
+    builder.set_not_before(&not_before)?;
+    builder.set_not_after(&not_after)?;
+    let san = SubjectAlternativeName::new()
+        .dns("localhost")
+        .build(&builder.x509v3_context(None, None))?;
+    builder.append_extension(san)?;
+    // Ed25519 签名不使用单独的摘要算法
+    builder.sign(&pkey, MessageDigest::null())?;
+
+    let cert = CertificateDer::from(builder.build().to_der()?);
+    let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(pkey.private_key_to_pkcs8()?));
+    Ok((cert, key))
+}
+
+/// Extract the raw Ed25519 public key from a DER-encoded certificate.
+pub fn certificate_ed25519_public_key(cert: &CertificateDer<'_>) -> Result<[u8; 32]> {
+    let cert =
+        X509::from_der(cert.as_ref()).map_err(|e| anyhow!("Failed to parse certificate: {}", e))?;
+    let pkey = cert.public_key()?;
+    if pkey.id() != Id::ED25519 {
+        return Err(anyhow!("Certificate key is not Ed25519"));
+    }
+    let raw = pkey.raw_public_key()?;
+    <[u8; 32]>::try_from(raw.as_slice()).map_err(|_| anyhow!("Invalid Ed25519 key length"))
+}
+
+/// Whether the certificate parses but does not carry an Ed25519 key, i.e. a
+/// CA-issued file certificate from a node that predates identity certificates.
+pub fn is_legacy_certificate(cert: &CertificateDer<'_>) -> bool {
+    X509::from_der(cert.as_ref())
+        .and_then(|cert| cert.public_key())
+        .is_ok_and(|key| key.id() != Id::ED25519)
+}
+
+/// Certificates expiring within this many days are regenerated by `ensure_certificates`.
+pub const CERT_RENEW_BEFORE_DAYS: i32 = 30;
+
+/// Whole days until the PEM certificate at `cert_path` expires (negative once expired).
+pub fn certificate_days_remaining(cert_path: &str) -> Result<i32> {
+    let pem = fs::read(cert_path)?;
+    let cert = X509::from_pem(&pem).map_err(|e| anyhow!("Failed to parse certificate: {}", e))?;
+    let now = Asn1Time::days_from_now(0)?;
+    Ok(now.diff(cert.not_after())?.days)
+}
+
+/// Whether the certificate is missing, unreadable or expires within `within_days`.
+fn needs_renewal(cert_path: &str, within_days: i32) -> bool {
+    match certificate_days_remaining(cert_path) {
+        Ok(days) => days < within_days,
+        Err(_) => true,
+    }
+}
+
+fn remove_pair(cert_path: &str, key_path: &str) {
+    let _ = fs::remove_file(cert_path);
+    let _ = fs::remove_file(key_path);
+}
+
 /// Ensure certificates exist: generate CA once, then generate different server certs.
+///
+/// Existing certificates that expire within `CERT_RENEW_BEFORE_DAYS` are regenerated;
+/// a renewed CA also renews the server certificate it signed.
 pub fn ensure_certificates(cert_path: &str, key_path: &str, ca_cert_path: &str) -> Result<()> {
+    renew_certificates(cert_path, key_path, ca_cert_path, false).map(|_| ())
+}
+
+/// Regenerate the server certificate (and the CA if it is expiring), returning
+/// whether the server certificate was replaced.
+///
+/// With `force` the server certificate is rotated even if it is still valid.
+pub fn renew_certificates(
+    cert_path: &str,
+    key_path: &str,
+    ca_cert_path: &str,
+    force: bool,
+) -> Result<bool> {
     // Derive CA key path from CA cert path
     let ca_key_path = ca_cert_path.replace(".pem", "-key.pem");
 
@@ -129,8 +235,31 @@ pub fn ensure_certificates(cert_path: &str, key_path: &str, ca_cert_path: &str)
 
     if cert_exists != key_exists {
         // Mismatch - delete both and regenerate
-        let _ = std::fs::remove_file(cert_path);
-        let _ = std::fs::remove_file(key_path);
+        remove_pair(cert_path, key_path);
+    }
+
+    // 旧证书过期或即将过期时重新生成；CA 更换后由旧 CA 签发的服务器证书也一并更换
+    if Path::new(ca_cert_path).exists() && needs_renewal(ca_cert_path, CERT_RENEW_BEFORE_DAYS) {
+        tracing::warn!(
+            "CA certificate {} expires within {} days, regenerating",
+            ca_cert_path,
+            CERT_RENEW_BEFORE_DAYS
+        );
+        remove_pair(ca_cert_path, &ca_key_path);
+        remove_pair(cert_path, key_path);
+    }
+    if Path::new(cert_path).exists() {
+        if force {
+            tracing::info!("Rotating server certificate {}", cert_path);
+            remove_pair(cert_path, key_path);
+        } else if needs_renewal(cert_path, CERT_RENEW_BEFORE_DAYS) {
+            tracing::warn!(
+                "Server certificate {} expires within {} days, regenerating",
+                cert_path,
+                CERT_RENEW_BEFORE_DAYS
+            );
+            remove_pair(cert_path, key_path);
+        }
     }
 
     // Generate CA certificate if needed (only once)
@@ -141,11 +270,12 @@ pub fn ensure_certificates(cert_path: &str, key_path: &str, ca_cert_path: &str)
 
     // Generate server certificate signed by CA
     // If server cert and key don't both exist, regenerate them
-    if !cert_exists || !key_exists {
+    let renewed = !Path::new(cert_path).exists() || !Path::new(key_path).exists();
+    if renewed {
         generate_server_cert(cert_path, key_path, &ca_cert, &ca_key_path)?;
     }
 
-    Ok(())
+    Ok(renewed)
 }
 
 #[cfg(test)]
@@ -266,4 +396,66 @@ mod tests {
 
         let _ = std::fs::remove_dir_all(&dir);
     }
+
+    #[test]
+    fn test_build_node_certificate_pins_keypair() {
+        let keypair = KeyPair::generate().expect("generate keypair");
+        let (cert, _key) = build_node_certificate(&keypair).expect("build node cert");
+
+        let public_key = certificate_ed25519_public_key(&cert).expect("extract public key");
+        assert_eq!(public_key, keypair.verifying_key_bytes());
+    }
+
+    /// Write a self-signed certificate that expires in `days` over the server cert files
+    fn write_short_lived_cert(cert_path: &Path, key_path: &Path, days: u32) {
+        let pkey = PKey::from_rsa(openssl::rsa::Rsa::generate(2048).unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", "short-lived").unwrap();
+        let name = name.build();
+        let mut builder = X509::builder().unwrap();
+        builder.set_version(2).unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder.set_issuer_name(&name).unwrap();
+        builder.set_pubkey(&pkey).unwrap();
+        builder
+            .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&Asn1Time::days_from_now(days).unwrap())
+            .unwrap();
+        builder.sign(&pkey, MessageDigest::sha256()).unwrap();
+        std::fs::write(cert_path, builder.build().to_pem().unwrap()).unwrap();
+        std::fs::write(key_path, pkey.private_key_to_pem_pkcs8().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_expiring_certificate_is_renewed() {
+        let dir = test_dir("cert-renew");
+        std::fs::create_dir_all(&dir).expect("create temp dir");
+
+        let cert_path = dir.join("cert.pem");
+        let key_path = dir.join("key.pem");
+        let ca_cert_path = dir.join("ca-cert.pem");
+        let cert = cert_path.to_str().expect("cert path utf8");
+        let key = key_path.to_str().expect("key path utf8");
+        let ca = ca_cert_path.to_str().expect("ca cert path utf8");
+
+        ensure_certificates(cert, key, ca).expect("first ensure");
+        assert!(certificate_days_remaining(cert).unwrap() > 3000);
+
+        // 即将过期的证书在下一次 ensure 时被替换
+        write_short_lived_cert(&cert_path, &key_path, 5);
+        assert!((4..=5).contains(&certificate_days_remaining(cert).unwrap()));
+        ensure_certificates(cert, key, ca).expect("renewing ensure");
+        assert!(certificate_days_remaining(cert).unwrap() > 3000);
+
+        // 未到期时只有 force 才会轮换
+        let before = std::fs::read(&cert_path).unwrap();
+        assert!(!renew_certificates(cert, key, ca, false).unwrap());
+        assert_eq!(std::fs::read(&cert_path).unwrap(), before);
+        assert!(renew_certificates(cert, key, ca, true).unwrap());
+        assert_ne!(std::fs::read(&cert_path).unwrap(), before);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 }
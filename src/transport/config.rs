@@ -1,6 +1,12 @@
-use anyhow::Result;
+use crate::identity::keypair::KeyPair;
+use crate::node::node_id::NodeId;
+use crate::transport::cert::{
+    build_node_certificate, certificate_ed25519_public_key, is_legacy_certificate,
+};
+use anyhow::{anyhow, Result};
 use quinn::crypto::rustls::{QuicClientConfig, QuicServerConfig};
 use quinn::{ClientConfig, IdleTimeout, ServerConfig, TransportConfig, VarInt};
+use rustls::client::danger::ServerCertVerifier;
 use rustls::pki_types::{CertificateDer, PrivateKeyDer};
 use std::fs::File;
 use std::io::BufReader;
@@ -9,6 +15,18 @@ use std::sync::Arc;
 use std::time::Duration;
 
 pub const ALPN_QUIC_HTTP: &[&[u8]] = &[b"h3"];
+/// gossip 消息的默认大小上限
+pub const DEFAULT_MAX_GOSSIP_MESSAGE_SIZE: usize = 256 * 1024;
+/// 数据消息（bundle 分块等）的默认大小上限
+pub const DEFAULT_MAX_DATA_MESSAGE_SIZE: usize = 1024 * 1024;
+/// 连接空闲超时的默认值
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+/// 连接保活包的默认发送间隔
+pub const DEFAULT_KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(30);
+/// 大块传输预设的单流接收窗口
+pub const BULK_STREAM_RECEIVE_WINDOW: u64 = 8 * 1024 * 1024;
+/// 大块传输预设的连接接收窗口和发送窗口
+pub const BULK_CONNECTION_WINDOW: u64 = 32 * 1024 * 1024;
 
 /// 用于开发/测试环境的服务器证书验证器
 /// 跳过所有服务器证书验证，允许自签名证书和不同的 CA
@@ -47,21 +65,148 @@ impl rustls::client::danger::ServerCertVerifier for NoServerCertificateVerificat
     }
 
     fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
-        let provider = rustls::crypto::CryptoProvider::get_default()
-            .cloned()
-            .unwrap_or(Arc::new(rustls::crypto::ring::default_provider()));
-        provider
+        crypto_provider()
             .signature_verification_algorithms
             .supported_schemes()
     }
 }
 
+/// 获取当前安装的 rustls CryptoProvider，未安装时使用 ring
+fn crypto_provider() -> Arc<rustls::crypto::CryptoProvider> {
+    rustls::crypto::CryptoProvider::get_default()
+        .cloned()
+        .unwrap_or(Arc::new(rustls::crypto::ring::default_provider()))
+}
+
+/// 基于 did:key 的服务器证书验证器
+/// 要求对端证书的公钥（SPKI）与目标 NodeId 中的 Ed25519 公钥一致，防止中间人攻击
+#[derive(Debug)]
+pub struct PinnedNodeVerifier {
+    expected_key: [u8; 32],
+    /// 是否接受旧版节点使用的非 Ed25519 文件证书
+    allow_legacy: bool,
+    provider: Arc<rustls::crypto::CryptoProvider>,
+}
+
+impl PinnedNodeVerifier {
+    pub fn new(peer_id: &NodeId) -> Result<Self> {
+        Ok(Self {
+            expected_key: peer_id.to_keypair()?.verifying_key_bytes(),
+            allow_legacy: false,
+            provider: crypto_provider(),
+        })
+    }
+
+    /// 接受旧版节点的非 Ed25519 证书，此时对端身份只由连接后的签名握手保证；
+    /// Ed25519 证书的公钥仍必须与 NodeId 一致
+    pub fn with_legacy_certs(mut self, allow: bool) -> Self {
+        self.allow_legacy = allow;
+        self
+    }
+}
+
+impl ServerCertVerifier for PinnedNodeVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &rustls::pki_types::ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: rustls::pki_types::UnixTime,
+    ) -> Result<rustls::client::danger::ServerCertVerified, rustls::Error> {
+        let public_key = match certificate_ed25519_public_key(end_entity) {
+            Ok(key) => key,
+            Err(_) if self.allow_legacy && is_legacy_certificate(end_entity) => {
+                return Ok(rustls::client::danger::ServerCertVerified::assertion());
+            }
+            Err(_) => {
+                return Err(rustls::Error::InvalidCertificate(
+                    rustls::CertificateError::BadEncoding,
+                ))
+            }
+        };
+
+        if public_key != self.expected_key {
+            return Err(rustls::Error::InvalidCertificate(
+                rustls::CertificateError::ApplicationVerificationFailure,
+            ));
+        }
+        Ok(rustls::client::danger::ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+/// 将窗口大小转换为 QUIC 变长整数，超出范围时报错
+fn window_varint(window: u64, name: &str) -> Result<VarInt> {
+    VarInt::from_u64(window).map_err(|_| anyhow!("{} {} is too large", name, window))
+}
+
 #[derive(Clone, Debug)]
 pub struct QuicConfig {
     pub bind_addr: SocketAddr,
+    /// 额外监听的地址（如同时监听 IPv4 和 IPv6），绑定失败的地址只告警
+    pub extra_bind_addrs: Vec<SocketAddr>,
     pub cert_path: String,
     pub key_path: String,
     pub ca_cert_path: String,
+    /// 是否校验对端证书公钥与目标 NodeId 一致（默认关闭）
+    pub peer_verification: bool,
+    /// 节点身份密钥，开启 peer_verification 时用于生成 TLS 证书
+    pub identity: Option<KeyPair>,
+    /// 开启 peer_verification 时是否仍接受旧版节点的文件证书，用于混合版本网络的过渡
+    pub allow_legacy_peer_certs: bool,
+    /// 接收 gossip 消息的大小上限，超过的消息在反序列化前丢弃
+    pub max_gossip_message_size: usize,
+    /// 接收数据消息的大小上限，与 gossip 上限相互独立
+    pub max_data_message_size: usize,
+    /// 是否为未直连的节点转发数据消息（中继节点开启）
+    pub relay_forwarding: bool,
+    /// 信任的中继节点：它们转交的数据总是接受；其他节点只有登记为 Relay 类型、
+    /// 且最近报告与来源直连时才接受
+    pub trusted_relays: Vec<NodeId>,
+    /// 连接在此时间内没有收到任何数据即关闭，服务端与客户端共用
+    pub idle_timeout: Duration,
+    /// 保活包的发送间隔，必须小于 idle_timeout；为零时不发送保活包
+    pub keep_alive_interval: Duration,
+    /// 整个连接的接收窗口，None 时使用 quinn 默认值
+    pub receive_window: Option<u64>,
+    /// 单个流的接收窗口，None 时使用 quinn 默认值
+    pub stream_receive_window: Option<u64>,
+    /// 整个连接的发送窗口，None 时使用 quinn 默认值
+    pub send_window: Option<u64>,
 }
 
 impl QuicConfig {
@@ -73,17 +218,152 @@ impl QuicConfig {
     ) -> Self {
         QuicConfig {
             bind_addr,
+            extra_bind_addrs: Vec::new(),
             cert_path,
             key_path,
             ca_cert_path,
+            peer_verification: false,
+            identity: None,
+            allow_legacy_peer_certs: false,
+            max_gossip_message_size: DEFAULT_MAX_GOSSIP_MESSAGE_SIZE,
+            max_data_message_size: DEFAULT_MAX_DATA_MESSAGE_SIZE,
+            relay_forwarding: false,
+            trusted_relays: Vec::new(),
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+            keep_alive_interval: DEFAULT_KEEP_ALIVE_INTERVAL,
+            receive_window: None,
+            stream_receive_window: None,
+            send_window: None,
+        }
+    }
+
+    /// 设置额外监听的地址
+    pub fn with_extra_bind_addrs(mut self, addrs: Vec<SocketAddr>) -> Self {
+        self.extra_bind_addrs = addrs;
+        self
+    }
+
+    /// 开启/关闭基于 NodeId 的对端证书校验
+    pub fn with_peer_verification(mut self, enabled: bool) -> Self {
+        self.peer_verification = enabled;
+        self
+    }
+
+    /// 设置节点身份密钥，开启 peer_verification 时使用它生成 TLS 证书
+    pub fn with_identity(mut self, keypair: KeyPair) -> Self {
+        self.identity = Some(keypair);
+        self
+    }
+
+    /// 设置是否接受旧版节点的文件证书（非 Ed25519），默认不接受
+    pub fn with_legacy_peer_certs(mut self, allow: bool) -> Self {
+        self.allow_legacy_peer_certs = allow;
+        self
+    }
+
+    /// 设置接收 gossip 消息的大小上限
+    pub fn with_max_gossip_message_size(mut self, size: usize) -> Self {
+        self.max_gossip_message_size = size;
+        self
+    }
+
+    /// 设置接收数据消息的大小上限
+    pub fn with_max_data_message_size(mut self, size: usize) -> Self {
+        self.max_data_message_size = size;
+        self
+    }
+
+    /// 开启/关闭中继转发
+    pub fn with_relay_forwarding(mut self, enabled: bool) -> Self {
+        self.relay_forwarding = enabled;
+        self
+    }
+
+    /// 设置信任的中继节点
+    pub fn with_trusted_relays(mut self, relays: Vec<NodeId>) -> Self {
+        self.trusted_relays = relays;
+        self
+    }
+
+    /// 设置连接空闲超时
+    pub fn with_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// 设置保活包的发送间隔，为零时不发送
+    pub fn with_keep_alive_interval(mut self, interval: Duration) -> Self {
+        self.keep_alive_interval = interval;
+        self
+    }
+
+    /// 设置流控窗口（字节），传 None 的项保持 quinn 默认值
+    pub fn with_flow_control_windows(
+        mut self,
+        receive_window: Option<u64>,
+        stream_receive_window: Option<u64>,
+        send_window: Option<u64>,
+    ) -> Self {
+        self.receive_window = receive_window;
+        self.stream_receive_window = stream_receive_window;
+        self.send_window = send_window;
+        self
+    }
+
+    /// 大块传输预设：放大流控窗口，减少传输大 bundle 时等待对端放开窗口的次数
+    pub fn for_bulk_transfer(self) -> Self {
+        self.with_flow_control_windows(
+            Some(BULK_CONNECTION_WINDOW),
+            Some(BULK_STREAM_RECEIVE_WINDOW),
+            Some(BULK_CONNECTION_WINDOW),
+        )
+    }
+
+    /// 按空闲超时、保活间隔和流控窗口生成传输参数，服务端和客户端使用同一份设置
+    fn transport_config(&self) -> Result<TransportConfig> {
+        if self.idle_timeout.is_zero() {
+            return Err(anyhow!("Idle timeout must be greater than zero"));
         }
+        if self.keep_alive_interval >= self.idle_timeout {
+            return Err(anyhow!(
+                "Keep-alive interval ({:?}) must be shorter than the idle timeout ({:?})",
+                self.keep_alive_interval,
+                self.idle_timeout
+            ));
+        }
+        let idle_timeout = IdleTimeout::try_from(self.idle_timeout)
+            .map_err(|_| anyhow!("Idle timeout {:?} is too large", self.idle_timeout))?;
+
+        let mut transport_config = TransportConfig::default();
+        transport_config.max_idle_timeout(Some(idle_timeout));
+        transport_config.keep_alive_interval(
+            (!self.keep_alive_interval.is_zero()).then_some(self.keep_alive_interval),
+        );
+        if let Some(window) = self.receive_window {
+            transport_config.receive_window(window_varint(window, "Receive window")?);
+        }
+        if let Some(window) = self.stream_receive_window {
+            transport_config.stream_receive_window(window_varint(window, "Stream receive window")?);
+        }
+        if let Some(window) = self.send_window {
+            transport_config.send_window(window);
+        }
+        Ok(transport_config)
+    }
+
+    /// 单条消息流允许读取的最大字节数（含消息前缀和中继信封中的 NodeId）
+    pub(crate) fn max_frame_size(&self) -> usize {
+        const PREFIX_ALLOWANCE: usize = 256;
+        self.max_gossip_message_size
+            .max(self.max_data_message_size)
+            .saturating_add(PREFIX_ALLOWANCE)
     }
 
     /// 获取服务器配置
     /// 注意：不验证客户端证书，仅适用于开发/测试环境
     /// 生产环境应该使用正确的 CA 证书验证
     pub fn get_server_config(&self) -> Result<ServerConfig> {
-        let (certs, key) = self.get_certificate_from_file()?;
+        let (certs, key) = self.get_certificate()?;
 
         let mut server_crypto = rustls::ServerConfig::builder()
             .with_no_client_auth() // 不验证客户端证书
@@ -94,10 +374,7 @@ impl QuicConfig {
         let mut server_config =
             ServerConfig::with_crypto(Arc::new(QuicServerConfig::try_from(server_crypto)?));
 
-        let mut transport_config = TransportConfig::default();
-        transport_config.max_idle_timeout(Some(IdleTimeout::from(VarInt::from_u32(300_000))));
-        transport_config.keep_alive_interval(Some(Duration::from_secs(30)));
-        server_config.transport_config(Arc::new(transport_config));
+        server_config.transport_config(Arc::new(self.transport_config()?));
 
         Ok(server_config)
     }
@@ -106,13 +383,24 @@ impl QuicConfig {
     /// 注意：使用不验证服务器证书的配置，仅适用于开发/测试环境
     /// 生产环境应该使用正确的 CA 证书验证
     pub fn get_client_config(&self) -> Result<ClientConfig> {
-        let (certs, key) = self.get_certificate_from_file()?;
-
         // 创建一个不验证服务器证书的客户端配置
         // 这对于开发/测试环境很有用，当每个节点都有独立的证书时
+        self.build_client_config(Arc::new(NoServerCertificateVerification))
+    }
+
+    /// 获取连接指定节点用的客户端配置：对端证书公钥必须与 peer_id 一致
+    pub fn get_pinned_client_config(&self, peer_id: &NodeId) -> Result<ClientConfig> {
+        let verifier =
+            PinnedNodeVerifier::new(peer_id)?.with_legacy_certs(self.allow_legacy_peer_certs);
+        self.build_client_config(Arc::new(verifier))
+    }
+
+    fn build_client_config(&self, verifier: Arc<dyn ServerCertVerifier>) -> Result<ClientConfig> {
+        let (certs, key) = self.get_certificate()?;
+
         let mut client_crypto = rustls::ClientConfig::builder()
             .dangerous()
-            .with_custom_certificate_verifier(Arc::new(NoServerCertificateVerification))
+            .with_custom_certificate_verifier(verifier)
             .with_client_auth_cert(certs, key)?;
 
         client_crypto.alpn_protocols = ALPN_QUIC_HTTP.iter().map(|&x| x.into()).collect();
@@ -120,14 +408,28 @@ impl QuicConfig {
         let mut client_config =
             ClientConfig::new(Arc::new(QuicClientConfig::try_from(client_crypto)?));
 
-        let mut transport_config = TransportConfig::default();
-        transport_config.max_idle_timeout(Some(IdleTimeout::from(VarInt::from_u32(300_000))));
-        transport_config.keep_alive_interval(Some(Duration::from_secs(30)));
-        client_config.transport_config(Arc::new(transport_config));
+        client_config.transport_config(Arc::new(self.transport_config()?));
 
         Ok(client_config)
     }
 
+    /// 获取本节点使用的证书和密钥
+    /// 开启 peer_verification 时由身份密钥生成，否则从文件读取
+    pub fn get_certificate(
+        &self,
+    ) -> Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)> {
+        if !self.peer_verification {
+            return self.get_certificate_from_file();
+        }
+
+        let keypair = self
+            .identity
+            .as_ref()
+            .ok_or_else(|| anyhow!("Peer verification requires an identity keypair"))?;
+        let (cert, key) = build_node_certificate(keypair)?;
+        Ok((vec![cert], key))
+    }
+
     /// 从文件读取证书和密钥
     pub fn get_certificate_from_file(
         &self,
@@ -0,0 +1,103 @@
+use std::io;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// 帧头长度：4 字节大端无符号整数，表示随后负载的字节数
+pub const FRAME_HEADER_LEN: usize = 4;
+
+/// 写入一帧：长度前缀 + 负载
+pub async fn write_frame<W>(writer: &mut W, payload: &[u8]) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let len = u32::try_from(payload.len()).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("frame too large ({} bytes)", payload.len()),
+        )
+    })?;
+    writer.write_all(&len.to_be_bytes()).await?;
+    writer.write_all(payload).await
+}
+
+/// 读取一帧的负载，可以连续调用以读出同一条流上的多帧
+///
+/// 流在帧边界处结束时返回 `Ok(None)`；帧头或负载读到一半结束时返回 `UnexpectedEof`，
+/// 负载超过 `max_len` 时返回 `InvalidData`，此时不会读取负载
+pub async fn read_frame<R>(reader: &mut R, max_len: usize) -> io::Result<Option<Vec<u8>>>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut header = [0u8; FRAME_HEADER_LEN];
+    let mut filled = 0;
+    while filled < header.len() {
+        let read = reader.read(&mut header[filled..]).await?;
+        if read == 0 {
+            if filled == 0 {
+                return Ok(None);
+            }
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "stream ended inside a frame header",
+            ));
+        }
+        filled += read;
+    }
+
+    let len = u32::from_be_bytes(header) as usize;
+    if len > max_len {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("frame of {} bytes exceeds the limit of {}", len, max_len),
+        ));
+    }
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload).await?;
+    Ok(Some(payload))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_frames_survive_partial_reads() {
+        // 管道容量只有 3 字节，每次读取最多拿到 3 字节，帧头和负载都会被拆开
+        let (mut writer, mut reader) = tokio::io::duplex(3);
+        let frames: Vec<Vec<u8>> = vec![b"hello".to_vec(), Vec::new(), vec![7u8; 1000]];
+        let expected = frames.clone();
+        let write = tokio::spawn(async move {
+            for frame in &frames {
+                write_frame(&mut writer, frame).await.unwrap();
+            }
+        });
+
+        for frame in expected {
+            assert_eq!(read_frame(&mut reader, 1024).await.unwrap(), Some(frame));
+        }
+        write.await.unwrap();
+        assert_eq!(read_frame(&mut reader, 1024).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_truncated_and_oversized_frames() {
+        let mut encoded = Vec::new();
+        write_frame(&mut encoded, b"payload").await.unwrap();
+
+        // 帧头不完整
+        let mut truncated = &encoded[..2];
+        let err = read_frame(&mut truncated, 1024).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        // 负载不完整
+        let mut truncated = &encoded[..encoded.len() - 1];
+        let err = read_frame(&mut truncated, 1024).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        let mut oversized = &encoded[..];
+        let err = read_frame(&mut oversized, 4).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let mut empty: &[u8] = &[];
+        assert_eq!(read_frame(&mut empty, 1024).await.unwrap(), None);
+    }
+}
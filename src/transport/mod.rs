@@ -1,3 +1,4 @@
 pub mod cert;
 pub mod config;
+pub mod framing;
 pub mod quic;
@@ -1,38 +1,161 @@
+use crate::error::{MegaError, Result as MegaResult};
 use crate::node::node_id::NodeId;
 use crate::transport::config::QuicConfig;
-use anyhow::{Context, Result};
-use quinn::{Connection, Endpoint, Incoming};
-use std::collections::HashMap;
+use crate::transport::framing::{read_frame, write_frame};
+use anyhow::Result;
+use ed25519_dalek::Signature;
+use quinn::{Connection, Endpoint, Incoming, VarInt};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use tokio::sync::mpsc::Receiver;
-use tokio::sync::{mpsc, Mutex};
-use tracing::{error, info};
+use tokio::sync::{mpsc, oneshot, Mutex};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+use tracing::{error, info, warn};
 
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc::Sender as TokioSender;
 
 const READ_BUF_SIZE: usize = 1024 * 1024;
+const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
 const CONNECTION_CLEANUP_INTERVAL: Duration = Duration::from_secs(30);
 
+// 节点正常关闭时使用的应用层关闭码
+const SHUTDOWN_CLOSE_CODE: u32 = 0;
+const SHUTDOWN_CLOSE_REASON: &[u8] = b"node shutdown";
+
+// 身份流只携带 NodeId 和一个签名，超过该长度视为非法
+const IDENTITY_MAX_LEN: usize = 512;
+// 从 TLS 会话导出握手挑战时使用的标签，签名绑定到当前连接，无法在其他连接上重放
+const HANDSHAKE_PROOF_LABEL: &[u8] = b"megaengine quic handshake";
+// 握手阶段身份校验失败时使用的关闭码
+const HANDSHAKE_ERROR_CODE: u32 = 1;
+const HANDSHAKE_ERROR_REASON: &[u8] = b"invalid node id";
+// 对端未注册请求处理器或处理器未回复时，用该错误码重置响应流
+const REQUEST_REJECTED_CODE: u32 = 2;
+// 同一节点存在多条连接时，关闭被替换的那条使用的关闭码
+const DUPLICATE_CLOSE_CODE: u32 = 3;
+const DUPLICATE_CLOSE_REASON: &[u8] = b"duplicate connection";
+// 对端连续多次未回应存活探测时，主动关闭连接使用的关闭码
+const UNRESPONSIVE_CLOSE_CODE: u32 = 4;
+const UNRESPONSIVE_CLOSE_REASON: &[u8] = b"peer unresponsive";
+/// 对端广播离开公告后关闭连接
+const DEPARTED_CLOSE_CODE: u32 = 5;
+const DEPARTED_CLOSE_REASON: &[u8] = b"peer left";
+
 // 消息前缀：用于区分 Gossip 控制消息和数据传输
 const GOSSIP_MESSAGE_PREFIX: &[u8] = b"GOSSIP:";
 const DATA_MESSAGE_PREFIX: &[u8] = b"DATA:";
+// 中继信封：发送方交给中继的消息（RELAY:<目标 NodeId>\n<数据>），
+// 以及中继转交给目标的消息（RELAYED:<来源 NodeId>\n<数据>）
+const RELAY_MESSAGE_PREFIX: &[u8] = b"RELAY:";
+const RELAYED_MESSAGE_PREFIX: &[u8] = b"RELAYED:";
+// 中继报告的直连节点在该时间内有效，超过后不再据此选择中继或接受其转交的数据
+const RELAY_REPORT_TTL: Duration = Duration::from_secs(120);
+// 数据流：一条单向流上按顺序写入多条数据消息（DATA-STREAM:<帧>...，帧格式见 framing 模块）
+const DATA_STREAM_PREFIX: &[u8] = b"DATA-STREAM:";
+// 数据流中的帧超过上限时，用该错误码停止读取
+const DATA_STREAM_REJECTED_CODE: u32 = 6;
 
 // Type alias for Gossip 消息发送端（控制流）
 type GossipMessageSender = Arc<Mutex<Option<TokioSender<(NodeId, Vec<u8>)>>>>;
 // Type alias for 数据传输发送端（数据流）
 type DataMessageSender = Arc<Mutex<Option<TokioSender<(NodeId, Vec<u8>)>>>>;
+// Type alias for 连接事件发送端（连接/断开通知）
+type ConnectionEventSender = Arc<Mutex<Option<TokioSender<ConnectionEvent>>>>;
+// Type alias for 请求处理器（请求/响应流）
+type RequestHandlerSender = Arc<Mutex<Option<TokioSender<IncomingRequest>>>>;
+
+/// 已接受的连接及其消息接收端，登记连接后再开始分发消息
+type AcceptedConnection = (QuicConnection, Receiver<Vec<u8>>);
 
 #[derive(Debug, Clone)]
 pub struct ConnectionManager {
-    #[allow(dead_code)]
     config: QuicConfig,
     endpoint: Arc<Endpoint>,
-    connection_tx: mpsc::Sender<QuicConnection>,
+    // 绑定在其他监听地址上的 endpoint，与主 endpoint 一起接受连接
+    extra_endpoints: Arc<Vec<Arc<Endpoint>>>,
+    connection_tx: mpsc::Sender<AcceptedConnection>,
     connections: Arc<Mutex<HashMap<NodeId, Arc<QuicConnection>>>>,
     gossip_sender: GossipMessageSender,
     data_sender: DataMessageSender,
+    event_sender: ConnectionEventSender,
+    request_handler: RequestHandlerSender,
+    // 每个目标节点一把拨号锁，合并对同一节点的并发 connect
+    dial_locks: Arc<Mutex<HashMap<NodeId, Arc<Mutex<()>>>>>,
+    shutdown_token: CancellationToken,
+    tasks: TaskTracker,
+    counters: Arc<TransportCounters>,
+    // Relay 类型节点最近一次报告的直连节点
+    relay_reports: Arc<Mutex<HashMap<NodeId, RelayReport>>>,
+}
+
+/// 中继节点报告的直连节点及报告时间
+#[derive(Debug)]
+struct RelayReport {
+    peers: HashSet<NodeId>,
+    reported_at: Instant,
+}
+
+impl RelayReport {
+    fn vouches_for(&self, node_id: &NodeId) -> bool {
+        self.reported_at.elapsed() < RELAY_REPORT_TTL && self.peers.contains(node_id)
+    }
+}
+
+/// 传输层流量计数器，由 ConnectionManager 的所有克隆共享
+#[derive(Debug, Default)]
+struct TransportCounters {
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    messages_sent: AtomicU64,
+    gossip_messages_received: AtomicU64,
+    data_messages_received: AtomicU64,
+}
+
+impl TransportCounters {
+    fn record_sent(&self, len: usize) {
+        self.bytes_sent.fetch_add(len as u64, Ordering::Relaxed);
+        self.messages_sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 按消息前缀分别统计数据消息和 Gossip 消息（无前缀的按 Gossip 计）
+    fn record_received(&self, msg: &[u8]) {
+        self.bytes_received
+            .fetch_add(msg.len() as u64, Ordering::Relaxed);
+        if msg.starts_with(DATA_MESSAGE_PREFIX)
+            || msg.starts_with(RELAY_MESSAGE_PREFIX)
+            || msg.starts_with(RELAYED_MESSAGE_PREFIX)
+        {
+            self.data_messages_received.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.gossip_messages_received
+                .fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// 节点运行指标快照：当前连接数及启动以来的累计流量
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeMetrics {
+    pub active_connections: usize,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub messages_sent: u64,
+    pub gossip_messages_received: u64,
+    pub data_messages_received: u64,
+}
+
+/// 客户端在身份流中发送的握手消息：NodeId 以及对握手挑战的签名
+#[derive(Debug, Serialize, Deserialize)]
+struct HandshakeIdentity {
+    node_id: NodeId,
+    signature: String,
 }
 
 #[derive(Debug, Clone)]
@@ -41,65 +164,237 @@ pub struct QuicConnection {
     pub peer_addr: SocketAddr,
     pub node_id: NodeId,
     pub connection_type: ConnectionType,
+    pub state: Arc<Mutex<ConnectionState>>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ConnectionType {
     Client,
     Server,
 }
 
+/// 连接状态：连接关闭时先标记为 Disconnected，再从连接表中移除
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Connected,
+    Disconnected,
+}
+
+/// 对端通过双向流发来的请求，处理方调用 respond 将响应写回同一条流
+///
+/// 未调用 respond 即丢弃时，请求方会收到流被重置的错误
+#[derive(Debug)]
+pub struct IncomingRequest {
+    pub from: NodeId,
+    pub payload: Vec<u8>,
+    responder: oneshot::Sender<Vec<u8>>,
+}
+
+impl IncomingRequest {
+    /// 回复请求方
+    pub fn respond(self, response: Vec<u8>) {
+        let _ = self.responder.send(response);
+    }
+}
+
+/// 连接事件：对端建立连接或断开连接时发出
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionEvent {
+    Connected(NodeId, SocketAddr),
+    Disconnected(NodeId),
+}
+
+/// 连接重试策略：失败后按指数退避重试，总尝试次数有上限
+///
+/// 每次尝试都有独立的超时，避免地址不可达时一直等待 QUIC 握手超时
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub attempt_timeout: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+            attempt_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new(max_attempts: u32, base_delay: Duration) -> Self {
+        Self {
+            max_attempts,
+            base_delay,
+            ..Self::default()
+        }
+    }
+
+    /// 设置单次尝试的超时
+    pub fn with_attempt_timeout(mut self, timeout: Duration) -> Self {
+        self.attempt_timeout = timeout;
+        self
+    }
+
+    /// 设置退避间隔上限
+    pub fn with_max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// 第 attempt 次失败后的等待时间（从 1 开始计数）
+    fn delay_after(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.base_delay.saturating_mul(factor).min(self.max_delay)
+    }
+}
+
 impl ConnectionManager {
-    fn server(config: QuicConfig) -> Result<(Self, Receiver<QuicConnection>)> {
+    fn server(config: QuicConfig) -> Result<(Self, Receiver<AcceptedConnection>)> {
         let server_config = config.get_server_config()?;
-
-        let mut endpoint = Endpoint::server(server_config, config.bind_addr)
-            .context("Failed to create QUIC server endpoint")?;
         let client_config = config.get_client_config()?;
-        endpoint.set_default_client_config(client_config);
 
-        info!(
-            "The quic service starts on address {}",
-            endpoint.local_addr()?
-        );
+        // 依次绑定所有监听地址，至少要有一个成功；第一个成功的作为主 endpoint
+        let mut endpoints = Vec::new();
+        let mut last_error = None;
+        for addr in std::iter::once(config.bind_addr).chain(config.extra_bind_addrs.iter().copied())
+        {
+            match Endpoint::server(server_config.clone(), addr) {
+                Ok(mut endpoint) => {
+                    endpoint.set_default_client_config(client_config.clone());
+                    info!(
+                        "The quic service starts on address {}",
+                        endpoint.local_addr()?
+                    );
+                    endpoints.push(Arc::new(endpoint));
+                }
+                Err(e) => {
+                    warn!("Failed to bind QUIC endpoint on {}: {}", addr, e);
+                    last_error = Some(e);
+                }
+            }
+        }
+        if endpoints.is_empty() {
+            return Err(
+                anyhow::Error::from(last_error.expect("at least one bind address"))
+                    .context("Failed to create QUIC server endpoint"),
+            );
+        }
+        let endpoint = endpoints.remove(0);
 
         let (connection_tx, connection_rx) = mpsc::channel(8);
 
         let transport = Self {
             config,
-            endpoint: Arc::new(endpoint),
+            endpoint,
+            extra_endpoints: Arc::new(endpoints),
             connection_tx,
             connections: Arc::new(Mutex::new(HashMap::new())),
             gossip_sender: Arc::new(Mutex::new(None)),
             data_sender: Arc::new(Mutex::new(None)),
+            event_sender: Arc::new(Mutex::new(None)),
+            request_handler: Arc::new(Mutex::new(None)),
+            dial_locks: Arc::new(Mutex::new(HashMap::new())),
+            shutdown_token: CancellationToken::new(),
+            tasks: TaskTracker::new(),
+            counters: Arc::new(TransportCounters::default()),
+            relay_reports: Arc::new(Mutex::new(HashMap::new())),
         };
         Ok((transport, connection_rx))
     }
 
     pub async fn run_server(config: QuicConfig) -> Result<Self> {
         let (manager, mut conn_rx) = ConnectionManager::server(config)?;
-        let endpoint = Arc::clone(&manager.endpoint);
-        let connection_tx = manager.connection_tx.clone();
-        let connections = Arc::clone(&manager.connections);
-        let manager_clone = manager.clone();
+        let watcher = manager.clone();
+        let shutdown = manager.shutdown_token.clone();
 
         manager.start_connection_cleanup();
 
-        tokio::spawn(async move {
+        for endpoint in manager.endpoints() {
+            manager.spawn_accept_loop(endpoint);
+        }
+
+        // 保存连接
+        manager.spawn_task(async move {
+            loop {
+                let (conn, msg_rx) = tokio::select! {
+                    _ = shutdown.cancelled() => break,
+                    conn = conn_rx.recv() => match conn {
+                        Some((conn, msg_rx)) => (Arc::new(conn), msg_rx),
+                        None => break,
+                    },
+                };
+                // 先登记连接再分发消息，处理器才能立即回复对端（如回应 Ping）；
+                // 被丢弃的重复连接上已收到的消息仍然照常处理
+                let installed = watcher.install_connection(Arc::clone(&conn)).await;
+                watcher
+                    .spawn_message_handler(conn.node_id.clone(), msg_rx)
+                    .await;
+                if !installed {
+                    continue;
+                }
+                watcher
+                    .emit_event(ConnectionEvent::Connected(
+                        conn.node_id.clone(),
+                        conn.peer_addr,
+                    ))
+                    .await;
+                watcher.spawn_request_listener(Arc::clone(&conn));
+                watcher.spawn_disconnect_watcher(conn);
+            }
+        });
+
+        Ok(manager.clone())
+    }
+
+    /// 主 endpoint 和所有额外 endpoint
+    fn endpoints(&self) -> Vec<Arc<Endpoint>> {
+        std::iter::once(Arc::clone(&self.endpoint))
+            .chain(self.extra_endpoints.iter().cloned())
+            .collect()
+    }
+
+    /// 实际监听的地址，按绑定顺序排列（绑定失败的地址不在其中）
+    pub fn local_addrs(&self) -> Vec<SocketAddr> {
+        self.endpoints()
+            .iter()
+            .filter_map(|endpoint| endpoint.local_addr().ok())
+            .collect()
+    }
+
+    /// 拨号使用与目标地址同一地址族的 endpoint，没有时使用主 endpoint
+    fn endpoint_for(&self, addr: &SocketAddr) -> Arc<Endpoint> {
+        self.endpoints()
+            .into_iter()
+            .find(|endpoint| {
+                endpoint
+                    .local_addr()
+                    .is_ok_and(|local| local.is_ipv4() == addr.is_ipv4())
+            })
+            .unwrap_or_else(|| Arc::clone(&self.endpoint))
+    }
+
+    fn spawn_accept_loop(&self, endpoint: Arc<Endpoint>) {
+        let connection_tx = self.connection_tx.clone();
+        let tasks = self.tasks.clone();
+        let max_frame_size = self.config.max_frame_size();
+
+        // endpoint 关闭后 accept() 返回 None，循环自然结束
+        self.spawn_task(async move {
             while let Some(incoming) = endpoint.accept().await {
                 info!("Accepting connection from {}", incoming.remote_address());
                 let tx = connection_tx.clone();
-                let manager_clone = manager_clone.clone();
-                tokio::spawn(async move {
-                    match Self::accept_connection(incoming).await {
+                tasks.spawn(async move {
+                    match Self::accept_connection(incoming, max_frame_size).await {
                         Ok((conn, msg_rx)) => {
-                            if let Err(e) = tx.send(conn.clone()).await {
+                            if let Err(e) = tx.send((conn, msg_rx)).await {
                                 error!("Failed to send connection: {}", e);
-                                return;
                             }
-                            manager_clone
-                                .spawn_message_handler(conn.node_id.clone(), msg_rx)
-                                .await;
                         }
                         Err(e) => {
                             error!("Connection failed: {}", e);
@@ -108,77 +403,133 @@ impl ConnectionManager {
                 });
             }
         });
-
-        // 保存连接
-        tokio::spawn(async move {
-            while let Some(conn) = conn_rx.recv().await {
-                connections
-                    .lock()
-                    .await
-                    .insert(conn.node_id.clone(), Arc::from(conn.clone()));
-            }
-        });
-
-        Ok(manager.clone())
     }
 
     pub async fn accept_connection(
         incoming: Incoming,
+        max_frame_size: usize,
     ) -> Result<(QuicConnection, Receiver<Vec<u8>>)> {
         let connection = incoming.await?;
         let peer_addr = connection.remote_address();
 
-        // 等待客户端发来的身份流
-        let mut recv = connection.accept_uni().await?;
-        let node_id_bytes = recv.read_to_end(READ_BUF_SIZE).await?;
-        let node_id_str = String::from_utf8(node_id_bytes)?;
-        let node_id: NodeId = node_id_str.parse().unwrap();
+        // 等待客户端发来的身份流，校验失败时关闭连接而不是 panic
+        let node_id = match Self::read_peer_identity(&connection).await {
+            Ok(node_id) => node_id,
+            Err(e) => {
+                connection.close(
+                    VarInt::from_u32(HANDSHAKE_ERROR_CODE),
+                    HANDSHAKE_ERROR_REASON,
+                );
+                return Err(anyhow::anyhow!(
+                    "Rejected connection from {}: {}",
+                    peer_addr,
+                    e
+                ));
+            }
+        };
 
         info!(
             "Accepted connection from {}, NodeId = {}",
-            peer_addr, node_id
+            peer_addr,
+            node_id.short()
         );
 
-        let (message_tx, message_rx) = mpsc::channel(32);
-        let connection_clone = connection.clone();
-        tokio::spawn(async move {
-            while let Ok(mut recv) = connection_clone.accept_uni().await {
-                if let Ok(msg) = recv.read_to_end(READ_BUF_SIZE).await {
-                    if message_tx.send(msg).await.is_err() {
-                        break;
-                    }
-                }
-            }
-        });
+        let message_rx = spawn_uni_reader(connection.clone(), max_frame_size);
         Ok((
             QuicConnection {
                 connection,
                 peer_addr,
                 node_id,
                 connection_type: ConnectionType::Server,
+                state: Arc::new(Mutex::new(ConnectionState::Connected)),
             },
             message_rx,
         ))
     }
 
+    /// 读取客户端在第一个单向流中发送的握手消息，并用其 NodeId 对应的公钥校验签名
+    async fn read_peer_identity(connection: &Connection) -> Result<NodeId> {
+        let mut recv = connection.accept_uni().await?;
+        let identity_bytes = recv
+            .read_to_end(IDENTITY_MAX_LEN)
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to read identity stream: {}", e))?;
+        verify_peer_identity(&identity_bytes, &handshake_proof(connection)?)
+    }
+
     /// 生成消息处理任务，将接收到的消息路由到对应的处理器（Gossip 或数据传输）
     ///
     /// 路由策略基于消息前缀：
     /// - b"GOSSIP:" 前缀：路由到 gossip_sender（控制流消息）
     /// - b"DATA:" 前缀：路由到 data_sender（数据传输）
+    /// - b"RELAY:" 前缀：开启中继转发时转交给目标节点，否则丢弃
+    /// - b"RELAYED:" 前缀：中继转交的数据，以信封中的来源节点路由到 data_sender；
+    ///   只接受信任的中继，或最近报告与来源直连的 Relay 类型节点
     /// - 无前缀：默认路由到 gossip_sender（向后兼容）
     async fn spawn_message_handler(&self, peer_id: NodeId, mut receiver: Receiver<Vec<u8>>) {
         let gossip = Arc::clone(&self.gossip_sender);
         let data = Arc::clone(&self.data_sender);
+        let counters = Arc::clone(&self.counters);
+        let max_gossip_size = self.config.max_gossip_message_size;
+        let max_data_size = self.config.max_data_message_size;
+        let manager = self.clone();
 
-        tokio::spawn(async move {
+        self.spawn_task(async move {
             while let Some(bytes) = receiver.recv().await {
+                counters.record_received(&bytes);
+
+                if let Some(envelope) = bytes.strip_prefix(RELAY_MESSAGE_PREFIX) {
+                    manager
+                        .forward_relay_message(&peer_id, envelope, max_data_size)
+                        .await;
+                    continue;
+                }
+
+                if let Some(envelope) = bytes.strip_prefix(RELAYED_MESSAGE_PREFIX) {
+                    let Some((origin, payload)) = decode_relay_envelope(envelope) else {
+                        warn!(
+                            "Dropping malformed relayed message from {}",
+                            peer_id.short()
+                        );
+                        continue;
+                    };
+                    if payload.len() > max_data_size {
+                        warn!(
+                            "Dropping oversized relayed message ({} bytes) from {} via {}",
+                            payload.len(),
+                            origin.short(),
+                            peer_id.short()
+                        );
+                        continue;
+                    }
+                    if !manager.accepts_relayed(&peer_id, &origin).await {
+                        warn!(
+                            "Dropping relayed message from {} via {}: not a relay vouching for that origin",
+                            origin.short(),
+                            peer_id.short()
+                        );
+                        continue;
+                    }
+                    let maybe_data = data.lock().await;
+                    if let Some(tx) = maybe_data.as_ref() {
+                        let _ = tx.send((origin, payload.to_vec())).await;
+                    }
+                    continue;
+                }
                 // 检查消息前缀来路由
                 let is_data_transfer = bytes.starts_with(DATA_MESSAGE_PREFIX);
 
                 if is_data_transfer {
                     // 移除前缀并转发到 data_sender
                     let payload = bytes[DATA_MESSAGE_PREFIX.len()..].to_vec();
+                    if payload.len() > max_data_size {
+                        warn!(
+                            "Dropping oversized data message ({} bytes) from {}",
+                            payload.len(),
+                            peer_id.short()
+                        );
+                        continue;
+                    }
                     let maybe_data = data.lock().await;
                     if let Some(tx) = maybe_data.as_ref() {
                         let _ = tx.send((peer_id.clone(), payload)).await;
@@ -192,6 +543,15 @@ impl ConnectionManager {
                 } else {
                     bytes.clone()
                 };
+                // 在反序列化之前丢弃过大的 gossip 消息
+                if payload.len() > max_gossip_size {
+                    warn!(
+                        "Dropping oversized gossip message ({} bytes) from {}",
+                        payload.len(),
+                        peer_id.short()
+                    );
+                    continue;
+                }
 
                 // 路由到 gossip_sender
                 let maybe_gossip = gossip.lock().await;
@@ -199,12 +559,47 @@ impl ConnectionManager {
                     let _ = tx.send((peer_id.clone(), payload)).await;
                 } else {
                     let message = String::from_utf8(payload).unwrap_or_default();
-                    info!("Received message from {}: {}", peer_id, message);
+                    info!("Received message from {}: {}", peer_id.short(), message);
                 }
             }
         });
     }
 
+    /// 中继节点处理 RELAY 信封：目标已直连时以 RELAYED 信封转交，来源为发来信封的对端
+    ///
+    /// 来源取自握手时已认证且仍然存在的连接，发送方无法冒充其他节点；目标未直连或
+    /// 未开启中继转发时直接丢弃，不做存储
+    async fn forward_relay_message(&self, from: &NodeId, envelope: &[u8], max_data_size: usize) {
+        if !self.config.relay_forwarding {
+            warn!(
+                "Dropping relay request from {}: relay forwarding is disabled",
+                from.short()
+            );
+            return;
+        }
+        let Some((target, payload)) = decode_relay_envelope(envelope) else {
+            warn!("Dropping malformed relay request from {}", from.short());
+            return;
+        };
+        if payload.len() > max_data_size || &target == from || !self.is_connected(from).await {
+            warn!(
+                "Dropping relay request from {} to {}",
+                from.short(),
+                target.short()
+            );
+            return;
+        }
+        let message = encode_relay_envelope(RELAYED_MESSAGE_PREFIX, from, payload);
+        if let Err(e) = self.send_message(target.clone(), message).await {
+            warn!(
+                "Failed to relay message from {} to {}: {}",
+                from.short(),
+                target.short(),
+                e
+            );
+        }
+    }
+
     /// 注册 Gossip 消息接收器（用于控制流消息）
     pub async fn register_gossip_sender(&self, tx: TokioSender<(NodeId, Vec<u8>)>) {
         let mut guard = self.gossip_sender.lock().await;
@@ -217,20 +612,306 @@ impl ConnectionManager {
         *guard = Some(tx);
     }
 
+    /// 注册连接事件接收器（用于监控对端的连接与断开）
+    ///
+    /// 仅支持一个订阅者，重复注册会替换之前的接收器
+    pub async fn register_connection_event_sender(&self, tx: TokioSender<ConnectionEvent>) {
+        let mut guard = self.event_sender.lock().await;
+        *guard = Some(tx);
+    }
+
+    /// 注册请求处理器（用于双向流上的请求/响应）
+    ///
+    /// 仅支持一个处理器，重复注册会替换之前的处理器；未注册时对端的请求会被拒绝
+    pub async fn register_request_handler(&self, tx: TokioSender<IncomingRequest>) {
+        let mut guard = self.request_handler.lock().await;
+        *guard = Some(tx);
+    }
+
+    /// 向已注册的接收器发送连接事件，未注册时直接忽略
+    async fn emit_event(&self, event: ConnectionEvent) {
+        let maybe_tx = self.event_sender.lock().await;
+        if let Some(tx) = maybe_tx.as_ref() {
+            let _ = tx.send(event).await;
+        }
+    }
+
+    /// 在 ConnectionManager 的任务跟踪器中启动后台任务，shutdown 时会等待其结束
+    pub fn spawn_task<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.tasks.spawn(future)
+    }
+
+    /// 关闭信号，供下游服务的后台循环在 shutdown 时退出
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.shutdown_token.clone()
+    }
+
+    /// 优雅关闭：停止后台循环，以应用关闭码关闭 endpoint，并等待后台任务结束
+    ///
+    /// 连接全部关闭后会释放已注册的接收器，下游服务（如 bundle 接收）
+    /// 处理完已收到的消息后退出，避免留下写了一半的 bundle 文件
+    pub async fn shutdown(&self) {
+        info!("Shutting down connection manager");
+        self.shutdown_token.cancel();
+        let endpoints = self.endpoints();
+        for endpoint in &endpoints {
+            endpoint.close(VarInt::from_u32(SHUTDOWN_CLOSE_CODE), SHUTDOWN_CLOSE_REASON);
+        }
+        for endpoint in &endpoints {
+            endpoint.wait_idle().await;
+        }
+
+        *self.gossip_sender.lock().await = None;
+        *self.data_sender.lock().await = None;
+        *self.event_sender.lock().await = None;
+        *self.request_handler.lock().await = None;
+
+        self.tasks.close();
+        self.tasks.wait().await;
+        info!("Connection manager shut down");
+    }
+
+    /// 当前连接数与累计流量统计
+    pub async fn metrics(&self) -> NodeMetrics {
+        let c = &self.counters;
+        NodeMetrics {
+            active_connections: self.connections.lock().await.len(),
+            bytes_sent: c.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: c.bytes_received.load(Ordering::Relaxed),
+            messages_sent: c.messages_sent.load(Ordering::Relaxed),
+            gossip_messages_received: c.gossip_messages_received.load(Ordering::Relaxed),
+            data_messages_received: c.data_messages_received.load(Ordering::Relaxed),
+        }
+    }
+
+    async fn is_connected(&self, node_id: &NodeId) -> bool {
+        self.connections.lock().await.contains_key(node_id)
+    }
+
+    /// 记录 Relay 类型节点报告的直连节点，覆盖该节点之前的报告
+    pub async fn record_relay_peers(&self, relay: NodeId, peers: impl IntoIterator<Item = NodeId>) {
+        let report = RelayReport {
+            peers: peers.into_iter().collect(),
+            reported_at: Instant::now(),
+        };
+        self.relay_reports.lock().await.insert(relay, report);
+    }
+
+    /// 已直连、且最近报告与目标直连的中继节点
+    pub async fn relays_connected_to(&self, target: &NodeId) -> Vec<NodeId> {
+        let connected = self.list_peers().await;
+        let reports = self.relay_reports.lock().await;
+        connected
+            .into_iter()
+            .filter(|peer| {
+                reports
+                    .get(peer)
+                    .is_some_and(|report| report.vouches_for(target))
+            })
+            .collect()
+    }
+
+    /// 是否接受 relay 以 origin 为来源转交的数据
+    async fn accepts_relayed(&self, relay: &NodeId, origin: &NodeId) -> bool {
+        if self.config.trusted_relays.contains(relay) {
+            return true;
+        }
+        self.relay_reports
+            .lock()
+            .await
+            .get(relay)
+            .is_some_and(|report| report.vouches_for(origin))
+    }
+
     /// Return list of connected peer NodeIds
     pub async fn list_peers(&self) -> Vec<NodeId> {
         let connections = self.connections.lock().await;
         connections.keys().cloned().collect()
     }
 
+    /// 关闭到该节点的连接（对端失去响应时使用），连接表由断开监听任务清理
+    ///
+    /// 返回 false 表示当前没有到该节点的连接
+    pub async fn close_unresponsive(&self, node_id: &NodeId) -> bool {
+        self.close_connection(node_id, UNRESPONSIVE_CLOSE_CODE, UNRESPONSIVE_CLOSE_REASON)
+            .await
+    }
+
+    /// 关闭到已主动离开的节点的连接，不必等待存活探测超时
+    pub async fn close_departed(&self, node_id: &NodeId) -> bool {
+        self.close_connection(node_id, DEPARTED_CLOSE_CODE, DEPARTED_CLOSE_REASON)
+            .await
+    }
+
+    async fn close_connection(&self, node_id: &NodeId, code: u32, reason: &[u8]) -> bool {
+        let conn = self.connections.lock().await.get(node_id).cloned();
+        match conn {
+            Some(conn) => {
+                conn.connection.close(VarInt::from_u32(code), reason);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 是否已有到该节点的可用连接
+    async fn has_live_connection(&self, node_id: &NodeId) -> bool {
+        let conn = self.connections.lock().await.get(node_id).cloned();
+        match conn {
+            Some(conn) => {
+                conn.connection.close_reason().is_none()
+                    && *conn.state.lock().await == ConnectionState::Connected
+            }
+            None => false,
+        }
+    }
+
+    /// 将新连接放入连接表，与已有连接冲突时只保留一条并关闭另一条
+    ///
+    /// 返回 false 表示新连接被丢弃（已关闭），调用方不应再使用它
+    async fn install_connection(&self, conn: Arc<QuicConnection>) -> bool {
+        let mut conns = self.connections.lock().await;
+        if let Some(existing) = conns.get(&conn.node_id) {
+            if existing.connection.close_reason().is_none() && !self.should_replace(existing, &conn)
+            {
+                drop(conns);
+                info!(
+                    "Dropping duplicate connection to node[{}]",
+                    conn.node_id.short()
+                );
+                conn.connection.close(
+                    VarInt::from_u32(DUPLICATE_CLOSE_CODE),
+                    DUPLICATE_CLOSE_REASON,
+                );
+                return false;
+            }
+        }
+
+        let superseded = conns.insert(conn.node_id.clone(), Arc::clone(&conn));
+        drop(conns);
+        if let Some(old) = superseded {
+            if old.connection.stable_id() != conn.connection.stable_id() {
+                info!(
+                    "Closing superseded connection to node[{}]",
+                    conn.node_id.short()
+                );
+                old.connection.close(
+                    VarInt::from_u32(DUPLICATE_CLOSE_CODE),
+                    DUPLICATE_CLOSE_REASON,
+                );
+            }
+        }
+        true
+    }
+
+    /// 新连接是否应替换已有的可用连接
+    ///
+    /// 同方向的重复连接保留较新的一条；双方同时互相拨号时，两端都保留
+    /// NodeId 较小的一方发起的连接，保证两端留下的是同一条连接
+    fn should_replace(&self, existing: &QuicConnection, new: &QuicConnection) -> bool {
+        if existing.connection_type == new.connection_type {
+            return true;
+        }
+        let Some(local) = self.config.identity.as_ref().map(NodeId::from_keypair) else {
+            return true;
+        };
+        let dialer = |conn: &QuicConnection| match conn.connection_type {
+            ConnectionType::Client => local.clone(),
+            ConnectionType::Server => conn.node_id.clone(),
+        };
+        dialer(new).as_str() < dialer(existing).as_str()
+    }
+
+    /// 监听连接关闭事件：记录 Disconnected 状态后将其从连接表中移除
+    ///
+    /// 只有当连接表中仍是同一条连接时才移除，避免误删重连后建立的新连接
+    fn spawn_disconnect_watcher(&self, conn: Arc<QuicConnection>) {
+        let connections = Arc::clone(&self.connections);
+        let manager = self.clone();
+
+        self.spawn_task(async move {
+            let reason = conn.connection.closed().await;
+            *conn.state.lock().await = ConnectionState::Disconnected;
+            info!(
+                "Connection to node[{}] closed, reason: {}",
+                conn.node_id.short(),
+                reason
+            );
+
+            let mut conns = connections.lock().await;
+            let is_current = conns
+                .get(&conn.node_id)
+                .map(|c| c.connection.stable_id() == conn.connection.stable_id())
+                .unwrap_or(false);
+            if is_current {
+                conns.remove(&conn.node_id);
+                drop(conns);
+                info!("Removed dead connection for node: {}", conn.node_id.short());
+                manager
+                    .emit_event(ConnectionEvent::Disconnected(conn.node_id.clone()))
+                    .await;
+            }
+        });
+    }
+
+    /// 接收对端打开的双向流：读取请求，交给请求处理器，并把响应写回同一条流
+    fn spawn_request_listener(&self, conn: Arc<QuicConnection>) {
+        let handler = Arc::clone(&self.request_handler);
+        let counters = Arc::clone(&self.counters);
+
+        self.spawn_task(async move {
+            while let Ok((mut send, mut recv)) = conn.connection.accept_bi().await {
+                let handler = Arc::clone(&handler);
+                let counters = Arc::clone(&counters);
+                let from = conn.node_id.clone();
+                tokio::spawn(async move {
+                    let Ok(payload) = recv.read_to_end(READ_BUF_SIZE).await else {
+                        return;
+                    };
+
+                    let (responder, response) = oneshot::channel();
+                    let request = IncomingRequest {
+                        from,
+                        payload,
+                        responder,
+                    };
+                    let handler_tx = handler.lock().await.clone();
+                    let response = match handler_tx {
+                        Some(tx) if tx.send(request).await.is_ok() => response.await.ok(),
+                        _ => None,
+                    };
+
+                    match response {
+                        Some(response) => {
+                            if send.write_all(&response).await.is_ok() && send.finish().is_ok() {
+                                counters.record_sent(response.len());
+                            }
+                        }
+                        None => {
+                            let _ = send.reset(VarInt::from_u32(REQUEST_REJECTED_CODE));
+                        }
+                    }
+                });
+            }
+        });
+    }
+
     /// Start background task to periodically clean up stale connections
     pub fn start_connection_cleanup(&self) {
         let connections = Arc::clone(&self.connections);
+        let shutdown = self.shutdown_token.clone();
 
-        tokio::spawn(async move {
+        self.spawn_task(async move {
             let mut interval = tokio::time::interval(CONNECTION_CLEANUP_INTERVAL);
             loop {
-                interval.tick().await;
+                tokio::select! {
+                    _ = shutdown.cancelled() => break,
+                    _ = interval.tick() => {}
+                }
                 let mut conns = connections.lock().await;
                 let mut dead_nodes = Vec::new();
 
@@ -238,7 +919,8 @@ impl ConnectionManager {
                     if let Some(reason) = conn.connection.close_reason() {
                         info!(
                             "Connection to node[{}] closed, reason: {:?}",
-                            node_id, reason
+                            node_id.short(),
+                            reason
                         );
                         dead_nodes.push(node_id.clone());
                     }
@@ -246,24 +928,133 @@ impl ConnectionManager {
 
                 for node_id in dead_nodes {
                     conns.remove(&node_id);
-                    info!("Cleaned up stale connection for node: {}", node_id);
+                    info!("Cleaned up stale connection for node: {}", node_id.short());
                 }
             }
         });
     }
 
+    /// 按重试策略连接指定节点，所有尝试失败后返回最后一次的错误
+    pub async fn connect_with_retry(
+        &self,
+        self_node_id: NodeId,
+        target_node_id: NodeId,
+        addrs: Vec<SocketAddr>,
+        policy: RetryPolicy,
+    ) -> MegaResult<()> {
+        let max_attempts = policy.max_attempts.max(1);
+        let mut attempt = 1;
+        loop {
+            let result = tokio::time::timeout(
+                policy.attempt_timeout,
+                self.connect(self_node_id.clone(), target_node_id.clone(), addrs.clone()),
+            )
+            .await
+            .unwrap_or_else(|_| {
+                Err(MegaError::Transport(format!(
+                    "connection attempt timed out after {:?}",
+                    policy.attempt_timeout
+                )))
+            });
+
+            let e = match result {
+                Ok(()) => return Ok(()),
+                Err(e) => e,
+            };
+            if attempt >= max_attempts {
+                return Err(MegaError::Transport(format!(
+                    "Failed to connect to node[{}] after {} attempts: {}",
+                    target_node_id.short(),
+                    attempt,
+                    e
+                )));
+            }
+
+            let delay = policy.delay_after(attempt);
+            info!(
+                "Connect attempt {}/{} to node[{}] failed: {}; retrying in {:?}",
+                attempt,
+                max_attempts,
+                target_node_id.short(),
+                e,
+                delay
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// 连接指定节点，已存在可用连接时直接返回
+    ///
+    /// 对同一节点的并发调用会排队执行，后到的调用会复用先建立的连接
     pub async fn connect(
         &self,
         self_node_id: NodeId,
         target_node_id: NodeId,
         addrs: Vec<SocketAddr>,
-    ) -> Result<()> {
-        let endpoint = self.endpoint.clone();
+    ) -> MegaResult<()> {
+        let dial_lock = Arc::clone(
+            self.dial_locks
+                .lock()
+                .await
+                .entry(target_node_id.clone())
+                .or_default(),
+        );
+        let result = {
+            let _dialing = dial_lock.lock().await;
+            if self.has_live_connection(&target_node_id).await {
+                Ok(())
+            } else {
+                self.dial(self_node_id, target_node_id.clone(), addrs).await
+            }
+        };
+
+        // 没有其他调用在等待这把锁时将其移除
+        drop(dial_lock);
+        self.dial_locks
+            .lock()
+            .await
+            .retain(|_, lock| Arc::strong_count(lock) > 1);
+        result
+    }
+
+    async fn dial(
+        &self,
+        self_node_id: NodeId,
+        target_node_id: NodeId,
+        addrs: Vec<SocketAddr>,
+    ) -> MegaResult<()> {
+        // 握手时需要用身份密钥签名，证明本端确实持有 self_node_id 对应的私钥
+        let identity = self
+            .config
+            .identity
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("QUIC handshake requires an identity keypair"))?;
+        if NodeId::from_keypair(&identity) != self_node_id {
+            return Err(anyhow::anyhow!(
+                "NodeId {} does not match the configured identity",
+                self_node_id
+            )
+            .into());
+        }
+
         let mut connection = None;
 
-        info!("Trying to connect to node[{}]", target_node_id.to_string());
+        // 开启对端校验时，要求对端证书公钥与 target_node_id 一致
+        let pinned_config = if self.config.peer_verification {
+            Some(self.config.get_pinned_client_config(&target_node_id)?)
+        } else {
+            None
+        };
+
+        info!("Trying to connect to node[{}]", target_node_id.short());
         for addr in addrs.iter() {
-            match endpoint.connect(*addr, "localhost")?.await {
+            let endpoint = self.endpoint_for(addr);
+            let connecting = match &pinned_config {
+                Some(config) => endpoint.connect_with(config.clone(), *addr, "localhost")?,
+                None => endpoint.connect(*addr, "localhost")?,
+            };
+            match connecting.await {
                 Ok(c) => {
                     connection = Some(c);
                     break;
@@ -275,109 +1066,342 @@ impl ConnectionManager {
         let connection = match connection {
             Some(c) => c,
             None => {
-                return Err(anyhow::anyhow!(
+                return Err(MegaError::Transport(format!(
                     "Failed to connect to node[{}], no address available",
                     target_node_id
-                ))
+                )))
             }
         };
 
         let peer_addr = connection.remote_address();
         info!(
             "Node[{}] connect to[[{}] successfully: {}",
-            self_node_id.to_string(),
-            target_node_id.to_string(),
+            self_node_id.short(),
+            target_node_id.short(),
             peer_addr
         );
 
-        //Send node_id
+        // 发送 NodeId 及其对握手挑战的签名
+        let signature = identity.sign(&handshake_proof(&connection)?)?;
+        let hello = HandshakeIdentity {
+            node_id: self_node_id.clone(),
+            signature: hex::encode(signature.to_bytes()),
+        };
         let mut send = connection.open_uni().await?;
-        send.write_all(self_node_id.as_bytes()).await?;
+        send.write_all(&serde_json::to_vec(&hello).map_err(anyhow::Error::from)?)
+            .await?;
         send.finish()?;
 
-        let quic_conn = QuicConnection {
+        let quic_conn = Arc::new(QuicConnection {
             connection: connection.clone(),
             peer_addr,
             node_id: target_node_id.clone(),
             connection_type: ConnectionType::Client,
-        };
-        let connections = Arc::clone(&self.connections);
-        connections
-            .lock()
-            .await
-            .insert(target_node_id.clone(), Arc::from(quic_conn.clone()));
+            state: Arc::new(Mutex::new(ConnectionState::Connected)),
+        });
+        if !self.install_connection(Arc::clone(&quic_conn)).await {
+            return Ok(());
+        }
+        self.emit_event(ConnectionEvent::Connected(
+            target_node_id.clone(),
+            peer_addr,
+        ))
+        .await;
+        self.spawn_request_listener(Arc::clone(&quic_conn));
+        self.spawn_disconnect_watcher(quic_conn);
 
         // 启动消息接收任务，用于接收服务端发来的消息
-        let peer_id = target_node_id.clone();
-        let connection_clone = connection.clone();
-        let gossip_sender = Arc::clone(&self.gossip_sender);
-        let data_sender = Arc::clone(&self.data_sender);
-
-        tokio::spawn(async move {
-            while let Ok(mut recv) = connection_clone.accept_uni().await {
-                if let Ok(msg) = recv.read_to_end(READ_BUF_SIZE).await {
-                    // 基于前缀路由消息
-                    let is_data_transfer = msg.starts_with(DATA_MESSAGE_PREFIX);
-
-                    if is_data_transfer {
-                        // 移除前缀并路由到 data_sender
-                        let payload = msg[DATA_MESSAGE_PREFIX.len()..].to_vec();
-                        let maybe_data = data_sender.lock().await;
-                        if let Some(tx) = maybe_data.as_ref() {
-                            let _ = tx.send((peer_id.clone(), payload)).await;
-                            continue;
-                        }
-                    }
-
-                    // 检查并移除 GOSSIP 前缀（如果存在）
-                    let payload = if msg.starts_with(GOSSIP_MESSAGE_PREFIX) {
-                        msg[GOSSIP_MESSAGE_PREFIX.len()..].to_vec()
-                    } else {
-                        msg.clone()
-                    };
-
-                    // 路由到 gossip_sender
-                    let maybe_gossip = gossip_sender.lock().await;
-                    if let Some(tx) = maybe_gossip.as_ref() {
-                        let _ = tx.send((peer_id.clone(), payload)).await;
-                    }
-                }
-            }
-        });
+        let message_rx = spawn_uni_reader(connection, self.config.max_frame_size());
+        self.spawn_message_handler(target_node_id, message_rx).await;
 
         Ok(())
     }
 
-    pub async fn send_message(&self, node_id: NodeId, message: Vec<u8>) -> Result<()> {
+    pub async fn send_message(&self, node_id: NodeId, message: Vec<u8>) -> MegaResult<()> {
         let connections = self.connections.lock().await;
-        let conn = connections.get(&node_id).with_context(|| {
-            format!(
-                "Failed to send message to node[{}], connection not found",
-                node_id
-            )
-        })?;
+        let conn = connections
+            .get(&node_id)
+            .ok_or_else(|| MegaError::NotFound(format!("Connection to node[{}]", node_id)))?;
 
         let mut sender = conn.connection.open_uni().await?;
         sender.write_all(message.as_slice()).await?;
         sender.finish()?;
+        self.counters.record_sent(message.len());
         Ok(())
     }
 
+    /// 在新的双向流上发送请求并等待对端响应，使用默认超时
+    pub async fn request(&self, node_id: NodeId, payload: Vec<u8>) -> MegaResult<Vec<u8>> {
+        self.request_with_timeout(node_id, payload, DEFAULT_REQUEST_TIMEOUT)
+            .await
+    }
+
+    /// 在新的双向流上发送请求并等待对端响应，超时后返回错误
+    pub async fn request_with_timeout(
+        &self,
+        node_id: NodeId,
+        payload: Vec<u8>,
+        timeout: Duration,
+    ) -> MegaResult<Vec<u8>> {
+        let conn = self
+            .connections
+            .lock()
+            .await
+            .get(&node_id)
+            .cloned()
+            .ok_or_else(|| MegaError::NotFound(format!("Connection to node[{}]", node_id)))?;
+
+        let exchange = async {
+            let (mut send, mut recv) = conn.connection.open_bi().await?;
+            send.write_all(&payload).await?;
+            send.finish()?;
+            self.counters.record_sent(payload.len());
+            let response = recv.read_to_end(READ_BUF_SIZE).await?;
+            Ok::<_, MegaError>(response)
+        };
+
+        tokio::time::timeout(timeout, exchange).await.map_err(|_| {
+            MegaError::Transport(format!(
+                "Request to node[{}] timed out after {:?}",
+                node_id.short(),
+                timeout
+            ))
+        })?
+    }
+
     /// 发送 Gossip 消息（会自动添加 GOSSIP: 前缀）
-    pub async fn send_gossip_message(&self, node_id: NodeId, message: Vec<u8>) -> Result<()> {
+    pub async fn send_gossip_message(&self, node_id: NodeId, message: Vec<u8>) -> MegaResult<()> {
         let mut prefixed = Vec::with_capacity(GOSSIP_MESSAGE_PREFIX.len() + message.len());
         prefixed.extend_from_slice(GOSSIP_MESSAGE_PREFIX);
         prefixed.extend_from_slice(&message);
         self.send_message(node_id, prefixed).await
     }
 
+    /// 发送 Gossip 消息并等待对端确认收到全部数据，用于关闭连接前必须送达的消息
+    pub async fn send_gossip_message_confirmed(
+        &self,
+        node_id: NodeId,
+        message: Vec<u8>,
+    ) -> MegaResult<()> {
+        let conn = self
+            .connections
+            .lock()
+            .await
+            .get(&node_id)
+            .cloned()
+            .ok_or_else(|| MegaError::NotFound(format!("Connection to node[{}]", node_id)))?;
+
+        let mut sender = conn.connection.open_uni().await?;
+        sender.write_all(GOSSIP_MESSAGE_PREFIX).await?;
+        sender.write_all(&message).await?;
+        sender.finish()?;
+        self.counters
+            .record_sent(GOSSIP_MESSAGE_PREFIX.len() + message.len());
+        sender.stopped().await.map_err(|e| {
+            MegaError::Transport(format!("Send to node[{}]: {}", node_id.short(), e))
+        })?;
+        Ok(())
+    }
+
     /// 发送数据消息（会自动添加 DATA: 前缀，用于大文件传输）
-    pub async fn send_data_message(&self, node_id: NodeId, message: Vec<u8>) -> Result<()> {
+    pub async fn send_data_message(&self, node_id: NodeId, message: Vec<u8>) -> MegaResult<()> {
         let mut prefixed = Vec::with_capacity(DATA_MESSAGE_PREFIX.len() + message.len());
         prefixed.extend_from_slice(DATA_MESSAGE_PREFIX);
         prefixed.extend_from_slice(&message);
         self.send_message(node_id, prefixed).await
     }
+
+    /// 打开到指定节点的数据流，之后写入的消息按写入顺序交给对端的 data_sender
+    pub async fn open_data_stream(&self, node_id: NodeId) -> MegaResult<DataStream> {
+        let conn = self
+            .connections
+            .lock()
+            .await
+            .get(&node_id)
+            .cloned()
+            .ok_or_else(|| MegaError::NotFound(format!("Connection to node[{}]", node_id)))?;
+
+        let mut send = conn.connection.open_uni().await?;
+        send.write_all(DATA_STREAM_PREFIX).await?;
+        Ok(DataStream {
+            send,
+            counters: Arc::clone(&self.counters),
+        })
+    }
+
+    /// 经由已直连的中继节点向目标发送数据消息，用于目标无法直连（如位于 NAT 后）的情况
+    ///
+    /// 中继只能转交给与它直连的目标，目标收到后按发送方（而非中继）路由到 data_sender。
+    /// 信任假设：QUIC 只加密逐跳链路，中继能看到数据明文，目标看到的来源也由中继声明；
+    /// 私聊消息本身是端到端加密的，bundle 数据则需要信任中继
+    pub async fn send_relayed_data_message(
+        &self,
+        relay: NodeId,
+        target: NodeId,
+        message: Vec<u8>,
+    ) -> MegaResult<()> {
+        let envelope = encode_relay_envelope(RELAY_MESSAGE_PREFIX, &target, &message);
+        self.send_message(relay, envelope).await
+    }
+}
+
+/// 在一条专用单向流上按顺序发送多条数据消息，用于 bundle 等大块传输
+///
+/// 相比每条消息打开一条流，省去了逐条建流的开销，对端也按写入顺序收到消息
+pub struct DataStream {
+    send: quinn::SendStream,
+    counters: Arc<TransportCounters>,
+}
+
+impl DataStream {
+    /// 写入一条数据消息
+    pub async fn send(&mut self, message: &[u8]) -> MegaResult<()> {
+        write_frame(&mut self.send, message)
+            .await
+            .map_err(|e| MegaError::Transport(format!("Write data frame: {}", e)))?;
+        self.counters.record_sent(message.len());
+        Ok(())
+    }
+
+    /// 结束数据流，已写入的消息仍会送达对端
+    pub fn finish(mut self) -> MegaResult<()> {
+        self.send.finish()?;
+        Ok(())
+    }
+}
+
+/// 构造中继信封：前缀 + NodeId + 换行 + 数据
+fn encode_relay_envelope(prefix: &[u8], node_id: &NodeId, payload: &[u8]) -> Vec<u8> {
+    let mut envelope =
+        Vec::with_capacity(prefix.len() + node_id.as_str().len() + 1 + payload.len());
+    envelope.extend_from_slice(prefix);
+    envelope.extend_from_slice(node_id.as_bytes());
+    envelope.push(b'\n');
+    envelope.extend_from_slice(payload);
+    envelope
+}
+
+/// 解析去掉前缀后的中继信封，NodeId 非法时返回 None
+fn decode_relay_envelope(envelope: &[u8]) -> Option<(NodeId, &[u8])> {
+    let split = envelope.iter().position(|&b| b == b'\n')?;
+    let node_id = std::str::from_utf8(&envelope[..split]).ok()?;
+    let node_id = NodeId::from_string(node_id).ok()?;
+    Some((node_id, &envelope[split + 1..]))
+}
+
+/// 读取对端打开的单向流，每条流是一条完整消息，超过 max_frame_size 的流被丢弃；
+/// 数据流在单独的任务中逐帧读取，不阻塞其他流上的消息
+fn spawn_uni_reader(connection: Connection, max_frame_size: usize) -> Receiver<Vec<u8>> {
+    let (message_tx, message_rx) = mpsc::channel(32);
+    tokio::spawn(async move {
+        while let Ok(mut recv) = connection.accept_uni().await {
+            // 先读出足够判断是否为数据流的字节，短于前缀的消息原样交付
+            let mut head = vec![0u8; DATA_STREAM_PREFIX.len()];
+            let msg = match recv.read_exact(&mut head).await {
+                Ok(()) if head == DATA_STREAM_PREFIX => {
+                    tokio::spawn(read_data_stream(
+                        recv,
+                        message_tx.clone(),
+                        max_frame_size,
+                        connection.remote_address(),
+                    ));
+                    continue;
+                }
+                Ok(()) => match recv.read_to_end(max_frame_size - head.len()).await {
+                    Ok(rest) => {
+                        head.extend_from_slice(&rest);
+                        head
+                    }
+                    Err(e) => {
+                        warn!(
+                            "Dropping message from {}: {}",
+                            connection.remote_address(),
+                            e
+                        );
+                        continue;
+                    }
+                },
+                Err(quinn::ReadExactError::FinishedEarly(read)) => {
+                    head.truncate(read);
+                    head
+                }
+                Err(e) => {
+                    warn!(
+                        "Dropping message from {}: {}",
+                        connection.remote_address(),
+                        e
+                    );
+                    continue;
+                }
+            };
+            if message_tx.send(msg).await.is_err() {
+                break;
+            }
+        }
+    });
+    message_rx
+}
+
+/// 逐帧读取数据流，每帧作为一条 DATA: 消息交付；帧超过 max_frame_size 时停止读取该流
+async fn read_data_stream(
+    mut recv: quinn::RecvStream,
+    message_tx: mpsc::Sender<Vec<u8>>,
+    max_frame_size: usize,
+    remote: SocketAddr,
+) {
+    loop {
+        let payload = match read_frame(&mut recv, max_frame_size).await {
+            Ok(Some(payload)) => payload,
+            Ok(None) => return,
+            Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+                warn!("Dropping data stream from {}: {}", remote, e);
+                let _ = recv.stop(VarInt::from_u32(DATA_STREAM_REJECTED_CODE));
+                return;
+            }
+            Err(e) => {
+                warn!("Data stream from {} ended abnormally: {}", remote, e);
+                return;
+            }
+        };
+
+        let mut frame = Vec::with_capacity(DATA_MESSAGE_PREFIX.len() + payload.len());
+        frame.extend_from_slice(DATA_MESSAGE_PREFIX);
+        frame.extend_from_slice(&payload);
+        if message_tx.send(frame).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// 从当前 TLS 会话导出握手挑战，两端得到相同的值且每个连接都不同
+fn handshake_proof(connection: &Connection) -> Result<[u8; 32]> {
+    let mut proof = [0u8; 32];
+    connection
+        .export_keying_material(&mut proof, HANDSHAKE_PROOF_LABEL, &[])
+        .map_err(|_| anyhow::anyhow!("failed to export handshake keying material"))?;
+    Ok(proof)
+}
+
+/// 解析身份流内容并校验签名，空流、非法 did:key 或签名不匹配均返回错误
+fn verify_peer_identity(bytes: &[u8], proof: &[u8]) -> Result<NodeId> {
+    if bytes.is_empty() {
+        return Err(anyhow::anyhow!("empty identity stream"));
+    }
+    let hello: HandshakeIdentity = serde_json::from_slice(bytes)
+        .map_err(|e| anyhow::anyhow!("malformed identity stream: {}", e))?;
+    let node_id = NodeId::from_string(hello.node_id.as_str())?;
+
+    let sig_bytes = hex::decode(&hello.signature)
+        .map_err(|e| anyhow::anyhow!("malformed handshake signature: {}", e))?;
+    let signature = Signature::from_slice(&sig_bytes)
+        .map_err(|e| anyhow::anyhow!("malformed handshake signature: {}", e))?;
+    if !node_id.to_keypair()?.verify(proof, &signature) {
+        return Err(anyhow::anyhow!(
+            "handshake signature verification failed for {}",
+            node_id.short()
+        ));
+    }
+    Ok(node_id)
 }
 
 #[cfg(test)]
@@ -515,6 +1539,62 @@ mod tests {
         cleanup_test_certs();
     }
 
+    #[tokio::test]
+    async fn test_keep_alive_must_be_shorter_than_idle_timeout() {
+        init();
+        let config = QuicConfig::new(
+            "127.0.0.1:0".parse().unwrap(),
+            String::new(),
+            String::new(),
+            String::new(),
+        )
+        .with_peer_verification(true)
+        .with_identity(KeyPair::generate().unwrap())
+        .with_idle_timeout(Duration::from_secs(20));
+
+        let too_long = config
+            .clone()
+            .with_keep_alive_interval(Duration::from_secs(20));
+        assert!(too_long.get_server_config().is_err());
+        assert!(too_long.get_client_config().is_err());
+        assert!(ConnectionManager::run_server(too_long).await.is_err());
+
+        let disabled = config.clone().with_keep_alive_interval(Duration::ZERO);
+        assert!(disabled.get_client_config().is_ok());
+
+        let manager =
+            ConnectionManager::run_server(config.with_keep_alive_interval(Duration::from_secs(5)))
+                .await
+                .unwrap();
+        manager.shutdown().await;
+    }
+
+    #[test]
+    fn test_bulk_transfer_windows() {
+        init();
+        let config = QuicConfig::new(
+            "127.0.0.1:0".parse().unwrap(),
+            String::new(),
+            String::new(),
+            String::new(),
+        )
+        .with_peer_verification(true)
+        .with_identity(KeyPair::generate().unwrap());
+        assert_eq!(config.stream_receive_window, None);
+
+        let bulk = config.clone().for_bulk_transfer();
+        assert_eq!(
+            bulk.stream_receive_window,
+            Some(crate::transport::config::BULK_STREAM_RECEIVE_WINDOW)
+        );
+        assert!(bulk.get_server_config().is_ok());
+        assert!(bulk.get_client_config().is_ok());
+
+        // 超出 QUIC 变长整数范围的窗口
+        let invalid = config.with_flow_control_windows(Some(u64::MAX), None, None);
+        assert!(invalid.get_client_config().is_err());
+    }
+
     // Test the `connect` method
     #[tokio::test]
     async fn test_client_connection() {
@@ -541,7 +1621,7 @@ mod tests {
             keypair1.clone(),
         );
 
-        let config2 = mock_quic_config2();
+        let config2 = mock_quic_config2().with_identity(keypair2.clone());
         let manager2 = ConnectionManager::run_server(config2).await;
         assert!(manager2.is_ok());
         let manager2 = manager2.unwrap();
@@ -577,6 +1657,51 @@ mod tests {
         cleanup_test_certs();
     }
 
+    #[tokio::test]
+    async fn test_server_binds_multiple_addresses() {
+        let _guard = serial_lock().lock().await;
+        init();
+        cleanup_test_certs();
+        let keypair1 = KeyPair::generate().expect("generate keypair");
+        let keypair2 = KeyPair::generate().expect("generate keypair");
+
+        // 192.0.2.1 (TEST-NET-1) 不是本机地址，绑定失败只告警
+        let mut config = mock_quic_config().with_extra_bind_addrs(vec![
+            "192.0.2.1:0".parse().unwrap(),
+            "127.0.0.2:0".parse().unwrap(),
+        ]);
+        config.bind_addr = "127.0.0.1:0".parse().unwrap();
+        let manager = ConnectionManager::run_server(config).await.unwrap();
+        let local = manager.local_addrs();
+        assert_eq!(local.len(), 2);
+        assert_eq!(local[1].ip().to_string(), "127.0.0.2");
+
+        // 通过第二个监听地址也能连上
+        let config2 = mock_quic_config2().with_identity(keypair2.clone());
+        let manager2 = ConnectionManager::run_server(config2).await.unwrap();
+        manager2
+            .connect(
+                NodeId::from_keypair(&keypair2),
+                NodeId::from_keypair(&keypair1),
+                vec![local[1]],
+            )
+            .await
+            .unwrap();
+        assert!(manager2
+            .connections
+            .lock()
+            .await
+            .contains_key(&NodeId::from_keypair(&keypair1)));
+
+        let mut config3 = mock_quic_config();
+        config3.bind_addr = "192.0.2.1:0".parse().unwrap();
+        assert!(ConnectionManager::run_server(config3).await.is_err());
+
+        manager.shutdown().await;
+        manager2.shutdown().await;
+        cleanup_test_certs();
+    }
+
     #[tokio::test]
     async fn test_send_message() {
         let _guard = serial_lock().lock().await;
@@ -601,7 +1726,7 @@ mod tests {
             keypair1.clone(),
         );
 
-        let config2 = mock_quic_config2();
+        let config2 = mock_quic_config2().with_identity(keypair2.clone());
         let manager2 = ConnectionManager::run_server(config2).await;
         assert!(manager2.is_ok());
         let manager2 = manager2.unwrap();
@@ -637,7 +1762,256 @@ mod tests {
             .send_message(node1.node_id().clone(), b"hello".to_vec())
             .await
             .unwrap();
+        manager2
+            .send_data_message(node1.node_id().clone(), b"chunk".to_vec())
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        let sent = manager2.metrics().await;
+        assert_eq!(sent.active_connections, 1);
+        assert_eq!(sent.messages_sent, 2);
+        assert_eq!(sent.bytes_sent, (5 + DATA_MESSAGE_PREFIX.len() + 5) as u64);
+
+        let received = manager.metrics().await;
+        assert_eq!(received.active_connections, 1);
+        assert_eq!(received.gossip_messages_received, 1);
+        assert_eq!(received.data_messages_received, 1);
+        assert_eq!(received.bytes_received, sent.bytes_sent);
+        cleanup_test_certs();
+    }
+
+    #[tokio::test]
+    async fn test_dead_connection_removed() {
+        let _guard = serial_lock().lock().await;
+        init();
+        cleanup_test_certs();
+        let keypair1 = KeyPair::generate().expect("generate keypair");
+        let keypair2 = KeyPair::generate().expect("generate keypair");
+
+        let manager1 = ConnectionManager::run_server(mock_quic_config())
+            .await
+            .unwrap();
+        let manager2 =
+            ConnectionManager::run_server(mock_quic_config2().with_identity(keypair2.clone()))
+                .await
+                .unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let addr1 = manager1.endpoint.local_addr().expect("get local addr");
+        let addr1: SocketAddr = format!("127.0.0.1:{}", addr1.port()).parse().unwrap();
+        let node_id1 = NodeId::from_keypair(&keypair1);
+        let node_id2 = NodeId::from_keypair(&keypair2);
+
+        manager2
+            .connect(node_id2.clone(), node_id1.clone(), vec![addr1])
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(manager1.list_peers().await.contains(&node_id2));
+
+        let conn = manager2
+            .connections
+            .lock()
+            .await
+            .get(&node_id1)
+            .cloned()
+            .expect("connection to node1");
+        conn.connection.close(VarInt::from_u32(0), b"test close");
         tokio::time::sleep(Duration::from_millis(500)).await;
+
+        assert_eq!(*conn.state.lock().await, ConnectionState::Disconnected);
+        assert!(manager2.list_peers().await.is_empty());
+        assert!(manager1.list_peers().await.is_empty());
+        cleanup_test_certs();
+    }
+
+    #[tokio::test]
+    async fn test_connection_events() {
+        let _guard = serial_lock().lock().await;
+        init();
+        cleanup_test_certs();
+        let keypair1 = KeyPair::generate().expect("generate keypair");
+        let keypair2 = KeyPair::generate().expect("generate keypair");
+
+        let manager1 = ConnectionManager::run_server(mock_quic_config())
+            .await
+            .unwrap();
+        let manager2 =
+            ConnectionManager::run_server(mock_quic_config2().with_identity(keypair2.clone()))
+                .await
+                .unwrap();
+        let (event_tx, mut event_rx) = mpsc::channel(8);
+        manager1.register_connection_event_sender(event_tx).await;
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let addr1 = manager1.endpoint.local_addr().expect("get local addr");
+        let addr1: SocketAddr = format!("127.0.0.1:{}", addr1.port()).parse().unwrap();
+        let node_id1 = NodeId::from_keypair(&keypair1);
+        let node_id2 = NodeId::from_keypair(&keypair2);
+
+        manager2
+            .connect(node_id2.clone(), node_id1.clone(), vec![addr1])
+            .await
+            .unwrap();
+
+        let event = tokio::time::timeout(Duration::from_secs(2), event_rx.recv())
+            .await
+            .expect("connected event")
+            .unwrap();
+        assert!(matches!(event, ConnectionEvent::Connected(ref id, _) if *id == node_id2));
+
+        let conn = manager2
+            .connections
+            .lock()
+            .await
+            .get(&node_id1)
+            .cloned()
+            .expect("connection to node1");
+        conn.connection.close(VarInt::from_u32(0), b"test close");
+
+        let event = tokio::time::timeout(Duration::from_secs(2), event_rx.recv())
+            .await
+            .expect("disconnected event")
+            .unwrap();
+        assert_eq!(event, ConnectionEvent::Disconnected(node_id2));
+        cleanup_test_certs();
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_closes_connections() {
+        let _guard = serial_lock().lock().await;
+        init();
+        cleanup_test_certs();
+        let keypair1 = KeyPair::generate().expect("generate keypair");
+        let keypair2 = KeyPair::generate().expect("generate keypair");
+
+        let manager1 = ConnectionManager::run_server(mock_quic_config())
+            .await
+            .unwrap();
+        let manager2 =
+            ConnectionManager::run_server(mock_quic_config2().with_identity(keypair2.clone()))
+                .await
+                .unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let addr1 = manager1.endpoint.local_addr().expect("get local addr");
+        let addr1: SocketAddr = format!("127.0.0.1:{}", addr1.port()).parse().unwrap();
+        let node_id1 = NodeId::from_keypair(&keypair1);
+        let node_id2 = NodeId::from_keypair(&keypair2);
+
+        manager2
+            .connect(node_id2.clone(), node_id1.clone(), vec![addr1])
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(manager1.list_peers().await.contains(&node_id2));
+
+        tokio::time::timeout(Duration::from_secs(5), manager1.shutdown())
+            .await
+            .expect("shutdown should finish");
+        tokio::time::sleep(Duration::from_millis(300)).await;
+
+        assert!(manager1.list_peers().await.is_empty());
+        assert!(manager2.list_peers().await.is_empty());
+        cleanup_test_certs();
+    }
+
+    fn mock_pinned_quic_config(keypair: &KeyPair) -> QuicConfig {
+        QuicConfig::new(
+            "0.0.0.0:0".parse().unwrap(),
+            String::new(),
+            String::new(),
+            String::new(),
+        )
+        .with_peer_verification(true)
+        .with_identity(keypair.clone())
+    }
+
+    #[tokio::test]
+    async fn test_pinned_peer_verification() {
+        let _guard = serial_lock().lock().await;
+        init();
+        let keypair1 = KeyPair::generate().expect("generate keypair");
+        let keypair2 = KeyPair::generate().expect("generate keypair");
+        let other = KeyPair::generate().expect("generate keypair");
+
+        let manager1 = ConnectionManager::run_server(mock_pinned_quic_config(&keypair1))
+            .await
+            .unwrap();
+        let manager2 = ConnectionManager::run_server(mock_pinned_quic_config(&keypair2))
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let addr1 = manager1.endpoint.local_addr().expect("get local addr");
+        let addr1: SocketAddr = format!("127.0.0.1:{}", addr1.port()).parse().unwrap();
+        let node_id1 = NodeId::from_keypair(&keypair1);
+        let node_id2 = NodeId::from_keypair(&keypair2);
+
+        // 目标 NodeId 与对端证书不一致时应拒绝连接
+        let wrong_id = NodeId::from_keypair(&other);
+        let result = manager2
+            .connect(node_id2.clone(), wrong_id, vec![addr1])
+            .await;
+        assert!(result.is_err());
+
+        manager2
+            .connect(node_id2.clone(), node_id1.clone(), vec![addr1])
+            .await
+            .expect("connect with pinned node id");
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(manager1.list_peers().await.contains(&node_id2));
+    }
+
+    #[tokio::test]
+    async fn test_pinned_verification_accepts_legacy_certs() {
+        let _guard = serial_lock().lock().await;
+        init();
+        cleanup_test_certs();
+        let keypair1 = KeyPair::generate().expect("generate keypair");
+        let keypair2 = KeyPair::generate().expect("generate keypair");
+        let keypair3 = KeyPair::generate().expect("generate keypair");
+
+        // 旧版节点：仍使用证书目录中由 CA 签发的证书
+        let legacy = ConnectionManager::run_server(
+            mock_quic_config_no_shared_ca_1().with_identity(keypair1.clone()),
+        )
+        .await
+        .unwrap();
+        let strict = ConnectionManager::run_server(mock_pinned_quic_config(&keypair2))
+            .await
+            .unwrap();
+        let lenient = ConnectionManager::run_server(
+            mock_pinned_quic_config(&keypair3).with_legacy_peer_certs(true),
+        )
+        .await
+        .unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let addr = legacy.endpoint.local_addr().expect("get local addr");
+        let addr: SocketAddr = format!("127.0.0.1:{}", addr.port()).parse().unwrap();
+        let legacy_id = NodeId::from_keypair(&keypair1);
+
+        let result = strict
+            .connect(
+                NodeId::from_keypair(&keypair2),
+                legacy_id.clone(),
+                vec![addr],
+            )
+            .await;
+        assert!(result.is_err());
+
+        lenient
+            .connect(
+                NodeId::from_keypair(&keypair3),
+                legacy_id.clone(),
+                vec![addr],
+            )
+            .await
+            .expect("connect to legacy node");
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(lenient.list_peers().await.contains(&legacy_id));
         cleanup_test_certs();
     }
 
@@ -665,7 +2039,7 @@ mod tests {
             keypair1.clone(),
         );
 
-        let config2 = mock_quic_config_no_shared_ca_2();
+        let config2 = mock_quic_config_no_shared_ca_2().with_identity(keypair2.clone());
         let manager2 = ConnectionManager::run_server(config2).await;
         assert!(manager2.is_ok());
         let manager2 = manager2.unwrap();
@@ -698,4 +2072,455 @@ mod tests {
         assert!(connections2.contains_key(&node1.node_id().clone()));
         cleanup_test_certs();
     }
+
+    fn signed_identity(keypair: &KeyPair, proof: &[u8]) -> Vec<u8> {
+        let hello = HandshakeIdentity {
+            node_id: NodeId::from_keypair(keypair),
+            signature: hex::encode(keypair.sign(proof).unwrap().to_bytes()),
+        };
+        serde_json::to_vec(&hello).unwrap()
+    }
+
+    #[test]
+    fn test_verify_peer_identity() {
+        let keypair = KeyPair::generate().unwrap();
+        let node_id = NodeId::from_keypair(&keypair);
+        let proof = [7u8; 32];
+
+        let verified = verify_peer_identity(&signed_identity(&keypair, &proof), &proof).unwrap();
+        assert_eq!(verified, node_id);
+
+        // 签名来自其他连接的挑战
+        assert!(verify_peer_identity(&signed_identity(&keypair, &[8u8; 32]), &proof).is_err());
+
+        // 冒用他人 NodeId
+        let other = KeyPair::generate().unwrap();
+        let forged = HandshakeIdentity {
+            node_id: NodeId::from_keypair(&other),
+            signature: hex::encode(keypair.sign(&proof).unwrap().to_bytes()),
+        };
+        let forged = serde_json::to_vec(&forged).unwrap();
+        assert!(verify_peer_identity(&forged, &proof).is_err());
+
+        assert!(verify_peer_identity(b"", &proof).is_err());
+        assert!(verify_peer_identity(node_id.as_bytes(), &proof).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_connect_requires_matching_identity() {
+        let _guard = serial_lock().lock().await;
+        init();
+        let keypair1 = KeyPair::generate().expect("generate keypair");
+        let keypair2 = KeyPair::generate().expect("generate keypair");
+        let other = KeyPair::generate().expect("generate keypair");
+
+        let manager1 = ConnectionManager::run_server(mock_pinned_quic_config(&keypair1))
+            .await
+            .unwrap();
+        let manager2 = ConnectionManager::run_server(mock_pinned_quic_config(&keypair2))
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let addr1 = manager1.endpoint.local_addr().expect("get local addr");
+        let addr1: SocketAddr = format!("127.0.0.1:{}", addr1.port()).parse().unwrap();
+        let node_id1 = NodeId::from_keypair(&keypair1);
+
+        // 不能以未持有私钥的 NodeId 发起连接
+        let result = manager2
+            .connect(NodeId::from_keypair(&other), node_id1, vec![addr1])
+            .await;
+        assert!(result.is_err());
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(manager1.list_peers().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_request_response() {
+        let _guard = serial_lock().lock().await;
+        init();
+        let keypair1 = KeyPair::generate().expect("generate keypair");
+        let keypair2 = KeyPair::generate().expect("generate keypair");
+
+        let manager1 = ConnectionManager::run_server(mock_pinned_quic_config(&keypair1))
+            .await
+            .unwrap();
+        let manager2 = ConnectionManager::run_server(mock_pinned_quic_config(&keypair2))
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let addr1 = manager1.endpoint.local_addr().expect("get local addr");
+        let addr1: SocketAddr = format!("127.0.0.1:{}", addr1.port()).parse().unwrap();
+        let node_id1 = NodeId::from_keypair(&keypair1);
+        let node_id2 = NodeId::from_keypair(&keypair2);
+
+        manager2
+            .connect(node_id2.clone(), node_id1.clone(), vec![addr1])
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+
+        // 未注册处理器时请求被拒绝
+        let result = manager2.request(node_id1.clone(), b"ping".to_vec()).await;
+        assert!(result.is_err());
+
+        let (tx, mut rx) = mpsc::channel::<IncomingRequest>(8);
+        manager1.register_request_handler(tx).await;
+        let expected_from = node_id2.clone();
+        tokio::spawn(async move {
+            while let Some(request) = rx.recv().await {
+                assert_eq!(request.from, expected_from);
+                let mut response = b"pong:".to_vec();
+                response.extend_from_slice(&request.payload);
+                request.respond(response);
+            }
+        });
+
+        let response = manager2
+            .request(node_id1.clone(), b"ping".to_vec())
+            .await
+            .unwrap();
+        assert_eq!(response, b"pong:ping");
+
+        // 反方向：服务端也可以向客户端发起请求，客户端未注册处理器
+        let result = manager1
+            .request_with_timeout(node_id2.clone(), b"ping".to_vec(), Duration::from_secs(2))
+            .await;
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_retry_policy_backoff() {
+        let policy = RetryPolicy::new(5, Duration::from_millis(100))
+            .with_max_delay(Duration::from_millis(300));
+        assert_eq!(policy.delay_after(1), Duration::from_millis(100));
+        assert_eq!(policy.delay_after(2), Duration::from_millis(200));
+        assert_eq!(policy.delay_after(3), Duration::from_millis(300));
+        assert_eq!(policy.delay_after(40), Duration::from_millis(300));
+    }
+
+    #[tokio::test]
+    async fn test_connect_with_retry() {
+        let _guard = serial_lock().lock().await;
+        init();
+        let keypair1 = KeyPair::generate().expect("generate keypair");
+        let keypair2 = KeyPair::generate().expect("generate keypair");
+        let node_id1 = NodeId::from_keypair(&keypair1);
+        let node_id2 = NodeId::from_keypair(&keypair2);
+
+        let manager2 = ConnectionManager::run_server(mock_pinned_quic_config(&keypair2))
+            .await
+            .unwrap();
+
+        // 预留一个端口，稍后才在上面启动对端
+        let addr1 = std::net::UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let policy = RetryPolicy::new(3, Duration::from_millis(50))
+            .with_attempt_timeout(Duration::from_millis(300));
+
+        // 对端始终不可达时，尝试次数有上限并返回最终错误
+        let err = manager2
+            .connect_with_retry(node_id2.clone(), node_id1.clone(), vec![addr1], policy)
+            .await
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains("after 3 attempts"));
+
+        // 对端稍后启动时，重试可以连上
+        let mut config1 = mock_pinned_quic_config(&keypair1);
+        config1.bind_addr = addr1;
+        let server = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(400)).await;
+            ConnectionManager::run_server(config1).await.unwrap()
+        });
+        let policy = RetryPolicy::new(10, Duration::from_millis(100))
+            .with_attempt_timeout(Duration::from_millis(300));
+        manager2
+            .connect_with_retry(node_id2.clone(), node_id1.clone(), vec![addr1], policy)
+            .await
+            .expect("connect after peer starts");
+        let manager1 = server.await.unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(manager1.list_peers().await.contains(&node_id2));
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_connect_is_deduplicated() {
+        let _guard = serial_lock().lock().await;
+        init();
+        let keypair1 = KeyPair::generate().expect("generate keypair");
+        let keypair2 = KeyPair::generate().expect("generate keypair");
+
+        let manager1 = ConnectionManager::run_server(mock_pinned_quic_config(&keypair1))
+            .await
+            .unwrap();
+        let manager2 = ConnectionManager::run_server(mock_pinned_quic_config(&keypair2))
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let addr1 = manager1.endpoint.local_addr().expect("get local addr");
+        let addr1: SocketAddr = format!("127.0.0.1:{}", addr1.port()).parse().unwrap();
+        let node_id1 = NodeId::from_keypair(&keypair1);
+        let node_id2 = NodeId::from_keypair(&keypair2);
+
+        let (a, b) = tokio::join!(
+            manager2.connect(node_id2.clone(), node_id1.clone(), vec![addr1]),
+            manager2.connect(node_id2.clone(), node_id1.clone(), vec![addr1]),
+        );
+        a.unwrap();
+        b.unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+
+        // 只建立了一条连接
+        assert_eq!(manager1.endpoint.open_connections(), 1);
+        assert_eq!(manager2.endpoint.open_connections(), 1);
+        assert!(manager2.dial_locks.lock().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_simultaneous_dial_keeps_one_connection() {
+        let _guard = serial_lock().lock().await;
+        init();
+        let keypair1 = KeyPair::generate().expect("generate keypair");
+        let keypair2 = KeyPair::generate().expect("generate keypair");
+
+        let manager1 = ConnectionManager::run_server(mock_pinned_quic_config(&keypair1))
+            .await
+            .unwrap();
+        let manager2 = ConnectionManager::run_server(mock_pinned_quic_config(&keypair2))
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let port1 = manager1.endpoint.local_addr().unwrap().port();
+        let port2 = manager2.endpoint.local_addr().unwrap().port();
+        let addr1: SocketAddr = format!("127.0.0.1:{}", port1).parse().unwrap();
+        let addr2: SocketAddr = format!("127.0.0.1:{}", port2).parse().unwrap();
+        let node_id1 = NodeId::from_keypair(&keypair1);
+        let node_id2 = NodeId::from_keypair(&keypair2);
+
+        let (a, b) = tokio::join!(
+            manager1.connect(node_id1.clone(), node_id2.clone(), vec![addr2]),
+            manager2.connect(node_id2.clone(), node_id1.clone(), vec![addr1]),
+        );
+        a.unwrap();
+        b.unwrap();
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        // 两端保留的是同一条连接，另一条被关闭
+        assert!(manager1.has_live_connection(&node_id2).await);
+        assert!(manager2.has_live_connection(&node_id1).await);
+        assert_eq!(manager1.endpoint.open_connections(), 1);
+        assert_eq!(manager2.endpoint.open_connections(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_messages_are_dropped() {
+        let _guard = serial_lock().lock().await;
+        init();
+        let keypair1 = KeyPair::generate().expect("generate keypair");
+        let keypair2 = KeyPair::generate().expect("generate keypair");
+
+        let config1 = mock_pinned_quic_config(&keypair1)
+            .with_max_gossip_message_size(16)
+            .with_max_data_message_size(64);
+        let manager1 = ConnectionManager::run_server(config1).await.unwrap();
+        let manager2 = ConnectionManager::run_server(mock_pinned_quic_config(&keypair2))
+            .await
+            .unwrap();
+        let (gossip_tx, mut gossip_rx) = mpsc::channel(8);
+        let (data_tx, mut data_rx) = mpsc::channel(8);
+        manager1.register_gossip_sender(gossip_tx).await;
+        manager1.register_data_sender(data_tx).await;
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let addr1 = manager1.endpoint.local_addr().expect("get local addr");
+        let addr1: SocketAddr = format!("127.0.0.1:{}", addr1.port()).parse().unwrap();
+        let node_id1 = NodeId::from_keypair(&keypair1);
+        let node_id2 = NodeId::from_keypair(&keypair2);
+        manager2
+            .connect(node_id2.clone(), node_id1.clone(), vec![addr1])
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+
+        manager2
+            .send_gossip_message(node_id1.clone(), vec![b'x'; 32])
+            .await
+            .unwrap();
+        manager2
+            .send_gossip_message(node_id1.clone(), b"small".to_vec())
+            .await
+            .unwrap();
+        // 数据消息使用独立的上限，超过 gossip 上限但未超过数据上限仍可送达
+        manager2
+            .send_data_message(node_id1.clone(), vec![b'y'; 48])
+            .await
+            .unwrap();
+        manager2
+            .send_data_message(node_id1.clone(), vec![b'z'; 128])
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        let (_, gossip) = gossip_rx.try_recv().expect("small gossip message");
+        assert_eq!(gossip, b"small");
+        assert!(gossip_rx.try_recv().is_err());
+
+        let (_, data) = data_rx.try_recv().expect("data message under the cap");
+        assert_eq!(data.len(), 48);
+        assert!(data_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_data_stream_delivers_frames_in_order() {
+        let _guard = serial_lock().lock().await;
+        init();
+        let keypair1 = KeyPair::generate().expect("generate keypair");
+        let keypair2 = KeyPair::generate().expect("generate keypair");
+
+        let manager1 = ConnectionManager::run_server(
+            mock_pinned_quic_config(&keypair1)
+                .with_max_gossip_message_size(256)
+                .with_max_data_message_size(1024),
+        )
+        .await
+        .unwrap();
+        let manager2 = ConnectionManager::run_server(mock_pinned_quic_config(&keypair2))
+            .await
+            .unwrap();
+        let (gossip_tx, mut gossip_rx) = mpsc::channel(8);
+        let (data_tx, mut data_rx) = mpsc::channel(64);
+        manager1.register_gossip_sender(gossip_tx).await;
+        manager1.register_data_sender(data_tx).await;
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let addr1 = manager1.endpoint.local_addr().expect("get local addr");
+        let addr1: SocketAddr = format!("127.0.0.1:{}", addr1.port()).parse().unwrap();
+        let node_id1 = NodeId::from_keypair(&keypair1);
+        let node_id2 = NodeId::from_keypair(&keypair2);
+        manager2
+            .connect(node_id2.clone(), node_id1.clone(), vec![addr1])
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+
+        let mut stream = manager2.open_data_stream(node_id1.clone()).await.unwrap();
+        for i in 0..50u8 {
+            stream.send(&[i; 100]).await.unwrap();
+        }
+        // 数据流保持打开时，其他流上的 gossip 消息照常送达
+        manager2
+            .send_gossip_message(node_id1.clone(), b"hi".to_vec())
+            .await
+            .unwrap();
+        let (_, gossip) = tokio::time::timeout(Duration::from_secs(2), gossip_rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(gossip, b"hi");
+        stream.send(b"").await.unwrap();
+        stream.finish().unwrap();
+
+        for i in 0..50u8 {
+            let (from, data) = tokio::time::timeout(Duration::from_secs(2), data_rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(from, node_id2);
+            assert_eq!(data, vec![i; 100]);
+        }
+        let (_, empty) = data_rx.recv().await.unwrap();
+        assert!(empty.is_empty());
+
+        // 超过上限的帧使接收方停止读取该数据流
+        let mut stream = manager2.open_data_stream(node_id1.clone()).await.unwrap();
+        stream.send(&[1; 4096]).await.unwrap();
+        stream.send(b"after").await.unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(data_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_relay_forwards_data_messages() {
+        let _guard = serial_lock().lock().await;
+        init();
+        let keypair_a = KeyPair::generate().expect("generate keypair");
+        let keypair_relay = KeyPair::generate().expect("generate keypair");
+        let keypair_c = KeyPair::generate().expect("generate keypair");
+        let node_a = NodeId::from_keypair(&keypair_a);
+        let node_relay = NodeId::from_keypair(&keypair_relay);
+        let node_c = NodeId::from_keypair(&keypair_c);
+
+        let manager_a = ConnectionManager::run_server(mock_pinned_quic_config(&keypair_a))
+            .await
+            .unwrap();
+        let relay = ConnectionManager::run_server(
+            mock_pinned_quic_config(&keypair_relay).with_relay_forwarding(true),
+        )
+        .await
+        .unwrap();
+        let manager_c = ConnectionManager::run_server(mock_pinned_quic_config(&keypair_c))
+            .await
+            .unwrap();
+        let (data_tx, mut data_rx) = mpsc::channel(8);
+        manager_c.register_data_sender(data_tx).await;
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        // A 与 C 互不直连，只各自连到中继
+        let relay_port = relay.endpoint.local_addr().expect("get local addr").port();
+        let relay_addr: SocketAddr = format!("127.0.0.1:{}", relay_port).parse().unwrap();
+        manager_a
+            .connect(node_a.clone(), node_relay.clone(), vec![relay_addr])
+            .await
+            .unwrap();
+        manager_c
+            .connect(node_c.clone(), node_relay.clone(), vec![relay_addr])
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(!manager_a.list_peers().await.contains(&node_c));
+
+        // 中继报告与 C 直连之前，A 不会选择它
+        assert!(manager_a.relays_connected_to(&node_c).await.is_empty());
+        manager_a
+            .record_relay_peers(node_relay.clone(), [node_c.clone()])
+            .await;
+        assert_eq!(
+            manager_a.relays_connected_to(&node_c).await,
+            vec![node_relay.clone()]
+        );
+
+        // C 未把中继登记为与 A 直连时，丢弃它转交的数据
+        manager_c
+            .record_relay_peers(node_relay.clone(), [node_c.clone()])
+            .await;
+        manager_a
+            .send_relayed_data_message(node_relay.clone(), node_c.clone(), b"dropped".to_vec())
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(data_rx.try_recv().is_err());
+
+        manager_c
+            .record_relay_peers(node_relay.clone(), [node_a.clone()])
+            .await;
+        manager_a
+            .send_relayed_data_message(node_relay.clone(), node_c.clone(), b"bundle".to_vec())
+            .await
+            .unwrap();
+        let (from, data) = tokio::time::timeout(Duration::from_secs(2), data_rx.recv())
+            .await
+            .expect("relayed message delivered")
+            .unwrap();
+        assert_eq!(from, node_a);
+        assert_eq!(data, b"bundle");
+
+        manager_a.shutdown().await;
+        relay.shutdown().await;
+        manager_c.shutdown().await;
+    }
 }
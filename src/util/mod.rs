@@ -1,13 +1,248 @@
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+
+/// 当前 Unix 时间戳，单位为秒，与时区无关
+///
+/// 直接读取系统时钟，时钟回拨时可能比上一次调用小；gossip 消息、数据库记录的
+/// 时间字段都使用这个单位
 pub fn timestamp_now() -> i64 {
     chrono::Local::now().timestamp()
 }
 
+/// 本进程内最近一次 `timestamp_now_millis` 的返回值
+static LAST_MILLIS: AtomicI64 = AtomicI64::new(0);
+
+/// 当前 Unix 时间戳，单位为毫秒，用于需要区分先后的场景（如聊天消息排序）
+///
+/// 在同一进程内严格递增：系统时钟回拨或同一毫秒内多次调用时，返回上一次的值加一
+pub fn timestamp_now_millis() -> i64 {
+    let wall = chrono::Local::now().timestamp_millis();
+    let mut last = LAST_MILLIS.load(Ordering::Relaxed);
+    loop {
+        let next = wall.max(last + 1);
+        match LAST_MILLIS.compare_exchange_weak(last, next, Ordering::Relaxed, Ordering::Relaxed) {
+            Ok(_) => return next,
+            Err(actual) => last = actual,
+        }
+    }
+}
+
 /// 获取 repo_id 的最后一段字符串（用 : 分割）
 pub fn get_repo_id_last_part(repo_id: &str) -> String {
-    repo_id.split(':').next_back().unwrap_or(repo_id).to_string()
+    repo_id
+        .split(':')
+        .next_back()
+        .unwrap_or(repo_id)
+        .to_string()
 }
 
 /// 获取 node_id 的最后一段字符串（用 : 分割）
 pub fn get_node_id_last_part(node_id: &str) -> String {
-    node_id.split(':').next_back().unwrap_or(node_id).to_string()
+    node_id
+        .split(':')
+        .next_back()
+        .unwrap_or(node_id)
+        .to_string()
+}
+
+/// 计算目录总大小（不跟随符号链接，深度/条目数/总大小均有上限）
+///
+/// 使用显式栈迭代遍历，目录层级再深也不会耗尽调用栈；这是阻塞调用，
+/// 异步上下文中请使用 `calculate_directory_size_async`
+pub fn calculate_directory_size(path: &std::path::Path) -> u64 {
+    use std::fs;
+
+    const MAX_DEPTH: usize = 64;
+    const MAX_ENTRIES: u64 = 200_000;
+    const MAX_TOTAL_SIZE: u64 = 20 * 1024 * 1024 * 1024; // 20 GiB
+
+    let mut entries_seen: u64 = 0;
+    let mut total: u64 = 0;
+    let mut stack = vec![(path.to_path_buf(), 0usize)];
+
+    while let Some((dir, depth)) = stack.pop() {
+        if entries_seen >= MAX_ENTRIES || total >= MAX_TOTAL_SIZE {
+            break;
+        }
+
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+
+        for entry in entries.flatten() {
+            if entries_seen >= MAX_ENTRIES || total >= MAX_TOTAL_SIZE {
+                break;
+            }
+
+            entries_seen += 1;
+            let p = entry.path();
+
+            // Never follow symlinks to avoid cycles and unbounded traversal.
+            let Ok(meta) = fs::symlink_metadata(&p) else {
+                continue;
+            };
+
+            let file_type = meta.file_type();
+            if file_type.is_symlink() {
+                continue;
+            }
+
+            if file_type.is_file() {
+                total = total.saturating_add(meta.len());
+            } else if file_type.is_dir() && depth < MAX_DEPTH {
+                stack.push((p, depth + 1));
+            }
+        }
+    }
+
+    total
+}
+
+/// 在阻塞线程池中计算目录总大小，避免大仓库阻塞异步运行时
+pub async fn calculate_directory_size_async(path: std::path::PathBuf) -> u64 {
+    tokio::task::spawn_blocking(move || calculate_directory_size(&path))
+        .await
+        .unwrap_or(0)
+}
+
+/// 以 1024 为进制格式化字节数，例如 "1.50 MB"
+pub fn format_bytes(bytes: u64) -> String {
+    const KB: u64 = 1024;
+    const MB: u64 = KB * 1024;
+    const GB: u64 = MB * 1024;
+
+    if bytes >= GB {
+        format!("{:.2} GB", bytes as f64 / GB as f64)
+    } else if bytes >= MB {
+        format!("{:.2} MB", bytes as f64 / MB as f64)
+    } else if bytes >= KB {
+        format!("{:.2} KB", bytes as f64 / KB as f64)
+    } else {
+        format!("{} B", bytes)
+    }
+}
+
+/// 显示时间戳时是否使用 UTC（RFC 3339）而不是本地时间，由命令行的 `--utc` 设置
+static UTC_TIMESTAMPS: AtomicBool = AtomicBool::new(false);
+
+/// 设置时间戳的显示方式：true 时所有显示位置都输出 RFC 3339 UTC 时间
+pub fn set_utc_timestamps(enabled: bool) {
+    UTC_TIMESTAMPS.store(enabled, Ordering::Relaxed);
+}
+
+/// 格式化 Unix 时间戳（秒），时间戳无效时返回空字符串
+///
+/// 数据库和消息中保存的都是与时区无关的 Unix 秒数，只在显示时转换：默认按 `fmt`
+/// 输出本地时间，开启 `set_utc_timestamps` 后忽略 `fmt`，输出 RFC 3339 UTC 时间
+pub fn format_timestamp(ts: i64, fmt: &str) -> String {
+    if UTC_TIMESTAMPS.load(Ordering::Relaxed) {
+        return format_iso8601(ts);
+    }
+    chrono::DateTime::from_timestamp(ts, 0)
+        .map(|dt| dt.with_timezone(&chrono::Local).format(fmt).to_string())
+        .unwrap_or_default()
+}
+
+/// 将 Unix 时间戳（秒）格式化为 ISO 8601 / RFC 3339 UTC 时间，例如 "2023-11-14T22:13:20Z"
+///
+/// 供脚本和 MCP 等机器读取的输出使用，与本机时区和 `--utc` 设置无关
+pub fn format_iso8601(ts: i64) -> String {
+    chrono::DateTime::from_timestamp(ts, 0)
+        .map(|dt| dt.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+        .unwrap_or_default()
+}
+
+/// 将 Unix 时间戳（秒）格式化为相对当前的时间，例如 "3m ago"；未来的时间视为刚刚
+pub fn format_relative_time(ts: i64) -> String {
+    relative_time_since(ts, timestamp_now())
+}
+
+fn relative_time_since(ts: i64, now: i64) -> String {
+    let secs = now.saturating_sub(ts).max(0);
+    match secs {
+        0..=59 => format!("{}s ago", secs),
+        60..=3599 => format!("{}m ago", secs / 60),
+        3600..=86399 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timestamps_do_not_go_backwards() {
+        let first = timestamp_now();
+        let second = timestamp_now();
+        assert!(second >= first);
+
+        let mut last = timestamp_now_millis();
+        assert!(last / 1000 >= first);
+        for _ in 0..1000 {
+            let next = timestamp_now_millis();
+            assert!(next > last);
+            last = next;
+        }
+    }
+
+    #[test]
+    fn test_format_bytes_boundaries() {
+        assert_eq!(format_bytes(0), "0 B");
+        assert_eq!(format_bytes(1023), "1023 B");
+        assert_eq!(format_bytes(1024), "1.00 KB");
+        assert_eq!(format_bytes(1024 * 1024 - 1), "1024.00 KB");
+        assert_eq!(format_bytes(1024 * 1024), "1.00 MB");
+        assert_eq!(format_bytes(1024 * 1024 * 1024), "1.00 GB");
+    }
+
+    #[test]
+    fn test_relative_time_boundaries() {
+        let now = 1_700_000_000;
+        assert_eq!(relative_time_since(now, now), "0s ago");
+        assert_eq!(relative_time_since(now + 30, now), "0s ago");
+        assert_eq!(relative_time_since(now - 59, now), "59s ago");
+        assert_eq!(relative_time_since(now - 60, now), "1m ago");
+        assert_eq!(relative_time_since(now - 3600, now), "1h ago");
+        assert_eq!(relative_time_since(now - 86399, now), "23h ago");
+        assert_eq!(relative_time_since(now - 86400 * 3, now), "3d ago");
+    }
+
+    #[test]
+    fn test_format_timestamp_local_and_utc() {
+        use chrono::TimeZone;
+
+        let ts = 1_700_000_000;
+        assert_eq!(format_iso8601(ts), "2023-11-14T22:13:20Z");
+        assert_eq!(format_iso8601(i64::MAX), "");
+
+        let expected = chrono::Local
+            .timestamp_opt(ts, 0)
+            .unwrap()
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string();
+        assert_eq!(format_timestamp(ts, "%Y-%m-%d %H:%M:%S"), expected);
+        assert_eq!(format_timestamp(i64::MAX, "%Y"), "");
+
+        set_utc_timestamps(true);
+        let utc = format_timestamp(ts, "%Y-%m-%d %H:%M:%S");
+        set_utc_timestamps(false);
+        assert_eq!(utc, "2023-11-14T22:13:20Z");
+    }
+
+    #[tokio::test]
+    async fn test_calculate_directory_size_deep_tree() {
+        let root = std::env::temp_dir().join(format!("megaengine-size-{}", uuid::Uuid::new_v4()));
+        let mut dir = root.clone();
+        for i in 0..100 {
+            dir = dir.join(format!("d{}", i));
+        }
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(root.join("top.txt"), [0u8; 10]).unwrap();
+        std::fs::write(root.join("d0").join("a.txt"), [0u8; 5]).unwrap();
+        // 超过深度上限的文件不计入
+        std::fs::write(dir.join("deep.txt"), [0u8; 1000]).unwrap();
+
+        assert_eq!(calculate_directory_size_async(root.clone()).await, 15);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
 }
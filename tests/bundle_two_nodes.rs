@@ -1,5 +1,5 @@
 //! 集成测试：两个节点之间通过网络传输 bundle
-use megaengine::bundle::BundleService;
+use megaengine::bundle::{BundleService, TransferDirection};
 use megaengine::git::pack::{pack_repo_bundle, restore_repo_from_bundle};
 use megaengine::gossip::GossipService;
 use megaengine::identity::keypair::KeyPair;
@@ -445,3 +445,314 @@ async fn test_bundle_transfer_between_two_nodes() {
     // 清理生成的证书目录（包含 CA 文件）
     fs::remove_dir_all(&cert_dir).ok();
 }
+
+#[tokio::test]
+async fn test_compressed_encrypted_bundle_transfer_between_two_nodes() {
+    let _ = rustls::crypto::ring::default_provider().install_default();
+
+    let base_dir = std::env::current_dir()
+        .unwrap()
+        .join("tmp/bundle_two_nodes_compressed");
+    fs::remove_dir_all(&base_dir).ok();
+    fs::create_dir_all(&base_dir).expect("Failed to create test directory");
+
+    let path_of = |name: &str| base_dir.join(name).to_string_lossy().to_string();
+    let ca_cert_path = path_of("ca-cert.pem");
+    megaengine::transport::cert::ensure_certificates(
+        &path_of("cert_sender.pem"),
+        &path_of("key_sender.pem"),
+        &ca_cert_path,
+    )
+    .expect("Failed to ensure sender certificates");
+    megaengine::transport::cert::ensure_certificates(
+        &path_of("cert_receiver.pem"),
+        &path_of("key_receiver.pem"),
+        &ca_cert_path,
+    )
+    .expect("Failed to ensure receiver certificates");
+
+    let sender_addr: SocketAddr = "127.0.0.1:19012".parse().unwrap();
+    let receiver_addr: SocketAddr = "127.0.0.1:19013".parse().unwrap();
+    let mut sender_node = Node::from_keypair(
+        &KeyPair::generate().unwrap(),
+        "compressed_sender",
+        vec![sender_addr],
+        NodeType::Normal,
+    );
+    let mut receiver_node = Node::from_keypair(
+        &KeyPair::generate().unwrap(),
+        "compressed_receiver",
+        vec![receiver_addr],
+        NodeType::Normal,
+    );
+    sender_node
+        .start_quic_server(QuicConfig::new(
+            sender_addr,
+            path_of("cert_sender.pem"),
+            path_of("key_sender.pem"),
+            ca_cert_path.clone(),
+        ))
+        .await
+        .expect("Failed to start sender QUIC server");
+    receiver_node
+        .start_quic_server(QuicConfig::new(
+            receiver_addr,
+            path_of("cert_receiver.pem"),
+            path_of("key_receiver.pem"),
+            ca_cert_path.clone(),
+        ))
+        .await
+        .expect("Failed to start receiver QUIC server");
+
+    let sender_mgr = Arc::clone(sender_node.connection_manager.as_ref().unwrap());
+    let receiver_mgr = Arc::clone(receiver_node.connection_manager.as_ref().unwrap());
+
+    // 只有发送端开启压缩和加密，接收端根据 Start 消息中的标记用自己的密钥解密后解压
+    let (send_progress_tx, mut send_progress_rx) = tokio::sync::mpsc::channel(1024);
+    let (recv_progress_tx, mut recv_progress_rx) = tokio::sync::mpsc::channel(1024);
+    let sender_bundle = Arc::new(
+        BundleService::new(Arc::clone(&sender_mgr), base_dir.join("sender_storage"))
+            .with_compression(true)
+            .with_encryption(true)
+            .with_progress(send_progress_tx),
+    );
+    let receiver_storage = base_dir.join("receiver_storage");
+    let receiver_bundle = Arc::new(
+        BundleService::new(Arc::clone(&receiver_mgr), receiver_storage.clone())
+            .with_keypair(receiver_node.keypair.clone())
+            .with_progress(recv_progress_tx),
+    );
+    sender_bundle.clone().start().await.unwrap();
+    receiver_bundle.clone().start().await.unwrap();
+
+    // 构造一个跨越多个 64KB 数据块的仓库
+    let repo_path = base_dir.join("repo");
+    create_test_repo(repo_path.to_str().unwrap()).expect("Failed to create test repository");
+    let mut seed: u32 = 12345;
+    let content: String = (0..200_000)
+        .map(|_| {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            (b'a' + ((seed >> 16) % 26) as u8) as char
+        })
+        .collect();
+    fs::write(repo_path.join("large.txt"), content).unwrap();
+    run_git_command(repo_path.to_str().unwrap(), &["add", "."]);
+    run_git_command(
+        repo_path.to_str().unwrap(),
+        &["commit", "-m", "Add large file"],
+    );
+
+    let bundle_path = base_dir.join("compressed.bundle");
+    pack_repo_bundle(repo_path.to_str().unwrap(), bundle_path.to_str().unwrap())
+        .expect("Failed to pack repository");
+    let original = fs::read(&bundle_path).unwrap();
+    assert!(original.len() > 64 * 1024);
+
+    sender_mgr
+        .lock()
+        .await
+        .connect(
+            sender_node.node_id().clone(),
+            receiver_node.node_id().clone(),
+            vec![receiver_addr],
+        )
+        .await
+        .expect("Failed to connect sender to receiver");
+    sleep(Duration::from_millis(500)).await;
+
+    sender_bundle
+        .send_bundle(
+            receiver_node.node_id().clone(),
+            "compressed_repo".to_string(),
+            bundle_path.to_str().unwrap(),
+        )
+        .await
+        .expect("Failed to send bundle");
+
+    let sender_id = sender_node.node_id().to_string();
+    let encoded_sender_id = sender_id.split(':').next_back().unwrap().replace('/', "_");
+    let received_path =
+        receiver_storage.join(format!("{}/compressed_repo.bundle", encoded_sender_id));
+
+    let mut received = Vec::new();
+    for _ in 0..20 {
+        sleep(Duration::from_millis(250)).await;
+        if let Ok(bytes) = fs::read(&received_path) {
+            if bytes.len() == original.len() {
+                received = bytes;
+                break;
+            }
+        }
+    }
+    assert!(
+        received == original,
+        "received bundle differs from the original"
+    );
+
+    // 双方都应上报进度，且最后一个事件为传输完成（按解压后的字节计）
+    sleep(Duration::from_millis(200)).await;
+    let mut last_sent = None;
+    while let Ok(p) = send_progress_rx.try_recv() {
+        assert_eq!(p.direction, TransferDirection::Sending);
+        assert_eq!(&p.peer, receiver_node.node_id());
+        last_sent = Some(p);
+    }
+    let last_sent = last_sent.expect("sender reported no progress");
+    assert_eq!(last_sent.repo_id, "compressed_repo");
+    assert_eq!(last_sent.bytes_transferred, original.len() as u64);
+    assert!(last_sent.is_complete());
+
+    let mut last_received = None;
+    while let Ok(p) = recv_progress_rx.try_recv() {
+        assert_eq!(p.direction, TransferDirection::Receiving);
+        assert_eq!(&p.peer, sender_node.node_id());
+        last_received = Some(p);
+    }
+    let last_received = last_received.expect("receiver reported no progress");
+    assert_eq!(last_received.total_size, original.len() as u64);
+    assert!(last_received.is_complete());
+
+    let _ = megaengine::storage::node_model::delete_node_from_db(&sender_id).await;
+    let _ =
+        megaengine::storage::node_model::delete_node_from_db(&receiver_node.node_id().to_string())
+            .await;
+    fs::remove_dir_all(&base_dir).ok();
+}
+
+/// 用给定的传输配置在两个节点间发送一个 `size` 字节的 bundle，返回从发送到接收方落盘的耗时
+async fn timed_bundle_transfer(bulk: bool, ports: (u16, u16), size: usize) -> Duration {
+    let sender_kp = KeyPair::generate().unwrap();
+    let receiver_kp = KeyPair::generate().unwrap();
+    let sender_addr: SocketAddr = format!("127.0.0.1:{}", ports.0).parse().unwrap();
+    let receiver_addr: SocketAddr = format!("127.0.0.1:{}", ports.1).parse().unwrap();
+
+    let quic_config = |addr: SocketAddr, kp: &KeyPair| {
+        let config = QuicConfig::new(addr, String::new(), String::new(), String::new())
+            .with_peer_verification(true)
+            .with_identity(kp.clone());
+        if bulk {
+            config.for_bulk_transfer()
+        } else {
+            config
+        }
+    };
+
+    let mut sender_node =
+        Node::from_keypair(&sender_kp, "sender", vec![sender_addr], NodeType::Normal);
+    let mut receiver_node = Node::from_keypair(
+        &receiver_kp,
+        "receiver",
+        vec![receiver_addr],
+        NodeType::Normal,
+    );
+    sender_node
+        .start_quic_server(quic_config(sender_addr, &sender_kp))
+        .await
+        .unwrap();
+    receiver_node
+        .start_quic_server(quic_config(receiver_addr, &receiver_kp))
+        .await
+        .unwrap();
+
+    let tmp = std::env::current_dir()
+        .unwrap()
+        .join(format!("tmp/bulk_bench_{}", ports.0));
+    fs::remove_dir_all(&tmp).ok();
+    fs::create_dir_all(&tmp).unwrap();
+    let sender_bundle = Arc::new(BundleService::new(
+        Arc::clone(sender_node.connection_manager.as_ref().unwrap()),
+        tmp.join("sender"),
+    ));
+    let receiver_bundle = Arc::new(BundleService::new(
+        Arc::clone(receiver_node.connection_manager.as_ref().unwrap()),
+        tmp.join("receiver"),
+    ));
+    sender_bundle.clone().start().await.unwrap();
+    receiver_bundle.clone().start().await.unwrap();
+
+    // 内容不可压缩，避免受压缩设置影响
+    let bundle_path = tmp.join("large.bundle");
+    let data: Vec<u8> = (0..size as u64)
+        .map(|i| (i.wrapping_mul(2654435761) >> 13) as u8)
+        .collect();
+    fs::write(&bundle_path, &data).unwrap();
+
+    sender_node
+        .connection_manager
+        .as_ref()
+        .unwrap()
+        .lock()
+        .await
+        .connect(
+            sender_node.node_id().clone(),
+            receiver_node.node_id().clone(),
+            vec![receiver_addr],
+        )
+        .await
+        .unwrap();
+    sleep(Duration::from_millis(300)).await;
+
+    let sender_id = sender_node.node_id().to_string();
+    let encoded_sender_id = sender_id.split(':').next_back().unwrap().to_string();
+    let received_path = tmp
+        .join("receiver")
+        .join(encoded_sender_id)
+        .join("bench_repo.bundle");
+
+    let started = std::time::Instant::now();
+    sender_bundle
+        .send_bundle(
+            receiver_node.node_id().clone(),
+            "bench_repo".to_string(),
+            bundle_path.to_str().unwrap(),
+        )
+        .await
+        .unwrap();
+    while fs::metadata(&received_path).map(|m| m.len()).unwrap_or(0) != size as u64 {
+        assert!(
+            started.elapsed() < Duration::from_secs(120),
+            "bundle not received"
+        );
+        sleep(Duration::from_millis(5)).await;
+    }
+    let elapsed = started.elapsed();
+
+    for node in [&sender_node, &receiver_node] {
+        node.connection_manager
+            .as_ref()
+            .unwrap()
+            .lock()
+            .await
+            .shutdown()
+            .await;
+    }
+    fs::remove_dir_all(&tmp).ok();
+    elapsed
+}
+
+/// 对比默认流控窗口和 `QuicConfig::for_bulk_transfer` 预设传输大 bundle 的耗时
+///
+/// 回环网络几乎没有往返延迟，窗口不是瓶颈，所以只打印结果不做断言；
+/// 在有延迟的链路上运行（如 `tc qdisc add dev lo root netem delay 50ms`）才能看出差别。
+/// 运行：`cargo test --release --test bundle_two_nodes -- --ignored --nocapture`
+#[tokio::test]
+#[ignore]
+async fn bench_large_bundle_transfer_with_bulk_windows() {
+    let _ = rustls::crypto::ring::default_provider().install_default();
+    const SIZE: usize = 64 * 1024 * 1024;
+
+    let default_time = timed_bundle_transfer(false, (19020, 19021), SIZE).await;
+    let bulk_time = timed_bundle_transfer(true, (19022, 19023), SIZE).await;
+
+    let throughput = |elapsed: Duration| SIZE as f64 / 1024.0 / 1024.0 / elapsed.as_secs_f64();
+    println!(
+        "default windows: {:.2?} ({:.1} MiB/s)",
+        default_time,
+        throughput(default_time)
+    );
+    println!(
+        "bulk windows:    {:.2?} ({:.1} MiB/s)",
+        bulk_time,
+        throughput(bulk_time)
+    );
+}
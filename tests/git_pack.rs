@@ -1,13 +1,25 @@
-use megaengine::git::pack::pack_repo_bundle;
+use megaengine::error::MegaError;
+use megaengine::git::git_repo::{
+    bundle_default_branch, bundle_recent_commits, default_branch, history_stats,
+    pending_ref_updates, read_repo_refs, read_repo_refs_filtered, recent_commits, unbundle_into,
+    HistoryStats, RefFilter,
+};
+use megaengine::git::pack::{
+    apply_delta_bundle, extract_bundle_refs, pack_repo_bundle, pack_repo_delta_bundle,
+    pack_repo_thin_bundle, pull_repo_from_bundle, restore_repo_from_bundle,
+    restore_repo_from_bundle_with_progress, verify_bundle, BundleKind,
+};
+use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
 use std::process::Command;
+use tokio_util::sync::CancellationToken;
 
 /// Ensure tmp directory exists
 fn ensure_tmp_dir() -> PathBuf {
     let tmp_path = PathBuf::from("tmp");
     if !tmp_path.exists() {
-        fs::create_dir(&tmp_path).expect("Failed to create tmp directory");
+        fs::create_dir_all(&tmp_path).expect("Failed to create tmp directory");
     }
     tmp_path
 }
@@ -231,7 +243,10 @@ fn test_pack_repo_bundle() {
         .expect("Failed to list tags");
     let tags = String::from_utf8_lossy(&output.stdout);
     println!("Tags in restored repo:\n{}", tags);
-    // Note: Tags might not be included in the bundle by default
+    assert!(
+        tags.lines().any(|t| t == "v1.0"),
+        "Tag v1.0 not found in restored repo"
+    );
 
     println!("✅ All verifications passed!");
     println!("\n📊 Summary:");
@@ -251,3 +266,725 @@ fn test_pack_repo_bundle() {
     fs::remove_file(&bundle_path_abs).ok();
     println!("✅ Cleanup completed!");
 }
+
+/// Test pack_repo_delta_bundle:
+/// 1. Clone repo B from a full bundle of repo A
+/// 2. Add commits to A and pack a thin bundle against B's refs
+/// 3. Unbundle the thin bundle into B
+/// 4. Nothing is packed once B is up to date
+/// 5. Unknown `have` commits fall back to a full bundle
+#[test]
+fn test_pack_repo_delta_bundle() {
+    let tmp_dir = std::env::current_dir().unwrap().join(ensure_tmp_dir());
+    let src_path = tmp_dir.join("delta_src");
+    let dst_path = tmp_dir.join("delta_dst");
+    let full_bundle = tmp_dir.join("delta_full.bundle");
+    let thin_bundle = tmp_dir.join("delta_thin.bundle");
+    fs::remove_dir_all(&src_path).ok();
+    fs::remove_dir_all(&dst_path).ok();
+    fs::create_dir(&src_path).expect("Failed to create source directory");
+    let src = src_path.to_str().unwrap();
+    let dst = dst_path.to_str().unwrap();
+
+    assert!(run_git_command(src, &["init"]));
+    assert!(run_git_command(
+        src,
+        &["config", "user.email", "test@example.com"]
+    ));
+    assert!(run_git_command(src, &["config", "user.name", "Test User"]));
+    // Poorly compressible content so the full bundle is clearly larger than the thin one
+    let mut seed: u64 = 42;
+    let big: String = (0..20000)
+        .map(|_| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+            char::from(b'a' + (seed >> 59) as u8)
+        })
+        .collect();
+    fs::write(src_path.join("big.txt"), big).unwrap();
+    assert!(run_git_command(src, &["add", "."]));
+    assert!(run_git_command(src, &["commit", "-m", "Base commit"]));
+
+    // Step 1: full bundle -> clone
+    pack_repo_bundle(src, full_bundle.to_str().unwrap()).expect("Failed to pack full bundle");
+    assert!(run_git_command(
+        tmp_dir.to_str().unwrap(),
+        &["clone", full_bundle.to_str().unwrap(), dst]
+    ));
+
+    // Step 2: new commit on the source, thin bundle against the clone's refs
+    fs::write(src_path.join("new.txt"), "new content\n").unwrap();
+    assert!(run_git_command(src, &["add", "."]));
+    assert!(run_git_command(src, &["commit", "-m", "Second commit"]));
+
+    let have: Vec<String> = read_repo_refs(dst).unwrap().into_values().collect();
+    let kind = pack_repo_delta_bundle(src, thin_bundle.to_str().unwrap(), &have)
+        .expect("Failed to pack delta bundle");
+    assert_eq!(kind, BundleKind::Delta, "Expected a thin bundle");
+    assert!(
+        fs::metadata(&thin_bundle).unwrap().len() < fs::metadata(&full_bundle).unwrap().len(),
+        "Thin bundle should be smaller than the full bundle"
+    );
+
+    // Step 3: unbundle into the existing clone
+    apply_delta_bundle(dst, thin_bundle.to_str().unwrap()).expect("Failed to apply thin bundle");
+    let new_head = Command::new("git")
+        .current_dir(src)
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .unwrap();
+    let new_head = String::from_utf8_lossy(&new_head.stdout).trim().to_string();
+    assert!(
+        run_git_command(dst, &["cat-file", "-e", &new_head]),
+        "New commit should exist in the clone after unbundle"
+    );
+    let dst_head = Command::new("git")
+        .current_dir(dst)
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&dst_head.stdout).trim(),
+        new_head,
+        "The clone's branch should move to the new commit"
+    );
+
+    // Step 4: an up-to-date receiver gets no bundle at all
+    fs::remove_file(&thin_bundle).ok();
+    let have: Vec<String> = read_repo_refs(dst).unwrap().into_values().collect();
+    let kind = pack_repo_delta_bundle(src, thin_bundle.to_str().unwrap(), &have)
+        .expect("Failed to check up-to-date receiver");
+    assert_eq!(kind, BundleKind::UpToDate);
+    assert!(!thin_bundle.exists(), "No bundle should be written");
+
+    // Step 5: diverged history falls back to a full bundle
+    let unknown = vec!["0123456789abcdef0123456789abcdef01234567".to_string()];
+    let kind = pack_repo_delta_bundle(src, thin_bundle.to_str().unwrap(), &unknown)
+        .expect("Failed to pack fallback bundle");
+    assert_eq!(
+        kind,
+        BundleKind::Full,
+        "Unknown commits should fall back to a full bundle"
+    );
+
+    fs::remove_dir_all(&src_path).ok();
+    fs::remove_dir_all(&dst_path).ok();
+    fs::remove_file(&full_bundle).ok();
+    fs::remove_file(&thin_bundle).ok();
+}
+
+/// Test pack_repo_thin_bundle against the receiver's ref set:
+/// 1. An empty ref set falls back to a full bundle
+/// 2. Only the advanced and new refs are bundled; a deleted ref is left out
+/// 3. The thin bundle unbundles onto the receiver, and nothing is bundled once it is up to date
+#[test]
+fn test_pack_repo_thin_bundle() {
+    let tmp_dir = std::env::current_dir().unwrap().join(ensure_tmp_dir());
+    let src_path = tmp_dir.join("thin_src");
+    let dst_path = tmp_dir.join("thin_dst");
+    let full_bundle = tmp_dir.join("thin_full.bundle");
+    let thin_bundle = tmp_dir.join("thin_thin.bundle");
+    fs::remove_dir_all(&src_path).ok();
+    fs::remove_dir_all(&dst_path).ok();
+    fs::remove_file(&thin_bundle).ok();
+    fs::create_dir(&src_path).expect("Failed to create source directory");
+    let src = src_path.to_str().unwrap();
+    let dst = dst_path.to_str().unwrap();
+
+    assert!(run_git_command(src, &["init"]));
+    assert!(run_git_command(src, &["checkout", "-q", "-b", "main"]));
+    assert!(run_git_command(
+        src,
+        &["config", "user.email", "test@example.com"]
+    ));
+    assert!(run_git_command(src, &["config", "user.name", "Test User"]));
+    fs::write(src_path.join("base.txt"), "base\n").unwrap();
+    assert!(run_git_command(src, &["add", "."]));
+    assert!(run_git_command(src, &["commit", "-m", "Base commit"]));
+    assert!(run_git_command(src, &["branch", "stale"]));
+
+    // Step 1: empty ref set -> full bundle with every branch
+    let included = pack_repo_thin_bundle(src, full_bundle.to_str().unwrap(), &HashMap::new())
+        .expect("Failed to pack full bundle");
+    assert_eq!(included, vec!["refs/heads/main", "refs/heads/stale"]);
+    assert!(run_git_command(
+        tmp_dir.to_str().unwrap(),
+        &["clone", "-q", full_bundle.to_str().unwrap(), dst]
+    ));
+
+    // Step 2: advance main, add a branch and delete one
+    let base = read_repo_refs(src).unwrap()["refs/heads/main"].clone();
+    fs::write(src_path.join("new.txt"), "new content\n").unwrap();
+    assert!(run_git_command(src, &["add", "."]));
+    assert!(run_git_command(src, &["commit", "-m", "Second commit"]));
+    assert!(run_git_command(src, &["branch", "feature"]));
+    assert!(run_git_command(src, &["branch", "-D", "stale"]));
+
+    let have: HashMap<String, String> = [
+        ("refs/heads/main".to_string(), base.clone()),
+        ("refs/heads/stale".to_string(), base),
+    ]
+    .into();
+    let included = pack_repo_thin_bundle(src, thin_bundle.to_str().unwrap(), &have)
+        .expect("Failed to pack thin bundle");
+    assert_eq!(included, vec!["refs/heads/feature", "refs/heads/main"]);
+    let mut bundled: Vec<String> = extract_bundle_refs(thin_bundle.to_str().unwrap())
+        .unwrap()
+        .into_keys()
+        .collect();
+    bundled.sort();
+    assert_eq!(bundled, included);
+
+    // Step 3: the thin bundle applies onto the receiver
+    apply_delta_bundle(dst, thin_bundle.to_str().unwrap()).expect("Failed to apply thin bundle");
+    let src_refs = read_repo_refs(src).unwrap();
+    assert!(
+        run_git_command(dst, &["cat-file", "-e", &src_refs["refs/heads/main"]]),
+        "New commit should exist in the receiver after unbundle"
+    );
+    let dst_refs = read_repo_refs(dst).unwrap();
+    assert_eq!(dst_refs["refs/heads/main"], src_refs["refs/heads/main"]);
+    assert_eq!(
+        dst_refs["refs/heads/feature"],
+        src_refs["refs/heads/feature"]
+    );
+
+    fs::remove_file(&thin_bundle).ok();
+    let included = pack_repo_thin_bundle(src, thin_bundle.to_str().unwrap(), &src_refs)
+        .expect("Failed to pack up-to-date bundle");
+    assert!(included.is_empty());
+    assert!(!thin_bundle.exists(), "No bundle should be written");
+
+    fs::remove_dir_all(&src_path).ok();
+    fs::remove_dir_all(&dst_path).ok();
+    fs::remove_file(&full_bundle).ok();
+}
+
+/// Test pull_repo_from_bundle on a non-master default branch:
+/// 1. Clone from a full bundle of a repo whose default branch is `main`
+/// 2. Advance `main` and add a new branch, then pull from a new full bundle
+/// 3. A thin bundle whose prerequisites are missing locally fails clearly
+#[test]
+fn test_pull_repo_from_bundle() {
+    let tmp_dir = std::env::current_dir().unwrap().join(ensure_tmp_dir());
+    let src_path = tmp_dir.join("pull_src");
+    let dst_path = tmp_dir.join("pull_dst");
+    let stale_path = tmp_dir.join("pull_stale");
+    let bundle = tmp_dir.join("pull_full.bundle");
+    let thin_bundle = tmp_dir.join("pull_thin.bundle");
+    for dir in [&src_path, &dst_path, &stale_path] {
+        fs::remove_dir_all(dir).ok();
+    }
+    fs::create_dir(&src_path).expect("Failed to create source directory");
+    let src = src_path.to_str().unwrap();
+    let dst = dst_path.to_str().unwrap();
+    let stale = stale_path.to_str().unwrap();
+
+    assert!(run_git_command(src, &["init", "-b", "main"]));
+    assert!(run_git_command(
+        src,
+        &["config", "user.email", "test@example.com"]
+    ));
+    assert!(run_git_command(src, &["config", "user.name", "Test User"]));
+    fs::write(src_path.join("a.txt"), "a\n").unwrap();
+    assert!(run_git_command(src, &["add", "."]));
+    assert!(run_git_command(src, &["commit", "-m", "First"]));
+
+    // Step 1: clone from a full bundle
+    pack_repo_bundle(src, bundle.to_str().unwrap()).expect("Failed to pack bundle");
+    let tmp = tmp_dir.to_str().unwrap();
+    assert!(run_git_command(
+        tmp,
+        &["clone", bundle.to_str().unwrap(), dst]
+    ));
+    assert!(run_git_command(
+        tmp,
+        &["clone", bundle.to_str().unwrap(), stale]
+    ));
+    // Bundles carry no HEAD, so check out the default branch like restore_repo_from_bundle
+    assert!(run_git_command(dst, &["checkout", "main"]));
+    assert!(run_git_command(stale, &["checkout", "main"]));
+    let first_head = read_repo_refs(src).unwrap()["refs/heads/main"].clone();
+
+    // Step 2: advance main, add a branch, pull from a new full bundle
+    fs::write(src_path.join("b.txt"), "b\n").unwrap();
+    assert!(run_git_command(src, &["add", "."]));
+    assert!(run_git_command(src, &["commit", "-m", "Second"]));
+    assert!(run_git_command(src, &["branch", "feature"]));
+    fs::remove_file(&bundle).ok();
+    pack_repo_bundle(src, bundle.to_str().unwrap()).expect("Failed to pack bundle");
+
+    let report = pull_repo_from_bundle(dst, bundle.to_str().unwrap()).expect("Pull failed");
+    let src_refs = read_repo_refs(src).unwrap();
+    assert_eq!(
+        report.advanced,
+        vec![(
+            "refs/heads/main".to_string(),
+            first_head.clone(),
+            src_refs["refs/heads/main"].clone()
+        )]
+    );
+    assert_eq!(
+        report.created,
+        vec![(
+            "refs/heads/feature".to_string(),
+            src_refs["refs/heads/feature"].clone()
+        )]
+    );
+    assert!(
+        dst_path.join("b.txt").exists(),
+        "Working tree should follow main"
+    );
+    assert!(pull_repo_from_bundle(dst, bundle.to_str().unwrap())
+        .unwrap()
+        .is_up_to_date());
+
+    // Step 3: thin bundle built against a commit the stale clone does not have
+    fs::write(src_path.join("c.txt"), "c\n").unwrap();
+    assert!(run_git_command(src, &["add", "."]));
+    assert!(run_git_command(src, &["commit", "-m", "Third"]));
+    let have = vec![src_refs["refs/heads/main"].clone()];
+    assert_eq!(
+        pack_repo_delta_bundle(src, thin_bundle.to_str().unwrap(), &have).unwrap(),
+        BundleKind::Delta
+    );
+    let err = pull_repo_from_bundle(stale, thin_bundle.to_str().unwrap())
+        .expect_err("Pull should fail without prerequisites");
+    assert!(
+        err.to_string().contains("prerequisite"),
+        "Unexpected error: {}",
+        err
+    );
+
+    for dir in [&src_path, &dst_path, &stale_path] {
+        fs::remove_dir_all(dir).ok();
+    }
+    fs::remove_file(&bundle).ok();
+    fs::remove_file(&thin_bundle).ok();
+}
+
+/// Test unbundle_into on an existing clone:
+/// 1. A thin bundle is applied in place and the ref updates are reported
+/// 2. A clone missing the prerequisite commits gets MegaError::MissingPrerequisite
+#[test]
+fn test_unbundle_into() {
+    let tmp_dir = std::env::current_dir().unwrap().join(ensure_tmp_dir());
+    let src_path = tmp_dir.join("unbundle_src");
+    let dst_path = tmp_dir.join("unbundle_dst");
+    let stale_path = tmp_dir.join("unbundle_stale");
+    let bundle = tmp_dir.join("unbundle_full.bundle");
+    let thin_bundle = tmp_dir.join("unbundle_thin.bundle");
+    for dir in [&src_path, &dst_path, &stale_path] {
+        fs::remove_dir_all(dir).ok();
+    }
+    fs::remove_file(&thin_bundle).ok();
+    fs::create_dir(&src_path).expect("Failed to create source directory");
+    let src = src_path.to_str().unwrap();
+    let dst = dst_path.to_str().unwrap();
+    let stale = stale_path.to_str().unwrap();
+    let tmp = tmp_dir.to_str().unwrap();
+
+    assert!(run_git_command(src, &["init", "-b", "main"]));
+    assert!(run_git_command(
+        src,
+        &["config", "user.email", "test@example.com"]
+    ));
+    assert!(run_git_command(src, &["config", "user.name", "Test User"]));
+    fs::write(src_path.join("a.txt"), "a\n").unwrap();
+    assert!(run_git_command(src, &["add", "."]));
+    assert!(run_git_command(src, &["commit", "-m", "First"]));
+    pack_repo_bundle(src, bundle.to_str().unwrap()).expect("Failed to pack bundle");
+    assert!(run_git_command(
+        tmp,
+        &["clone", bundle.to_str().unwrap(), stale]
+    ));
+    assert!(run_git_command(stale, &["checkout", "main"]));
+
+    fs::write(src_path.join("b.txt"), "b\n").unwrap();
+    assert!(run_git_command(src, &["add", "."]));
+    assert!(run_git_command(src, &["commit", "-m", "Second"]));
+    fs::remove_file(&bundle).ok();
+    pack_repo_bundle(src, bundle.to_str().unwrap()).expect("Failed to pack bundle");
+    assert!(run_git_command(
+        tmp,
+        &["clone", bundle.to_str().unwrap(), dst]
+    ));
+    assert!(run_git_command(dst, &["checkout", "main"]));
+    let have = read_repo_refs(src).unwrap();
+
+    // Step 1: advance main, add a branch, apply the thin bundle in place
+    fs::write(src_path.join("c.txt"), "c\n").unwrap();
+    assert!(run_git_command(src, &["add", "."]));
+    assert!(run_git_command(src, &["commit", "-m", "Third"]));
+    assert!(run_git_command(src, &["branch", "feature"]));
+    pack_repo_thin_bundle(src, thin_bundle.to_str().unwrap(), &have)
+        .expect("Failed to pack thin bundle");
+
+    let updates = unbundle_into(dst, thin_bundle.to_str().unwrap()).expect("Unbundle failed");
+    let src_refs = read_repo_refs(src).unwrap();
+    assert_eq!(
+        updates,
+        vec![
+            (
+                "refs/heads/feature".to_string(),
+                "0".repeat(40),
+                src_refs["refs/heads/feature"].clone()
+            ),
+            (
+                "refs/heads/main".to_string(),
+                have["refs/heads/main"].clone(),
+                src_refs["refs/heads/main"].clone()
+            ),
+        ]
+    );
+    assert!(
+        dst_path.join("c.txt").exists(),
+        "Working tree should follow main"
+    );
+
+    // Step 2: the stale clone lacks the "Second" commit the thin bundle is based on
+    let err = unbundle_into(stale, thin_bundle.to_str().unwrap())
+        .expect_err("Unbundle should fail without prerequisites");
+    assert!(
+        matches!(err, MegaError::MissingPrerequisite(_)),
+        "Unexpected error: {}",
+        err
+    );
+
+    for dir in [&src_path, &dst_path, &stale_path] {
+        fs::remove_dir_all(dir).ok();
+    }
+    fs::remove_file(&bundle).ok();
+    fs::remove_file(&thin_bundle).ok();
+}
+
+/// Test default branch detection:
+/// 1. HEAD's branch wins, including a `main` default
+/// 2. A detached HEAD falls back to main/master, then the single branch, else errors
+/// 3. Restoring a bundle checks out its only branch even when it is neither main nor master
+#[tokio::test]
+async fn test_default_branch_detection() {
+    let tmp_dir = std::env::current_dir().unwrap().join(ensure_tmp_dir());
+    let repo_path = tmp_dir.join("default_branch_repo");
+    let clone_path = tmp_dir.join("default_branch_clone");
+    let bundle = tmp_dir.join("default_branch.bundle");
+    fs::remove_dir_all(&repo_path).ok();
+    fs::remove_dir_all(&clone_path).ok();
+    fs::remove_file(&bundle).ok();
+    fs::create_dir(&repo_path).expect("Failed to create repo directory");
+    let repo = repo_path.to_str().unwrap();
+
+    assert!(run_git_command(repo, &["init", "-b", "trunk"]));
+    assert!(run_git_command(
+        repo,
+        &["config", "user.email", "test@example.com"]
+    ));
+    assert!(run_git_command(repo, &["config", "user.name", "Test User"]));
+    fs::write(repo_path.join("a.txt"), "a\n").unwrap();
+    assert!(run_git_command(repo, &["add", "."]));
+    assert!(run_git_command(repo, &["commit", "-m", "First"]));
+    assert_eq!(default_branch(repo).unwrap(), "trunk");
+
+    // Step 3: the bundle only has `trunk`
+    pack_repo_bundle(repo, bundle.to_str().unwrap()).expect("Failed to pack bundle");
+    assert_eq!(
+        bundle_default_branch(bundle.to_str().unwrap()).unwrap(),
+        Some("trunk".to_string())
+    );
+    restore_repo_from_bundle(bundle.to_str().unwrap(), clone_path.to_str().unwrap())
+        .await
+        .expect("Failed to restore bundle");
+    assert!(
+        clone_path.join("a.txt").exists(),
+        "trunk should be checked out"
+    );
+    assert_eq!(
+        default_branch(clone_path.to_str().unwrap()).unwrap(),
+        "trunk"
+    );
+
+    // Step 2: detached HEAD with a single branch, then with two
+    assert!(run_git_command(repo, &["checkout", "-q", "--detach"]));
+    assert_eq!(default_branch(repo).unwrap(), "trunk");
+    assert!(run_git_command(repo, &["branch", "topic"]));
+    let err = default_branch(repo).expect_err("Two branches and no HEAD should be ambiguous");
+    assert!(err.to_string().contains("topic, trunk"), "{}", err);
+
+    // Step 1: main is preferred when detached, HEAD's branch otherwise
+    assert!(run_git_command(repo, &["branch", "main"]));
+    assert_eq!(default_branch(repo).unwrap(), "main");
+    assert!(run_git_command(repo, &["checkout", "-q", "topic"]));
+    assert_eq!(default_branch(repo).unwrap(), "topic");
+
+    fs::remove_dir_all(&repo_path).ok();
+    fs::remove_dir_all(&clone_path).ok();
+    fs::remove_file(&bundle).ok();
+}
+
+#[test]
+fn test_history_stats_counts_commits_and_authors() {
+    let tmp_dir = std::env::current_dir().unwrap().join(ensure_tmp_dir());
+    let repo_path = tmp_dir.join("history_stats_repo");
+    fs::remove_dir_all(&repo_path).ok();
+    fs::create_dir(&repo_path).expect("Failed to create repo directory");
+    let repo = repo_path.to_str().unwrap();
+
+    assert!(run_git_command(repo, &["init"]));
+    assert!(run_git_command(repo, &["config", "user.name", "Test User"]));
+    let authors = ["alice@example.com", "bob@example.com", "Alice@Example.com"];
+    for (i, email) in authors.iter().enumerate() {
+        assert!(run_git_command(repo, &["config", "user.email", email]));
+        fs::write(repo_path.join("file.txt"), format!("{}\n", i)).unwrap();
+        assert!(run_git_command(repo, &["add", "."]));
+        assert!(run_git_command(
+            repo,
+            &["commit", "-m", &format!("Commit {}", i)]
+        ));
+    }
+
+    assert_eq!(
+        history_stats(repo, 100).unwrap(),
+        HistoryStats {
+            commits: 3,
+            contributors: 2,
+            truncated: false,
+        }
+    );
+    // The walk starts at HEAD, so a limit of 1 only sees the last author
+    assert_eq!(
+        history_stats(repo, 1).unwrap(),
+        HistoryStats {
+            commits: 1,
+            contributors: 1,
+            truncated: true,
+        }
+    );
+
+    fs::remove_dir_all(&repo_path).ok();
+}
+
+#[test]
+fn test_recent_commits_from_repo_and_bundle() {
+    let tmp_dir = std::env::current_dir().unwrap().join(ensure_tmp_dir());
+    let repo_path = tmp_dir.join("recent_commits_repo");
+    let bundle = tmp_dir.join("recent_commits.bundle");
+    fs::remove_dir_all(&repo_path).ok();
+    fs::create_dir(&repo_path).expect("Failed to create repo directory");
+    let repo = repo_path.to_str().unwrap();
+
+    assert!(run_git_command(repo, &["init"]));
+    assert!(run_git_command(repo, &["config", "user.name", "Test User"]));
+    assert!(run_git_command(
+        repo,
+        &["config", "user.email", "test@example.com"]
+    ));
+    for i in 0..3 {
+        fs::write(repo_path.join("file.txt"), format!("{}\n", i)).unwrap();
+        assert!(run_git_command(repo, &["add", "."]));
+        assert!(run_git_command(
+            repo,
+            &["commit", "-m", &format!("Commit {}\n\nbody", i)]
+        ));
+    }
+
+    let commits = recent_commits(repo, 2).unwrap();
+    let subjects: Vec<&str> = commits.iter().map(|c| c.subject.as_str()).collect();
+    assert_eq!(subjects, vec!["Commit 2", "Commit 1"]);
+    assert_eq!(commits[0].author, "Test User");
+    assert_eq!(commits[0].email, "test@example.com");
+    assert!(commits[0].time > 0);
+
+    // A bundle gives the same history without a working copy
+    pack_repo_bundle(repo, bundle.to_str().unwrap()).expect("Failed to pack bundle");
+    let from_bundle = bundle_recent_commits(bundle.to_str().unwrap(), 10).unwrap();
+    assert_eq!(from_bundle.len(), 3);
+    assert_eq!(from_bundle[..2], commits[..]);
+
+    fs::remove_dir_all(&repo_path).ok();
+    fs::remove_file(&bundle).ok();
+}
+
+#[test]
+fn test_verify_bundle_detects_truncation() {
+    let tmp_dir = std::env::current_dir()
+        .unwrap()
+        .join(ensure_tmp_dir())
+        .join("verify_bundle");
+    fs::remove_dir_all(&tmp_dir).ok();
+    let repo_path = tmp_dir.join("repo");
+    fs::create_dir_all(&repo_path).unwrap();
+    let repo = repo_path.to_str().unwrap();
+
+    assert!(run_git_command(repo, &["init"]));
+    assert!(run_git_command(
+        repo,
+        &["config", "user.email", "test@example.com"]
+    ));
+    assert!(run_git_command(repo, &["config", "user.name", "Test User"]));
+    let mut seed: u32 = 7;
+    let content: String = (0..50_000)
+        .map(|_| {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            (b'a' + ((seed >> 16) % 26) as u8) as char
+        })
+        .collect();
+    fs::write(repo_path.join("data.txt"), content).unwrap();
+    assert!(run_git_command(repo, &["add", "."]));
+    assert!(run_git_command(repo, &["commit", "-m", "Initial commit"]));
+
+    let bundle = tmp_dir.join("repo.bundle");
+    let bundle_str = bundle.to_str().unwrap();
+    pack_repo_bundle(repo, bundle_str).expect("Failed to pack repository");
+
+    let refs = verify_bundle(bundle_str, None).expect("intact bundle should verify");
+    assert_eq!(refs, read_repo_refs(repo).unwrap().into_iter().collect());
+
+    // 截断 packfile 的后半部分
+    let bytes = fs::read(&bundle).unwrap();
+    let truncated = tmp_dir.join("truncated.bundle");
+    fs::write(&truncated, &bytes[..bytes.len() / 2]).unwrap();
+    assert!(verify_bundle(truncated.to_str().unwrap(), None).is_err());
+    assert!(verify_bundle(tmp_dir.join("missing.bundle").to_str().unwrap(), None).is_err());
+
+    fs::remove_dir_all(&tmp_dir).ok();
+}
+
+#[test]
+fn test_sync_status_ignores_remote_refs() {
+    let tmp_dir = std::env::current_dir()
+        .unwrap()
+        .join(ensure_tmp_dir())
+        .join("sync_status_remotes");
+    fs::remove_dir_all(&tmp_dir).ok();
+    let src_path = tmp_dir.join("src");
+    let work_path = tmp_dir.join("work");
+    fs::create_dir_all(&src_path).unwrap();
+    let src = src_path.to_str().unwrap();
+    let work = work_path.to_str().unwrap();
+
+    assert!(run_git_command(src, &["init"]));
+    assert!(run_git_command(
+        src,
+        &["symbolic-ref", "HEAD", "refs/heads/main"]
+    ));
+    assert!(run_git_command(
+        src,
+        &["config", "user.email", "test@example.com"]
+    ));
+    assert!(run_git_command(src, &["config", "user.name", "Test User"]));
+    fs::write(src_path.join("README.md"), "# Upstream\n").unwrap();
+    assert!(run_git_command(src, &["add", "."]));
+    assert!(run_git_command(src, &["commit", "-m", "Initial commit"]));
+
+    // Step 1: 工作仓库带有远程跟踪分支，打包后 bundle 中也包含它们
+    assert!(run_git_command(
+        tmp_dir.to_str().unwrap(),
+        &["clone", "-q", src, work]
+    ));
+    let bundle = tmp_dir.join("work.bundle");
+    let bundle_str = bundle.to_str().unwrap();
+    pack_repo_bundle(work, bundle_str).expect("Failed to pack repository");
+    let bundle_refs = extract_bundle_refs(bundle_str).unwrap();
+    assert!(bundle_refs.contains_key("refs/remotes/origin/main"));
+    assert!(
+        pending_ref_updates(work, &bundle_refs, RefFilter::default())
+            .unwrap()
+            .is_empty()
+    );
+
+    // Step 2: 只有远程跟踪分支前进了
+    fs::write(src_path.join("README.md"), "# Upstream\n\nMore.\n").unwrap();
+    assert!(run_git_command(src, &["commit", "-am", "Upstream change"]));
+    assert!(run_git_command(work, &["fetch", "-q", "origin"]));
+
+    assert!(
+        pending_ref_updates(work, &bundle_refs, RefFilter::heads_only())
+            .unwrap()
+            .is_empty()
+    );
+    assert!(
+        pending_ref_updates(work, &bundle_refs, RefFilter::default())
+            .unwrap()
+            .is_empty()
+    );
+    let remote_head = read_repo_refs_filtered(work, RefFilter::heads_only().with_remotes(true))
+        .unwrap()["refs/remotes/origin/main"]
+        .clone();
+    let pending = pending_ref_updates(
+        work,
+        &bundle_refs,
+        RefFilter::heads_only().with_remotes(true),
+    )
+    .unwrap();
+    assert_eq!(
+        pending,
+        vec![("refs/remotes/origin/main".to_string(), Some(remote_head))]
+    );
+
+    fs::remove_dir_all(&tmp_dir).ok();
+}
+
+/// Test restore_repo_from_bundle_with_progress:
+/// 1. git clone progress lines are streamed to the channel
+/// 2. A cancelled restore fails and leaves no partial clone behind
+#[tokio::test]
+async fn test_restore_progress_and_cancellation() {
+    let tmp_dir = std::env::current_dir()
+        .unwrap()
+        .join(ensure_tmp_dir())
+        .join("restore_progress");
+    fs::remove_dir_all(&tmp_dir).ok();
+    let repo_path = tmp_dir.join("repo");
+    fs::create_dir_all(&repo_path).unwrap();
+    let repo = repo_path.to_str().unwrap();
+
+    assert!(run_git_command(repo, &["init", "-b", "main"]));
+    assert!(run_git_command(
+        repo,
+        &["config", "user.email", "test@example.com"]
+    ));
+    assert!(run_git_command(repo, &["config", "user.name", "Test User"]));
+    fs::write(repo_path.join("a.txt"), "a\n").unwrap();
+    assert!(run_git_command(repo, &["add", "."]));
+    assert!(run_git_command(repo, &["commit", "-m", "First"]));
+    let bundle = tmp_dir.join("repo.bundle");
+    pack_repo_bundle(repo, bundle.to_str().unwrap()).expect("Failed to pack bundle");
+
+    // Step 1: progress lines arrive on the channel
+    let clone_path = tmp_dir.join("clone");
+    let (tx, mut rx) = tokio::sync::mpsc::channel(256);
+    restore_repo_from_bundle_with_progress(
+        bundle.to_str().unwrap(),
+        clone_path.to_str().unwrap(),
+        Some(tx),
+        CancellationToken::new(),
+    )
+    .await
+    .expect("Failed to restore bundle");
+    assert!(clone_path.join("a.txt").exists());
+    let mut lines = Vec::new();
+    while let Some(line) = rx.recv().await {
+        lines.push(line);
+    }
+    assert!(
+        lines.iter().any(|line| line.starts_with("Cloning into")),
+        "{:?}",
+        lines
+    );
+
+    // Step 2: cancellation removes the partial clone
+    let cancelled_path = tmp_dir.join("cancelled");
+    let cancel = CancellationToken::new();
+    cancel.cancel();
+    let err = restore_repo_from_bundle_with_progress(
+        bundle.to_str().unwrap(),
+        cancelled_path.to_str().unwrap(),
+        None,
+        cancel,
+    )
+    .await
+    .expect_err("Cancelled restore should fail");
+    assert!(err.to_string().contains("cancelled"), "{}", err);
+    assert!(!cancelled_path.exists());
+
+    fs::remove_dir_all(&tmp_dir).ok();
+}
@@ -1,7 +1,9 @@
 //! 集成测试：启动三个节点，gossip 传递消息
-use megaengine::gossip::{GossipService, SignedMessage};
+use megaengine::gossip::message::Envelope;
+use megaengine::gossip::{ForwardRateLimit, GossipService, SignedMessage};
 use megaengine::identity::keypair::KeyPair;
 use megaengine::node::node::{Node, NodeType};
+use megaengine::node::node_id::NodeId;
 use megaengine::storage::node_model;
 use megaengine::transport::config::QuicConfig;
 use std::net::SocketAddr;
@@ -166,3 +168,283 @@ async fn test_gossip_three_nodes_message_relay() {
     let _ = std::fs::remove_file("cert/ca-cert.pem");
     let _ = std::fs::remove_file("cert/ca-cert-key.pem");
 }
+
+#[tokio::test]
+async fn test_gossip_forwarding_is_rate_limited() {
+    let _ = rustls::crypto::ring::default_provider().install_default();
+
+    let kp1 = KeyPair::generate().unwrap();
+    let kp2 = KeyPair::generate().unwrap();
+    let kp3 = KeyPair::generate().unwrap();
+    let addr1: SocketAddr = "127.0.0.1:19021".parse().unwrap();
+    let addr2: SocketAddr = "127.0.0.1:19022".parse().unwrap();
+    let addr3: SocketAddr = "127.0.0.1:19023".parse().unwrap();
+
+    let mut node1 = Node::from_keypair(&kp1, "flooder", vec![addr1], NodeType::Normal);
+    let mut node2 = Node::from_keypair(&kp2, "relay", vec![addr2], NodeType::Normal);
+    let mut node3 = Node::from_keypair(&kp3, "observer", vec![addr3], NodeType::Normal);
+
+    // 使用身份密钥生成证书，避免与其他测试共享证书文件
+    let config = |addr: SocketAddr, kp: &KeyPair| {
+        QuicConfig::new(addr, String::new(), String::new(), String::new())
+            .with_peer_verification(true)
+            .with_identity(kp.clone())
+    };
+    node1.start_quic_server(config(addr1, &kp1)).await.unwrap();
+    node2.start_quic_server(config(addr2, &kp2)).await.unwrap();
+    node3.start_quic_server(config(addr3, &kp3)).await.unwrap();
+
+    // 只有 node2 运行 gossip 服务，每个邻居每秒最多转发 3 条
+    let gossip2 = Arc::new(
+        GossipService::new(
+            Arc::clone(node2.connection_manager.as_ref().unwrap()),
+            node2.clone(),
+            None,
+        )
+        .with_forward_rate_limit(ForwardRateLimit {
+            messages_per_sec: 3,
+            bytes_per_sec: 1024 * 1024,
+        }),
+    );
+    gossip2.start().await.unwrap();
+
+    // node3 直接接收 gossip 消息，统计 node2 转发来的 node1 消息
+    let mgr3 = node3
+        .connection_manager
+        .as_ref()
+        .unwrap()
+        .lock()
+        .await
+        .clone();
+    let (tx3, mut rx3) = tokio::sync::mpsc::channel::<(NodeId, Vec<u8>)>(256);
+    mgr3.register_gossip_sender(tx3).await;
+
+    let mgr1 = node1
+        .connection_manager
+        .as_ref()
+        .unwrap()
+        .lock()
+        .await
+        .clone();
+    let mgr2 = node2
+        .connection_manager
+        .as_ref()
+        .unwrap()
+        .lock()
+        .await
+        .clone();
+    mgr1.connect(
+        node1.node_id().clone(),
+        node2.node_id().clone(),
+        vec![addr2],
+    )
+    .await
+    .unwrap();
+    mgr2.connect(
+        node2.node_id().clone(),
+        node3.node_id().clone(),
+        vec![addr3],
+    )
+    .await
+    .unwrap();
+    sleep(Duration::from_millis(500)).await;
+
+    // node1 短时间内发送 20 条不同的消息
+    for _ in 0..20 {
+        let env = Envelope {
+            payload: SignedMessage::new_node_sign_message(node1.clone()).unwrap(),
+            ttl: 3,
+        };
+        mgr1.send_gossip_message(node2.node_id().clone(), serde_json::to_vec(&env).unwrap())
+            .await
+            .unwrap();
+    }
+    sleep(Duration::from_millis(500)).await;
+
+    let mut forwarded = 0;
+    while let Ok((_, data)) = rx3.try_recv() {
+        if let Ok(env) = serde_json::from_slice::<Envelope>(&data) {
+            if env.payload.node_id == *node1.node_id() {
+                forwarded += 1;
+            }
+        }
+    }
+    assert!(forwarded > 0, "some messages should be forwarded");
+    assert!(
+        forwarded <= 4,
+        "forwarded {} messages, expected throttling",
+        forwarded
+    );
+
+    let _ = node_model::delete_node_from_db(node1.node_id().as_str()).await;
+    for mgr in [mgr1, mgr2, mgr3] {
+        mgr.shutdown().await;
+    }
+}
+
+#[tokio::test]
+async fn test_node_leave_marks_node_offline() {
+    let _ = rustls::crypto::ring::default_provider().install_default();
+
+    let kp1 = KeyPair::generate().unwrap();
+    let kp2 = KeyPair::generate().unwrap();
+    let addr1: SocketAddr = "127.0.0.1:19031".parse().unwrap();
+    let addr2: SocketAddr = "127.0.0.1:19032".parse().unwrap();
+
+    let mut node1 = Node::from_keypair(&kp1, "leaver", vec![addr1], NodeType::Normal);
+    let mut node2 = Node::from_keypair(&kp2, "stayer", vec![addr2], NodeType::Normal);
+    let config = |addr: SocketAddr, kp: &KeyPair| {
+        QuicConfig::new(addr, String::new(), String::new(), String::new())
+            .with_peer_verification(true)
+            .with_identity(kp.clone())
+    };
+    node1.start_quic_server(config(addr1, &kp1)).await.unwrap();
+    node2.start_quic_server(config(addr2, &kp2)).await.unwrap();
+
+    let gossip1 = Arc::new(GossipService::new(
+        Arc::clone(node1.connection_manager.as_ref().unwrap()),
+        node1.clone(),
+        None,
+    ));
+    let gossip2 = Arc::new(GossipService::new(
+        Arc::clone(node2.connection_manager.as_ref().unwrap()),
+        node2.clone(),
+        None,
+    ));
+    Arc::clone(&gossip1).start().await.unwrap();
+    gossip2.start().await.unwrap();
+
+    let mgr1 = node1
+        .connection_manager
+        .as_ref()
+        .unwrap()
+        .lock()
+        .await
+        .clone();
+    let mgr2 = node2
+        .connection_manager
+        .as_ref()
+        .unwrap()
+        .lock()
+        .await
+        .clone();
+    mgr1.connect(
+        node1.node_id().clone(),
+        node2.node_id().clone(),
+        vec![addr2],
+    )
+    .await
+    .unwrap();
+    sleep(Duration::from_millis(500)).await;
+    // node2 之前已收到过 node1 的公告
+    let leaver = node1.node_id().to_string();
+    node_model::save_node_info_to_db(&node1.info).await.unwrap();
+    node_model::mark_node_seen(&leaver).await.unwrap();
+
+    gossip1.announce_leave().await.unwrap();
+    sleep(Duration::from_millis(300)).await;
+
+    let left = node_model::left_nodes().await.unwrap();
+    assert!(left.contains_key(&leaver), "node1 should be marked as left");
+    // 只标记离线，不删除节点记录
+    assert!(node_model::load_node_info_from_db(&leaver)
+        .await
+        .unwrap()
+        .is_some());
+    assert!(
+        !mgr2.list_peers().await.contains(node1.node_id()),
+        "node2 should drop the connection to node1"
+    );
+
+    let _ = node_model::delete_node_from_db(&leaver).await;
+    let _ = node_model::delete_node_from_db(node2.node_id().as_str()).await;
+    for mgr in [mgr1, mgr2] {
+        mgr.shutdown().await;
+    }
+}
+
+#[tokio::test]
+async fn test_ping_peer_measures_round_trip() {
+    let _ = rustls::crypto::ring::default_provider().install_default();
+
+    let kp_server = KeyPair::generate().unwrap();
+    let kp_client = KeyPair::generate().unwrap();
+    let addr: SocketAddr = "127.0.0.1:19041".parse().unwrap();
+    let config = |addr: SocketAddr, kp: &KeyPair| {
+        QuicConfig::new(addr, String::new(), String::new(), String::new())
+            .with_peer_verification(true)
+            .with_identity(kp.clone())
+    };
+
+    let mut server = Node::from_keypair(&kp_server, "pinged", vec![addr], NodeType::Normal);
+    server
+        .start_quic_server(config(addr, &kp_server))
+        .await
+        .unwrap();
+    let ping_interval = Duration::from_millis(300);
+    let gossip = Arc::new(
+        GossipService::new(
+            Arc::clone(server.connection_manager.as_ref().unwrap()),
+            server.clone(),
+            None,
+        )
+        .with_ping_interval(ping_interval),
+    );
+    gossip.start().await.unwrap();
+
+    // 客户端只建立连接，不运行 GossipService
+    let client = Node::from_keypair(&kp_client, "pinger", Vec::new(), NodeType::Normal);
+    let mgr = megaengine::transport::quic::ConnectionManager::run_server(config(
+        "127.0.0.1:0".parse().unwrap(),
+        &kp_client,
+    ))
+    .await
+    .unwrap();
+    let (tx, mut rx) = tokio::sync::mpsc::channel(64);
+    mgr.register_gossip_sender(tx).await;
+    mgr.connect(
+        client.node_id().clone(),
+        server.node_id().clone(),
+        vec![addr],
+    )
+    .await
+    .unwrap();
+
+    for _ in 0..3 {
+        let rtt = tokio::time::timeout(
+            Duration::from_secs(5),
+            megaengine::gossip::ping_peer(&mgr, &client, &mut rx, server.node_id()),
+        )
+        .await
+        .expect("pong before timeout")
+        .unwrap();
+        assert!(rtt < Duration::from_secs(5));
+        tokio::time::sleep(ping_interval).await;
+    }
+
+    // 同一个探测周期内的第二个 Ping 不会得到回应
+    tokio::time::timeout(
+        Duration::from_secs(5),
+        megaengine::gossip::ping_peer(&mgr, &client, &mut rx, server.node_id()),
+    )
+    .await
+    .expect("pong before timeout")
+    .unwrap();
+    let flooded = tokio::time::timeout(
+        Duration::from_millis(200),
+        megaengine::gossip::ping_peer(&mgr, &client, &mut rx, server.node_id()),
+    )
+    .await;
+    assert!(flooded.is_err());
+
+    let _ = node_model::delete_node_from_db(client.node_id().as_str()).await;
+    mgr.shutdown().await;
+    server
+        .connection_manager
+        .as_ref()
+        .unwrap()
+        .lock()
+        .await
+        .shutdown()
+        .await;
+}